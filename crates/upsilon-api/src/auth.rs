@@ -14,21 +14,41 @@
  *    limitations under the License.
  */
 
-use std::fmt;
-use std::fmt::Formatter;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Formatter, Write as _};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::{fmt, io};
 
-use jwt::{AlgorithmType, PKeyWithDigest, SignWithKey, Token, VerifyWithKey};
+use jwt::{AlgorithmType, PKeyWithDigest, SignWithKey, Token, Verified, VerifyWithStore};
 use openssl::pkey::{PKey, Private, Public};
 use openssl::rsa::Rsa;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Request, State};
+use upsilon_core::config::{TokenKeyFile, TokenKeysConfig, TokensConfig};
+use upsilon_data::{CommonDataClientError, DataClientMasterHolder, DataQueryMaster};
+use upsilon_models::repo::{Repo, RepoId, RepoPermissions};
+use upsilon_models::users::sessions::SessionId;
+use upsilon_models::users::tokens::{PersonalAccessToken, TokenScope};
 use upsilon_models::users::UserId;
 
+/// The prefix of all personal access tokens, which tells them apart from session tokens.
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "upat_";
+/// The prefix of the refresh tokens of sessions.
+pub const REFRESH_TOKEN_PREFIX: &str = "upsr_";
+/// The prefix of the tokens emailed to reset passwords.
+pub const PASSWORD_RESET_TOKEN_PREFIX: &str = "upprt_";
+/// The prefix of the codes of invites to register.
+pub const INVITE_CODE_PREFIX: &str = "upinv_";
+
 #[derive(Clone, Debug)]
 pub struct AuthToken {
     pub claims: AuthTokenClaims,
+    /// Set if the user authenticated with a personal access token,
+    /// instead of a session token.
+    pub personal_access_token: Option<PersonalAccessToken>,
     token: String,
 }
 
@@ -39,8 +59,131 @@ impl AuthToken {
             Err(jwt) => return Err(jwt.into()),
         };
 
-        Ok(AuthToken { claims, token })
+        if claims.exp <= chrono::Utc::now().timestamp() as usize {
+            return Err(AuthTokenError::Expired);
+        }
+
+        Ok(AuthToken {
+            claims,
+            personal_access_token: None,
+            token,
+        })
+    }
+
+    /// Accepts both session tokens and personal access tokens.
+    ///
+    /// Unlike [`from_string`](Self::from_string), this also rejects the
+    /// tokens of sessions that were logged out of, and the tokens of
+    /// suspended users.
+    pub async fn authenticate(
+        token: String,
+        cx: &AuthContext,
+        qm: &DataQueryMaster<'_>,
+    ) -> Result<Self, AuthTokenError> {
+        let token = Self::authenticate_token(token, cx, qm).await?;
+
+        if qm.query_user(token.claims.sub).await?.suspended {
+            return Err(AuthTokenError::UserSuspended);
+        }
+
+        Ok(token)
+    }
+
+    async fn authenticate_token(
+        token: String,
+        cx: &AuthContext,
+        qm: &DataQueryMaster<'_>,
+    ) -> Result<Self, AuthTokenError> {
+        if !token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+            let token = Self::from_string(token, cx)?;
+
+            if let Some(sid) = token.claims.sid {
+                let session = qm.query_session(sid).await?;
+
+                let active = session.map_or(false, |session| {
+                    session.user == token.claims.sub
+                        && !session.is_expired(chrono::Utc::now().naive_utc())
+                });
+
+                if !active {
+                    return Err(AuthTokenError::SessionEnded);
+                }
+            }
+
+            return Ok(token);
+        }
+
+        let pat = qm
+            .query_personal_access_token(hash_secret(&token))
+            .await?
+            .ok_or(AuthTokenError::InvalidPersonalAccessToken)?;
+
+        if pat.is_expired(chrono::Utc::now().naive_utc()) {
+            return Err(AuthTokenError::ExpiredPersonalAccessToken);
+        }
+
+        let exp = pat.expires_at.map_or(usize::MAX, |expires_at| {
+            expires_at.and_utc().timestamp() as usize
+        });
+
+        Ok(AuthToken {
+            claims: AuthTokenClaims {
+                sub: pat.user,
+                exp,
+                sid: None,
+            },
+            personal_access_token: Some(pat),
+            token,
+        })
+    }
+
+    /// Session tokens can be used for everything, personal access tokens
+    /// only for their scopes.
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.personal_access_token
+            .as_ref()
+            .map_or(true, |pat| pat.has_scope(scope))
     }
+
+    pub fn allows_repo(&self, repo: RepoId) -> bool {
+        self.personal_access_token
+            .as_ref()
+            .map_or(true, |pat| pat.allows_repo(repo))
+    }
+
+    /// Narrows the permissions of the user on `repo` down to what the token allows.
+    pub fn restrict_repo_permissions(
+        &self,
+        repo: &Repo,
+        perms: RepoPermissions,
+    ) -> RepoPermissions {
+        upsilon_vcs_permissions::restrict_to_token(repo, perms, self.personal_access_token.as_ref())
+    }
+}
+
+/// Generates a new random token starting with `prefix`, like a personal access
+/// token or a refresh token, returning the token and its hash.
+pub fn generate_secret(prefix: &str) -> (String, String) {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+
+    let token = format!("{prefix}{}", hex(&bytes));
+    let hash = hash_secret(&token);
+
+    (token, hash)
+}
+
+/// Tokens from [`generate_secret`] are stored hashed; as they are random
+/// and long, a plain SHA-256 is enough.
+pub fn hash_secret(token: &str) -> String {
+    hex(&openssl::sha::sha256(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -51,6 +194,18 @@ pub enum AuthTokenError {
     InvalidAuthorizationHeader,
     #[error("jwt: {0}")]
     Jwt(#[from] jwt::error::Error),
+    #[error("Expired token")]
+    Expired,
+    #[error("The session of the token has ended")]
+    SessionEnded,
+    #[error("Invalid personal access token")]
+    InvalidPersonalAccessToken,
+    #[error("Expired personal access token")]
+    ExpiredPersonalAccessToken,
+    #[error("The user is suspended")]
+    UserSuspended,
+    #[error("data backend error: {0}")]
+    DataBackend(#[from] CommonDataClientError),
     #[error("internal error")]
     InternalError,
 }
@@ -76,15 +231,20 @@ impl<'r> FromRequest<'r> for AuthToken {
             Outcome::Forward(_) => return Outcome::Forward(()),
         };
 
-        let claims = match cx.verify(token) {
-            Ok(claims) => claims,
-            Err(jwt) => return Outcome::Failure((Status::Unauthorized, jwt.into())),
+        let data = match <&State<DataClientMasterHolder>>::from_request(request).await {
+            Outcome::Success(data) => data,
+            Outcome::Failure((status, _)) => {
+                return Outcome::Failure((status, AuthTokenError::InternalError))
+            }
+            Outcome::Forward(_) => return Outcome::Forward(()),
         };
 
-        Outcome::Success(AuthToken {
-            claims,
-            token: token.to_owned(),
-        })
+        let qm = data.query_master();
+
+        match AuthToken::authenticate(token.to_owned(), &cx, &qm).await {
+            Ok(token) => Outcome::Success(token),
+            Err(e) => Outcome::Failure((Status::Unauthorized, e)),
+        }
     }
 }
 
@@ -98,28 +258,112 @@ impl fmt::Display for AuthToken {
 pub struct AuthTokenClaims {
     pub sub: UserId,
     exp: usize,
+    /// The session the token was issued for, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<SessionId>,
 }
 
 impl AuthTokenClaims {
     pub fn new(sub: UserId, expires_in: chrono::Duration) -> Self {
         let exp = (chrono::Utc::now() + expires_in).timestamp() as usize;
 
-        Self { sub, exp }
+        Self {
+            sub,
+            exp,
+            sid: None,
+        }
+    }
+
+    pub fn with_session(self, sid: SessionId) -> Self {
+        Self {
+            sid: Some(sid),
+            ..self
+        }
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum AuthContextError {
+    #[error("{path}: {error}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+    #[error("openssl error: {0}")]
+    Openssl(#[from] openssl::error::ErrorStack),
+    #[error("{0}: not an RSA key")]
+    NotRsa(PathBuf),
+    #[error("duplicate key id: {0}")]
+    DuplicateKid(String),
+}
+
 #[derive(Clone)]
 pub struct AuthContext(Arc<AuthContextInternal>);
 
 impl AuthContext {
-    pub fn new(bits: u32) -> Self {
-        let pkey = Rsa::generate(bits).unwrap();
-        let k0pkey = pkey.public_key_to_pem().unwrap();
-        let pubkey = Rsa::public_key_from_pem(&k0pkey).unwrap();
-        Self(Arc::new(AuthContextInternal {
-            private_key: PKey::from_rsa(pkey).unwrap(),
-            public_key: PKey::from_rsa(pubkey).unwrap(),
-        }))
+    pub fn from_config(config: &TokensConfig) -> Result<Self, AuthContextError> {
+        let (signing_key, verification_keys) = match &config.keys {
+            TokenKeysConfig::Generated { path: None, bits } => {
+                (SigningKey::new(Rsa::generate(*bits)?, None)?, vec![])
+            }
+            TokenKeysConfig::Generated {
+                path: Some(path),
+                bits,
+            } => (load_or_generate_key(path, *bits)?, vec![]),
+            TokenKeysConfig::Files {
+                signing,
+                verification,
+            } => {
+                let signing_key = load_signing_key(signing)?;
+                let verification_keys = verification
+                    .iter()
+                    .map(load_verification_key)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                (signing_key, verification_keys)
+            }
+        };
+
+        let mut kids = BTreeSet::from([signing_key.kid.as_str()]);
+        for (kid, _) in &verification_keys {
+            if !kids.insert(kid.as_str()) {
+                return Err(AuthContextError::DuplicateKid(kid.clone()));
+            }
+        }
+
+        let lifetime = chrono::Duration::from_std(config.lifetime)
+            .unwrap_or_else(|_| chrono::Duration::minutes(15));
+        let refresh_lifetime = chrono::Duration::from_std(config.refresh_lifetime)
+            .unwrap_or_else(|_| chrono::Duration::days(30));
+
+        Ok(Self(Arc::new(AuthContextInternal::new(
+            signing_key,
+            verification_keys,
+            lifetime,
+            refresh_lifetime,
+        ))))
+    }
+
+    /// How long the tokens signed by [`sign`](Self::sign) should be valid for.
+    pub fn token_lifetime(&self) -> chrono::Duration {
+        self.0.token_lifetime
+    }
+
+    /// How long a session lasts without being refreshed.
+    pub fn refresh_token_lifetime(&self) -> chrono::Duration {
+        self.0.refresh_token_lifetime
+    }
+
+    /// Signs a token for `sub`, valid for the [configured lifetime](Self::token_lifetime).
+    pub fn issue_token(&self, sub: UserId) -> AuthToken {
+        self.sign(AuthTokenClaims::new(sub, self.token_lifetime()))
+    }
+
+    /// Like [`issue_token`](Self::issue_token), but the token stops working
+    /// as soon as the session `sid` ends.
+    pub fn issue_session_token(&self, sub: UserId, sid: SessionId) -> AuthToken {
+        self.sign(AuthTokenClaims::new(sub, self.token_lifetime()).with_session(sid))
     }
 
     pub fn sign(&self, claims: AuthTokenClaims) -> AuthToken {
@@ -131,16 +375,161 @@ impl AuthContext {
     }
 }
 
-struct AuthContextInternal {
+/// A short fingerprint of the public key, used as the `kid` when none is configured.
+fn fingerprint(key: &PKey<Public>) -> Result<String, AuthContextError> {
+    let digest = openssl::sha::sha256(&key.public_key_to_der()?);
+
+    Ok(hex(&digest[..8]))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, AuthContextError> {
+    std::fs::read(path).map_err(|error| AuthContextError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn load_signing_key(file: &TokenKeyFile) -> Result<SigningKey, AuthContextError> {
+    let pkey = PKey::private_key_from_pem(&read_file(&file.path)?)?;
+    let rsa = pkey
+        .rsa()
+        .map_err(|_| AuthContextError::NotRsa(file.path.clone()))?;
+
+    SigningKey::new(rsa, file.kid.clone())
+}
+
+fn load_verification_key(file: &TokenKeyFile) -> Result<(String, PKey<Public>), AuthContextError> {
+    let pem = read_file(&file.path)?;
+
+    // accept both public keys and the old private keys themselves
+    let public_key = match PKey::public_key_from_pem(&pem) {
+        Ok(key) => key,
+        Err(_) => public_part(&PKey::private_key_from_pem(&pem)?)?,
+    };
+
+    if public_key.rsa().is_err() {
+        return Err(AuthContextError::NotRsa(file.path.clone()));
+    }
+
+    let kid = match &file.kid {
+        Some(kid) => kid.clone(),
+        None => fingerprint(&public_key)?,
+    };
+
+    Ok((kid, public_key))
+}
+
+fn load_or_generate_key(path: &Path, bits: u32) -> Result<SigningKey, AuthContextError> {
+    if path.exists() {
+        return load_signing_key(&TokenKeyFile {
+            kid: None,
+            path: path.to_path_buf(),
+        });
+    }
+
+    let rsa = Rsa::generate(bits)?;
+    let pem = rsa.private_key_to_pem()?;
+
+    let io_err = |error| AuthContextError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_err)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(&pem))
+        .map_err(io_err)?;
+
+    SigningKey::new(rsa, None)
+}
+
+fn public_part(key: &PKey<Private>) -> Result<PKey<Public>, openssl::error::ErrorStack> {
+    PKey::public_key_from_der(&key.public_key_to_der()?)
+}
+
+struct SigningKey {
+    kid: String,
     private_key: PKey<Private>,
     public_key: PKey<Public>,
 }
 
+impl SigningKey {
+    fn new(rsa: Rsa<Private>, kid: Option<String>) -> Result<Self, AuthContextError> {
+        let private_key = PKey::from_rsa(rsa)?;
+        let public_key = public_part(&private_key)?;
+
+        let kid = match kid {
+            Some(kid) => kid,
+            None => fingerprint(&public_key)?,
+        };
+
+        Ok(Self {
+            kid,
+            private_key,
+            public_key,
+        })
+    }
+}
+
+struct AuthContextInternal {
+    signing_kid: String,
+    private_key: PKey<Private>,
+    /// All the keys tokens can be verified with, by `kid`,
+    /// including the public part of the signing key.
+    verification_keys: BTreeMap<String, PKeyWithDigest<Public>>,
+    token_lifetime: chrono::Duration,
+    refresh_token_lifetime: chrono::Duration,
+}
+
 impl AuthContextInternal {
+    fn new(
+        signing_key: SigningKey,
+        verification_keys: Vec<(String, PKey<Public>)>,
+        token_lifetime: chrono::Duration,
+        refresh_token_lifetime: chrono::Duration,
+    ) -> Self {
+        let SigningKey {
+            kid: signing_kid,
+            private_key,
+            public_key,
+        } = signing_key;
+
+        let verification_keys = std::iter::once((signing_kid.clone(), public_key))
+            .chain(verification_keys)
+            .map(|(kid, key)| {
+                (
+                    kid,
+                    PKeyWithDigest {
+                        digest: openssl::hash::MessageDigest::sha256(),
+                        key,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            signing_kid,
+            private_key,
+            verification_keys,
+            token_lifetime,
+            refresh_token_lifetime,
+        }
+    }
+
     fn sign(&self, claims: AuthTokenClaims) -> AuthToken {
         let token = Token::new(
             jwt::Header {
                 algorithm: AlgorithmType::Rs256,
+                key_id: Some(self.signing_kid.clone()),
                 ..Default::default()
             },
             claims.clone(),
@@ -153,15 +542,15 @@ impl AuthContextInternal {
 
         AuthToken {
             claims,
+            personal_access_token: None,
             token: token.as_str().to_owned(),
         }
     }
 
     fn verify(&self, token: &str) -> Result<AuthTokenClaims, jwt::Error> {
-        let claims: AuthTokenClaims = token.verify_with_key(&PKeyWithDigest {
-            digest: openssl::hash::MessageDigest::sha256(),
-            key: self.public_key.clone(),
-        })?;
-        Ok(claims)
+        let token: Token<jwt::Header, AuthTokenClaims, Verified> =
+            token.verify_with_store(&self.verification_keys)?;
+
+        Ok(token.claims().clone())
     }
 }
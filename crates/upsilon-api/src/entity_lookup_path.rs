@@ -29,6 +29,8 @@ use crate::error::ApiResult;
 
 const LOOKUP_PATH_SEGMENT_SEPARATOR: char = '.';
 
+/// A path like `org/team/sub-team/repo`; teams can be nested to any depth,
+/// so there is no limit on the number of segments.
 pub struct EntityLookupPath {
     path: Vec<PlainNamespaceFragment>,
 }
@@ -46,10 +48,6 @@ impl EntityLookupPath {
             return Err(NsLookupPathError::Empty);
         }
 
-        if iter.len() > 3 {
-            return Err(NsLookupPathError::TooManySegments);
-        }
-
         Ok(EntityLookupPath {
             path: iter.map(Into::into).collect(),
         })
@@ -80,8 +78,6 @@ impl Index<usize> for EntityLookupPath {
 pub enum NsLookupPathError {
     #[error("empty")]
     Empty,
-    #[error("too many segments")]
-    TooManySegments,
 }
 
 impl<'r> FromSegments<'r> for EntityLookupPath {
@@ -131,6 +127,22 @@ pub enum ResolvedEntity {
     },
 }
 
+/// Repos are looked up by the names they had before being renamed or moved
+/// too, but a repo that currently has the name always takes precedence.
+async fn query_repo_by_current_or_previous_name(
+    qm: &DataQueryMaster<'_>,
+    name: &PlainNamespaceFragment,
+    namespace: NamespaceId,
+) -> ApiResult<Option<Repo>> {
+    let namespace = RepoNamespace(namespace);
+
+    if let Some(repo) = qm.query_repo_by_name(name, &namespace).await? {
+        return Ok(Some(repo));
+    }
+
+    Ok(qm.query_repo_by_previous_name(name, &namespace).await?)
+}
+
 pub async fn resolve<'a>(
     qm: &'a DataQueryMaster<'a>,
     path: &'a EntityLookupPath,
@@ -147,12 +159,12 @@ pub async fn resolve<'a>(
                     Some(org) => ResolvedEntity::Organization(org),
                     None => match qm.query_user_by_username(fragment).await? {
                         Some(user) => ResolvedEntity::User(user),
-                        None => match qm
-                            .query_repo_by_name(
-                                fragment,
-                                &RepoNamespace(NamespaceId::GlobalNamespace),
-                            )
-                            .await?
+                        None => match query_repo_by_current_or_previous_name(
+                            qm,
+                            fragment,
+                            NamespaceId::GlobalNamespace,
+                        )
+                        .await?
                         {
                             Some(repo) => ResolvedEntity::Repo {
                                 parent_ns: Box::new(ns),
@@ -164,9 +176,12 @@ pub async fn resolve<'a>(
                 }
             }
             ResolvedEntity::User(user) => {
-                match qm
-                    .query_repo_by_name(fragment, &RepoNamespace(NamespaceId::User(user.id)))
-                    .await?
+                match query_repo_by_current_or_previous_name(
+                    qm,
+                    fragment,
+                    NamespaceId::User(user.id),
+                )
+                .await?
                 {
                     Some(repo) => ResolvedEntity::Repo {
                         parent_ns: Box::new(ResolvedEntity::User(user)),
@@ -176,14 +191,19 @@ pub async fn resolve<'a>(
                 }
             }
             ResolvedEntity::Organization(org) => {
-                match qm.query_team_by_name(org.id, fragment).await? {
+                // nested teams are only reachable through their parent
+                match qm
+                    .query_team_by_name(org.id, fragment)
+                    .await?
+                    .filter(|team| team.parent.is_none())
+                {
                     Some(team) => ResolvedEntity::Team(org, team),
-                    None => match qm
-                        .query_repo_by_name(
-                            fragment,
-                            &RepoNamespace(NamespaceId::Organization(org.id)),
-                        )
-                        .await?
+                    None => match query_repo_by_current_or_previous_name(
+                        qm,
+                        fragment,
+                        NamespaceId::Organization(org.id),
+                    )
+                    .await?
                     {
                         Some(repo) => ResolvedEntity::Repo {
                             parent_ns: Box::new(ResolvedEntity::Organization(org)),
@@ -193,16 +213,28 @@ pub async fn resolve<'a>(
                     },
                 }
             }
-            ResolvedEntity::Team(org, team) => match qm
-                .query_repo_by_name(fragment, &RepoNamespace(NamespaceId::Team(org.id, team.id)))
-                .await?
-            {
-                Some(repo) => ResolvedEntity::Repo {
-                    parent_ns: Box::new(ResolvedEntity::Team(org, team)),
-                    repo,
-                },
-                None => return Ok(None),
-            },
+            ResolvedEntity::Team(org, team) => {
+                match qm
+                    .query_team_by_name(org.id, fragment)
+                    .await?
+                    .filter(|sub_team| sub_team.parent == Some(team.id))
+                {
+                    Some(sub_team) => ResolvedEntity::Team(org, sub_team),
+                    None => match query_repo_by_current_or_previous_name(
+                        qm,
+                        fragment,
+                        NamespaceId::Team(org.id, team.id),
+                    )
+                    .await?
+                    {
+                        Some(repo) => ResolvedEntity::Repo {
+                            parent_ns: Box::new(ResolvedEntity::Team(org, team)),
+                            repo,
+                        },
+                        None => return Ok(None),
+                    },
+                }
+            }
             ResolvedEntity::Repo { .. } => return Err(crate::error::Error::ResolveImpossible),
         };
         namespace = new_ns;
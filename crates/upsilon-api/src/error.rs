@@ -17,6 +17,8 @@
 use rocket::response::Responder;
 use rocket::Request;
 
+use crate::oidc::OidcError;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("IO Error: {0}")]
@@ -25,6 +27,14 @@ pub enum Error {
     Vcs(#[from] upsilon_vcs::Error),
     #[error("data backend error: {0}")]
     DataBackend(#[from] upsilon_data::CommonDataClientError),
+    #[error("OpenID Connect error: {0}")]
+    Oidc(#[from] crate::oidc::OidcError),
+    #[error("{0}")]
+    RateLimited(#[from] upsilon_core::rate_limit::RateLimited),
+    #[error("Mailer error: {0}")]
+    Mailer(#[from] crate::mailer::MailerError),
+    #[error("LDAP error: {0}")]
+    Ldap(#[from] crate::ldap::LdapError),
 
     #[error("Repo not found")]
     RepoNotFound,
@@ -34,10 +44,82 @@ pub enum Error {
     #[error("Resolve impossible")]
     ResolveImpossible,
 
+    #[error("Team is not part of the organization the repo belongs to")]
+    TeamNotInRepoOrganization,
+    #[error("Tokens must expire at least one day after they are created")]
+    InvalidTokenExpiry,
+    #[error("This SSH key is already in use")]
+    SshKeyInUse,
+
+    #[error("Invalid or expired password reset token")]
+    InvalidPasswordResetToken,
+
     #[error("Unauthorized")]
     Unauthorized,
     #[error("Forbidden")]
     Forbidden,
+    #[error("The personal access token is missing a required scope")]
+    MissingTokenScope,
+    #[error("Personal access tokens cannot be used for this")]
+    SessionTokenRequired,
+    #[error("Refresh token was already used; the session has been ended")]
+    RefreshTokenReused,
+    #[error("No account is linked to this login, and registration is disabled")]
+    OidcRegistrationDisabled,
+    #[error("An account with this email already exists, but it cannot be linked to this login")]
+    OidcEmailConflict,
+    #[error("The provider did not share an email address")]
+    OidcEmailMissing,
+    #[error("The directory entry has no email address")]
+    LdapEmailMissing,
+    #[error("An account with this username exists, but it does not belong to the directory")]
+    LdapAccountConflict,
+    #[error("The directory entry has no account yet, and registration is disabled")]
+    LdapRegistrationDisabled,
+    #[error("Two-factor authentication is enabled for this account; log in with beginLogin")]
+    TwoFactorRequired,
+    #[error("Invalid two-factor code")]
+    InvalidTwoFactorCode,
+    #[error("Two-factor authentication is already enabled")]
+    TwoFactorAlreadyEnabled,
+    #[error("Two-factor authentication is not set up")]
+    TwoFactorNotEnrolled,
+    #[error("Organization owners must have two-factor authentication enabled")]
+    TwoFactorRequiredForOrgOwners,
+    #[error("Only instance admins can do this")]
+    AdminRequired,
+    #[error("This account is suspended")]
+    UserSuspended,
+    #[error("The user still owns organizations or repos; transfer them first")]
+    UserOwnsEntities,
+    #[error("Admins cannot do this to their own account")]
+    CannotTargetSelf,
+    #[error("Registration is invite-only")]
+    InviteRequired,
+    #[error("Invalid, expired or already used invite code")]
+    InvalidInviteCode,
+    #[error("Registration is not open to this email domain")]
+    EmailDomainNotAllowed,
+    #[error("This account is waiting for an admin to approve it")]
+    PendingApproval,
+    #[error("This account does not need approval")]
+    NotPendingApproval,
+    #[error("Users cannot block themselves")]
+    CannotBlockSelf,
+    #[error("The owner of the repo has blocked this user")]
+    UserBlocked,
+    #[error("Organizations must keep at least one owner")]
+    LastOrganizationOwner,
+    #[error("Teams can only be nested inside teams of the same organization")]
+    TeamNotInOrganization,
+    #[error("A team cannot be nested inside itself or one of its sub-teams")]
+    TeamParentCycle,
+    #[error("Something else is already stored where the team would move to")]
+    TeamDirectoryTaken,
+    #[error("The user is not a member of the organization")]
+    NotAnOrganizationMember,
+    #[error("Repo names have to be a single path segment")]
+    InvalidRepoName,
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
@@ -46,13 +128,56 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
             Error::Io(_) => rocket::http::Status::InternalServerError,
             Error::Vcs(_) => rocket::http::Status::InternalServerError,
             Error::DataBackend(_) => rocket::http::Status::InternalServerError,
+            Error::Oidc(OidcError::UnknownProvider(_)) => rocket::http::Status::NotFound,
+            Error::Oidc(OidcError::Http(_) | OidcError::InvalidMetadata(_)) => {
+                rocket::http::Status::BadGateway
+            }
+            Error::Oidc(_) => rocket::http::Status::Unauthorized,
+            Error::RateLimited(_) => rocket::http::Status::TooManyRequests,
+            Error::Mailer(_) => rocket::http::Status::InternalServerError,
+            Error::Ldap(_) => rocket::http::Status::BadGateway,
 
             Error::RepoNotFound => rocket::http::Status::NotFound,
             Error::RepoAlreadyExists => rocket::http::Status::Conflict,
             Error::ResolveImpossible => rocket::http::Status::Conflict,
+            Error::TeamNotInRepoOrganization => rocket::http::Status::BadRequest,
+            Error::InvalidTokenExpiry => rocket::http::Status::BadRequest,
+            Error::SshKeyInUse => rocket::http::Status::Conflict,
 
+            Error::InvalidPasswordResetToken => rocket::http::Status::BadRequest,
             Error::Unauthorized => rocket::http::Status::Unauthorized,
             Error::Forbidden => rocket::http::Status::Forbidden,
+            Error::MissingTokenScope => rocket::http::Status::Forbidden,
+            Error::SessionTokenRequired => rocket::http::Status::Forbidden,
+            Error::RefreshTokenReused => rocket::http::Status::Unauthorized,
+            Error::OidcRegistrationDisabled => rocket::http::Status::Forbidden,
+            Error::OidcEmailConflict => rocket::http::Status::Conflict,
+            Error::OidcEmailMissing => rocket::http::Status::BadRequest,
+            Error::LdapEmailMissing => rocket::http::Status::BadRequest,
+            Error::LdapAccountConflict => rocket::http::Status::Conflict,
+            Error::LdapRegistrationDisabled => rocket::http::Status::Forbidden,
+            Error::TwoFactorRequired => rocket::http::Status::Unauthorized,
+            Error::InvalidTwoFactorCode => rocket::http::Status::Unauthorized,
+            Error::TwoFactorAlreadyEnabled => rocket::http::Status::Conflict,
+            Error::TwoFactorNotEnrolled => rocket::http::Status::BadRequest,
+            Error::TwoFactorRequiredForOrgOwners => rocket::http::Status::Forbidden,
+            Error::AdminRequired => rocket::http::Status::Forbidden,
+            Error::UserSuspended => rocket::http::Status::Forbidden,
+            Error::UserOwnsEntities => rocket::http::Status::Conflict,
+            Error::CannotTargetSelf => rocket::http::Status::BadRequest,
+            Error::InviteRequired => rocket::http::Status::Forbidden,
+            Error::InvalidInviteCode => rocket::http::Status::BadRequest,
+            Error::EmailDomainNotAllowed => rocket::http::Status::Forbidden,
+            Error::PendingApproval => rocket::http::Status::Forbidden,
+            Error::NotPendingApproval => rocket::http::Status::Conflict,
+            Error::CannotBlockSelf => rocket::http::Status::BadRequest,
+            Error::UserBlocked => rocket::http::Status::Forbidden,
+            Error::LastOrganizationOwner => rocket::http::Status::Conflict,
+            Error::TeamNotInOrganization => rocket::http::Status::BadRequest,
+            Error::TeamParentCycle => rocket::http::Status::Conflict,
+            Error::TeamDirectoryTaken => rocket::http::Status::Conflict,
+            Error::NotAnOrganizationMember => rocket::http::Status::BadRequest,
+            Error::InvalidRepoName => rocket::http::Status::BadRequest,
         };
 
         let response = rocket::response::status::Custom(status, self.to_string());
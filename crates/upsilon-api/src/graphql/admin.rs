@@ -0,0 +1,431 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::{graphql_object, FieldResult};
+use upsilon_models::namespace::NamespaceId;
+use upsilon_models::organization::{OrganizationId, OrganizationMember, OrganizationRole};
+use upsilon_models::repo::{Repo, RepoId, RepoNamespace};
+use upsilon_models::users::password::PasswordHashAlgorithmDescriptor;
+use upsilon_models::users::UserId;
+
+use super::invites::InviteRef;
+use super::passwords::PasswordHashReport;
+use super::{GraphQLContext, OrganizationRef, RepoRef, UserRef, Wrap};
+use crate::error::Error;
+
+// Only handed out by `QueryRoot::admin`, after checking that the
+// current user is an instance admin.
+pub struct AdminQuery;
+
+#[graphql_object(context = GraphQLContext)]
+impl AdminQuery {
+    async fn users(context: &GraphQLContext) -> FieldResult<Vec<UserRef>> {
+        Ok(context
+            .query(|qm| async move { qm.query_users().await })
+            .await?
+            .wrap(UserRef))
+    }
+
+    // accounts waiting to be approved or rejected
+    async fn pending_users(context: &GraphQLContext) -> FieldResult<Vec<UserRef>> {
+        Ok(context
+            .query(|qm| async move { qm.query_users().await })
+            .await?
+            .into_iter()
+            .filter(|user| user.pending_approval)
+            .wrap(UserRef))
+    }
+
+    // all invites, including the used and expired ones
+    async fn invites(context: &GraphQLContext) -> FieldResult<Vec<InviteRef>> {
+        Ok(context
+            .query(|qm| async move { qm.query_invites().await })
+            .await?
+            .wrap(InviteRef))
+    }
+
+    async fn repos(context: &GraphQLContext) -> FieldResult<Vec<RepoRef>> {
+        Ok(context
+            .query(|qm| async move { qm.query_repos().await })
+            .await?
+            .wrap(RepoRef))
+    }
+
+    async fn organizations(context: &GraphQLContext) -> FieldResult<Vec<OrganizationRef>> {
+        Ok(context
+            .query(|qm| async move { qm.query_organizations().await })
+            .await?
+            .wrap(OrganizationRef))
+    }
+
+    async fn stats(context: &GraphQLContext) -> FieldResult<InstanceStats> {
+        let users = context
+            .query(|qm| async move { qm.query_users().await })
+            .await?;
+        let repos = context
+            .query(|qm| async move { qm.query_repos().await })
+            .await?;
+        let organizations = context
+            .query(|qm| async move { qm.query_organizations().await })
+            .await?;
+
+        Ok(InstanceStats {
+            users: users.len(),
+            admins: users.iter().filter(|user| user.admin).count(),
+            suspended_users: users.iter().filter(|user| user.suspended).count(),
+            pending_users: users.iter().filter(|user| user.pending_approval).count(),
+            repos: repos.len(),
+            organizations: organizations.len(),
+        })
+    }
+
+    // How many users have passwords hashed with outdated parameters.
+    async fn password_hash_report(context: &GraphQLContext) -> FieldResult<PasswordHashReport> {
+        let users = context
+            .query(|qm| async move { qm.query_users().await })
+            .await?;
+
+        let password_hash_algo =
+            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
+
+        let outdated = users
+            .iter()
+            .filter(|user| password_hash_algo.is_outdated(&user.password))
+            .count();
+
+        Ok(PasswordHashReport {
+            users: users.len(),
+            outdated,
+        })
+    }
+}
+
+// Only handed out by `MutationRoot::admin`, after checking that the
+// current user is an instance admin.
+pub struct AdminMutation;
+
+impl AdminMutation {
+    /// Admins cannot demote, suspend or delete themselves, so that there
+    /// is always someone left to undo it.
+    fn require_other_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<()> {
+        let auth = context.session_auth()?;
+
+        if auth.claims.sub == user_id {
+            Err(Error::CannotTargetSelf)?;
+        }
+
+        Ok(())
+    }
+
+    async fn require_pending_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<()> {
+        if !context.query_user(user_id).await?.0.pending_approval {
+            Err(Error::NotPendingApproval)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves `repo` to `namespace`, along with its directory on disk.
+    async fn move_repo(
+        context: &GraphQLContext,
+        repo: Repo,
+        namespace: NamespaceId,
+    ) -> FieldResult<RepoRef> {
+        let old_dir = context.vcs_config.repo_dir(
+            RepoRef(repo.clone())
+                .ns_path(context.db.query_master())
+                .await?,
+        );
+
+        let name = repo.name.clone();
+        let taken = context
+            .query(|qm| async move {
+                qm.query_repo_by_name(&name, &RepoNamespace(namespace))
+                    .await
+            })
+            .await?;
+
+        if taken.is_some() {
+            Err(Error::RepoAlreadyExists)?;
+        }
+
+        let old_org = match repo.namespace.0 {
+            NamespaceId::Organization(org) | NamespaceId::Team(org, _) => Some(org),
+            _ => None,
+        };
+
+        let repo_id = repo.id;
+        let repo = RepoRef(Repo {
+            namespace: RepoNamespace(namespace),
+            ..repo
+        });
+
+        let new_dir = context
+            .vcs_config
+            .repo_dir(repo.ns_path(context.db.query_master()).await?);
+
+        if new_dir.exists() {
+            Err(Error::RepoAlreadyExists)?;
+        }
+
+        if let Some(parent) = new_dir.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // same as with renames, the directory goes first so that a failed
+        // move leaves the repo where it was
+        tokio::fs::rename(&old_dir, &new_dir).await?;
+
+        let moved_in_db = context
+            .query(|qm| async move {
+                qm.set_repo_namespace(repo_id, RepoNamespace(namespace))
+                    .await
+            })
+            .await;
+
+        if let Err(e) = moved_in_db {
+            tokio::fs::rename(&new_dir, &old_dir).await?;
+
+            Err(e)?;
+        }
+
+        let new_org = match namespace {
+            NamespaceId::Organization(org) | NamespaceId::Team(org, _) => Some(org),
+            _ => None,
+        };
+
+        if old_org.is_some() && old_org != new_org {
+            // teams can only be granted access to the repos of their organization
+            let team_perms = context
+                .query(|qm| async move { qm.query_repo_team_perms(repo_id).await })
+                .await?;
+
+            for (team_id, _) in team_perms {
+                context
+                    .query(|qm| async move { qm.set_repo_team_perms(repo_id, team_id, None).await })
+                    .await?;
+            }
+        }
+
+        Ok(repo)
+    }
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl AdminMutation {
+    async fn set_user_admin(
+        context: &GraphQLContext,
+        user_id: UserId,
+        admin: bool,
+    ) -> FieldResult<UserRef> {
+        Self::require_other_user(context, user_id)?;
+
+        context
+            .query(|qm| async move { qm.set_user_admin(user_id, admin).await })
+            .await?;
+
+        context.query_user(user_id).await
+    }
+
+    // Suspended users cannot log in, and all their sessions are ended.
+    async fn suspend_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<UserRef> {
+        Self::require_other_user(context, user_id)?;
+
+        context
+            .query(|qm| async move {
+                qm.set_user_suspended(user_id, true).await?;
+                qm.delete_user_sessions(user_id).await
+            })
+            .await?;
+
+        context.query_user(user_id).await
+    }
+
+    async fn unsuspend_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<UserRef> {
+        context
+            .query(|qm| async move { qm.set_user_suspended(user_id, false).await })
+            .await?;
+
+        context.query_user(user_id).await
+    }
+
+    async fn approve_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<UserRef> {
+        Self::require_pending_user(context, user_id).await?;
+
+        context
+            .query(|qm| async move { qm.set_user_pending_approval(user_id, false).await })
+            .await?;
+
+        context.query_user(user_id).await
+    }
+
+    // Removes the account.
+    async fn reject_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<bool> {
+        Self::require_pending_user(context, user_id).await?;
+
+        context
+            .query(|qm| async move { qm.delete_user(user_id).await })
+            .await
+    }
+
+    // The organizations the user is the only owner of, and the repos
+    // of the user, have to be transferred to someone else first.
+    async fn delete_user(context: &GraphQLContext, user_id: UserId) -> FieldResult<bool> {
+        Self::require_other_user(context, user_id)?;
+
+        let memberships = context
+            .query(|qm| async move { qm.query_user_organizations(user_id).await })
+            .await?;
+
+        let mut owns_orgs = false;
+        for membership in memberships.iter().filter(|it| it.role.is_owner()) {
+            let owners = context.org_owners(membership.organization_id).await?;
+            owns_orgs |= owners.iter().all(|&owner| owner == user_id);
+        }
+
+        let owns_repos = context
+            .query(|qm| async move { qm.query_repos().await })
+            .await?
+            .iter()
+            .any(|repo| repo.namespace.0 == NamespaceId::User(user_id));
+
+        if owns_orgs || owns_repos {
+            Err(Error::UserOwnsEntities)?;
+        }
+
+        context
+            .query(|qm| async move { qm.delete_user(user_id).await })
+            .await
+    }
+
+    async fn transfer_repo_to_user(
+        context: &GraphQLContext,
+        repo_id: RepoId,
+        user_id: UserId,
+    ) -> FieldResult<RepoRef> {
+        let repo = context
+            .query(|qm| async move { qm.query_repo(repo_id).await })
+            .await?;
+
+        // make sure the user exists
+        context.query_user(user_id).await?;
+
+        Self::move_repo(context, repo, NamespaceId::User(user_id)).await
+    }
+
+    async fn transfer_repo_to_organization(
+        context: &GraphQLContext,
+        repo_id: RepoId,
+        organization_id: OrganizationId,
+    ) -> FieldResult<RepoRef> {
+        let repo = context
+            .query(|qm| async move { qm.query_repo(repo_id).await })
+            .await?;
+
+        // make sure the organization exists
+        context.query_org(organization_id).await?;
+
+        Self::move_repo(context, repo, NamespaceId::Organization(organization_id)).await
+    }
+
+    // Makes the user the only owner of the organization; the previous
+    // owners become admins. The new owner has to have 2FA enabled if the
+    // instance requires it from organization owners.
+    async fn transfer_organization(
+        context: &GraphQLContext,
+        organization_id: OrganizationId,
+        user_id: UserId,
+    ) -> FieldResult<OrganizationRef> {
+        context.query_user(user_id).await?;
+        context.require_two_factor_for_org_owner(user_id).await?;
+
+        let members = context
+            .query(|qm| async move { qm.query_organization_members(organization_id).await })
+            .await?;
+
+        let new_owner = members
+            .iter()
+            .find(|member| member.user_id == user_id)
+            .cloned()
+            .unwrap_or_else(|| {
+                OrganizationMember::new(organization_id, user_id, OrganizationRole::Owner)
+            });
+
+        context
+            .query(|qm| async move {
+                qm.set_organization_member(OrganizationMember {
+                    role: OrganizationRole::Owner,
+                    ..new_owner
+                })
+                .await
+            })
+            .await?;
+
+        for member in members {
+            if member.user_id == user_id || !member.role.is_owner() {
+                continue;
+            }
+
+            context
+                .query(|qm| async move {
+                    qm.set_organization_member(OrganizationMember {
+                        role: OrganizationRole::Admin,
+                        ..member
+                    })
+                    .await
+                })
+                .await?;
+        }
+
+        context.query_org(organization_id).await
+    }
+}
+
+pub struct InstanceStats {
+    users: usize,
+    admins: usize,
+    suspended_users: usize,
+    pending_users: usize,
+    repos: usize,
+    organizations: usize,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl InstanceStats {
+    fn users(&self) -> i32 {
+        self.users as i32
+    }
+
+    fn admins(&self) -> i32 {
+        self.admins as i32
+    }
+
+    fn suspended_users(&self) -> i32 {
+        self.suspended_users as i32
+    }
+
+    fn pending_users(&self) -> i32 {
+        self.pending_users as i32
+    }
+
+    fn repos(&self) -> i32 {
+        self.repos as i32
+    }
+
+    fn organizations(&self) -> i32 {
+        self.organizations as i32
+    }
+}
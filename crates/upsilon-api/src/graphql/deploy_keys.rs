@@ -0,0 +1,46 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::graphql_object;
+use upsilon_models::repo::{DeployKey, DeployKeyId, RepoId};
+
+use super::GraphQLContext;
+
+pub struct DeployKeyRef(pub(crate) DeployKey);
+
+#[graphql_object(name = "DeployKey", context = GraphQLContext)]
+impl DeployKeyRef {
+    fn id(&self) -> DeployKeyId {
+        self.0.id
+    }
+
+    fn repo_id(&self) -> RepoId {
+        self.0.repo
+    }
+
+    fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    fn fingerprint(&self) -> String {
+        self.0.key.fingerprint()
+    }
+
+    // read-only keys can only clone and fetch
+    fn write(&self) -> bool {
+        self.0.write
+    }
+}
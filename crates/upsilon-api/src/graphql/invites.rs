@@ -0,0 +1,69 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::{graphql_object, FieldResult};
+use upsilon_models::users::invites::{Invite, InviteId};
+
+use super::{format_timestamp, GraphQLContext, OrganizationRef, UserRef};
+
+pub struct InviteRef(pub(crate) Invite);
+
+#[graphql_object(name = "Invite", context = GraphQLContext)]
+impl InviteRef {
+    fn id(&self) -> InviteId {
+        self.0.id
+    }
+
+    // the organization the invited user joins when they register
+    async fn organization(&self, context: &GraphQLContext) -> FieldResult<Option<OrganizationRef>> {
+        match self.0.organization {
+            Some(org_id) => Ok(Some(context.query_org(org_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn created_by(&self, context: &GraphQLContext) -> FieldResult<UserRef> {
+        context.query_user(self.0.created_by).await
+    }
+
+    fn expires_at(&self) -> String {
+        format_timestamp(self.0.expires_at)
+    }
+
+    async fn used_by(&self, context: &GraphQLContext) -> FieldResult<Option<UserRef>> {
+        match self.0.used_by {
+            Some(user_id) => Ok(Some(context.query_user(user_id).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct CreatedInvite {
+    pub(crate) invite: InviteRef,
+    pub(crate) code: String,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl CreatedInvite {
+    fn invite(&self) -> &InviteRef {
+        &self.invite
+    }
+
+    // only returned once; pass it on to the person being invited
+    fn code(&self) -> &str {
+        &self.code
+    }
+}
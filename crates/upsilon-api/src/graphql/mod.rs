@@ -17,13 +17,25 @@
 // FIXME: graphql_object macro uses str.to_string() instead of str.to_owned()
 #![allow(clippy::str_to_string)]
 
+mod admin;
+mod deploy_keys;
 mod git;
-
+mod invites;
+mod oidc;
+mod passwords;
+mod permissions;
+mod repo_settings;
+mod sessions;
+mod tokens;
+mod two_factor;
+
+use std::collections::BTreeSet;
 use std::future::Future;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::pin::Pin;
 
-use chrono::Duration;
+use chrono::{Duration, NaiveDateTime, Utc};
 use futures::{Stream, StreamExt, TryStreamExt};
 use juniper::{graphql_object, graphql_subscription, FieldError, FieldResult};
 use path_slash::PathBufExt;
@@ -31,24 +43,40 @@ use rocket::outcome::try_outcome;
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Ignite, Request, Rocket, Sentinel, State};
 use upsilon_core::config::{Cfg, GqlDebugConfig, UsersConfig};
+use upsilon_core::rate_limit::{RateLimitKey, RateLimiter};
 use upsilon_data::{CommonDataClientError, DataQueryMaster};
 use upsilon_models::assets::ImageAssetId;
 use upsilon_models::email::Email;
 use upsilon_models::namespace::NamespaceId;
 use upsilon_models::organization::{
-    Organization, OrganizationDisplayName, OrganizationId, OrganizationMember, OrganizationName, Team, TeamDisplayName, TeamId, TeamName
+    Organization, OrganizationDisplayName, OrganizationId, OrganizationMember, OrganizationName, OrganizationRole, Team, TeamDisplayName, TeamId, TeamName
+};
+use upsilon_models::repo::{
+    DeployKey, DeployKeyId, Repo, RepoBranchName, RepoDescription, RepoDisplayName, RepoId, RepoName, RepoNamespace, RepoPermissions, RepoRole, RepoTopic, RepoWebsite
 };
-use upsilon_models::repo::{Repo, RepoId, RepoName, RepoNamespace, RepoPermissions};
 use upsilon_models::users::emails::UserEmails;
+use upsilon_models::users::identities::ExternalIdentity;
+use upsilon_models::users::invites::{Invite, InviteId};
 use upsilon_models::users::password::{
     HashedPassword, PasswordHashAlgorithmDescriptor, PlainPassword
 };
+use upsilon_models::users::password_resets::PasswordReset;
+use upsilon_models::users::sessions::{Session, SessionId};
+use upsilon_models::users::tokens::{PersonalAccessToken, PersonalAccessTokenId, TokenScope};
+use upsilon_models::users::two_factor::TwoFactor;
 use upsilon_models::users::{User, UserDisplayName, UserId, UserSshKey, Username};
 use upsilon_vcs::{RepoConfig, RepoVisibility, UpsilonVcsConfig};
+use upsilon_vcs_permissions::namespace_dir;
 
-use crate::auth::{AuthContext, AuthToken, AuthTokenClaims};
+use crate::auth::{
+    generate_secret, hash_secret, AuthContext, AuthToken, INVITE_CODE_PREFIX, PASSWORD_RESET_TOKEN_PREFIX, PERSONAL_ACCESS_TOKEN_PREFIX, REFRESH_TOKEN_PREFIX
+};
 use crate::entity_lookup_path::{EntityLookupPath, ResolvedEntity};
 use crate::error::Error;
+use crate::ldap::{LdapContext, LdapIdentity, LDAP_PROVIDER};
+use crate::mailer::{Mail, MailerHandle};
+use crate::oidc::{OidcContext, OidcIdentity};
+use crate::two_factor::TwoFactorContext;
 
 pub type Schema = juniper::RootNode<'static, QueryRoot, MutationRoot, SubscriptionRoot>;
 
@@ -72,6 +100,13 @@ pub struct GraphQLContext {
     http_port: u16,
     auth_context: AuthContext,
     auth: Option<AuthToken>,
+    oidc: OidcContext,
+    ldap: LdapContext,
+    two_factor: TwoFactorContext,
+    user_agent: Option<String>,
+    client_ip: Option<IpAddr>,
+    rate_limiter: RateLimiter,
+    mailer: MailerHandle,
 }
 
 #[async_trait]
@@ -94,6 +129,16 @@ impl<'r> FromRequest<'r> for GraphQLContext {
             .map(|it| (it.domain().to_string(), it.port()));
         let auth_context = try_outcome!(request.guard::<&State<AuthContext>>().await);
         let auth = request.guard::<Option<AuthToken>>().await.unwrap();
+        let oidc = try_outcome!(request.guard::<&State<OidcContext>>().await);
+        let ldap = try_outcome!(request.guard::<&State<LdapContext>>().await);
+        let two_factor = try_outcome!(request.guard::<&State<TwoFactorContext>>().await);
+        let user_agent = request
+            .headers()
+            .get_one("User-Agent")
+            .map(ToOwned::to_owned);
+        let client_ip = request.client_ip();
+        let rate_limiter = try_outcome!(request.guard::<&State<RateLimiter>>().await);
+        let mailer = try_outcome!(request.guard::<&State<MailerHandle>>().await);
 
         Outcome::Success(Self {
             db: db.inner().clone(),
@@ -105,6 +150,13 @@ impl<'r> FromRequest<'r> for GraphQLContext {
             http_port,
             auth_context: auth_context.inner().clone(),
             auth,
+            oidc: oidc.inner().clone(),
+            ldap: ldap.inner().clone(),
+            two_factor: two_factor.inner().clone(),
+            user_agent,
+            client_ip,
+            rate_limiter: rate_limiter.inner().clone(),
+            mailer: mailer.inner().clone(),
         })
     }
 }
@@ -117,6 +169,11 @@ impl Sentinel for GraphQLContext {
             || <&State<Cfg<UsersConfig>>>::abort(rocket)
             || <&State<Cfg<UshArgs>>>::abort(rocket)
             || <&State<AuthContext>>::abort(rocket)
+            || <&State<OidcContext>>::abort(rocket)
+            || <&State<LdapContext>>::abort(rocket)
+            || <&State<TwoFactorContext>>::abort(rocket)
+            || <&State<RateLimiter>>::abort(rocket)
+            || <&State<MailerHandle>>::abort(rocket)
     }
 }
 
@@ -153,17 +210,25 @@ impl GraphQLContext {
             .map(OrganizationRef)
     }
 
-    async fn init_repo(&self, repo_config: RepoConfig, path: PathBuf) -> FieldResult<()> {
+    async fn init_repo(&self, repo: &Repo, path: PathBuf) -> FieldResult<()> {
         let vcs_config_clone = self.vcs_config.clone();
+        let repo_config = RepoConfig::new(RepoVisibility::Public, repo.id.to_string());
+        let path_clone = path.clone();
 
         tokio::task::spawn_blocking(move || {
-            let _ = upsilon_vcs::init_repo_absolute(&vcs_config_clone, repo_config, &path)?;
+            let _ = upsilon_vcs::init_repo_absolute(&vcs_config_clone, repo_config, &path_clone)?;
             // drop the repository on the same thread
 
             Ok::<_, FieldError>(())
         })
         .await??;
 
+        upsilon_vcs::write_hook_repo_config_absolute(
+            path,
+            &upsilon_vcs_permissions::lower_repo_config(repo),
+        )
+        .await?;
+
         Ok(())
     }
 
@@ -186,225 +251,1107 @@ impl GraphQLContext {
         Ok(())
     }
 
-    fn require_debug(&self) -> FieldResult<()> {
-        if !self.debug_config.debug_enabled {
-            Err(DebugModeNotEnabled)?;
+    /// Fails if `user_id` was blocked by the user owning `repo`.
+    async fn require_not_blocked(&self, repo: &Repo, user_id: UserId) -> FieldResult<()> {
+        let NamespaceId::User(owner) = repo.namespace.0 else {
+            return Ok(());
+        };
+
+        let blocked = self
+            .query(|qm| async move { qm.query_user_blocked(owner, user_id).await })
+            .await?;
+
+        if blocked {
+            Err(Error::UserBlocked)?;
         }
 
         Ok(())
     }
-}
-
-#[derive(Debug, thiserror::Error)]
-#[error("Debug mode is not enabled")]
-struct DebugModeNotEnabled;
 
-impl juniper::Context for GraphQLContext {}
+    /// The current user, if the token they used allows `scope`.
+    fn auth_with_scope(&self, scope: TokenScope) -> FieldResult<&AuthToken> {
+        let auth = self.auth.as_ref().ok_or(Error::Unauthorized)?;
 
-pub struct QueryRoot;
+        if !auth.has_scope(scope) {
+            Err(Error::MissingTokenScope)?;
+        }
 
-#[graphql_object(Context = GraphQLContext)]
-impl QueryRoot {
-    fn api_version() -> &'static str {
-        "v1"
+        Ok(auth)
     }
 
-    fn ush_cli_args(context: &GraphQLContext) -> Vec<String> {
-        if !context.debug_config.debug_enabled {
-            // we are in production
-            // do not return an error, but also do not return the args
-            return vec!["--upsilon-is-not-debug-rerun-shell-with-'--no-reconfigure'".to_owned()];
+    /// The current user, if they used a session token rather than
+    /// a personal access token.
+    fn session_auth(&self) -> FieldResult<&AuthToken> {
+        let auth = self.auth.as_ref().ok_or(Error::Unauthorized)?;
+
+        if auth.personal_access_token.is_some() {
+            Err(Error::SessionTokenRequired)?;
         }
 
-        let mut v = (*context.ush_args).0.clone();
-        let mut port = context.http_port;
+        Ok(auth)
+    }
 
-        if let Some((hn, http_port)) = &context.host {
-            v.extend(["--hostname".to_owned(), hn.clone()]);
+    /// The second factor of `user`, if they finished setting it up.
+    async fn confirmed_two_factor(&self, user: UserId) -> FieldResult<Option<TwoFactor>> {
+        let two_factor = self
+            .query(|qm| async move { qm.query_user_two_factor(user).await })
+            .await?;
 
-            if let Some(http_port) = *http_port {
-                port = http_port;
+        Ok(two_factor.filter(|two_factor| two_factor.confirmed))
+    }
+
+    /// Checks a TOTP or recovery code, marking it as used if it is right.
+    async fn check_second_factor(&self, mut two_factor: TwoFactor, code: &str) -> FieldResult<()> {
+        let now = Utc::now().timestamp() as u64;
+
+        let totp_step = self
+            .two_factor
+            .decrypt(&two_factor.encrypted_secret)
+            .and_then(|secret| {
+                crate::two_factor::verify_totp(&secret, code, now, two_factor.last_used_step)
+            });
+
+        match totp_step {
+            Some(step) => two_factor.last_used_step = Some(step),
+            None => {
+                // recovery codes only work once 2FA is set up
+                let code_hash = crate::two_factor::hash_recovery_code(code);
+
+                if !two_factor.confirmed || !two_factor.use_recovery_code(&code_hash) {
+                    Err(Error::InvalidTwoFactorCode)?;
+                }
             }
         }
 
-        v.extend(["--http-port".to_owned(), port.to_string()]);
+        self.query(|qm| async move { qm.set_user_two_factor(two_factor).await })
+            .await?;
 
-        v
+        Ok(())
     }
 
-    async fn user(context: &GraphQLContext, user_id: UserId) -> FieldResult<UserRef> {
-        context.query_user(user_id).await
+    /// The role of `user` in `org`, if they are a member.
+    async fn org_role(
+        &self,
+        org: OrganizationId,
+        user: UserId,
+    ) -> FieldResult<Option<OrganizationRole>> {
+        let member = self
+            .query(|qm| async move { qm.query_organization_member(org, user).await })
+            .await?;
+
+        Ok(member.map(|member| member.role))
     }
 
-    async fn viewer(context: &GraphQLContext) -> FieldResult<Option<UserRef>> {
-        match &context.auth {
-            Some(auth) => Ok(Some(context.query_user(auth.claims.sub).await?)),
-            None => Ok(None),
+    /// Checks that the current user is an owner of `org`, and has 2FA
+    /// enabled if the instance requires it from organization owners.
+    async fn require_org_owner(&self, org: &Organization) -> FieldResult<()> {
+        let auth = self.auth_with_scope(TokenScope::AdminOrg)?;
+
+        let role = self.org_role(org.id, auth.claims.sub).await?;
+
+        if !role.map_or(false, OrganizationRole::is_owner) {
+            Err(Error::Forbidden)?;
         }
-    }
 
-    async fn user_by_username(
-        context: &GraphQLContext,
-        username: Username,
-    ) -> FieldResult<Option<UserRef>> {
-        context
-            .query(|qm| async move { qm.query_user_by_username(&username).await })
-            .await
-            .map(|opt| opt.map(UserRef))
+        self.require_two_factor_for_org_owner(auth.claims.sub).await
     }
 
-    async fn organization(
-        context: &GraphQLContext,
-        org_id: OrganizationId,
-    ) -> FieldResult<OrganizationRef> {
-        context.query_org(org_id).await
-    }
+    /// Checks that the current user is an owner or an admin of `org`,
+    /// returning their role.
+    async fn require_org_admin(&self, org: &Organization) -> FieldResult<OrganizationRole> {
+        let auth = self.auth_with_scope(TokenScope::AdminOrg)?;
 
-    async fn organization_by_name(
-        context: &GraphQLContext,
-        name: OrganizationName,
-    ) -> FieldResult<Option<OrganizationRef>> {
-        context
-            .query(|qm| async move { qm.query_organization_by_name(&name).await })
-            .await
-            .map(|opt| opt.map(OrganizationRef))
-    }
+        let Some(role) = self
+            .org_role(org.id, auth.claims.sub)
+            .await?
+            .filter(|role| role.can_manage())
+        else {
+            return Err(Error::Forbidden.into());
+        };
 
-    async fn repo(context: &GraphQLContext, repo_id: RepoId) -> FieldResult<RepoRef> {
-        context
-            .query(|qm| async move { qm.query_repo(repo_id).await })
-            .await
-            .map(RepoRef)
+        if role.is_owner() {
+            self.require_two_factor_for_org_owner(auth.claims.sub)
+                .await?;
+        }
+
+        Ok(role)
     }
 
-    async fn lookup_entity(
-        context: &GraphQLContext,
-        path: String,
-    ) -> FieldResult<Option<EntityValue>> {
-        let path = EntityLookupPath::from_iter(path.split('/').collect::<Vec<_>>().into_iter())?;
+    async fn org_owners(&self, org: OrganizationId) -> FieldResult<Vec<UserId>> {
+        Ok(self
+            .query(|qm| async move { qm.query_organization_members(org).await })
+            .await?
+            .into_iter()
+            .filter(|member| member.role.is_owner())
+            .map(|member| member.user_id)
+            .collect())
+    }
 
-        let resolved = match context
-            .query(|qm| async move { crate::entity_lookup_path::resolve(&qm, &path).await })
+    /// Checks that `org` has owners other than `user`, so that it
+    /// isn't left without any.
+    async fn require_other_org_owner(&self, org: OrganizationId, user: UserId) -> FieldResult<()> {
+        if !self
+            .org_owners(org)
             .await?
+            .iter()
+            .any(|&owner| owner != user)
         {
-            Some(entity) => entity,
-            None => return Ok(None),
-        };
+            Err(Error::LastOrganizationOwner)?;
+        }
 
-        let entity_ref = match resolved {
-            ResolvedEntity::GlobalNamespace => panic!("global namespace is not an entity"),
-            ResolvedEntity::User(user) => EntityValue::from(UserRef(user)),
-            ResolvedEntity::Organization(org) => EntityValue::from(OrganizationRef(org)),
-            ResolvedEntity::Team(org, team) => EntityValue::from(TeamRef(team)),
-            ResolvedEntity::Repo { repo, .. } => EntityValue::from(RepoRef(repo)),
-        };
+        Ok(())
+    }
 
-        Ok(Some(entity_ref))
+    async fn require_two_factor_for_org_owner(&self, user: UserId) -> FieldResult<()> {
+        if self.two_factor.require_for_org_owners()
+            && self.confirmed_two_factor(user).await?.is_none()
+        {
+            Err(Error::TwoFactorRequiredForOrgOwners)?;
+        }
+
+        Ok(())
     }
 
-    async fn lookup_repo(context: &GraphQLContext, path: String) -> FieldResult<Option<RepoRef>> {
-        let path = EntityLookupPath::from_iter(path.split('/').collect::<Vec<_>>().into_iter())?;
+    /// Finds the user an OpenID Connect login is for, linking the login to
+    /// the account with the same verified email, or creating a new account
+    /// if there isn't one.
+    async fn oidc_user(&self, identity: OidcIdentity) -> FieldResult<UserId> {
+        let provider = identity.provider.clone();
+        let subject = identity.subject.clone();
+        let linked = self
+            .query(|qm| async move { qm.query_external_identity(provider, subject).await })
+            .await?;
 
-        let repo = match context
-            .query(|qm| async move { crate::entity_lookup_path::resolve(&qm, &path).await })
+        if let Some(linked) = linked {
+            return Ok(linked.user);
+        }
+
+        let Some(email) = identity.email.clone().map(Email::from) else {
+            return Err(Error::OidcEmailMissing.into());
+        };
+
+        // only an email can link accounts, never a username that happens to look like one
+        let email_clone = email.clone();
+        let existing = self
+            .query(|qm| async move { qm.query_user_by_username_email(email_clone.as_str()).await })
             .await?
-        {
-            Some(ResolvedEntity::Repo { repo, .. }) => repo,
-            _ => return Ok(None),
+            .filter(|user| user.emails.email_index(&email).is_some());
+
+        let user_id = match existing {
+            Some(user) => {
+                let link_by_email = self
+                    .oidc
+                    .provider_config(&identity.provider)
+                    .map_or(false, |config| config.link_by_email);
+
+                if !link_by_email || !identity.email_verified {
+                    Err(Error::OidcEmailConflict)?;
+                }
+
+                user.id
+            }
+            None => {
+                if !self.users_config.register.enabled {
+                    Err(Error::OidcRegistrationDisabled)?;
+                }
+
+                let pending_approval = self.check_registration_policy(&email, None)?;
+
+                let id = UserId::new();
+                let username = self.unused_username(&identity).await?;
+
+                // the user logs in through the provider, so nobody should know the password
+                let (password, _) = generate_secret("");
+                let password = PlainPassword::from(password);
+                let password_hash_algo =
+                    PasswordHashAlgorithmDescriptor::from(self.users_config.auth.password);
+                let password_hash = tokio::task::spawn_blocking(move || {
+                    password_hash_algo.hash_password(
+                        &password,
+                        &id.chrono_ts().and_utc().timestamp().to_le_bytes(),
+                    )
+                })
+                .await?;
+
+                let user = User {
+                    id,
+                    admin: false,
+                    username,
+                    password: password_hash,
+                    display_name: identity.name.clone().map(UserDisplayName::from),
+                    emails: UserEmails::new(email),
+                    avatar: None,
+                    suspended: false,
+                    pending_approval,
+                };
+
+                self.query(|qm| async move { qm.create_user(user).await })
+                    .await?;
+
+                id
+            }
         };
 
-        Ok(Some(RepoRef(repo)))
+        let external_identity = ExternalIdentity {
+            provider: identity.provider,
+            subject: identity.subject,
+            user: user_id,
+        };
+
+        self.query(|qm| async move { qm.create_external_identity(external_identity).await })
+            .await?;
+
+        Ok(user_id)
     }
-}
 
-mod ent {
-    #![allow(clippy::enum_variant_names)]
+    /// Picks a username for a new user from what the provider knows about them.
+    async fn unused_username(&self, identity: &OidcIdentity) -> FieldResult<Username> {
+        let hint = identity
+            .preferred_username
+            .as_deref()
+            .or_else(|| {
+                identity
+                    .email
+                    .as_deref()
+                    .and_then(|email| email.split('@').next())
+            })
+            .unwrap_or_default();
 
-    use juniper::graphql_interface;
+        let mut base = hint
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .collect::<String>();
 
-    use super::{GraphQLContext, OrganizationRef, RepoRef, TeamRef, UserRef};
+        if base.is_empty() {
+            base = "user".to_owned();
+        }
 
-    #[graphql_interface(for = [UserRef, OrganizationRef, TeamRef, RepoRef])]
-    pub(super) trait Entity {
-        fn _entity_id(&self, ctx: &GraphQLContext) -> String;
+        for i in 1.. {
+            let username = match i {
+                1 => Username::from(base.clone()),
+                _ => Username::from(format!("{base}{i}")),
+            };
 
-        fn entity_user(&self, ctx: &GraphQLContext) -> Option<&UserRef> {
-            None
+            let username_clone = username.clone();
+            let taken = self
+                .query(|qm| async move { qm.query_user_by_username(&username_clone).await })
+                .await?
+                .is_some();
+
+            if !taken {
+                return Ok(username);
+            }
         }
 
-        fn entity_organization(&self, ctx: &GraphQLContext) -> Option<&OrganizationRef> {
-            None
+        unreachable!()
+    }
+
+    /// Checks the password of the user, returning them if it is right.
+    ///
+    /// Failed attempts are counted against both the client IP and the
+    /// account, and are refused while either is locked out.
+    async fn check_credentials(
+        &self,
+        username_or_email: String,
+        password: PlainPassword,
+    ) -> FieldResult<User> {
+        let mut rate_limit_keys = self
+            .client_ip
+            .map(RateLimitKey::Ip)
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        self.rate_limiter
+            .check(&rate_limit_keys)
+            .map_err(Error::from)?;
+
+        if self.ldap.handles(&username_or_email) {
+            return self
+                .check_ldap_credentials(username_or_email, password, rate_limit_keys)
+                .await;
         }
 
-        fn entity_team(&self, ctx: &GraphQLContext) -> Option<&TeamRef> {
-            None
+        let user = self
+            .query(|qm| async move { qm.query_user_by_username_email(&username_or_email).await })
+            .await?;
+
+        let Some(user) = user else {
+            self.rate_limiter.record_failure(&rate_limit_keys);
+            return Err(Error::Unauthorized.into());
+        };
+
+        let account_key = RateLimitKey::Account(user.username.to_string());
+        rate_limit_keys.push(account_key.clone());
+
+        self.rate_limiter
+            .check(&rate_limit_keys)
+            .map_err(Error::from)?;
+
+        let password_check = {
+            let hash = user.password.clone();
+            let password = password.clone();
+
+            tokio::task::spawn_blocking(move || hash.verify(&password)).await?
+        };
+
+        if !password_check {
+            self.rate_limiter.record_failure(&rate_limit_keys);
+            Err(Error::Unauthorized)?;
         }
 
-        fn entity_repo(&self, ctx: &GraphQLContext) -> Option<&RepoRef> {
-            None
+        self.rate_limiter.record_success(&account_key);
+
+        let password_hash_algo =
+            PasswordHashAlgorithmDescriptor::from(self.users_config.auth.password);
+
+        if password_hash_algo.is_outdated(&user.password) {
+            // only now that we know the password can it be hashed again
+            let user_id = user.id;
+            let password_hash = self.hash_password(password).await?;
+
+            self.query(|qm| async move { qm.set_user_password(user_id, password_hash).await })
+                .await?;
         }
+
+        Ok(user)
     }
-}
 
-use ent::{Entity, EntityValue};
+    /// Checks the password against the LDAP directory, creating the account
+    /// the first time the user logs in, and syncing their groups every time.
+    async fn check_ldap_credentials(
+        &self,
+        username: String,
+        password: PlainPassword,
+        mut rate_limit_keys: Vec<RateLimitKey>,
+    ) -> FieldResult<User> {
+        let account_key = RateLimitKey::Account(username.clone());
+        rate_limit_keys.push(account_key.clone());
+
+        self.rate_limiter
+            .check(&rate_limit_keys)
+            .map_err(Error::from)?;
+
+        let identity = self
+            .ldap
+            .authenticate(&username, password.as_str())
+            .await
+            .map_err(Error::from)?;
 
-macro_rules! defer_entity_impl {
-    () => {
-        fn _entity_id(&self, ctx: &GraphQLContext) -> String {
-            <Self as Entity>::_entity_id(self, ctx)
-        }
+        let Some(identity) = identity else {
+            self.rate_limiter.record_failure(&rate_limit_keys);
+            return Err(Error::Unauthorized.into());
+        };
 
-        fn entity_user(&self, ctx: &GraphQLContext) -> Option<&UserRef> {
-            <Self as Entity>::entity_user(self, ctx)
-        }
+        self.rate_limiter.record_success(&account_key);
 
-        fn entity_repo(&self, ctx: &GraphQLContext) -> Option<&RepoRef> {
-            <Self as Entity>::entity_repo(self, ctx)
-        }
+        let user = self.ldap_user(&identity).await?;
+        self.sync_ldap_groups(user.id, &identity).await?;
 
-        fn entity_organization(&self, ctx: &GraphQLContext) -> Option<&OrganizationRef> {
-            <Self as Entity>::entity_organization(self, ctx)
-        }
+        Ok(user)
+    }
 
-        fn entity_team(&self, ctx: &GraphQLContext) -> Option<&TeamRef> {
-            <Self as Entity>::entity_team(self, ctx)
+    /// Finds the user linked to the directory entry, linking it to the
+    /// account with the same username or creating a new account if needed.
+    ///
+    /// Only accounts that already came from the directory are linked, as
+    /// when the entry was moved to another DN; any other account with the
+    /// same username is a conflict.
+    async fn ldap_user(&self, identity: &LdapIdentity) -> FieldResult<User> {
+        let dn = identity.dn.clone();
+        let linked = self
+            .query(|qm| async move {
+                qm.query_external_identity(LDAP_PROVIDER.to_owned(), dn)
+                    .await
+            })
+            .await?;
+
+        if let Some(linked) = linked {
+            return self
+                .query(|qm| async move { qm.query_user(linked.user).await })
+                .await;
         }
-    };
-}
 
-// #[graphql_object(Context = GraphQLContext)]
-// impl EntityRef {
-//     fn user_id(&self) -> Option<UserId> {
-//         match self {
-//             EntityRef::User(user) => Some(user.id),
-//             _ => None,
-//         }
-//     }
-//
-//     async fn user(&self) -> Option<UserRef> {
-//         match self {
-//             EntityRef::User(user) => Some(UserRef(user.clone())),
-//             _ => None,
-//         }
-//     }
-//
-//     fn organization_id(&self) -> Option<OrganizationId> {
-//         match self {
-//             EntityRef::Organization(org) => Some(org.id),
-//             EntityRef::Team(org, _) => Some(org.id),
-//             _ => None,
-//         }
-//     }
-//
-//     async fn organization(&self) -> Option<OrganizationRef> {
-//         match self {
-//             EntityRef::Organization(org) => Some(OrganizationRef(org.clone())),
-//             EntityRef::Team(org, _) => Some(OrganizationRef(org.clone())),
-//             _ => None,
-//         }
-//     }
-//
-//     fn team_id(&self) -> Option<TeamId> {
+        let username = Username::from(identity.username.clone());
+        let username_clone = username.clone();
+        let existing = self
+            .query(|qm| async move { qm.query_user_by_username(&username_clone).await })
+            .await?;
+
+        let user = match existing {
+            Some(user) => {
+                let user_id = user.id;
+                let is_ldap_backed = self
+                    .query(|qm| async move { qm.query_user_external_identities(user_id).await })
+                    .await?
+                    .iter()
+                    .any(|identity| identity.provider == LDAP_PROVIDER);
+
+                if !is_ldap_backed {
+                    Err(Error::LdapAccountConflict)?;
+                }
+
+                user
+            }
+            None => {
+                if !self.users_config.register.enabled {
+                    Err(Error::LdapRegistrationDisabled)?;
+                }
+
+                let Some(email) = identity.email.clone().map(Email::from) else {
+                    return Err(Error::LdapEmailMissing.into());
+                };
+
+                let pending_approval = self.check_registration_policy(&email, None)?;
+
+                // the password is checked by the directory, so nobody should know this one
+                let (password, _) = generate_secret("");
+                let password_hash = self.hash_password(PlainPassword::from(password)).await?;
+
+                let user = User {
+                    id: UserId::new(),
+                    admin: false,
+                    username,
+                    password: password_hash,
+                    display_name: identity.display_name.clone().map(UserDisplayName::from),
+                    emails: UserEmails::new(email),
+                    avatar: None,
+                    suspended: false,
+                    pending_approval,
+                };
+
+                let user_clone = user.clone();
+                self.query(|qm| async move { qm.create_user(user_clone).await })
+                    .await?;
+
+                user
+            }
+        };
+
+        let external_identity = ExternalIdentity {
+            provider: LDAP_PROVIDER.to_owned(),
+            subject: identity.dn.clone(),
+            user: user.id,
+        };
+
+        self.query(|qm| async move { qm.create_external_identity(external_identity).await })
+            .await?;
+
+        Ok(user)
+    }
+
+    /// Adds `user` to the organizations and teams mapped to the groups they
+    /// are in, and removes them from the ones mapped to groups they are not in.
+    async fn sync_ldap_groups(&self, user: UserId, identity: &LdapIdentity) -> FieldResult<()> {
+        let Some(config) = self.ldap.config() else {
+            return Ok(());
+        };
+
+        let org_names = config
+            .group_sync
+            .iter()
+            .map(|mapping| mapping.organization.as_str())
+            .collect::<BTreeSet<_>>();
+
+        for org_name in org_names {
+            let org = self
+                .query(|qm| async move { qm.query_organization_by_name(org_name).await })
+                .await?;
+
+            let Some(org) = org else {
+                warn!("LDAP group sync: organization {org_name} does not exist");
+                continue;
+            };
+
+            let mut is_member = false;
+            let mut managed_teams = BTreeSet::new();
+            let mut teams_to_join = BTreeSet::new();
+
+            for mapping in config
+                .group_sync
+                .iter()
+                .filter(|mapping| mapping.organization == org_name)
+            {
+                let in_group = identity.is_in_group(&mapping.group);
+                is_member |= in_group;
+
+                let Some(team_name) = mapping.team.as_deref() else {
+                    continue;
+                };
+
+                let team = self
+                    .query(|qm| async move { qm.query_team_by_name(org.id, team_name).await })
+                    .await?;
+
+                let Some(team) = team else {
+                    warn!("LDAP group sync: team {org_name}/{team_name} does not exist");
+                    continue;
+                };
+
+                managed_teams.insert(team.id);
+                if in_group {
+                    teams_to_join.insert(team.id);
+                }
+            }
+
+            let member = self
+                .query(|qm| async move { qm.query_organization_member(org.id, user).await })
+                .await?;
+
+            if !is_member {
+                // owners and admins are managed in upsilon, not in the directory
+                if member
+                    .as_ref()
+                    .map_or(false, |member| !member.role.can_manage())
+                {
+                    self.query(
+                        |qm| async move { qm.remove_organization_member(org.id, user).await },
+                    )
+                    .await?;
+                }
+
+                continue;
+            }
+
+            let mut member = member
+                .unwrap_or_else(|| OrganizationMember::new(org.id, user, OrganizationRole::Member));
+            member.teams.retain(|team| !managed_teams.contains(team));
+            member.teams.extend(teams_to_join);
+
+            self.query(|qm| async move { qm.set_organization_member(member).await })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Hashes `password` with the configured algorithm and a random salt.
+    async fn hash_password(&self, password: PlainPassword) -> FieldResult<HashedPassword> {
+        let password_hash_algo =
+            PasswordHashAlgorithmDescriptor::from(self.users_config.auth.password);
+        let mut salt = [0u8; 16];
+        openssl::rand::rand_bytes(&mut salt).expect("Failed to generate random bytes");
+
+        let password_hash =
+            tokio::task::spawn_blocking(move || password_hash_algo.hash_password(&password, &salt))
+                .await?;
+
+        Ok(password_hash)
+    }
+
+    /// Hashes `password` with the configured algorithm and makes it the
+    /// password of `user`, ending all their sessions and pending resets.
+    async fn set_password(&self, user: UserId, password: PlainPassword) -> FieldResult<()> {
+        let password_hash = self.hash_password(password).await?;
+
+        self.query(|qm| async move { qm.set_user_password(user, password_hash).await })
+            .await?;
+        self.query(|qm| async move { qm.delete_user_sessions(user).await })
+            .await?;
+        self.query(|qm| async move { qm.delete_user_password_resets(user).await })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn start_session(&self, user: UserId) -> FieldResult<sessions::SessionTokens> {
+        let user_model = self.query_user(user).await?.0;
+
+        if user_model.suspended {
+            Err(Error::UserSuspended)?;
+        }
+
+        if user_model.pending_approval {
+            Err(Error::PendingApproval)?;
+        }
+
+        let now = Utc::now().naive_utc();
+        let (refresh_token, refresh_token_hash) = generate_secret(REFRESH_TOKEN_PREFIX);
+
+        let session = Session {
+            id: SessionId::new(),
+            user,
+            refresh_token_hash,
+            used_refresh_token_hashes: Vec::new(),
+            user_agent: self.user_agent.clone(),
+            ip: self.client_ip.map(|ip| ip.to_string()),
+            created_at: now,
+            last_seen_at: now,
+            expires_at: now + self.auth_context.refresh_token_lifetime(),
+        };
+
+        let session_clone = session.clone();
+        self.query(|qm| async move { qm.create_session(session_clone).await })
+            .await?;
+
+        let access_token = self.auth_context.issue_session_token(user, session.id);
+
+        Ok(sessions::SessionTokens {
+            session: sessions::SessionRef(session),
+            access_token: access_token.to_string(),
+            refresh_token,
+        })
+    }
+
+    /// Whether the current user, or anyone if not logged in, can read `repo`.
+    async fn can_read_repo(&self, repo: &Repo) -> FieldResult<bool> {
+        let user = self.auth.as_ref().map(|auth| auth.claims.sub);
+
+        let perms = self
+            .query(|qm| async move {
+                upsilon_vcs_permissions::effective_permissions(repo, &qm, user).await
+            })
+            .await?;
+
+        let perms = match &self.auth {
+            Some(auth) => auth.restrict_repo_permissions(repo, perms),
+            None => perms,
+        };
+
+        Ok(perms.can_read())
+    }
+
+    /// Checks that the current user has admin permissions on `repo`.
+    async fn require_repo_admin(&self, repo: &Repo) -> FieldResult<()> {
+        let auth = self.auth.as_ref().ok_or(Error::Unauthorized)?;
+
+        let perms = self
+            .query(|qm| async move {
+                upsilon_vcs_permissions::effective_permissions(repo, &qm, Some(auth.claims.sub))
+                    .await
+            })
+            .await?;
+
+        if !auth.restrict_repo_permissions(repo, perms).has_admin() {
+            Err(Error::Forbidden)?;
+        }
+
+        Ok(())
+    }
+
+    /// Teams can only be granted access to the repos of their organization.
+    async fn query_team_for_repo(&self, repo: &Repo, team_id: TeamId) -> FieldResult<Team> {
+        let team = self
+            .query(|qm| async move { qm.query_team(team_id).await })
+            .await?;
+
+        match repo.namespace.0 {
+            NamespaceId::Organization(org_id) | NamespaceId::Team(org_id, _)
+                if org_id == team.organization_id => {}
+            _ => Err(Error::TeamNotInRepoOrganization)?,
+        }
+
+        Ok(team)
+    }
+
+    /// Teams can only be nested inside teams of the same organization.
+    async fn query_team_in_org(
+        &self,
+        org_id: OrganizationId,
+        team_id: TeamId,
+    ) -> FieldResult<Team> {
+        let team = self
+            .query(|qm| async move { qm.query_team(team_id).await })
+            .await?;
+
+        if team.organization_id != org_id {
+            Err(Error::TeamNotInOrganization)?;
+        }
+
+        Ok(team)
+    }
+
+    /// `team` together with all the teams nested inside it, at any depth.
+    async fn team_and_sub_teams(&self, team: &Team) -> FieldResult<BTreeSet<TeamId>> {
+        let org_id = team.organization_id;
+        let teams = self
+            .query(|qm| async move { qm.query_organization_teams(org_id).await })
+            .await?;
+
+        let mut found = BTreeSet::from([team.id]);
+
+        // `found` only grows, so this stops even if the hierarchy has a cycle.
+        loop {
+            let found_before = found.len();
+
+            for team in &teams {
+                if team.parent.map_or(false, |parent| found.contains(&parent)) {
+                    found.insert(team.id);
+                }
+            }
+
+            if found.len() == found_before {
+                break;
+            }
+        }
+
+        Ok(found)
+    }
+
+    async fn query_repo_as_admin(&self, repo_id: RepoId) -> FieldResult<Repo> {
+        let repo = self
+            .query(|qm| async move { qm.query_repo(repo_id).await })
+            .await?;
+
+        self.require_repo_admin(&repo).await?;
+
+        Ok(repo)
+    }
+
+    /// Hands the current protection rules of `repo` to its git hooks, including
+    /// the hooks of the pushes that are already in progress.
+    async fn update_hook_repo_config(&self, repo: &Repo) -> FieldResult<()> {
+        let repo_path = self
+            .query(|qm| async move { upsilon_vcs_permissions::repo_path(&qm, repo).await })
+            .await?;
+
+        upsilon_vcs::write_hook_repo_config(
+            &self.vcs_config,
+            repo_path,
+            &upsilon_vcs_permissions::lower_repo_config(repo),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Applies the invite and email domain rules of the registration
+    /// policy to a new account, returning whether it has to be approved
+    /// before it can log in.
+    ///
+    /// Every way of creating an account goes through this, once it has
+    /// checked that registration is enabled at all.
+    fn check_registration_policy(
+        &self,
+        email: &Email,
+        invite: Option<&Invite>,
+    ) -> FieldResult<bool> {
+        let register_config = &self.users_config.register;
+
+        if invite.is_none() {
+            if register_config.invite_only {
+                Err(Error::InviteRequired)?;
+            }
+
+            if !register_config.allows_email_domain(email.as_str()) {
+                Err(Error::EmailDomainNotAllowed)?;
+            }
+        }
+
+        Ok(invite.is_none() && register_config.require_approval)
+    }
+
+    /// Finds the invite with `code`, if it can still be used.
+    async fn query_usable_invite(&self, code: &str) -> FieldResult<Invite> {
+        let code_hash = hash_secret(code);
+
+        let invite = self
+            .query(|qm| async move { qm.query_invite_by_code(code_hash).await })
+            .await?
+            .filter(|invite| invite.is_usable(Utc::now().naive_utc()))
+            .ok_or(Error::InvalidInviteCode)?;
+
+        Ok(invite)
+    }
+
+    /// Marks `invite` as used by the newly registered `user`, and adds
+    /// them to the organization they were invited to.
+    ///
+    /// If someone else used the invite in the meantime, the new account
+    /// is removed again.
+    async fn accept_invite(&self, invite: Invite, user: UserId) -> FieldResult<()> {
+        let used = self
+            .query(|qm| async move { qm.use_invite(invite.id, user).await })
+            .await?;
+
+        if !used {
+            self.query(|qm| async move { qm.delete_user(user).await })
+                .await?;
+
+            Err(Error::InvalidInviteCode)?;
+        }
+
+        if let Some(organization_id) = invite.organization {
+            let member = OrganizationMember::new(organization_id, user, OrganizationRole::Member);
+
+            self.query(|qm| async move { qm.set_organization_member(member).await })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the current user is an instance admin.
+    ///
+    /// The admin API can only be used with session tokens.
+    async fn require_admin(&self) -> FieldResult<&AuthToken> {
+        let auth = self.session_auth()?;
+
+        let user = self.query_user(auth.claims.sub).await?.0;
+
+        if !user.admin {
+            Err(Error::AdminRequired)?;
+        }
+
+        Ok(auth)
+    }
+
+    fn require_debug(&self) -> FieldResult<()> {
+        if !self.debug_config.debug_enabled {
+            Err(DebugModeNotEnabled)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Debug mode is not enabled")]
+struct DebugModeNotEnabled;
+
+impl juniper::Context for GraphQLContext {}
+
+pub struct QueryRoot;
+
+#[graphql_object(Context = GraphQLContext)]
+impl QueryRoot {
+    fn api_version() -> &'static str {
+        "v1"
+    }
+
+    fn ush_cli_args(context: &GraphQLContext) -> Vec<String> {
+        if !context.debug_config.debug_enabled {
+            // we are in production
+            // do not return an error, but also do not return the args
+            return vec!["--upsilon-is-not-debug-rerun-shell-with-'--no-reconfigure'".to_owned()];
+        }
+
+        let mut v = (*context.ush_args).0.clone();
+        let mut port = context.http_port;
+
+        if let Some((hn, http_port)) = &context.host {
+            v.extend(["--hostname".to_owned(), hn.clone()]);
+
+            if let Some(http_port) = *http_port {
+                port = http_port;
+            }
+        }
+
+        v.extend(["--http-port".to_owned(), port.to_string()]);
+
+        v
+    }
+
+    async fn user(context: &GraphQLContext, user_id: UserId) -> FieldResult<UserRef> {
+        context.query_user(user_id).await
+    }
+
+    fn oidc_providers(context: &GraphQLContext) -> Vec<oidc::OidcProviderRef> {
+        context
+            .oidc
+            .providers()
+            .map(|(name, config)| oidc::OidcProviderRef {
+                name: name.to_owned(),
+                display_name: config.display_name.clone(),
+            })
+            .collect()
+    }
+
+    async fn viewer(context: &GraphQLContext) -> FieldResult<Option<UserRef>> {
+        match &context.auth {
+            Some(auth) => Ok(Some(context.query_user(auth.claims.sub).await?)),
+            None => Ok(None),
+        }
+    }
+
+    // Instance administration; only for admins.
+    async fn admin(context: &GraphQLContext) -> FieldResult<admin::AdminQuery> {
+        context.require_admin().await?;
+
+        Ok(admin::AdminQuery)
+    }
+
+    async fn user_by_username(
+        context: &GraphQLContext,
+        username: Username,
+    ) -> FieldResult<Option<UserRef>> {
+        context
+            .query(|qm| async move { qm.query_user_by_username(&username).await })
+            .await
+            .map(|opt| opt.map(UserRef))
+    }
+
+    async fn organization(
+        context: &GraphQLContext,
+        org_id: OrganizationId,
+    ) -> FieldResult<OrganizationRef> {
+        context.query_org(org_id).await
+    }
+
+    async fn organization_by_name(
+        context: &GraphQLContext,
+        name: OrganizationName,
+    ) -> FieldResult<Option<OrganizationRef>> {
+        context
+            .query(|qm| async move { qm.query_organization_by_name(&name).await })
+            .await
+            .map(|opt| opt.map(OrganizationRef))
+    }
+
+    async fn repo(context: &GraphQLContext, repo_id: RepoId) -> FieldResult<RepoRef> {
+        context
+            .query(|qm| async move { qm.query_repo(repo_id).await })
+            .await
+            .map(RepoRef)
+    }
+
+    async fn repos_with_topic(
+        context: &GraphQLContext,
+        topic: String,
+    ) -> FieldResult<Vec<RepoRef>> {
+        let topic = RepoTopic::parse(&topic)?;
+
+        let repos = context
+            .query(|qm| async move { qm.query_repos_with_topic(&topic).await })
+            .await?;
+
+        let mut readable = Vec::with_capacity(repos.len());
+        for repo in repos {
+            if context.can_read_repo(&repo).await? {
+                readable.push(RepoRef(repo));
+            }
+        }
+
+        Ok(readable)
+    }
+
+    async fn lookup_entity(
+        context: &GraphQLContext,
+        path: String,
+    ) -> FieldResult<Option<EntityValue>> {
+        let path = EntityLookupPath::from_iter(path.split('/').collect::<Vec<_>>().into_iter())?;
+
+        let resolved = match context
+            .query(|qm| async move { crate::entity_lookup_path::resolve(&qm, &path).await })
+            .await?
+        {
+            Some(entity) => entity,
+            None => return Ok(None),
+        };
+
+        let entity_ref = match resolved {
+            ResolvedEntity::GlobalNamespace => panic!("global namespace is not an entity"),
+            ResolvedEntity::User(user) => EntityValue::from(UserRef(user)),
+            ResolvedEntity::Organization(org) => EntityValue::from(OrganizationRef(org)),
+            ResolvedEntity::Team(org, team) => EntityValue::from(TeamRef(team)),
+            ResolvedEntity::Repo { repo, .. } => EntityValue::from(RepoRef(repo)),
+        };
+
+        Ok(Some(entity_ref))
+    }
+
+    async fn lookup_repo(context: &GraphQLContext, path: String) -> FieldResult<Option<RepoRef>> {
+        let path = EntityLookupPath::from_iter(path.split('/').collect::<Vec<_>>().into_iter())?;
+
+        let repo = match context
+            .query(|qm| async move { crate::entity_lookup_path::resolve(&qm, &path).await })
+            .await?
+        {
+            Some(ResolvedEntity::Repo { repo, .. }) => repo,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(RepoRef(repo)))
+    }
+}
+
+mod ent {
+    #![allow(clippy::enum_variant_names)]
+
+    use juniper::graphql_interface;
+
+    use super::{GraphQLContext, OrganizationRef, RepoRef, TeamRef, UserRef};
+
+    #[graphql_interface(for = [UserRef, OrganizationRef, TeamRef, RepoRef])]
+    pub(super) trait Entity {
+        fn _entity_id(&self, ctx: &GraphQLContext) -> String;
+
+        fn entity_user(&self, ctx: &GraphQLContext) -> Option<&UserRef> {
+            None
+        }
+
+        fn entity_organization(&self, ctx: &GraphQLContext) -> Option<&OrganizationRef> {
+            None
+        }
+
+        fn entity_team(&self, ctx: &GraphQLContext) -> Option<&TeamRef> {
+            None
+        }
+
+        fn entity_repo(&self, ctx: &GraphQLContext) -> Option<&RepoRef> {
+            None
+        }
+    }
+}
+
+use ent::{Entity, EntityValue};
+
+macro_rules! defer_entity_impl {
+    () => {
+        fn _entity_id(&self, ctx: &GraphQLContext) -> String {
+            <Self as Entity>::_entity_id(self, ctx)
+        }
+
+        fn entity_user(&self, ctx: &GraphQLContext) -> Option<&UserRef> {
+            <Self as Entity>::entity_user(self, ctx)
+        }
+
+        fn entity_repo(&self, ctx: &GraphQLContext) -> Option<&RepoRef> {
+            <Self as Entity>::entity_repo(self, ctx)
+        }
+
+        fn entity_organization(&self, ctx: &GraphQLContext) -> Option<&OrganizationRef> {
+            <Self as Entity>::entity_organization(self, ctx)
+        }
+
+        fn entity_team(&self, ctx: &GraphQLContext) -> Option<&TeamRef> {
+            <Self as Entity>::entity_team(self, ctx)
+        }
+    };
+}
+
+// #[graphql_object(Context = GraphQLContext)]
+// impl EntityRef {
+//     fn user_id(&self) -> Option<UserId> {
+//         match self {
+//             EntityRef::User(user) => Some(user.id),
+//             _ => None,
+//         }
+//     }
+//
+//     async fn user(&self) -> Option<UserRef> {
+//         match self {
+//             EntityRef::User(user) => Some(UserRef(user.clone())),
+//             _ => None,
+//         }
+//     }
+//
+//     fn organization_id(&self) -> Option<OrganizationId> {
+//         match self {
+//             EntityRef::Organization(org) => Some(org.id),
+//             EntityRef::Team(org, _) => Some(org.id),
+//             _ => None,
+//         }
+//     }
+//
+//     async fn organization(&self) -> Option<OrganizationRef> {
+//         match self {
+//             EntityRef::Organization(org) => Some(OrganizationRef(org.clone())),
+//             EntityRef::Team(org, _) => Some(OrganizationRef(org.clone())),
+//             _ => None,
+//         }
+//     }
+//
+//     fn team_id(&self) -> Option<TeamId> {
 //         match self {
 //             EntityRef::Team(_, team) => Some(team.id),
 //             _ => None,
@@ -433,34 +1380,808 @@ macro_rules! defer_entity_impl {
 //     }
 // }
 
-pub struct MutationRoot;
+pub struct MutationRoot;
+
+/// Formats a UTC timestamp as RFC 3339.
+fn format_timestamp(ts: NaiveDateTime) -> String {
+    ts.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn default_repo_config() -> upsilon_models::repo::RepoConfig {
+    upsilon_models::repo::RepoConfig {
+        global_permissions: RepoPermissions::READ,
+        protected_branches: Vec::new(),
+        protected_tags: Vec::new(),
+    }
+}
+
+impl MutationRoot {
+    async fn make_global_mirror(
+        context: &GraphQLContext,
+        name: String,
+        url: String,
+    ) -> FieldResult<RepoRef> {
+        let path = context.vcs_config.repo_dir(&name);
+
+        tokio::fs::create_dir_all(&path).await?;
+
+        let vcs_config_clone = context.vcs_config.clone();
+
+        let repo = Repo::new(
+            RepoNamespace(NamespaceId::GlobalNamespace),
+            RepoName::from(name),
+            default_repo_config(),
+        );
+
+        let repo_clone = repo.clone();
+
+        context
+            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .await?;
+
+        let repo_id_string = repo.id.to_string();
+        let path_clone = path.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let _ = upsilon_vcs::setup_mirror_absolute(
+                &vcs_config_clone,
+                url,
+                &RepoConfig::new(RepoVisibility::Public, repo_id_string),
+                path_clone,
+            )?;
+
+            Ok::<_, FieldError>(())
+        })
+        .await??;
+
+        upsilon_vcs::write_hook_repo_config_absolute(
+            path,
+            &upsilon_vcs_permissions::lower_repo_config(&repo),
+        )
+        .await?;
+
+        Ok(RepoRef(repo))
+    }
+}
+
+#[graphql_object(Context = GraphQLContext)]
+impl MutationRoot {
+    // Instance administration; only for admins.
+    async fn admin(context: &GraphQLContext) -> FieldResult<admin::AdminMutation> {
+        context.require_admin().await?;
+
+        Ok(admin::AdminMutation)
+    }
+
+    // Returns the access token of the new session, or null if the account
+    // has to be approved by an admin before it can log in.
+    async fn create_user(
+        context: &GraphQLContext,
+        username: Username,
+        email: Email,
+        password: PlainPassword,
+        invite_code: Option<String>,
+    ) -> FieldResult<Option<String>> {
+        let register_config = &context.users_config.register;
+
+        if !register_config.enabled {
+            Err(Error::Forbidden)?;
+        }
+
+        let invite = match invite_code {
+            Some(code) => Some(context.query_usable_invite(&code).await?),
+            None => None,
+        };
+
+        let pending_approval = context.check_registration_policy(&email, invite.as_ref())?;
+
+        let id = UserId::new();
+        let password_hash_algo =
+            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
+        let password_hash = tokio::task::spawn_blocking(move || {
+            password_hash_algo.hash_password(
+                &password,
+                &id.chrono_ts().and_utc().timestamp().to_le_bytes(),
+            )
+        })
+        .await?;
+
+        let user = User {
+            id,
+            admin: false,
+            username,
+            password: password_hash,
+            display_name: None,
+            emails: UserEmails::new(email),
+            avatar: None,
+            suspended: false,
+            pending_approval,
+        };
+
+        context
+            .query(|qm| async move { qm.create_user(user.clone()).await })
+            .await?;
+
+        if let Some(invite) = invite {
+            context.accept_invite(invite, id).await?;
+        }
+
+        if pending_approval {
+            return Ok(None);
+        }
+
+        let tokens = context.start_session(id).await?;
+
+        Ok(Some(tokens.access_token))
+    }
+
+    #[graphql(name = "_debug__createTestUser")]
+    async fn create_test_user(
+        context: &GraphQLContext,
+        username: Username,
+        email: Email,
+        password: PlainPassword,
+        // stored as is instead of hashing `password`, to set up users
+        // with hashes from other algorithms or parameters
+        password_hash: Option<HashedPassword>,
+    ) -> FieldResult<String> {
+        context.require_debug()?;
+
+        if !context.users_config.register.enabled {
+            Err(Error::Forbidden)?;
+        }
+
+        let id = UserId::new();
+        let password_hash_algo =
+            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
+        let password_hash = 'password_hash: {
+            if let Some(password_hash) = password_hash {
+                break 'password_hash password_hash;
+            }
+
+            if password == "test" {
+                break 'password_hash HashedPassword::from("test_hash");
+            }
+
+            tokio::task::spawn_blocking(move || {
+                password_hash_algo.hash_password(
+                    &password,
+                    &id.chrono_ts().and_utc().timestamp().to_le_bytes(),
+                )
+            })
+            .await?
+        };
+
+        // test instances start out empty, so `users.admins` can only be
+        // bootstrapped here
+        let user = User {
+            id,
+            admin: context.users_config.is_bootstrap_admin(username.as_str()),
+            username,
+            password: password_hash,
+            display_name: None,
+            emails: UserEmails::new(email),
+            avatar: None,
+            suspended: false,
+            pending_approval: false,
+        };
+
+        context
+            .query(|qm| async move { qm.create_user(user.clone()).await })
+            .await?;
+
+        let token = context.auth_context.issue_token(id);
+
+        Ok(token.to_string())
+    }
+
+    // Returns just the access token of the new session;
+    // use `startSession` to also get a refresh token.
+    async fn login(
+        context: &GraphQLContext,
+        username_or_email: String,
+        password: PlainPassword,
+    ) -> FieldResult<String> {
+        let user = context
+            .check_credentials(username_or_email, password)
+            .await?;
+
+        if context.confirmed_two_factor(user.id).await?.is_some() {
+            Err(Error::TwoFactorRequired)?;
+        }
+
+        let tokens = context.start_session(user.id).await?;
+
+        Ok(tokens.access_token)
+    }
+
+    async fn start_session(
+        context: &GraphQLContext,
+        username_or_email: String,
+        password: PlainPassword,
+    ) -> FieldResult<sessions::SessionTokens> {
+        let user = context
+            .check_credentials(username_or_email, password)
+            .await?;
+
+        if context.confirmed_two_factor(user.id).await?.is_some() {
+            Err(Error::TwoFactorRequired)?;
+        }
+
+        context.start_session(user.id).await
+    }
+
+    // Like `startSession`, but for users with 2FA enabled it asks for
+    // their code instead of starting the session right away.
+    async fn begin_login(
+        context: &GraphQLContext,
+        username_or_email: String,
+        password: PlainPassword,
+    ) -> FieldResult<two_factor::LoginStep> {
+        let user = context
+            .check_credentials(username_or_email, password)
+            .await?;
+
+        if context.confirmed_two_factor(user.id).await?.is_some() {
+            let token = context.two_factor.begin_challenge(user.id).await;
+
+            return Ok(two_factor::LoginStep::TwoFactorRequired(token));
+        }
+
+        let tokens = context.start_session(user.id).await?;
+
+        Ok(two_factor::LoginStep::Done(tokens))
+    }
+
+    // Takes either a code from the authenticator app, or a recovery code.
+    async fn complete_two_factor_login(
+        context: &GraphQLContext,
+        two_factor_token: String,
+        code: String,
+    ) -> FieldResult<sessions::SessionTokens> {
+        let user = context
+            .two_factor
+            .challenge_user(&two_factor_token)
+            .await
+            .ok_or(Error::Unauthorized)?;
+
+        let two_factor = context
+            .confirmed_two_factor(user)
+            .await?
+            .ok_or(Error::Unauthorized)?;
+
+        context.check_second_factor(two_factor, &code).await?;
+        context.two_factor.end_challenge(&two_factor_token).await;
+
+        context.start_session(user).await
+    }
+
+    // Starts setting up 2FA; it is only turned on by `confirmTwoFactor`.
+    async fn enroll_two_factor(
+        context: &GraphQLContext,
+    ) -> FieldResult<two_factor::TwoFactorEnrollment> {
+        let auth = context.session_auth()?;
+        let user_id = auth.claims.sub;
+
+        if context.confirmed_two_factor(user_id).await?.is_some() {
+            Err(Error::TwoFactorAlreadyEnabled)?;
+        }
+
+        let user = context
+            .query(|qm| async move { qm.query_user(user_id).await })
+            .await?;
+
+        let secret = crate::two_factor::generate_totp_secret();
+
+        let two_factor = TwoFactor {
+            user: user_id,
+            encrypted_secret: context.two_factor.encrypt(&secret),
+            confirmed: false,
+            recovery_code_hashes: Vec::new(),
+            last_used_step: None,
+        };
+
+        context
+            .query(|qm| async move { qm.set_user_two_factor(two_factor).await })
+            .await?;
+
+        Ok(two_factor::TwoFactorEnrollment {
+            secret: crate::two_factor::base32(&secret),
+            otpauth_uri: context
+                .two_factor
+                .otpauth_uri(user.username.as_str(), &secret),
+        })
+    }
+
+    // Turns 2FA on, given a code from the authenticator app.
+    // Returns the recovery codes, which are only shown this once.
+    async fn confirm_two_factor(
+        context: &GraphQLContext,
+        code: String,
+    ) -> FieldResult<Vec<String>> {
+        let auth = context.session_auth()?;
+        let user_id = auth.claims.sub;
+
+        let mut two_factor = context
+            .query(|qm| async move { qm.query_user_two_factor(user_id).await })
+            .await?
+            .ok_or(Error::TwoFactorNotEnrolled)?;
+
+        if two_factor.confirmed {
+            Err(Error::TwoFactorAlreadyEnabled)?;
+        }
+
+        let secret = context
+            .two_factor
+            .decrypt(&two_factor.encrypted_secret)
+            .ok_or(Error::TwoFactorNotEnrolled)?;
+
+        let now = Utc::now().timestamp() as u64;
+        let step = crate::two_factor::verify_totp(&secret, &code, now, None)
+            .ok_or(Error::InvalidTwoFactorCode)?;
+
+        let (codes, hashes) = crate::two_factor::generate_recovery_codes();
+
+        two_factor.confirmed = true;
+        two_factor.last_used_step = Some(step);
+        two_factor.recovery_code_hashes = hashes;
+
+        context
+            .query(|qm| async move { qm.set_user_two_factor(two_factor).await })
+            .await?;
+
+        Ok(codes)
+    }
+
+    // Replaces the recovery codes with new ones.
+    async fn regenerate_recovery_codes(
+        context: &GraphQLContext,
+        code: String,
+    ) -> FieldResult<Vec<String>> {
+        let auth = context.session_auth()?;
+        let user_id = auth.claims.sub;
+
+        let two_factor = context
+            .confirmed_two_factor(user_id)
+            .await?
+            .ok_or(Error::TwoFactorNotEnrolled)?;
+
+        context.check_second_factor(two_factor, &code).await?;
+
+        // re-read it, to keep the step of the code that was just used
+        let mut two_factor = context
+            .confirmed_two_factor(user_id)
+            .await?
+            .ok_or(Error::TwoFactorNotEnrolled)?;
+
+        let (codes, hashes) = crate::two_factor::generate_recovery_codes();
+        two_factor.recovery_code_hashes = hashes;
+
+        context
+            .query(|qm| async move { qm.set_user_two_factor(two_factor).await })
+            .await?;
 
-fn default_repo_config() -> upsilon_models::repo::RepoConfig {
-    upsilon_models::repo::RepoConfig {
-        global_permissions: RepoPermissions::READ,
-        protected_branches: Vec::new(),
+        Ok(codes)
     }
-}
 
-impl MutationRoot {
-    async fn make_global_mirror(
+    async fn disable_two_factor(context: &GraphQLContext, code: String) -> FieldResult<bool> {
+        let auth = context.session_auth()?;
+        let user_id = auth.claims.sub;
+
+        let two_factor = context
+            .confirmed_two_factor(user_id)
+            .await?
+            .ok_or(Error::TwoFactorNotEnrolled)?;
+
+        context.check_second_factor(two_factor, &code).await?;
+
+        context
+            .query(|qm| async move { qm.delete_user_two_factor(user_id).await })
+            .await
+    }
+
+    // Ends all the sessions of the user, and starts a new one.
+    async fn change_password(
+        context: &GraphQLContext,
+        current_password: PlainPassword,
+        new_password: PlainPassword,
+    ) -> FieldResult<sessions::SessionTokens> {
+        let auth = context.session_auth()?;
+        let user_id = auth.claims.sub;
+
+        let user = context
+            .query(|qm| async move { qm.query_user(user_id).await })
+            .await?;
+
+        context
+            .check_credentials(user.username.to_string(), current_password)
+            .await?;
+
+        context.set_password(user_id, new_password).await?;
+
+        context.start_session(user_id).await
+    }
+
+    // Emails a reset token to the user with this email.
+    //
+    // Always succeeds, so that it doesn't tell who has an account.
+    async fn request_password_reset(context: &GraphQLContext, email: Email) -> FieldResult<bool> {
+        let user = {
+            let email = email.clone();
+
+            context
+                .query(|qm| async move { qm.query_user_by_username_email(email.as_str()).await })
+                .await?
+        };
+
+        let Some(user) = user.filter(|user| user.emails.email_index(&email).is_some()) else {
+            return Ok(true);
+        };
+
+        let reset_config = &context.users_config.password_reset;
+        let lifetime =
+            Duration::from_std(reset_config.lifetime).unwrap_or_else(|_| Duration::hours(1));
+
+        let (token, token_hash) = generate_secret(PASSWORD_RESET_TOKEN_PREFIX);
+
+        let reset = PasswordReset {
+            user: user.id,
+            token_hash,
+            expires_at: Utc::now().naive_utc() + lifetime,
+        };
+
+        context
+            .query(|qm| async move { qm.create_password_reset(reset).await })
+            .await?;
+
+        let mut body = format!(
+            "Someone asked to reset the password of {}.\n\n\
+             If it was you, use this token to choose a new one: {token}\n",
+            user.username
+        );
+
+        if let Some(link) = &reset_config.link {
+            body.push_str(&format!(
+                "\nOr follow this link: {}\n",
+                link.replace("{token}", &token)
+            ));
+        }
+
+        body.push_str("\nOtherwise, you can ignore this email.");
+
+        context
+            .mailer
+            .send(Mail {
+                to: email.to_string(),
+                subject: "Reset your password".to_owned(),
+                body,
+            })
+            .await
+            .map_err(Error::from)?;
+
+        Ok(true)
+    }
+
+    // Ends all the sessions of the user; they have to log in again.
+    async fn reset_password(
+        context: &GraphQLContext,
+        token: String,
+        new_password: PlainPassword,
+    ) -> FieldResult<bool> {
+        let token_hash = hash_secret(&token);
+        let now = Utc::now().naive_utc();
+
+        let reset = context
+            .query(|qm| async move { qm.query_password_reset(token_hash).await })
+            .await?
+            .filter(|reset| !reset.is_expired(now))
+            .ok_or(Error::InvalidPasswordResetToken)?;
+
+        context.set_password(reset.user, new_password).await?;
+
+        Ok(true)
+    }
+
+    // Returns the URL of the provider to send the user to.
+    async fn begin_oidc_login(context: &GraphQLContext, provider: String) -> FieldResult<String> {
+        let url = context
+            .oidc
+            .begin_login(&provider)
+            .await
+            .map_err(Error::from)?;
+
+        Ok(url)
+    }
+
+    // Called with the `code` and `state` the provider redirected back with.
+    async fn complete_oidc_login(
+        context: &GraphQLContext,
+        provider: String,
+        code: String,
+        state: String,
+    ) -> FieldResult<sessions::SessionTokens> {
+        let identity = context
+            .oidc
+            .complete_login(&provider, &code, &state)
+            .await
+            .map_err(Error::from)?;
+
+        let user = context.oidc_user(identity).await?;
+
+        context.start_session(user).await
+    }
+
+    // Exchanges a refresh token for a new access token and a new refresh token.
+    // Using a refresh token a second time ends the session.
+    async fn refresh_session(
+        context: &GraphQLContext,
+        refresh_token: String,
+    ) -> FieldResult<sessions::SessionTokens> {
+        let hash = hash_secret(&refresh_token);
+        let now = Utc::now().naive_utc();
+
+        let hash_clone = hash.clone();
+        let mut session = context
+            .query(|qm| async move { qm.query_session_by_refresh_token(hash_clone).await })
+            .await?
+            .ok_or(Error::Unauthorized)?;
+
+        let reused = session.refresh_token_hash != hash;
+
+        if reused || session.is_expired(now) {
+            let (user, id) = (session.user, session.id);
+            context
+                .query(|qm| async move { qm.delete_session(user, id).await })
+                .await?;
+
+            if reused {
+                Err(Error::RefreshTokenReused)?;
+            }
+
+            Err(Error::Unauthorized)?;
+        }
+
+        let (refresh_token, refresh_token_hash) = generate_secret(REFRESH_TOKEN_PREFIX);
+
+        session.rotate_refresh_token(refresh_token_hash);
+        session.last_seen_at = now;
+        session.expires_at = now + context.auth_context.refresh_token_lifetime();
+        session.user_agent = context.user_agent.clone();
+        session.ip = context.client_ip.map(|ip| ip.to_string());
+
+        let session_clone = session.clone();
+        context
+            .query(|qm| async move { qm.update_session(session_clone).await })
+            .await?;
+
+        let access_token = context
+            .auth_context
+            .issue_session_token(session.user, session.id);
+
+        Ok(sessions::SessionTokens {
+            session: sessions::SessionRef(session),
+            access_token: access_token.to_string(),
+            refresh_token,
+        })
+    }
+
+    // Ends the session of the token used for this request.
+    async fn logout(context: &GraphQLContext) -> FieldResult<bool> {
+        let auth = context.session_auth()?;
+
+        let Some(sid) = auth.claims.sid else {
+            return Ok(false);
+        };
+
+        let ended = context
+            .query(|qm| async move { qm.delete_session(auth.claims.sub, sid).await })
+            .await?;
+
+        Ok(ended)
+    }
+
+    async fn revoke_session(context: &GraphQLContext, session: SessionId) -> FieldResult<bool> {
+        let auth = context.session_auth()?;
+
+        let revoked = context
+            .query(|qm| async move { qm.delete_session(auth.claims.sub, session).await })
+            .await?;
+
+        Ok(revoked)
+    }
+
+    #[graphql(name = "_debug__loginTestUser")]
+    async fn login_test_user(
+        context: &GraphQLContext,
+        username_or_email: String,
+        password: PlainPassword,
+    ) -> FieldResult<String> {
+        context.require_debug()?;
+
+        let user = context
+            .query(|qm| async move { qm.query_user_by_username_email(&username_or_email).await })
+            .await?
+            .ok_or(Error::Unauthorized)?;
+
+        let password_check = 'password_check: {
+            if password == "test" && user.password == "test_hash" {
+                break 'password_check true;
+            }
+
+            tokio::task::spawn_blocking(move || user.password.verify(&password)).await?
+        };
+
+        if !password_check {
+            Err(Error::Unauthorized)?;
+        }
+
+        let token = context.auth_context.issue_token(user.id);
+
+        Ok(token.to_string())
+    }
+
+    async fn create_organization(
+        context: &GraphQLContext,
+        name: OrganizationName,
+    ) -> FieldResult<OrganizationRef> {
+        let auth = context.auth_with_scope(TokenScope::AdminOrg)?;
+
+        // creating an organization makes the user its owner
+        context
+            .require_two_factor_for_org_owner(auth.claims.sub)
+            .await?;
+
+        let org = Organization::new(name);
+        let owner = OrganizationMember::new(org.id, auth.claims.sub, OrganizationRole::Owner);
+
+        let org_clone = org.clone();
+
+        context
+            .query(|qm| async move {
+                qm.create_organization(org_clone).await?;
+                qm.set_organization_member(owner).await
+            })
+            .await?;
+
+        Ok(OrganizationRef(org))
+    }
+
+    async fn create_repo(context: &GraphQLContext, name: RepoName) -> FieldResult<RepoRef> {
+        let auth = context.auth_with_scope(TokenScope::RepoWrite)?;
+
+        let user = context
+            .query(|qm| async move { qm.query_user(auth.claims.sub).await })
+            .await?;
+
+        let repo = Repo::new(
+            RepoNamespace(NamespaceId::User(auth.claims.sub)),
+            name.clone(),
+            default_repo_config(),
+        );
+
+        let repo_clone = repo.clone();
+        context
+            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .await?;
+
+        let mut pb = PathBuf::new();
+        pb.push(user.username.as_str());
+        pb.push(name.as_str());
+
+        let path = context.vcs_config.repo_dir(pb);
+
+        tokio::fs::create_dir_all(&path).await?;
+
+        context.init_repo(&repo, path).await?;
+
+        Ok(RepoRef(repo))
+    }
+
+    async fn create_repo_in_organization(
+        context: &GraphQLContext,
+        name: RepoName,
+        organization_id: OrganizationId,
+    ) -> FieldResult<RepoRef> {
+        context.auth_with_scope(TokenScope::AdminOrg)?;
+
+        let org = context
+            .query(|qm| async move { qm.query_organization(organization_id).await })
+            .await?;
+
+        context.require_org_admin(&org).await?;
+
+        let repo = Repo::new(
+            RepoNamespace(NamespaceId::Organization(organization_id)),
+            name.clone(),
+            default_repo_config(),
+        );
+
+        let repo_clone = repo.clone();
+
+        context
+            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .await?;
+
+        let mut pb = PathBuf::new();
+        pb.push(org.name.as_str());
+        pb.push(name.as_str());
+
+        let path = context.vcs_config.repo_dir(pb);
+
+        tokio::fs::create_dir_all(&path).await?;
+
+        context.init_repo(&repo, path).await?;
+
+        Ok(RepoRef(repo))
+    }
+
+    async fn create_repo_in_team(
+        context: &GraphQLContext,
+        name: RepoName,
+        team_id: TeamId,
+    ) -> FieldResult<RepoRef> {
+        context.auth_with_scope(TokenScope::AdminOrg)?;
+
+        let team = context
+            .query(|qm| async move { qm.query_team(team_id).await })
+            .await?;
+
+        let organization = context
+            .query(|qm| async move { qm.query_organization(team.organization_id).await })
+            .await?;
+
+        context.require_org_admin(&organization).await?;
+
+        let repo = Repo::new(
+            RepoNamespace(NamespaceId::Team(team.organization_id, team_id)),
+            name.clone(),
+            default_repo_config(),
+        );
+
+        let repo_clone = repo.clone();
+
+        context
+            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .await?;
+
+        let namespace = NamespaceId::Team(team.organization_id, team_id);
+        let mut pb = context
+            .query(|qm| async move { namespace_dir(&qm, namespace).await })
+            .await?;
+        pb.push(name.as_str());
+
+        let path = context.vcs_config.repo_dir(pb);
+
+        tokio::fs::create_dir_all(&path).await?;
+        context.init_repo(&repo, path).await?;
+
+        Ok(RepoRef(repo))
+    }
+
+    #[graphql(name = "_debug__globalMirror")]
+    async fn global_mirror(
         context: &GraphQLContext,
         name: String,
         url: String,
     ) -> FieldResult<RepoRef> {
-        let path = context.vcs_config.repo_dir(&name);
+        context.require_debug()?;
 
-        tokio::fs::create_dir_all(&path).await?;
+        Self::make_global_mirror(context, name, url).await
+    }
 
-        let vcs_config_clone = context.vcs_config.clone();
+    #[graphql(name = "_debug__silentInitGlobal")]
+    async fn silent_init_global(name: String, context: &GraphQLContext) -> FieldResult<RepoRef> {
+        context.require_debug()?;
 
-        let repo = Repo {
-            id: RepoId::new(),
-            namespace: RepoNamespace(NamespaceId::GlobalNamespace),
-            name: RepoName::from(name),
-            display_name: None,
-            repo_config: default_repo_config(),
-        };
+        let path = context.vcs_config.repo_dir(&name);
+
+        let vcs_config_clone = context.vcs_config.clone();
+
+        let repo = Repo::new(
+            RepoNamespace(NamespaceId::GlobalNamespace),
+            RepoName::from(name),
+            default_repo_config(),
+        );
 
         let repo_clone = repo.clone();
 
@@ -468,484 +2189,946 @@ impl MutationRoot {
             .query(|qm| async move { qm.create_repo(repo_clone).await })
             .await?;
 
+        if let Some(auth) = &context.auth {
+            let user_id = auth.claims.sub;
+            context
+                .query(|qm| async move {
+                    qm.init_repo_user_perms(repo.id, user_id).await?;
+
+                    qm.add_repo_user_perms(
+                        repo.id,
+                        user_id,
+                        RepoPermissions::ADMIN | RepoPermissions::WRITE | RepoPermissions::READ,
+                    )
+                    .await?;
+
+                    Ok::<_, CommonDataClientError>(())
+                })
+                .await?;
+        }
+
         let repo_id_string = repo.id.to_string();
 
+        let path_clone = path.clone();
+
         tokio::task::spawn_blocking(move || {
-            let _ = upsilon_vcs::setup_mirror_absolute(
+            let repo = upsilon_vcs::get_repo_absolute_no_check(&vcs_config_clone, &path_clone)?;
+
+            upsilon_vcs::silent_setup_repo_absolute(
                 &vcs_config_clone,
-                url,
+                &path_clone,
+                &repo,
                 &RepoConfig::new(RepoVisibility::Public, repo_id_string),
-                path,
             )?;
 
             Ok::<_, FieldError>(())
         })
         .await??;
 
+        upsilon_vcs::write_hook_repo_config_absolute(
+            path,
+            &upsilon_vcs_permissions::lower_repo_config(&repo),
+        )
+        .await?;
+
         Ok(RepoRef(repo))
     }
-}
 
-#[graphql_object(Context = GraphQLContext)]
-impl MutationRoot {
-    async fn create_user(
+    #[graphql(name = "_debug__cpGlrFromLocal")]
+    async fn cp_glr_from_local(
         context: &GraphQLContext,
-        username: Username,
-        email: Email,
-        password: PlainPassword,
-    ) -> FieldResult<String> {
-        if !context.users_config.register.enabled {
+        name: String,
+        local_path: String,
+    ) -> FieldResult<RepoRef> {
+        context.require_debug()?;
+
+        let local_path = PathBuf::from(local_path);
+
+        Self::make_global_mirror(
+            context,
+            name,
+            local_path.to_str().expect("Invalid path").to_owned(),
+        )
+        .await
+    }
+
+    async fn add_user_repo_perms(
+        context: &GraphQLContext,
+        repo: RepoId,
+        user: UserId,
+        perms: RepoPermissions,
+    ) -> FieldResult<RepoPermissions> {
+        let auth = context.auth_with_scope(TokenScope::RepoWrite)?;
+
+        let repo = context
+            .query(|qm| async move { qm.query_repo(repo).await })
+            .await?;
+
+        if repo.namespace != NamespaceId::User(auth.claims.sub) || !auth.allows_repo(repo.id) {
             Err(Error::Forbidden)?;
         }
 
-        let id = UserId::new();
-        let password_hash_algo =
-            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
-        let password_hash = tokio::task::spawn_blocking(move || {
-            password_hash_algo.hash_password(&password, &id.chrono_ts().timestamp().to_le_bytes())
-        })
-        .await?;
+        context.require_not_blocked(&repo, user).await?;
+        context.init_repo_user_permissions(repo.id, user).await?;
 
-        let user = User {
-            id,
-            username,
-            password: password_hash,
-            display_name: None,
-            emails: UserEmails::new(email),
-            avatar: None,
-        };
+        let new_perms = context
+            .query(|qm| async move { qm.add_repo_user_perms(repo.id, user, perms).await })
+            .await?;
+
+        Ok(new_perms)
+    }
+
+    async fn rm_user_repo_perms(
+        context: &GraphQLContext,
+        repo: RepoId,
+        user: UserId,
+        perms: RepoPermissions,
+    ) -> FieldResult<RepoPermissions> {
+        let auth = context.auth_with_scope(TokenScope::RepoWrite)?;
+
+        let repo = context
+            .query(|qm| async move { qm.query_repo(repo).await })
+            .await?;
+
+        if repo.namespace != NamespaceId::User(auth.claims.sub) || !auth.allows_repo(repo.id) {
+            Err(Error::Forbidden)?;
+        }
+
+        context.init_repo_user_permissions(repo.id, user).await?;
+
+        let new_perms = context
+            .query(|qm| async move { qm.remove_repo_user_perms(repo.id, user, perms).await })
+            .await?;
+
+        Ok(new_perms)
+    }
+
+    async fn set_repo_description(
+        context: &GraphQLContext,
+        repo: RepoId,
+        description: Option<RepoDescription>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
 
+        let description_clone = description.clone();
         context
-            .query(|qm| async move { qm.create_user(user.clone()).await })
+            .query(|qm| async move { qm.set_repo_description(repo.id, description_clone).await })
             .await?;
 
-        let token = context
-            .auth_context
-            .sign(AuthTokenClaims::new(id, Duration::days(15)));
+        repo.description = description;
 
-        Ok(token.to_string())
+        Ok(RepoRef(repo))
     }
 
-    #[graphql(name = "_debug__createTestUser")]
-    async fn create_test_user(
+    async fn set_repo_website(
         context: &GraphQLContext,
-        username: Username,
-        email: Email,
-        password: PlainPassword,
-    ) -> FieldResult<String> {
-        context.require_debug()?;
+        repo: RepoId,
+        website: Option<RepoWebsite>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
 
-        if !context.users_config.register.enabled {
-            Err(Error::Forbidden)?;
+        let website = website
+            .map(|website| RepoWebsite::parse(website.as_str()))
+            .transpose()?;
+
+        let website_clone = website.clone();
+        context
+            .query(|qm| async move { qm.set_repo_website(repo.id, website_clone).await })
+            .await?;
+
+        repo.website = website;
+
+        Ok(RepoRef(repo))
+    }
+
+    async fn set_repo_topics(
+        context: &GraphQLContext,
+        repo: RepoId,
+        topics: Vec<String>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
+
+        let mut topics = topics
+            .iter()
+            .map(|topic| RepoTopic::parse(topic))
+            .collect::<Result<Vec<_>, _>>()?;
+        topics.sort();
+        topics.dedup();
+
+        let topics_clone = topics.clone();
+        context
+            .query(|qm| async move { qm.set_repo_topics(repo.id, topics_clone).await })
+            .await?;
+
+        repo.topics = topics;
+
+        Ok(RepoRef(repo))
+    }
+
+    async fn set_repo_default_branch(
+        context: &GraphQLContext,
+        repo: RepoId,
+        branch: RepoBranchName,
+    ) -> FieldResult<RepoRef> {
+        let repo = context.query_repo_as_admin(repo).await?;
+        let mut repo = RepoRef(repo);
+
+        let ns_path = repo.ns_path(context.db.query_master()).await?;
+        let repo_dir = context.vcs_config.repo_dir(ns_path);
+        let vcs_config = context.vcs_config.clone();
+
+        let branch_clone = branch.clone();
+        tokio::task::spawn_blocking(move || {
+            let repo = upsilon_vcs::get_repo_absolute(&vcs_config, &repo_dir)?;
+            repo.set_default_branch(branch_clone.as_str())?;
+            // drop the repository on the same thread
+
+            Ok::<_, FieldError>(())
+        })
+        .await??;
+
+        let branch_clone = branch.clone();
+        context
+            .query(|qm| async move { qm.set_repo_default_branch(repo.0.id, branch_clone).await })
+            .await?;
+
+        repo.0.default_branch = Some(branch);
+
+        Ok(repo)
+    }
+
+    // Renames the repo, sets its display name, global permissions or protection rules;
+    // only for admins of the repo.
+    async fn update_repo_settings(
+        context: &GraphQLContext,
+        repo: RepoId,
+    ) -> FieldResult<repo_settings::RepoSettingsMutation> {
+        let repo = context.query_repo_as_admin(repo).await?;
+
+        Ok(repo_settings::RepoSettingsMutation(repo.id))
+    }
+
+    async fn set_repo_user_role(
+        context: &GraphQLContext,
+        repo: RepoId,
+        user: UserId,
+        role: Option<RepoRole>,
+    ) -> FieldResult<bool> {
+        let repo = context.query_repo_as_admin(repo).await?;
+
+        if role.is_some() {
+            context.require_not_blocked(&repo, user).await?;
         }
 
-        let id = UserId::new();
-        let password_hash_algo =
-            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
-        let password_hash = 'password_hash: {
-            if password == "test" {
-                break 'password_hash HashedPassword::from("test_hash");
-            }
+        context
+            .query(|qm| async move {
+                qm.set_repo_user_perms(repo.id, user, role.map(RepoRole::permissions))
+                    .await
+            })
+            .await?;
 
-            tokio::task::spawn_blocking(move || {
-                password_hash_algo
-                    .hash_password(&password, &id.chrono_ts().timestamp().to_le_bytes())
+        Ok(true)
+    }
+
+    async fn set_repo_team_role(
+        context: &GraphQLContext,
+        repo: RepoId,
+        team: TeamId,
+        role: Option<RepoRole>,
+    ) -> FieldResult<bool> {
+        let repo = context.query_repo_as_admin(repo).await?;
+        context.query_team_for_repo(&repo, team).await?;
+
+        context
+            .query(|qm| async move {
+                qm.set_repo_team_perms(repo.id, team, role.map(RepoRole::permissions))
+                    .await
             })
-            .await?
-        };
+            .await?;
 
-        let user = User {
-            id,
-            username,
-            password: password_hash,
-            display_name: None,
-            emails: UserEmails::new(email),
-            avatar: None,
-        };
+        Ok(true)
+    }
+
+    async fn set_organization_base_role(
+        context: &GraphQLContext,
+        organization: OrganizationId,
+        role: Option<RepoRole>,
+    ) -> FieldResult<OrganizationRef> {
+        context.auth_with_scope(TokenScope::AdminOrg)?;
+
+        let mut org = context.query_org(organization).await?;
+
+        context.require_org_admin(&org.0).await?;
 
         context
-            .query(|qm| async move { qm.create_user(user.clone()).await })
+            .query(|qm| async move { qm.set_organization_base_role(organization, role).await })
             .await?;
 
-        let token = context
-            .auth_context
-            .sign(AuthTokenClaims::new(id, Duration::days(15)));
+        org.0.base_role = role;
 
-        Ok(token.to_string())
+        Ok(org)
     }
 
-    async fn login(
+    async fn set_organization_display_name(
         context: &GraphQLContext,
-        username_or_email: String,
-        password: PlainPassword,
-    ) -> FieldResult<String> {
-        let user = context
-            .query(|qm| async move { qm.query_user_by_username_email(&username_or_email).await })
-            .await?
-            .ok_or(Error::Unauthorized)?;
+        organization: OrganizationId,
+        display_name: Option<OrganizationDisplayName>,
+    ) -> FieldResult<OrganizationRef> {
+        let mut org = context.query_org(organization).await?;
+
+        context.require_org_admin(&org.0).await?;
+
+        let display_name_clone = display_name.clone();
+        context
+            .query(|qm| async move {
+                qm.set_organization_display_name(organization, display_name_clone)
+                    .await
+            })
+            .await?;
+
+        org.0.display_name = display_name;
+
+        Ok(org)
+    }
+
+    async fn set_organization_email(
+        context: &GraphQLContext,
+        organization: OrganizationId,
+        email: Option<Email>,
+    ) -> FieldResult<OrganizationRef> {
+        let mut org = context.query_org(organization).await?;
+
+        context.require_org_admin(&org.0).await?;
+
+        let email_clone = email.clone();
+        context
+            .query(|qm| async move { qm.set_organization_email(organization, email_clone).await })
+            .await?;
+
+        org.0.email = email;
+
+        Ok(org)
+    }
+
+    // Adds the user to the organization if they are not a member yet.
+    // Only owners can make someone an owner, or change the role of an owner.
+    async fn set_organization_member_role(
+        context: &GraphQLContext,
+        organization: OrganizationId,
+        user: UserId,
+        role: OrganizationRole,
+    ) -> FieldResult<OrganizationMemberRef> {
+        let org = context.query_org(organization).await?;
+        let viewer_role = context.require_org_admin(&org.0).await?;
+
+        let member = context
+            .query(|qm| async move { qm.query_organization_member(organization, user).await })
+            .await?;
+
+        let was_owner = member
+            .as_ref()
+            .map_or(false, |member| member.role.is_owner());
+
+        if (role.is_owner() || was_owner) && !viewer_role.is_owner() {
+            Err(Error::Forbidden)?;
+        }
 
-        let password_hash_algo =
-            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
-        let password_check = tokio::task::spawn_blocking(move || {
-            password_hash_algo.verify_password(&password, &user.password)
-        })
-        .await?;
+        if was_owner && !role.is_owner() {
+            context.require_other_org_owner(organization, user).await?;
+        }
 
-        if !password_check {
-            Err(Error::Unauthorized)?;
+        if role.is_owner() {
+            context.require_two_factor_for_org_owner(user).await?;
         }
 
-        let token = context
-            .auth_context
-            .sign(AuthTokenClaims::new(user.id, Duration::days(15)));
+        let member = match member {
+            Some(member) => OrganizationMember { role, ..member },
+            None => {
+                // make sure the user exists
+                context.query_user(user).await?;
 
-        Ok(token.to_string())
+                OrganizationMember::new(organization, user, role)
+            }
+        };
+
+        let member_clone = member.clone();
+        context
+            .query(|qm| async move { qm.set_organization_member(member_clone).await })
+            .await?;
+
+        Ok(OrganizationMemberRef(member))
     }
 
-    #[graphql(name = "_debug__loginTestUser")]
-    async fn login_test_user(
+    async fn remove_organization_member(
         context: &GraphQLContext,
-        username_or_email: String,
-        password: PlainPassword,
-    ) -> FieldResult<String> {
-        context.require_debug()?;
+        organization: OrganizationId,
+        user: UserId,
+    ) -> FieldResult<bool> {
+        let org = context.query_org(organization).await?;
+        let viewer_role = context.require_org_admin(&org.0).await?;
 
-        let user = context
-            .query(|qm| async move { qm.query_user_by_username_email(&username_or_email).await })
+        if context
+            .org_role(organization, user)
             .await?
-            .ok_or(Error::Unauthorized)?;
-
-        let password_hash_algo =
-            PasswordHashAlgorithmDescriptor::from(context.users_config.auth.password);
-        let password_check = 'password_check: {
-            if password == "test" && user.password == "test_hash" {
-                break 'password_check true;
+            .map_or(false, OrganizationRole::is_owner)
+        {
+            if !viewer_role.is_owner() {
+                Err(Error::Forbidden)?;
             }
 
-            tokio::task::spawn_blocking(move || {
-                password_hash_algo.verify_password(&password, &user.password)
-            })
-            .await?
-        };
-
-        if !password_check {
-            Err(Error::Unauthorized)?;
+            context.require_other_org_owner(organization, user).await?;
         }
 
-        let token = context
-            .auth_context
-            .sign(AuthTokenClaims::new(user.id, Duration::days(15)));
-
-        Ok(token.to_string())
+        context
+            .query(|qm| async move { qm.remove_organization_member(organization, user).await })
+            .await
     }
 
-    async fn create_organization(
+    // Makes the user an owner of the organization, and the current user an admin.
+    async fn transfer_organization_ownership(
         context: &GraphQLContext,
-        name: OrganizationName,
+        organization: OrganizationId,
+        user: UserId,
     ) -> FieldResult<OrganizationRef> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+        let org = context.query_org(organization).await?;
+        context.require_org_owner(&org.0).await?;
 
-        let org = Organization {
-            id: OrganizationId::new(),
-            owner: auth.claims.sub,
-            name,
-            display_name: None,
-            email: None,
-        };
+        let current = context.auth_with_scope(TokenScope::AdminOrg)?.claims.sub;
 
-        let org_clone = org.clone();
+        if current == user {
+            return Ok(org);
+        }
+
+        // make sure the user exists
+        context.query_user(user).await?;
+        context.require_two_factor_for_org_owner(user).await?;
 
         context
-            .query(|qm| async move { qm.create_organization(org_clone).await })
+            .query(|qm| async move {
+                let new_owner = match qm.query_organization_member(organization, user).await? {
+                    Some(member) => OrganizationMember {
+                        role: OrganizationRole::Owner,
+                        ..member
+                    },
+                    None => OrganizationMember::new(organization, user, OrganizationRole::Owner),
+                };
+                qm.set_organization_member(new_owner).await?;
+
+                if let Some(old_owner) = qm.query_organization_member(organization, current).await?
+                {
+                    qm.set_organization_member(OrganizationMember {
+                        role: OrganizationRole::Admin,
+                        ..old_owner
+                    })
+                    .await?;
+                }
+
+                Ok::<_, CommonDataClientError>(())
+            })
             .await?;
 
-        Ok(OrganizationRef(org))
+        Ok(org)
     }
 
-    async fn create_repo(context: &GraphQLContext, name: RepoName) -> FieldResult<RepoRef> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+    async fn create_team(
+        context: &GraphQLContext,
+        organization: OrganizationId,
+        name: TeamName,
+        parent: Option<TeamId>,
+    ) -> FieldResult<TeamRef> {
+        let org = context.query_org(organization).await?;
+        context.require_org_admin(&org.0).await?;
+
+        if let Some(parent) = parent {
+            context.query_team_in_org(organization, parent).await?;
+        }
 
-        let user = context
-            .query(|qm| async move { qm.query_user(auth.claims.sub).await })
+        let team = Team::new(organization, name, parent);
+
+        let team_clone = team.clone();
+        context
+            .query(|qm| async move { qm.create_team(team_clone).await })
             .await?;
 
-        let repo = Repo {
-            id: RepoId::new(),
-            namespace: RepoNamespace(NamespaceId::User(auth.claims.sub)),
-            name: name.clone(),
-            display_name: None,
-            repo_config: default_repo_config(),
-        };
+        Ok(TeamRef(team))
+    }
 
-        let repo_clone = repo.clone();
-        context
-            .query(|qm| async move { qm.create_repo(repo_clone).await })
+    // Nests the team inside `parent`, or moves it to the top level of the
+    // organization if `parent` is null. The directory of the team moves along,
+    // with its repos and sub-teams.
+    async fn set_team_parent(
+        context: &GraphQLContext,
+        team: TeamId,
+        parent: Option<TeamId>,
+    ) -> FieldResult<TeamRef> {
+        let team = context
+            .query(|qm| async move { qm.query_team(team).await })
             .await?;
+        let org = context.query_org(team.organization_id).await?;
+        context.require_org_admin(&org.0).await?;
 
-        let mut pb = PathBuf::new();
-        pb.push(user.username.as_str());
-        pb.push(name.as_str());
+        if let Some(parent) = parent {
+            context
+                .query_team_in_org(team.organization_id, parent)
+                .await?;
 
-        let path = context.vcs_config.repo_dir(pb);
+            let ancestors = context
+                .query(
+                    |qm| async move { upsilon_vcs_permissions::team_ancestors(&qm, parent).await },
+                )
+                .await?;
 
-        tokio::fs::create_dir_all(&path).await?;
+            if ancestors.contains(&team.id) {
+                Err(Error::TeamParentCycle)?;
+            }
+        }
+
+        let namespace = NamespaceId::Team(team.organization_id, team.id);
+        let old_dir = context.vcs_config.repo_dir(
+            context
+                .query(|qm| async move { namespace_dir(&qm, namespace).await })
+                .await?,
+        );
+
+        let new_parent_namespace = match parent {
+            Some(parent) => NamespaceId::Team(team.organization_id, parent),
+            None => NamespaceId::Organization(team.organization_id),
+        };
+        let mut new_dir = context
+            .query(|qm| async move { namespace_dir(&qm, new_parent_namespace).await })
+            .await?;
+        new_dir.push(team.name.as_str());
+        let new_dir = context.vcs_config.repo_dir(new_dir);
+
+        // teams without repos don't have a directory yet
+        let move_dir = old_dir != new_dir && old_dir.exists();
+
+        if move_dir && new_dir.exists() {
+            Err(Error::TeamDirectoryTaken)?;
+        }
 
+        let team_id = team.id;
         context
-            .init_repo(
-                RepoConfig::new(RepoVisibility::Public, repo.id.to_string()),
-                path,
-            )
+            .query(|qm| async move { qm.set_team_parent(team_id, parent).await })
             .await?;
 
-        Ok(RepoRef(repo))
+        if move_dir {
+            let moved = async {
+                if let Some(new_parent_dir) = new_dir.parent() {
+                    tokio::fs::create_dir_all(new_parent_dir).await?;
+                }
+
+                tokio::fs::rename(&old_dir, &new_dir).await
+            }
+            .await;
+
+            if let Err(e) = moved {
+                // put the team back where its repos still are
+                let old_parent = team.parent;
+                context
+                    .query(|qm| async move { qm.set_team_parent(team_id, old_parent).await })
+                    .await?;
+
+                Err(e)?;
+            }
+        }
+
+        Ok(TeamRef(Team { parent, ..team }))
     }
 
-    async fn create_repo_in_organization(
+    // Only members of the organization can join its teams.
+    async fn add_team_member(
         context: &GraphQLContext,
-        name: RepoName,
-        organization_id: OrganizationId,
-    ) -> FieldResult<RepoRef> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
-
-        let org = context
-            .query(|qm| async move { qm.query_organization(organization_id).await })
+        team: TeamId,
+        user: UserId,
+    ) -> FieldResult<OrganizationMemberRef> {
+        let team = context
+            .query(|qm| async move { qm.query_team(team).await })
             .await?;
+        let org = context.query_org(team.organization_id).await?;
+        context.require_org_admin(&org.0).await?;
 
-        if org.owner != auth.claims.sub {
-            Err(Error::Forbidden)?;
+        let org_id = team.organization_id;
+        let Some(mut member) = context
+            .query(|qm| async move { qm.query_organization_member(org_id, user).await })
+            .await?
+        else {
+            return Err(Error::NotAnOrganizationMember.into());
+        };
+
+        if !member.teams.contains(&team.id) {
+            member.teams.push(team.id);
+
+            let member_clone = member.clone();
+            context
+                .query(|qm| async move { qm.set_organization_member(member_clone).await })
+                .await?;
         }
 
-        let repo = Repo {
-            id: RepoId::new(),
-            namespace: RepoNamespace(NamespaceId::Organization(organization_id)),
-            name: name.clone(),
-            display_name: None,
-            repo_config: default_repo_config(),
+        Ok(OrganizationMemberRef(member))
+    }
+
+    // Returns whether the user was in the team. Membership that comes
+    // from a sub-team is not affected.
+    async fn remove_team_member(
+        context: &GraphQLContext,
+        team: TeamId,
+        user: UserId,
+    ) -> FieldResult<bool> {
+        let team = context
+            .query(|qm| async move { qm.query_team(team).await })
+            .await?;
+        let org = context.query_org(team.organization_id).await?;
+        context.require_org_admin(&org.0).await?;
+
+        let org_id = team.organization_id;
+        let Some(mut member) = context
+            .query(|qm| async move { qm.query_organization_member(org_id, user).await })
+            .await?
+        else {
+            return Ok(false);
         };
 
-        let repo_clone = repo.clone();
+        if !member.teams.contains(&team.id) {
+            return Ok(false);
+        }
+
+        member.teams.retain(|it| *it != team.id);
 
         context
-            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .query(|qm| async move { qm.set_organization_member(member).await })
             .await?;
 
-        let mut pb = PathBuf::new();
-        pb.push(org.name.as_str());
-        pb.push(name.as_str());
+        Ok(true)
+    }
 
-        let path = context.vcs_config.repo_dir(pb);
+    #[graphql(deprecated = "Use `updateRepoSettings { setProtectedBranches }`")]
+    async fn set_branch_protection_rule(
+        context: &GraphQLContext,
+        repo: RepoId,
+        rule: permissions::BranchProtectionRuleInput,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
+        let rule = rule.into_rule()?;
 
-        tokio::fs::create_dir_all(&path).await?;
+        let rules = &mut repo.repo_config.protected_branches;
+        match rules.iter_mut().find(|it| it.pattern == rule.pattern) {
+            Some(existing) => *existing = rule,
+            None => rules.push(rule),
+        }
 
+        let rules = rules.clone();
         context
-            .init_repo(
-                RepoConfig::new(RepoVisibility::Public, repo.id.to_string()),
-                path,
-            )
+            .query(|qm| async move { qm.set_repo_protected_branches(repo.id, rules).await })
             .await?;
 
+        context.update_hook_repo_config(&repo).await?;
+
         Ok(RepoRef(repo))
     }
 
-    async fn create_repo_in_team(
+    #[graphql(deprecated = "Use `updateRepoSettings { setProtectedBranches }`")]
+    async fn remove_branch_protection_rule(
         context: &GraphQLContext,
-        name: RepoName,
-        team_id: TeamId,
+        repo: RepoId,
+        pattern: String,
     ) -> FieldResult<RepoRef> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+        let mut repo = context.query_repo_as_admin(repo).await?;
 
-        let team = context
-            .query(|qm| async move { qm.query_team(team_id).await })
-            .await?;
+        repo.repo_config
+            .protected_branches
+            .retain(|it| it.pattern != pattern);
 
-        let organization = context
-            .query(|qm| async move { qm.query_organization(team.organization_id).await })
+        let rules = repo.repo_config.protected_branches.clone();
+        context
+            .query(|qm| async move { qm.set_repo_protected_branches(repo.id, rules).await })
             .await?;
 
-        if organization.owner != auth.claims.sub {
-            Err(Error::Forbidden)?;
-        }
+        context.update_hook_repo_config(&repo).await?;
 
-        let repo = Repo {
-            id: RepoId::new(),
-            namespace: RepoNamespace(NamespaceId::Team(team.organization_id, team_id)),
-            name: name.clone(),
-            display_name: None,
-            repo_config: default_repo_config(),
-        };
+        Ok(RepoRef(repo))
+    }
 
-        let repo_clone = repo.clone();
+    #[graphql(deprecated = "Use `updateRepoSettings { setProtectedTags }`")]
+    async fn set_tag_protection_rule(
+        context: &GraphQLContext,
+        repo: RepoId,
+        rule: permissions::TagProtectionRuleInput,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
+        let rule = rule.into_rule()?;
+
+        let rules = &mut repo.repo_config.protected_tags;
+        match rules.iter_mut().find(|it| it.pattern == rule.pattern) {
+            Some(existing) => *existing = rule,
+            None => rules.push(rule),
+        }
 
+        let rules = rules.clone();
         context
-            .query(|qm| async move { qm.create_repo(repo_clone).await })
+            .query(|qm| async move { qm.set_repo_protected_tags(repo.id, rules).await })
             .await?;
 
-        let mut pb = PathBuf::new();
-        pb.push(organization.name.as_str());
-        pb.push(team.name.as_str());
-        pb.push(name.as_str());
+        context.update_hook_repo_config(&repo).await?;
 
-        let path = context.vcs_config.repo_dir(pb);
+        Ok(RepoRef(repo))
+    }
 
-        tokio::fs::create_dir_all(&path).await?;
+    #[graphql(deprecated = "Use `updateRepoSettings { setProtectedTags }`")]
+    async fn remove_tag_protection_rule(
+        context: &GraphQLContext,
+        repo: RepoId,
+        pattern: String,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = context.query_repo_as_admin(repo).await?;
+
+        repo.repo_config
+            .protected_tags
+            .retain(|it| it.pattern != pattern);
+
+        let rules = repo.repo_config.protected_tags.clone();
         context
-            .init_repo(
-                RepoConfig::new(RepoVisibility::Public, repo.id.to_string()),
-                path,
-            )
+            .query(|qm| async move { qm.set_repo_protected_tags(repo.id, rules).await })
             .await?;
 
+        context.update_hook_repo_config(&repo).await?;
+
         Ok(RepoRef(repo))
     }
 
-    #[graphql(name = "_debug__globalMirror")]
-    async fn global_mirror(
+    async fn add_deploy_key(
         context: &GraphQLContext,
-        name: String,
-        url: String,
-    ) -> FieldResult<RepoRef> {
-        context.require_debug()?;
-
-        Self::make_global_mirror(context, name, url).await
-    }
+        repo: RepoId,
+        title: String,
+        key: String,
+        write: bool,
+    ) -> FieldResult<deploy_keys::DeployKeyRef> {
+        let repo = context.query_repo_as_admin(repo).await?;
 
-    #[graphql(name = "_debug__silentInitGlobal")]
-    async fn silent_init_global(name: String, context: &GraphQLContext) -> FieldResult<RepoRef> {
-        context.require_debug()?;
+        let key = key.parse::<UserSshKey>()?;
 
-        let path = context.vcs_config.repo_dir(&name);
+        // a key has to identify either a user or a deploy key, never both
+        let key_clone = key.clone();
+        let user = context
+            .query(|qm| async move { qm.query_user_ssh_key(key_clone).await })
+            .await?;
 
-        let vcs_config_clone = context.vcs_config.clone();
+        if user.is_some() {
+            Err(Error::SshKeyInUse)?;
+        }
 
-        let repo = Repo {
-            id: RepoId::new(),
-            namespace: RepoNamespace(NamespaceId::GlobalNamespace),
-            name: RepoName::from(name),
-            display_name: None,
-            repo_config: default_repo_config(),
+        let deploy_key = DeployKey {
+            id: DeployKeyId::new(),
+            repo: repo.id,
+            title,
+            key,
+            write,
         };
 
-        let repo_clone = repo.clone();
-
-        context
-            .query(|qm| async move { qm.create_repo(repo_clone).await })
+        let deploy_key_clone = deploy_key.clone();
+        let added = context
+            .query(|qm| async move { qm.add_deploy_key(deploy_key_clone).await })
             .await?;
 
-        if let Some(auth) = &context.auth {
-            let user_id = auth.claims.sub;
-            context
-                .query(|qm| async move {
-                    qm.init_repo_user_perms(repo.id, user_id).await?;
+        if !added {
+            Err(Error::SshKeyInUse)?;
+        }
 
-                    qm.add_repo_user_perms(
-                        repo.id,
-                        user_id,
-                        RepoPermissions::ADMIN | RepoPermissions::WRITE | RepoPermissions::READ,
-                    )
-                    .await?;
+        Ok(deploy_keys::DeployKeyRef(deploy_key))
+    }
 
-                    Ok::<_, CommonDataClientError>(())
-                })
-                .await?;
-        }
+    async fn remove_deploy_key(
+        context: &GraphQLContext,
+        repo: RepoId,
+        deploy_key: DeployKeyId,
+    ) -> FieldResult<bool> {
+        let repo = context.query_repo_as_admin(repo).await?;
 
-        let repo_id_string = repo.id.to_string();
+        let removed = context
+            .query(|qm| async move { qm.delete_deploy_key(repo.id, deploy_key).await })
+            .await?;
 
-        tokio::task::spawn_blocking(move || {
-            let repo = upsilon_vcs::get_repo_absolute_no_check(&vcs_config_clone, &path)?;
+        Ok(removed)
+    }
 
-            upsilon_vcs::silent_setup_repo_absolute(
-                &vcs_config_clone,
-                &path,
-                &repo,
-                &RepoConfig::new(RepoVisibility::Public, repo_id_string),
-            )?;
+    async fn add_user_ssh_key(context: &GraphQLContext, key: String) -> FieldResult<bool> {
+        let auth = context.auth_with_scope(TokenScope::UserKeys)?;
 
-            Ok::<_, FieldError>(())
-        })
-        .await??;
+        let key = key.parse::<UserSshKey>()?;
 
-        Ok(RepoRef(repo))
-    }
+        let key_clone = key.clone();
+        let deploy_key = context
+            .query(|qm| async move { qm.query_deploy_key(key_clone).await })
+            .await?;
 
-    #[graphql(name = "_debug__cpGlrFromLocal")]
-    async fn cp_glr_from_local(
+        if deploy_key.is_some() {
+            return Ok(false);
+        }
+
+        let result = context
+            .query(|qm| async move { qm.add_user_ssh_key(auth.claims.sub, key).await })
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn create_personal_access_token(
         context: &GraphQLContext,
         name: String,
-        local_path: String,
-    ) -> FieldResult<RepoRef> {
-        context.require_debug()?;
+        scopes: Vec<TokenScope>,
+        repos: Option<Vec<RepoId>>,
+        expires_in_days: Option<i32>,
+    ) -> FieldResult<tokens::CreatedPersonalAccessToken> {
+        let auth = context.session_auth()?;
+
+        let expires_at = match expires_in_days {
+            Some(days) if days <= 0 => Err(Error::InvalidTokenExpiry)?,
+            Some(days) => Some(Utc::now().naive_utc() + Duration::days(i64::from(days))),
+            None => None,
+        };
 
-        let local_path = PathBuf::from(local_path);
+        let (secret, token_hash) = generate_secret(PERSONAL_ACCESS_TOKEN_PREFIX);
 
-        Self::make_global_mirror(
-            context,
+        let token = PersonalAccessToken {
+            id: PersonalAccessTokenId::new(),
+            user: auth.claims.sub,
             name,
-            local_path.to_str().expect("Invalid path").to_owned(),
-        )
-        .await
+            token_hash,
+            scopes,
+            repos: repos.unwrap_or_default(),
+            expires_at,
+        };
+
+        let token_clone = token.clone();
+        context
+            .query(|qm| async move { qm.create_personal_access_token(token_clone).await })
+            .await?;
+
+        Ok(tokens::CreatedPersonalAccessToken {
+            token: tokens::PersonalAccessTokenRef(token),
+            secret,
+        })
     }
 
-    async fn add_user_repo_perms(
+    async fn revoke_personal_access_token(
         context: &GraphQLContext,
-        repo: RepoId,
-        user: UserId,
-        perms: RepoPermissions,
-    ) -> FieldResult<RepoPermissions> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+        token: PersonalAccessTokenId,
+    ) -> FieldResult<bool> {
+        let auth = context.session_auth()?;
 
-        let repo = context
-            .query(|qm| async move { qm.query_repo(repo).await })
+        let revoked = context
+            .query(|qm| async move {
+                qm.delete_personal_access_token(auth.claims.sub, token)
+                    .await
+            })
             .await?;
 
-        if repo.namespace != NamespaceId::User(auth.claims.sub) {
-            Err(Error::Forbidden)?;
-        }
+        Ok(revoked)
+    }
 
-        context.init_repo_user_permissions(repo.id, user).await?;
+    // Without an organization, only instance admins can invite people;
+    // organization owners can invite people into their organization.
+    async fn create_invite(
+        context: &GraphQLContext,
+        organization_id: Option<OrganizationId>,
+    ) -> FieldResult<invites::CreatedInvite> {
+        let created_by = match organization_id {
+            Some(organization_id) => {
+                let org = context.query_org(organization_id).await?.0;
+                context.require_org_admin(&org).await?;
+
+                context.auth_with_scope(TokenScope::AdminOrg)?.claims.sub
+            }
+            None => context.require_admin().await?.claims.sub,
+        };
 
-        let new_perms = context
-            .query(|qm| async move { qm.add_repo_user_perms(repo.id, user, perms).await })
+        let (code, code_hash) = generate_secret(INVITE_CODE_PREFIX);
+        let lifetime = Duration::from_std(context.users_config.register.invite_lifetime)
+            .unwrap_or_else(|_| Duration::days(7));
+
+        let invite = Invite {
+            id: InviteId::new(),
+            code_hash,
+            created_by,
+            organization: organization_id,
+            expires_at: Utc::now().naive_utc() + lifetime,
+            used_by: None,
+        };
+
+        let invite_clone = invite.clone();
+        context
+            .query(|qm| async move { qm.create_invite(invite_clone).await })
             .await?;
 
-        Ok(new_perms)
+        Ok(invites::CreatedInvite {
+            invite: invites::InviteRef(invite),
+            code,
+        })
     }
 
-    async fn rm_user_repo_perms(
-        context: &GraphQLContext,
-        repo: RepoId,
-        user: UserId,
-        perms: RepoPermissions,
-    ) -> FieldResult<RepoPermissions> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+    async fn revoke_invite(context: &GraphQLContext, invite: InviteId) -> FieldResult<bool> {
+        let Some(found) = context
+            .query(|qm| async move { qm.query_invites().await })
+            .await?
+            .into_iter()
+            .find(|it| it.id == invite)
+        else {
+            return Ok(false);
+        };
 
-        let repo = context
-            .query(|qm| async move { qm.query_repo(repo).await })
-            .await?;
+        match found.organization {
+            Some(organization_id) => {
+                let org = context.query_org(organization_id).await?.0;
+                context.require_org_admin(&org).await?;
+            }
+            None => {
+                context.require_admin().await?;
+            }
+        }
 
-        if repo.namespace != NamespaceId::User(auth.claims.sub) {
-            Err(Error::Forbidden)?;
+        context
+            .query(|qm| async move { qm.delete_invite(invite).await })
+            .await
+    }
+
+    // Blocked users lose their permissions on the repos of the current user,
+    // and cannot be given new ones.
+    async fn block_user(context: &GraphQLContext, user: UserId) -> FieldResult<bool> {
+        let blocker = context.session_auth()?.claims.sub;
+
+        if blocker == user {
+            Err(Error::CannotBlockSelf)?;
         }
 
-        context.init_repo_user_permissions(repo.id, user).await?;
+        // make sure the user exists
+        context.query_user(user).await?;
 
-        let new_perms = context
-            .query(|qm| async move { qm.remove_repo_user_perms(repo.id, user, perms).await })
+        let blocked = context
+            .query(|qm| async move { qm.block_user(blocker, user).await })
             .await?;
 
-        Ok(new_perms)
-    }
+        let repos = context
+            .query(|qm| async move { qm.query_repos().await })
+            .await?;
 
-    async fn add_user_ssh_key(context: &GraphQLContext, key: String) -> FieldResult<bool> {
-        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+        for repo in repos
+            .into_iter()
+            .filter(|repo| repo.namespace.0 == NamespaceId::User(blocker))
+        {
+            context
+                .query(|qm| async move { qm.set_repo_user_perms(repo.id, user, None).await })
+                .await?;
+        }
 
-        let key = key.parse::<UserSshKey>()?;
+        Ok(blocked)
+    }
 
-        let result = context
-            .query(|qm| async move { qm.add_user_ssh_key(auth.claims.sub, key).await })
-            .await?;
+    async fn unblock_user(context: &GraphQLContext, user: UserId) -> FieldResult<bool> {
+        let blocker = context.session_auth()?.claims.sub;
 
-        Ok(result)
+        context
+            .query(|qm| async move { qm.unblock_user(blocker, user).await })
+            .await
     }
 }
 
@@ -1016,6 +3199,19 @@ impl UserRef {
         self.0.display_name.as_ref()
     }
 
+    // whether the user is an instance admin
+    fn admin(&self) -> bool {
+        self.0.admin
+    }
+
+    fn suspended(&self) -> bool {
+        self.0.suspended
+    }
+
+    fn pending_approval(&self) -> bool {
+        self.0.pending_approval
+    }
+
     async fn repo(&self, context: &GraphQLContext, name: RepoName) -> FieldResult<Option<RepoRef>> {
         context
             .query(|qm| async move {
@@ -1035,44 +3231,75 @@ impl UserRef {
             .await
             .map(|v| v.wrap(OrganizationMemberRef))
     }
+
+    // only visible to the user themselves
+    async fn sessions(&self, context: &GraphQLContext) -> FieldResult<Vec<sessions::SessionRef>> {
+        let auth = context.session_auth()?;
+
+        if auth.claims.sub != self.0.id {
+            Err(Error::Forbidden)?;
+        }
+
+        context
+            .query(|qm| async move { qm.query_user_sessions(self.0.id).await })
+            .await
+            .map(|v| v.wrap(sessions::SessionRef))
+    }
+
+    // only visible to the user themselves
+    async fn two_factor_enabled(&self, context: &GraphQLContext) -> FieldResult<bool> {
+        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+
+        if auth.claims.sub != self.0.id {
+            Err(Error::Forbidden)?;
+        }
+
+        Ok(context.confirmed_two_factor(self.0.id).await?.is_some())
+    }
+
+    // only visible to the user themselves
+    async fn personal_access_tokens(
+        &self,
+        context: &GraphQLContext,
+    ) -> FieldResult<Vec<tokens::PersonalAccessTokenRef>> {
+        let auth = context.session_auth()?;
+
+        if auth.claims.sub != self.0.id {
+            Err(Error::Forbidden)?;
+        }
+
+        context
+            .query(|qm| async move { qm.query_user_personal_access_tokens(self.0.id).await })
+            .await
+            .map(|v| v.wrap(tokens::PersonalAccessTokenRef))
+    }
+
+    // only visible to the user themselves
+    async fn blocked_users(&self, context: &GraphQLContext) -> FieldResult<Vec<UserRef>> {
+        let auth = context.session_auth()?;
+
+        if auth.claims.sub != self.0.id {
+            Err(Error::Forbidden)?;
+        }
+
+        let blocked = context
+            .query(|qm| async move { qm.query_blocked_users(self.0.id).await })
+            .await?;
+
+        let mut users = Vec::with_capacity(blocked.len());
+        for user_id in blocked {
+            users.push(context.query_user(user_id).await?);
+        }
+
+        Ok(users)
+    }
 }
 
 struct RepoRef(Repo);
 
 impl RepoRef {
     async fn ns_path(&self, qm: DataQueryMaster<'_>) -> Result<PathBuf, CommonDataClientError> {
-        let res = match self.0.namespace.0 {
-            NamespaceId::GlobalNamespace => {
-                let mut pb = PathBuf::new();
-                pb.push(self.0.name.as_str());
-                pb
-            }
-            NamespaceId::User(user) => {
-                let user = qm.query_user(user).await?;
-                let mut pb = PathBuf::new();
-                pb.push(user.username.as_str());
-                pb.push(self.0.name.as_str());
-                pb
-            }
-            NamespaceId::Organization(org) => {
-                let org = qm.query_organization(org).await?;
-                let mut pb = PathBuf::new();
-                pb.push(org.name.as_str());
-                pb.push(self.0.name.as_str());
-                pb
-            }
-            NamespaceId::Team(org, team) => {
-                let team = qm.query_team(team).await?;
-                let org = qm.query_organization(org).await?;
-                let mut pb = PathBuf::new();
-                pb.push(org.name.as_str());
-                pb.push(team.name.as_str());
-                pb.push(self.0.name.as_str());
-                pb
-            }
-        };
-
-        Ok(res)
+        upsilon_vcs_permissions::repo_path(&qm, &self.0).await
     }
 }
 
@@ -1116,6 +3343,133 @@ impl RepoRef {
         &self.0.name
     }
 
+    fn display_name(&self) -> Option<&RepoDisplayName> {
+        self.0.display_name.as_ref()
+    }
+
+    fn description(&self) -> Option<&RepoDescription> {
+        self.0.description.as_ref()
+    }
+
+    fn website(&self) -> Option<&RepoWebsite> {
+        self.0.website.as_ref()
+    }
+
+    fn topics(&self) -> &Vec<RepoTopic> {
+        &self.0.topics
+    }
+
+    fn default_branch(&self) -> Option<&RepoBranchName> {
+        self.0.default_branch.as_ref()
+    }
+
+    // what users without any other access to the repo can do
+    fn global_permissions(&self) -> RepoPermissions {
+        self.0.repo_config.global_permissions
+    }
+
+    fn branch_protection_rules(&self) -> Vec<permissions::BranchProtectionRuleRef> {
+        self.0
+            .repo_config
+            .protected_branches
+            .iter()
+            .cloned()
+            .map(permissions::BranchProtectionRuleRef)
+            .collect()
+    }
+
+    fn tag_protection_rules(&self) -> Vec<permissions::TagProtectionRuleRef> {
+        self.0
+            .repo_config
+            .protected_tags
+            .iter()
+            .cloned()
+            .map(permissions::TagProtectionRuleRef)
+            .collect()
+    }
+
+    async fn permission_explain(
+        &self,
+        context: &GraphQLContext,
+        user: UserId,
+        ref_name: Option<String>,
+    ) -> FieldResult<permissions::PermissionExplanationRef> {
+        let auth = context.auth.as_ref().ok_or(Error::Unauthorized)?;
+
+        if auth.claims.sub != user {
+            context.require_repo_admin(&self.0).await?;
+        }
+
+        let explanation = context
+            .query(|qm| async move {
+                upsilon_vcs_permissions::explain_permissions(&self.0, &qm, Some(user)).await
+            })
+            .await?;
+
+        let blocking_rules = match &ref_name {
+            Some(ref_name) => context
+                .query(|qm| async move {
+                    upsilon_vcs_permissions::blocking_branch_protection_rules(
+                        &self.0,
+                        &qm,
+                        Some(user),
+                        ref_name,
+                        explanation.permissions,
+                    )
+                    .await
+                })
+                .await?
+                .into_iter()
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(permissions::PermissionExplanationRef {
+            explanation,
+            blocking_rules,
+        })
+    }
+
+    async fn team_grants(&self, context: &GraphQLContext) -> FieldResult<Vec<TeamRepoGrantRef>> {
+        let grants = context
+            .query(|qm| async move { qm.query_repo_team_perms(self.0.id).await })
+            .await?;
+
+        Ok(grants
+            .into_iter()
+            .map(|(team_id, perms)| TeamRepoGrantRef {
+                repo_id: self.0.id,
+                team_id,
+                perms,
+            })
+            .collect())
+    }
+
+    // only visible to repo admins
+    async fn deploy_keys(
+        &self,
+        context: &GraphQLContext,
+    ) -> FieldResult<Vec<deploy_keys::DeployKeyRef>> {
+        context.require_repo_admin(&self.0).await?;
+
+        let keys = context
+            .query(|qm| async move { qm.query_repo_deploy_keys(self.0.id).await })
+            .await?;
+
+        Ok(keys.wrap(deploy_keys::DeployKeyRef))
+    }
+
+    async fn viewer_role(&self, context: &GraphQLContext) -> FieldResult<Option<RepoRole>> {
+        let user = context.auth.as_ref().map(|auth| auth.claims.sub);
+
+        context
+            .query(|qm| async move {
+                upsilon_vcs_permissions::effective_role(&self.0, &qm, user).await
+            })
+            .await
+    }
+
     async fn path(&self, context: &GraphQLContext) -> FieldResult<String> {
         let path = context
             .query(|qm| async move { Self::ns_path(self, qm).await })
@@ -1181,19 +3535,47 @@ impl OrganizationRef {
         &self.0.name
     }
 
+    // pending invites into the organization; only for its owners and admins
+    async fn invites(&self, context: &GraphQLContext) -> FieldResult<Vec<invites::InviteRef>> {
+        context.require_org_admin(&self.0).await?;
+
+        let now = Utc::now().naive_utc();
+
+        Ok(context
+            .query(|qm| async move { qm.query_invites().await })
+            .await?
+            .into_iter()
+            .filter(|invite| invite.organization == Some(self.0.id) && invite.is_usable(now))
+            .wrap(invites::InviteRef))
+    }
+
     fn display_name(&self) -> Option<&OrganizationDisplayName> {
         self.0.display_name.as_ref()
     }
 
-    fn owner_id(&self) -> UserId {
-        self.0.owner
+    fn email(&self) -> Option<&Email> {
+        self.0.email.as_ref()
     }
 
-    async fn owner(&self, context: &GraphQLContext) -> FieldResult<UserRef> {
-        context
-            .query(|qm| async move { qm.query_user(self.0.owner).await })
-            .await
-            .map(UserRef)
+    // the role members get on all the repos of the organization
+    fn base_role(&self) -> Option<RepoRole> {
+        self.0.base_role
+    }
+
+    async fn owners(&self, context: &GraphQLContext) -> FieldResult<Vec<UserRef>> {
+        let mut owners = Vec::new();
+        for user_id in context.org_owners(self.0.id).await? {
+            owners.push(context.query_user(user_id).await?);
+        }
+
+        Ok(owners)
+    }
+
+    async fn viewer_role(&self, context: &GraphQLContext) -> FieldResult<Option<OrganizationRole>> {
+        match &context.auth {
+            Some(auth) => context.org_role(self.0.id, auth.claims.sub).await,
+            None => Ok(None),
+        }
     }
 
     async fn members(&self, context: &GraphQLContext) -> FieldResult<Vec<OrganizationMemberRef>> {
@@ -1221,6 +3603,45 @@ impl OrganizationRef {
     }
 }
 
+pub struct TeamRepoGrantRef {
+    repo_id: RepoId,
+    team_id: TeamId,
+    perms: RepoPermissions,
+}
+
+#[graphql_object(name = "TeamRepoGrant", context = GraphQLContext)]
+impl TeamRepoGrantRef {
+    fn repo_id(&self) -> RepoId {
+        self.repo_id
+    }
+
+    fn team_id(&self) -> TeamId {
+        self.team_id
+    }
+
+    fn permissions(&self) -> RepoPermissions {
+        self.perms
+    }
+
+    fn role(&self) -> Option<RepoRole> {
+        RepoRole::from_permissions(self.perms)
+    }
+
+    async fn repo(&self, context: &GraphQLContext) -> FieldResult<RepoRef> {
+        context
+            .query(|qm| async move { qm.query_repo(self.repo_id).await })
+            .await
+            .map(RepoRef)
+    }
+
+    async fn team(&self, context: &GraphQLContext) -> FieldResult<TeamRef> {
+        context
+            .query(|qm| async move { qm.query_team(self.team_id).await })
+            .await
+            .map(TeamRef)
+    }
+}
+
 trait Wrap {
     type Item;
     fn wrap<T, F>(self, f: F) -> Vec<T>
@@ -1254,6 +3675,10 @@ impl OrganizationMemberRef {
         self.0.organization_id
     }
 
+    fn role(&self) -> OrganizationRole {
+        self.0.role
+    }
+
     fn team_ids(&self) -> &Vec<TeamId> {
         &self.0.teams
     }
@@ -1336,14 +3761,62 @@ impl TeamRef {
         context.query_org(self.0.organization_id).await
     }
 
-    async fn members(&self, context: &GraphQLContext) -> FieldResult<Vec<OrganizationMemberRef>> {
+    fn parent_id(&self) -> Option<TeamId> {
+        self.0.parent
+    }
+
+    async fn parent(&self, context: &GraphQLContext) -> FieldResult<Option<TeamRef>> {
+        let Some(parent) = self.0.parent else {
+            return Ok(None);
+        };
+
         context
-            .query(|qm| async move {
-                qm.query_team_members(self.0.organization_id, self.0.id)
-                    .await
-            })
+            .query(|qm| async move { qm.query_team(parent).await })
             .await
-            .map(|v| v.wrap(OrganizationMemberRef))
+            .map(|team| Some(TeamRef(team)))
+    }
+
+    // the teams nested directly inside this one
+    async fn children(&self, context: &GraphQLContext) -> FieldResult<Vec<TeamRef>> {
+        let teams = context
+            .query(|qm| async move { qm.query_organization_teams(self.0.organization_id).await })
+            .await?;
+
+        Ok(teams
+            .into_iter()
+            .filter(|team| team.parent == Some(self.0.id))
+            .map(TeamRef)
+            .collect())
+    }
+
+    // with `includeSubTeams`, also the members of the teams nested inside
+    // this one, who inherit its repo access
+    #[graphql(arguments(include_sub_teams(default = false)))]
+    async fn members(
+        &self,
+        context: &GraphQLContext,
+        include_sub_teams: bool,
+    ) -> FieldResult<Vec<OrganizationMemberRef>> {
+        if !include_sub_teams {
+            return context
+                .query(|qm| async move {
+                    qm.query_team_members(self.0.organization_id, self.0.id)
+                        .await
+                })
+                .await
+                .map(|v| v.wrap(OrganizationMemberRef));
+        }
+
+        let teams = context.team_and_sub_teams(&self.0).await?;
+        let members = context
+            .query(|qm| async move { qm.query_organization_members(self.0.organization_id).await })
+            .await?;
+
+        Ok(members
+            .into_iter()
+            .filter(|member| member.teams.iter().any(|team| teams.contains(team)))
+            .map(OrganizationMemberRef)
+            .collect())
     }
 
     async fn repo(&self, context: &GraphQLContext, name: RepoName) -> FieldResult<Option<RepoRef>> {
@@ -1358,4 +3831,19 @@ impl TeamRef {
             .await
             .map(|opt| opt.map(RepoRef))
     }
+
+    async fn repo_grants(&self, context: &GraphQLContext) -> FieldResult<Vec<TeamRepoGrantRef>> {
+        let grants = context
+            .query(|qm| async move { qm.query_team_repo_perms(self.0.id).await })
+            .await?;
+
+        Ok(grants
+            .into_iter()
+            .map(|(repo_id, perms)| TeamRepoGrantRef {
+                repo_id,
+                team_id: self.0.id,
+                perms,
+            })
+            .collect())
+    }
 }
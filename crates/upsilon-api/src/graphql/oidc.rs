@@ -1,5 +1,5 @@
 /*
- *        Copyright (c) 2023 Dinu Blanovschi
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
  *
  *    Licensed under the Apache License, Version 2.0 (the "License");
  *    you may not use this file except in compliance with the License.
@@ -14,19 +14,23 @@
  *    limitations under the License.
  */
 
-fn main() {
-    let k = russh_keys::key::KeyPair::generate_rsa(2048, russh_keys::key::SignatureHash::SHA2_512)
-        .unwrap();
+use juniper::graphql_object;
 
-    let kpub = k.clone_public_key().unwrap();
+use super::GraphQLContext;
 
-    let mut k2 = k.clone();
+pub struct OidcProviderRef {
+    pub(crate) name: String,
+    pub(crate) display_name: Option<String>,
+}
 
-    if let russh_keys::key::KeyPair::RSA { ref mut hash, .. } = k2 {
-        *hash = russh_keys::key::SignatureHash::SHA2_256;
+#[graphql_object(name = "OidcProvider", context = GraphQLContext)]
+impl OidcProviderRef {
+    // pass this to `beginOidcLogin`
+    fn name(&self) -> &str {
+        &self.name
     }
 
-    let kpub2 = k2.clone_public_key().unwrap();
-
-    assert_ne!(kpub, kpub2); // should be eq, but are not because of the hash
+    fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
 }
@@ -0,0 +1,37 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::graphql_object;
+
+use super::GraphQLContext;
+
+pub struct PasswordHashReport {
+    pub(crate) users: usize,
+    pub(crate) outdated: usize,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl PasswordHashReport {
+    fn users(&self) -> i32 {
+        self.users as i32
+    }
+
+    // hashed with another algorithm or other parameters than the configured ones;
+    // they are hashed again the next time the user logs in with their password
+    fn outdated(&self) -> i32 {
+        self.outdated as i32
+    }
+}
@@ -0,0 +1,233 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::{graphql_object, FieldResult, GraphQLEnum, GraphQLInputObject};
+use upsilon_models::organization::{OrganizationId, OrganizationRole, TeamId};
+use upsilon_models::repo::{BranchProtectionRule, RepoPermissions, RepoRole, TagProtectionRule};
+use upsilon_models::users::UserId;
+use upsilon_vcs::upsilon_git_hooks::repo_config::validate_branch_pattern;
+use upsilon_vcs_permissions::{PermissionGrant, PermissionSource, PermissionsExplanation};
+
+use super::{GraphQLContext, OrganizationRef, TeamRef};
+
+pub struct PermissionExplanationRef {
+    pub(crate) explanation: PermissionsExplanation,
+    pub(crate) blocking_rules: Vec<BranchProtectionRule>,
+}
+
+#[graphql_object(name = "PermissionExplanation", context = GraphQLContext)]
+impl PermissionExplanationRef {
+    fn permissions(&self) -> RepoPermissions {
+        self.explanation.permissions
+    }
+
+    fn role(&self) -> Option<RepoRole> {
+        self.explanation.role()
+    }
+
+    fn sources(&self) -> Vec<PermissionGrantRef> {
+        self.explanation
+            .grants
+            .iter()
+            .cloned()
+            .map(PermissionGrantRef)
+            .collect()
+    }
+
+    fn blocking_rules(&self) -> Vec<BranchProtectionRuleRef> {
+        self.blocking_rules
+            .iter()
+            .cloned()
+            .map(BranchProtectionRuleRef)
+            .collect()
+    }
+}
+
+#[derive(GraphQLEnum)]
+pub enum PermissionSourceKind {
+    Global,
+    Direct,
+    Team,
+    OrganizationBaseRole,
+    OrganizationRole,
+    NamespaceOwnership,
+}
+
+pub struct PermissionGrantRef(PermissionGrant);
+
+impl PermissionGrantRef {
+    fn source_team_id(&self) -> Option<TeamId> {
+        match self.0.source {
+            PermissionSource::Team(team_id) => Some(team_id),
+            _ => None,
+        }
+    }
+
+    fn source_organization_id(&self) -> Option<OrganizationId> {
+        match self.0.source {
+            PermissionSource::OrganizationBaseRole(org_id)
+            | PermissionSource::OrganizationRole(org_id, _) => Some(org_id),
+            _ => None,
+        }
+    }
+}
+
+#[graphql_object(name = "PermissionGrant", context = GraphQLContext)]
+impl PermissionGrantRef {
+    fn kind(&self) -> PermissionSourceKind {
+        match self.0.source {
+            PermissionSource::Global => PermissionSourceKind::Global,
+            PermissionSource::Direct => PermissionSourceKind::Direct,
+            PermissionSource::Team(_) => PermissionSourceKind::Team,
+            PermissionSource::OrganizationBaseRole(_) => PermissionSourceKind::OrganizationBaseRole,
+            PermissionSource::OrganizationRole(..) => PermissionSourceKind::OrganizationRole,
+            PermissionSource::NamespaceOwnership => PermissionSourceKind::NamespaceOwnership,
+        }
+    }
+
+    fn permissions(&self) -> RepoPermissions {
+        self.0.permissions
+    }
+
+    fn role(&self) -> Option<RepoRole> {
+        RepoRole::from_permissions(self.0.permissions)
+    }
+
+    fn team_id(&self) -> Option<TeamId> {
+        self.source_team_id()
+    }
+
+    async fn team(&self, context: &GraphQLContext) -> FieldResult<Option<TeamRef>> {
+        let team_id = match self.source_team_id() {
+            Some(team_id) => team_id,
+            None => return Ok(None),
+        };
+
+        context
+            .query(|qm| async move { qm.query_team(team_id).await })
+            .await
+            .map(|team| Some(TeamRef(team)))
+    }
+
+    fn organization_id(&self) -> Option<OrganizationId> {
+        self.source_organization_id()
+    }
+
+    // the role in the organization the permissions come from
+    fn organization_role(&self) -> Option<OrganizationRole> {
+        match self.0.source {
+            PermissionSource::OrganizationRole(_, role) => Some(role),
+            _ => None,
+        }
+    }
+
+    async fn organization(&self, context: &GraphQLContext) -> FieldResult<Option<OrganizationRef>> {
+        match self.source_organization_id() {
+            Some(org_id) => context.query_org(org_id).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct BranchProtectionRuleRef(pub(crate) BranchProtectionRule);
+
+#[graphql_object(name = "BranchProtectionRule", context = GraphQLContext)]
+impl BranchProtectionRuleRef {
+    fn pattern(&self) -> &str {
+        &self.0.pattern
+    }
+
+    fn needs_admin(&self) -> bool {
+        self.0.needs_admin
+    }
+
+    fn block_force_push(&self) -> bool {
+        self.0.block_force_push
+    }
+
+    fn block_deletion(&self) -> bool {
+        self.0.block_deletion
+    }
+
+    fn require_linear_history(&self) -> bool {
+        self.0.require_linear_history
+    }
+
+    fn push_user_ids(&self) -> &Vec<UserId> {
+        &self.0.push_users
+    }
+
+    fn push_team_ids(&self) -> &Vec<TeamId> {
+        &self.0.push_teams
+    }
+}
+
+#[derive(GraphQLInputObject)]
+pub struct BranchProtectionRuleInput {
+    pattern: String,
+    needs_admin: Option<bool>,
+    block_force_push: Option<bool>,
+    block_deletion: Option<bool>,
+    require_linear_history: Option<bool>,
+    push_users: Option<Vec<UserId>>,
+    push_teams: Option<Vec<TeamId>>,
+}
+
+impl BranchProtectionRuleInput {
+    pub(crate) fn into_rule(self) -> FieldResult<BranchProtectionRule> {
+        validate_branch_pattern(&self.pattern)?;
+
+        Ok(BranchProtectionRule {
+            pattern: self.pattern,
+            needs_admin: self.needs_admin.unwrap_or_default(),
+            block_force_push: self.block_force_push.unwrap_or_default(),
+            block_deletion: self.block_deletion.unwrap_or_default(),
+            require_linear_history: self.require_linear_history.unwrap_or_default(),
+            push_users: self.push_users.unwrap_or_default(),
+            push_teams: self.push_teams.unwrap_or_default(),
+        })
+    }
+}
+
+pub struct TagProtectionRuleRef(pub(crate) TagProtectionRule);
+
+#[graphql_object(name = "TagProtectionRule", context = GraphQLContext)]
+impl TagProtectionRuleRef {
+    fn pattern(&self) -> &str {
+        &self.0.pattern
+    }
+
+    fn allowed_user_ids(&self) -> &Vec<UserId> {
+        &self.0.allowed_users
+    }
+}
+
+#[derive(GraphQLInputObject)]
+pub struct TagProtectionRuleInput {
+    pattern: String,
+    allowed_users: Option<Vec<UserId>>,
+}
+
+impl TagProtectionRuleInput {
+    pub(crate) fn into_rule(self) -> FieldResult<TagProtectionRule> {
+        validate_branch_pattern(&self.pattern)?;
+
+        Ok(TagProtectionRule {
+            pattern: self.pattern,
+            allowed_users: self.allowed_users.unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,187 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use juniper::{graphql_object, FieldResult};
+use upsilon_models::repo::{
+    BranchProtectionRule, Repo, RepoDisplayName, RepoId, RepoName, RepoPermissions, TagProtectionRule
+};
+
+use super::permissions::{BranchProtectionRuleInput, TagProtectionRuleInput};
+use super::{GraphQLContext, RepoRef};
+use crate::error::Error;
+
+// Only handed out by `MutationRoot::update_repo_settings`, after checking
+// that the current user is an admin of the repo.
+pub struct RepoSettingsMutation(pub(super) RepoId);
+
+impl RepoSettingsMutation {
+    /// The repo as it is now, which is not necessarily how it was when
+    /// the settings were requested.
+    async fn repo(&self, context: &GraphQLContext) -> FieldResult<Repo> {
+        let repo_id = self.0;
+
+        context
+            .query(|qm| async move { qm.query_repo(repo_id).await })
+            .await
+    }
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl RepoSettingsMutation {
+    // Moves the directory of the repo too. The old name keeps pointing
+    // to the repo, until another repo takes it.
+    async fn rename(&self, context: &GraphQLContext, name: RepoName) -> FieldResult<RepoRef> {
+        // anything else would put the repo somewhere other than next to
+        // where it is now, or outside of the repos directory altogether
+        if Path::new(name.as_str()).file_name() != Some(OsStr::new(name.as_str())) {
+            Err(Error::InvalidRepoName)?;
+        }
+
+        let repo = self.repo(context).await?;
+        let old_dir = context.vcs_config.repo_dir(
+            RepoRef(repo.clone())
+                .ns_path(context.db.query_master())
+                .await?,
+        );
+
+        let renamed = RepoRef(Repo {
+            name: name.clone(),
+            ..repo.clone()
+        });
+        let new_dir = context
+            .vcs_config
+            .repo_dir(renamed.ns_path(context.db.query_master()).await?);
+
+        if new_dir.exists() {
+            Err(Error::RepoAlreadyExists)?;
+        }
+
+        // the directory is moved first, so that if that fails, the repo
+        // does not get a previous name it never really gave up.
+        tokio::fs::rename(&old_dir, &new_dir).await?;
+
+        let renamed_in_db = context
+            .query(|qm| async move { qm.set_repo_name(repo.id, name).await })
+            .await;
+
+        if let Err(e) = renamed_in_db {
+            tokio::fs::rename(&new_dir, &old_dir).await?;
+
+            Err(e)?;
+        }
+
+        Ok(renamed)
+    }
+
+    async fn set_display_name(
+        &self,
+        context: &GraphQLContext,
+        display_name: Option<RepoDisplayName>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = self.repo(context).await?;
+
+        let display_name_clone = display_name.clone();
+        context
+            .query(|qm| async move { qm.set_repo_display_name(repo.id, display_name_clone).await })
+            .await?;
+
+        repo.display_name = display_name;
+
+        Ok(RepoRef(repo))
+    }
+
+    // What users without any other access to the repo can do.
+    async fn set_global_permissions(
+        &self,
+        context: &GraphQLContext,
+        perms: RepoPermissions,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = self.repo(context).await?;
+
+        context
+            .query(|qm| async move { qm.set_repo_global_permissions(repo.id, perms).await })
+            .await?;
+
+        repo.repo_config.global_permissions = perms;
+
+        Ok(RepoRef(repo))
+    }
+
+    // Replaces all the branch protection rules; of several rules with the
+    // same pattern, the last one is kept.
+    async fn set_protected_branches(
+        &self,
+        context: &GraphQLContext,
+        rules: Vec<BranchProtectionRuleInput>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = self.repo(context).await?;
+
+        let mut new_rules = Vec::<BranchProtectionRule>::new();
+        for rule in rules {
+            let rule = rule.into_rule()?;
+
+            match new_rules.iter_mut().find(|it| it.pattern == rule.pattern) {
+                Some(existing) => *existing = rule,
+                None => new_rules.push(rule),
+            }
+        }
+
+        let rules = new_rules.clone();
+        context
+            .query(|qm| async move { qm.set_repo_protected_branches(repo.id, rules).await })
+            .await?;
+
+        repo.repo_config.protected_branches = new_rules;
+
+        context.update_hook_repo_config(&repo).await?;
+
+        Ok(RepoRef(repo))
+    }
+
+    // Replaces all the tag protection rules; of several rules with the
+    // same pattern, the last one is kept.
+    async fn set_protected_tags(
+        &self,
+        context: &GraphQLContext,
+        rules: Vec<TagProtectionRuleInput>,
+    ) -> FieldResult<RepoRef> {
+        let mut repo = self.repo(context).await?;
+
+        let mut new_rules = Vec::<TagProtectionRule>::new();
+        for rule in rules {
+            let rule = rule.into_rule()?;
+
+            match new_rules.iter_mut().find(|it| it.pattern == rule.pattern) {
+                Some(existing) => *existing = rule,
+                None => new_rules.push(rule),
+            }
+        }
+
+        let rules = new_rules.clone();
+        context
+            .query(|qm| async move { qm.set_repo_protected_tags(repo.id, rules).await })
+            .await?;
+
+        repo.repo_config.protected_tags = new_rules;
+
+        context.update_hook_repo_config(&repo).await?;
+
+        Ok(RepoRef(repo))
+    }
+}
@@ -0,0 +1,80 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::graphql_object;
+use upsilon_models::users::sessions::{Session, SessionId};
+
+use super::{format_timestamp, GraphQLContext};
+
+pub struct SessionRef(pub(crate) Session);
+
+#[graphql_object(name = "Session", context = GraphQLContext)]
+impl SessionRef {
+    fn id(&self) -> SessionId {
+        self.0.id
+    }
+
+    fn user_agent(&self) -> Option<&str> {
+        self.0.user_agent.as_deref()
+    }
+
+    fn ip(&self) -> Option<&str> {
+        self.0.ip.as_deref()
+    }
+
+    fn created_at(&self) -> String {
+        format_timestamp(self.0.created_at)
+    }
+
+    // the last time the session was refreshed
+    fn last_seen_at(&self) -> String {
+        format_timestamp(self.0.last_seen_at)
+    }
+
+    fn expires_at(&self) -> String {
+        format_timestamp(self.0.expires_at)
+    }
+
+    // whether this is the session of the token used for this request
+    fn current(&self, context: &GraphQLContext) -> bool {
+        context
+            .auth
+            .as_ref()
+            .map_or(false, |auth| auth.claims.sid == Some(self.0.id))
+    }
+}
+
+pub struct SessionTokens {
+    pub(crate) session: SessionRef,
+    pub(crate) access_token: String,
+    pub(crate) refresh_token: String,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl SessionTokens {
+    fn session(&self) -> &SessionRef {
+        &self.session
+    }
+
+    fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    // can only be used once; refreshing returns a new one
+    fn refresh_token(&self) -> &str {
+        &self.refresh_token
+    }
+}
@@ -0,0 +1,63 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::graphql_object;
+use upsilon_models::repo::RepoId;
+use upsilon_models::users::tokens::{PersonalAccessToken, PersonalAccessTokenId, TokenScope};
+
+use super::{format_timestamp, GraphQLContext};
+
+pub struct PersonalAccessTokenRef(pub(crate) PersonalAccessToken);
+
+#[graphql_object(name = "PersonalAccessToken", context = GraphQLContext)]
+impl PersonalAccessTokenRef {
+    fn id(&self) -> PersonalAccessTokenId {
+        self.0.id
+    }
+
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn scopes(&self) -> &Vec<TokenScope> {
+        &self.0.scopes
+    }
+
+    fn repo_ids(&self) -> &Vec<RepoId> {
+        &self.0.repos
+    }
+
+    fn expires_at(&self) -> Option<String> {
+        self.0.expires_at.map(format_timestamp)
+    }
+}
+
+pub struct CreatedPersonalAccessToken {
+    pub(crate) token: PersonalAccessTokenRef,
+    pub(crate) secret: String,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl CreatedPersonalAccessToken {
+    fn token(&self) -> &PersonalAccessTokenRef {
+        &self.token
+    }
+
+    // only returned once; store it somewhere safe
+    fn secret(&self) -> &str {
+        &self.secret
+    }
+}
@@ -0,0 +1,62 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use juniper::graphql_object;
+
+use super::sessions::SessionTokens;
+use super::GraphQLContext;
+
+pub struct TwoFactorEnrollment {
+    pub(crate) secret: String,
+    pub(crate) otpauth_uri: String,
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl TwoFactorEnrollment {
+    // base32-encoded, for entering it by hand
+    fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    // for the QR code
+    fn otpauth_uri(&self) -> &str {
+        &self.otpauth_uri
+    }
+}
+
+// Exactly one of the fields is set.
+pub enum LoginStep {
+    Done(SessionTokens),
+    TwoFactorRequired(String),
+}
+
+#[graphql_object(context = GraphQLContext)]
+impl LoginStep {
+    fn tokens(&self) -> Option<&SessionTokens> {
+        match self {
+            LoginStep::Done(tokens) => Some(tokens),
+            LoginStep::TwoFactorRequired(_) => None,
+        }
+    }
+
+    // pass this along with the code to `completeTwoFactorLogin`
+    fn two_factor_token(&self) -> Option<&str> {
+        match self {
+            LoginStep::Done(_) => None,
+            LoginStep::TwoFactorRequired(token) => Some(token.as_str()),
+        }
+    }
+}
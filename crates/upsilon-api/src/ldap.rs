@@ -0,0 +1,242 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Checking passwords against an LDAP directory, by binding as the user.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use upsilon_core::config::{LdapAttributesConfig, LdapConfig, LdapDirectoryConfig};
+
+/// The `provider` of the [`ExternalIdentity`](upsilon_models::users::identities::ExternalIdentity)
+/// linking users to their directory entry.
+pub const LDAP_PROVIDER: &str = "ldap";
+
+/// The result code of a bind with the wrong password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LdapError {
+    #[error("ldap: {0}")]
+    Ldap(#[from] ldap3::LdapError),
+    #[error("The directory entry {0} has no {1} attribute")]
+    MissingAttribute(String, String),
+}
+
+/// A directory entry, with the attributes we asked for.
+struct LdapEntry {
+    dn: String,
+    attrs: BTreeMap<String, Vec<String>>,
+}
+
+impl LdapEntry {
+    /// Attribute names are case-insensitive.
+    fn attr(&self, name: &str) -> &[String] {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map_or(&[], |(_, v)| v.as_slice())
+    }
+
+    fn first_attr(&self, name: &str) -> Option<String> {
+        self.attr(name).first().cloned()
+    }
+}
+
+/// Who the directory says logged in.
+#[derive(Debug, Clone)]
+pub struct LdapIdentity {
+    pub dn: String,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    /// The DNs of the groups the user is in.
+    pub groups: Vec<String>,
+}
+
+impl LdapIdentity {
+    fn from_entry(entry: LdapEntry, attributes: &LdapAttributesConfig) -> Result<Self, LdapError> {
+        let username = entry.first_attr(&attributes.username).ok_or_else(|| {
+            LdapError::MissingAttribute(entry.dn.clone(), attributes.username.clone())
+        })?;
+
+        Ok(Self {
+            username,
+            display_name: entry.first_attr(&attributes.display_name),
+            email: entry.first_attr(&attributes.email),
+            groups: entry.attr(&attributes.groups).to_vec(),
+            dn: entry.dn,
+        })
+    }
+
+    /// DNs are compared case-insensitively.
+    pub fn is_in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|it| it.eq_ignore_ascii_case(group))
+    }
+}
+
+#[derive(Clone)]
+pub struct LdapContext {
+    config: Option<Arc<LdapConfig>>,
+}
+
+impl LdapContext {
+    pub fn new(config: Option<LdapConfig>) -> Self {
+        Self {
+            config: config.map(Arc::new),
+        }
+    }
+
+    pub fn config(&self) -> Option<&LdapConfig> {
+        self.config.as_deref()
+    }
+
+    /// Whether the password of `username` should be checked against the
+    /// directory, rather than the local one.
+    pub fn handles(&self, username: &str) -> bool {
+        self.config().map_or(false, |config| {
+            !config.local_accounts.iter().any(|it| it == username)
+        })
+    }
+
+    /// Checks the password of `username`, returning who they are if it is right.
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LdapIdentity>, LdapError> {
+        let Some(config) = self.config() else {
+            return Ok(None);
+        };
+
+        // a bind with an empty password is an unauthenticated bind,
+        // which servers usually accept for any DN
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let entry = match &config.directory {
+            LdapDirectoryConfig::Server {
+                url,
+                bind_dn,
+                bind_password,
+                base_dn,
+                user_filter,
+                starttls,
+            } => {
+                let server = LdapServer {
+                    url,
+                    bind_dn: bind_dn.as_deref(),
+                    bind_password: bind_password.as_deref(),
+                    base_dn,
+                    user_filter,
+                    starttls: *starttls,
+                };
+
+                server
+                    .bind_user(&config.attributes, username, password)
+                    .await?
+            }
+            LdapDirectoryConfig::Static { entries } => entries
+                .iter()
+                .filter(|entry| entry.password == password)
+                .map(|entry| LdapEntry {
+                    dn: entry.dn.clone(),
+                    attrs: entry.attributes.clone(),
+                })
+                .find(|entry| {
+                    entry
+                        .attr(&config.attributes.username)
+                        .iter()
+                        .any(|it| it == username)
+                }),
+        };
+
+        entry
+            .map(|entry| LdapIdentity::from_entry(entry, &config.attributes))
+            .transpose()
+    }
+}
+
+struct LdapServer<'a> {
+    url: &'a str,
+    bind_dn: Option<&'a str>,
+    bind_password: Option<&'a str>,
+    base_dn: &'a str,
+    user_filter: &'a str,
+    starttls: bool,
+}
+
+impl<'a> LdapServer<'a> {
+    async fn bind_user(
+        &self,
+        attributes: &LdapAttributesConfig,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LdapEntry>, LdapError> {
+        let settings = LdapConnSettings::new().set_starttls(self.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, self.url).await?;
+        ldap3::drive!(conn);
+
+        if let Some(bind_dn) = self.bind_dn {
+            ldap.simple_bind(bind_dn, self.bind_password.unwrap_or_default())
+                .await?
+                .success()?;
+        }
+
+        let filter = self
+            .user_filter
+            .replace("{username}", &ldap3::ldap_escape(username));
+
+        let (entries, _) = ldap
+            .search(
+                self.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![
+                    attributes.username.as_str(),
+                    attributes.display_name.as_str(),
+                    attributes.email.as_str(),
+                    attributes.groups.as_str(),
+                ],
+            )
+            .await?
+            .success()?;
+
+        // no entry, or an ambiguous filter
+        if entries.len() != 1 {
+            ldap.unbind().await?;
+            return Ok(None);
+        }
+
+        let entry = SearchEntry::construct(entries.into_iter().next().unwrap());
+
+        let bind = ldap.simple_bind(&entry.dn, password).await?;
+        ldap.unbind().await?;
+
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+
+        bind.success()?;
+
+        Ok(Some(LdapEntry {
+            dn: entry.dn,
+            attrs: entry.attrs.into_iter().collect(),
+        }))
+    }
+}
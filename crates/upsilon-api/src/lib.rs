@@ -22,23 +22,48 @@ use graphql::GraphQLContext;
 pub use graphql::UshArgs;
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::{Build, Rocket, State};
-use upsilon_core::config::Cfg;
+use upsilon_core::config::{Cfg, MailerConfig, OidcConfig, UsersAuthConfig};
+use upsilon_data::{CommonDataClientError, DataClientMasterHolder};
 
 use crate::auth::AuthContext;
+use crate::ldap::LdapContext;
+use crate::mailer::MailerHandle;
+use crate::oidc::OidcContext;
+use crate::two_factor::TwoFactorContext;
 
 pub mod auth;
 mod graphql;
+mod ldap;
+pub mod mailer;
+mod oidc;
+mod two_factor;
 
 mod entity_lookup_path;
 mod error;
 
 pub struct GraphQLApiConfigurator {
     ush_args: UshArgs,
+    auth: UsersAuthConfig,
+    admins: Vec<String>,
+    oidc: OidcConfig,
+    mailer: MailerConfig,
 }
 
 impl GraphQLApiConfigurator {
-    pub fn new(ush_args: UshArgs) -> Self {
-        Self { ush_args }
+    pub fn new(
+        ush_args: UshArgs,
+        auth: UsersAuthConfig,
+        admins: Vec<String>,
+        oidc: OidcConfig,
+        mailer: MailerConfig,
+    ) -> Self {
+        Self {
+            ush_args,
+            auth,
+            admins,
+            oidc,
+            mailer,
+        }
     }
 }
 
@@ -52,6 +77,37 @@ impl Fairing for GraphQLApiConfigurator {
     }
 
     async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let auth_context = match AuthContext::from_config(&self.auth.tokens) {
+            Ok(auth_context) => auth_context,
+            Err(e) => {
+                error!("Failed to set up auth token keys: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        let two_factor_context = match TwoFactorContext::from_config(&self.auth.two_factor) {
+            Ok(two_factor_context) => two_factor_context,
+            Err(e) => {
+                error!("Failed to set up the two-factor encryption key: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        if !self.admins.is_empty() {
+            let data = match rocket.state::<DataClientMasterHolder>() {
+                Some(data) => data,
+                None => {
+                    error!("Cannot set up the configured admins without a data backend");
+                    return Err(rocket);
+                }
+            };
+
+            if let Err(e) = bootstrap_admins(data, &self.admins).await {
+                error!("Failed to set up the configured admins: {}", e);
+                return Err(rocket);
+            }
+        }
+
         Ok(rocket
             .mount(
                 "/",
@@ -63,10 +119,35 @@ impl Fairing for GraphQLApiConfigurator {
                 graphql::MutationRoot,
                 graphql::SubscriptionRoot,
             ))
-            .manage(AuthContext::new(2048)))
+            .manage(auth_context)
+            .manage(two_factor_context)
+            .manage(OidcContext::new(self.oidc.clone()))
+            .manage(LdapContext::new(self.auth.ldap.clone()))
+            .manage(MailerHandle::from_config(&self.mailer)))
     }
 }
 
+/// Makes the existing users listed in `users.admins` instance admins.
+///
+/// Only accounts that exist at startup are affected; a listed username
+/// that is registered later does not get admin rights from the config.
+async fn bootstrap_admins(
+    data: &DataClientMasterHolder,
+    admins: &[String],
+) -> Result<(), CommonDataClientError> {
+    let qm = data.query_master();
+
+    for admin in admins {
+        match qm.query_user_by_username(admin.as_str()).await? {
+            Some(user) if !user.admin => qm.set_user_admin(user.id, true).await?,
+            Some(_) => {}
+            None => warn!("Configured admin `{}` does not exist, skipping", admin),
+        }
+    }
+
+    Ok(())
+}
+
 #[rocket::get("/graphiql")]
 fn graphiql() -> rocket::response::content::RawHtml<String> {
     juniper_rocket::graphiql_source("/graphql", None)
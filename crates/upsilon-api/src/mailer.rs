@@ -0,0 +1,107 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Sending emails, with a backend picked by [`MailerConfig`].
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use upsilon_core::config::MailerConfig;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailerError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[rocket::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError>;
+}
+
+/// Used when no mailer is configured, so nothing is actually sent.
+struct LogMailer;
+
+#[rocket::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError> {
+        warn!(
+            "No mailer configured, not sending \"{}\" to {}",
+            mail.subject, mail.to
+        );
+
+        Ok(())
+    }
+}
+
+/// Writes every mail to its own file in `dir`, in the order they were sent.
+pub struct FileMailer {
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            count: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailerError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let n = self.count.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.join(format!("{n:06}.eml"));
+
+        let Mail { to, subject, body } = mail;
+
+        tokio::fs::write(path, format!("To: {to}\nSubject: {subject}\n\n{body}\n")).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct MailerHandle(Arc<dyn Mailer>);
+
+impl MailerHandle {
+    pub fn new(mailer: impl Mailer + 'static) -> Self {
+        Self(Arc::new(mailer))
+    }
+
+    pub fn from_config(config: &MailerConfig) -> Self {
+        match config {
+            MailerConfig::None => Self::new(LogMailer),
+            MailerConfig::File { path } => Self::new(FileMailer::new(path.clone())),
+        }
+    }
+
+    pub async fn send(&self, mail: Mail) -> Result<(), MailerError> {
+        self.0.send(mail).await
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Logging in with OpenID Connect providers, with the authorization
+//! code flow and PKCE.
+//!
+//! Only RS256-signed ID tokens are accepted.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jwt::{Header, PKeyWithDigest, Token, Unverified, VerifyWithKey};
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Rsa;
+use serde::Deserialize;
+use tokio::sync::{Mutex, OnceCell};
+use upsilon_core::config::{OidcConfig, OidcProviderConfig};
+
+/// How long users have to log in at the provider.
+const PENDING_LOGIN_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("Unknown OpenID Connect provider: {0}")]
+    UnknownProvider(String),
+    #[error("Unknown or expired login attempt")]
+    InvalidState,
+    #[error("Request to the provider failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid provider metadata: {0}")]
+    InvalidMetadata(&'static str),
+    #[error("The provider refused the login: {0}")]
+    TokenRequest(String),
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(&'static str),
+    #[error("jwt: {0}")]
+    Jwt(#[from] jwt::error::Error),
+    #[error("openssl: {0}")]
+    Openssl(#[from] openssl::error::ErrorStack),
+    #[error("base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+}
+
+/// The parts of the discovery document we need.
+#[derive(Deserialize, Debug)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct OidcProvider {
+    config: OidcProviderConfig,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+struct PendingLogin {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+    started_at: Instant,
+}
+
+/// Who the provider says logged in.
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct OidcContext {
+    providers: Arc<BTreeMap<String, OidcProvider>>,
+    client: reqwest::Client,
+    /// Logins started with [`OidcContext::begin_login`], by their `state`.
+    pending: Arc<Mutex<BTreeMap<String, PendingLogin>>>,
+}
+
+impl OidcContext {
+    pub fn new(config: OidcConfig) -> Self {
+        let providers = config
+            .into_iter()
+            .map(|(name, config)| {
+                let provider = OidcProvider {
+                    config,
+                    metadata: OnceCell::new(),
+                };
+
+                (name, provider)
+            })
+            .collect();
+
+        Self {
+            providers: Arc::new(providers),
+            client: reqwest::Client::new(),
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = (&str, &OidcProviderConfig)> {
+        self.providers
+            .iter()
+            .map(|(name, provider)| (name.as_str(), &provider.config))
+    }
+
+    pub fn provider_config(&self, name: &str) -> Option<&OidcProviderConfig> {
+        self.providers.get(name).map(|provider| &provider.config)
+    }
+
+    fn provider(&self, name: &str) -> Result<&OidcProvider, OidcError> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| OidcError::UnknownProvider(name.to_owned()))
+    }
+
+    async fn metadata<'a>(
+        &self,
+        provider: &'a OidcProvider,
+    ) -> Result<&'a ProviderMetadata, OidcError> {
+        provider
+            .metadata
+            .get_or_try_init(|| async {
+                let issuer = provider.config.issuer.trim_end_matches('/');
+
+                let metadata = self
+                    .client
+                    .get(format!("{issuer}/.well-known/openid-configuration"))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<ProviderMetadata>()
+                    .await?;
+
+                if metadata.issuer.trim_end_matches('/') != issuer {
+                    return Err(OidcError::InvalidMetadata("issuer mismatch"));
+                }
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Returns the URL to send the user to, to log in at the provider.
+    pub async fn begin_login(&self, provider_name: &str) -> Result<String, OidcError> {
+        let provider = self.provider(provider_name)?;
+        let metadata = self.metadata(provider).await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+        let code_challenge = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.config.client_id.as_str()),
+                ("redirect_uri", provider.config.redirect_uri.as_str()),
+                ("scope", &*provider.config.scopes.join(" ")),
+                ("state", &*state),
+                ("nonce", &*nonce),
+                ("code_challenge", &*code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| OidcError::InvalidMetadata("authorization_endpoint"))?;
+
+        let mut pending = self.pending.lock().await;
+        pending.retain(|_, login| login.started_at.elapsed() < PENDING_LOGIN_LIFETIME);
+        pending.insert(
+            state,
+            PendingLogin {
+                provider: provider_name.to_owned(),
+                nonce,
+                code_verifier,
+                started_at: Instant::now(),
+            },
+        );
+
+        Ok(url.to_string())
+    }
+
+    /// Exchanges the `code` the provider redirected back with for an ID token,
+    /// and returns who it is for.
+    pub async fn complete_login(
+        &self,
+        provider_name: &str,
+        code: &str,
+        state: &str,
+    ) -> Result<OidcIdentity, OidcError> {
+        let provider = self.provider(provider_name)?;
+
+        let login = self
+            .pending
+            .lock()
+            .await
+            .remove(state)
+            .filter(|login| {
+                login.provider == provider_name
+                    && login.started_at.elapsed() < PENDING_LOGIN_LIFETIME
+            })
+            .ok_or(OidcError::InvalidState)?;
+
+        let metadata = self.metadata(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", provider.config.redirect_uri.as_str()),
+            ("client_id", provider.config.client_id.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+
+        if let Some(client_secret) = &provider.config.client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error = response
+                .json::<TokenErrorResponse>()
+                .await
+                .map_or_else(|_| "unknown error".to_owned(), |it| it.error);
+
+            return Err(OidcError::TokenRequest(error));
+        }
+
+        let response = response.json::<TokenResponse>().await?;
+
+        let claims = self
+            .verify_id_token(&provider.config, metadata, &response.id_token)
+            .await?;
+
+        if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch"));
+        }
+
+        Ok(OidcIdentity {
+            provider: provider_name.to_owned(),
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            preferred_username: claims.preferred_username,
+            name: claims.name,
+        })
+    }
+
+    async fn verify_id_token(
+        &self,
+        config: &OidcProviderConfig,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let unverified: Token<Header, IdTokenClaims, Unverified<'_>> =
+            Token::parse_unverified(id_token)?;
+        let kid = unverified.header().key_id.clone();
+
+        // fetched for every login, so that key rotations at the
+        // provider are picked up
+        let jwks = self
+            .client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Jwks>()
+            .await?;
+
+        let mut rsa_keys = jwks.keys.into_iter().filter(|key| key.kty == "RSA");
+
+        let jwk = match kid {
+            Some(kid) => rsa_keys.find(|key| key.kid.as_deref() == Some(kid.as_str())),
+            None => match (rsa_keys.next(), rsa_keys.next()) {
+                (Some(key), None) => Some(key),
+                _ => None,
+            },
+        }
+        .ok_or(OidcError::InvalidIdToken("no matching key"))?;
+
+        let key = jwk.to_public_key()?;
+
+        let claims: IdTokenClaims = id_token.verify_with_key(&key)?;
+
+        if claims.iss.trim_end_matches('/') != metadata.issuer.trim_end_matches('/') {
+            return Err(OidcError::InvalidIdToken("issuer mismatch"));
+        }
+
+        if !claims.aud.contains(&config.client_id) {
+            return Err(OidcError::InvalidIdToken("audience mismatch"));
+        }
+
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            return Err(OidcError::InvalidIdToken("expired"));
+        }
+
+        Ok(claims)
+    }
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+impl Jwk {
+    fn to_public_key(&self) -> Result<PKeyWithDigest<Public>, OidcError> {
+        let (Some(n), Some(e)) = (&self.n, &self.e) else {
+            return Err(OidcError::InvalidIdToken("malformed key"));
+        };
+
+        let n = BigNum::from_slice(&URL_SAFE_NO_PAD.decode(n)?)?;
+        let e = BigNum::from_slice(&URL_SAFE_NO_PAD.decode(e)?)?;
+
+        Ok(PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: PKey::from_rsa(Rsa::from_public_components(n, e)?)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    exp: i64,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    #[serde(default)]
+    preferred_username: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
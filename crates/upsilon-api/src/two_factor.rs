@@ -0,0 +1,334 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! TOTP ([RFC 6238]) second factors, with SHA-1, 6 digits and 30 second steps,
+//! which is what authenticator apps expect.
+//!
+//! [RFC 6238]: https://www.rfc-editor.org/rfc/rfc6238
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::symm::Cipher;
+use tokio::sync::Mutex;
+use upsilon_core::config::TwoFactorConfig;
+use upsilon_models::users::UserId;
+
+use crate::auth::{generate_secret, hash_secret};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+const SECRET_LEN: usize = 20;
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// How many steps before and after the current one are accepted, for clock drift.
+const WINDOW: u64 = 1;
+
+const RECOVERY_CODES: usize = 10;
+
+/// How long the user has to enter their code after the password.
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+const CHALLENGE_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TwoFactorContextError {
+    #[error("{path}: {error}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+    #[error("{0}: the key should be {KEY_LEN} bytes long")]
+    InvalidKey(PathBuf),
+    #[error("openssl error: {0}")]
+    Openssl(#[from] openssl::error::ErrorStack),
+}
+
+struct Challenge {
+    user: UserId,
+    attempts: u32,
+    started_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct TwoFactorContext {
+    key: Arc<[u8; KEY_LEN]>,
+    issuer: String,
+    require_for_org_owners: bool,
+    /// Logins waiting for their second factor, by challenge token.
+    challenges: Arc<Mutex<BTreeMap<String, Challenge>>>,
+}
+
+impl TwoFactorContext {
+    pub fn from_config(config: &TwoFactorConfig) -> Result<Self, TwoFactorContextError> {
+        let key = load_or_generate_key(&config.encryption_key)?;
+
+        Ok(Self {
+            key: Arc::new(key),
+            issuer: config.issuer.clone(),
+            require_for_org_owners: config.require_for_org_owners,
+            challenges: Arc::new(Mutex::new(BTreeMap::new())),
+        })
+    }
+
+    pub fn require_for_org_owners(&self) -> bool {
+        self.require_for_org_owners
+    }
+
+    /// Encrypts a TOTP secret with AES-256-GCM, for storing it.
+    pub fn encrypt(&self, secret: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        openssl::rand::rand_bytes(&mut nonce).expect("Failed to generate random bytes");
+
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = openssl::symm::encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &*self.key,
+            Some(&nonce),
+            &[],
+            secret,
+            &mut tag,
+        )
+        .expect("Failed to encrypt TOTP secret");
+
+        [&nonce[..], &ciphertext, &tag].concat()
+    }
+
+    /// Decrypts what [`encrypt`](Self::encrypt) returned, or `None` if it
+    /// was encrypted with another key.
+    pub fn decrypt(&self, encrypted: &[u8]) -> Option<Vec<u8>> {
+        if encrypted.len() < NONCE_LEN + TAG_LEN {
+            return None;
+        }
+
+        let (nonce, rest) = encrypted.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+        openssl::symm::decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &*self.key,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .ok()
+    }
+
+    /// The URI to put in the QR code scanned by authenticator apps.
+    pub fn otpauth_uri(&self, account: &str, secret: &[u8]) -> String {
+        let label = format!("{}:{account}", self.issuer);
+
+        reqwest::Url::parse_with_params(
+            "otpauth://totp/",
+            &[
+                ("secret", base32(secret).as_str()),
+                ("issuer", self.issuer.as_str()),
+                ("algorithm", "SHA1"),
+                ("digits", &*DIGITS.to_string()),
+                ("period", &*STEP.to_string()),
+            ],
+        )
+        .map(|mut url| {
+            url.set_path(&label);
+            url.to_string()
+        })
+        .expect("Failed to build otpauth URI")
+    }
+
+    /// Remembers that `user` entered their password, and returns the token
+    /// to pass along with their code.
+    pub async fn begin_challenge(&self, user: UserId) -> String {
+        let (token, _) = generate_secret("");
+
+        let mut challenges = self.challenges.lock().await;
+        challenges.retain(|_, challenge| challenge.started_at.elapsed() < CHALLENGE_LIFETIME);
+        challenges.insert(
+            token.clone(),
+            Challenge {
+                user,
+                attempts: 0,
+                started_at: Instant::now(),
+            },
+        );
+
+        token
+    }
+
+    /// Returns the user a challenge is for, counting it as an attempt.
+    pub async fn challenge_user(&self, token: &str) -> Option<UserId> {
+        let mut challenges = self.challenges.lock().await;
+
+        let challenge = challenges.get_mut(token)?;
+        challenge.attempts += 1;
+
+        if challenge.attempts > CHALLENGE_ATTEMPTS
+            || challenge.started_at.elapsed() >= CHALLENGE_LIFETIME
+        {
+            challenges.remove(token);
+            return None;
+        }
+
+        Some(challenge.user)
+    }
+
+    pub async fn end_challenge(&self, token: &str) {
+        self.challenges.lock().await.remove(token);
+    }
+}
+
+fn generate_key() -> Result<[u8; KEY_LEN], openssl::error::ErrorStack> {
+    let mut key = [0u8; KEY_LEN];
+    openssl::rand::rand_bytes(&mut key)?;
+
+    Ok(key)
+}
+
+fn load_or_generate_key(path: &Path) -> Result<[u8; KEY_LEN], TwoFactorContextError> {
+    let io_err = |error| TwoFactorContextError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    if path.exists() {
+        let key = std::fs::read(path).map_err(io_err)?;
+
+        return key
+            .try_into()
+            .map_err(|_| TwoFactorContextError::InvalidKey(path.to_path_buf()));
+    }
+
+    let key = generate_key()?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_err)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(&key))
+        .map_err(io_err)?;
+
+    Ok(key)
+}
+
+pub fn generate_totp_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    openssl::rand::rand_bytes(&mut secret).expect("Failed to generate random bytes");
+
+    secret
+}
+
+/// The RFC 4648 base32 encoding, without padding, which is how
+/// authenticator apps take secrets.
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut result = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            result.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        result.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    result
+}
+
+fn totp(secret: &[u8], step: u64) -> u32 {
+    let key = PKey::hmac(secret).expect("Failed to create HMAC key");
+    let mut signer = Signer::new(MessageDigest::sha1(), &key).expect("Failed to create signer");
+    let mac = signer
+        .sign_oneshot_to_vec(&step.to_be_bytes())
+        .expect("Failed to compute HMAC");
+
+    let offset = (mac[mac.len() - 1] & 0xf) as usize;
+    let code = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+
+    code % 10u32.pow(DIGITS)
+}
+
+/// Checks `code` against the steps around `now` (in seconds since the epoch),
+/// returning the step it matched.
+///
+/// Steps up to `last_used_step` are skipped, so that every code works once.
+pub fn verify_totp(
+    secret: &[u8],
+    code: &str,
+    now: u64,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    let code = code.trim();
+
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let code: u32 = code.parse().ok()?;
+    let current = now / STEP;
+
+    (current.saturating_sub(WINDOW)..=current + WINDOW)
+        .filter(|step| last_used_step.map_or(true, |last| *step > last))
+        .find(|step| totp(secret, *step) == code)
+}
+
+/// Generates new recovery codes, returning them and their hashes.
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+
+            let code = base32(&bytes).to_lowercase();
+            let hash = hash_recovery_code(&code);
+
+            (code, hash)
+        })
+        .unzip()
+}
+
+pub fn hash_recovery_code(code: &str) -> String {
+    hash_secret(&code.trim().to_lowercase())
+}
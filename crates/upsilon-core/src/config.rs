@@ -14,14 +14,58 @@
  *    limitations under the License.
  */
 
+use std::collections::BTreeMap;
 use std::ops::Deref;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct UsersRegisterConfig {
     pub enabled: bool,
+    /// Whether new users need an invite code, issued by an admin or
+    /// an organization owner.
+    #[serde(rename = "invite-only", default)]
+    pub invite_only: bool,
+    /// If not empty, new users need an email address in one of these
+    /// domains, unless they were invited.
+    #[serde(rename = "allowed-email-domains", default)]
+    pub allowed_email_domains: Vec<String>,
+    /// Whether new accounts have to be approved by an admin before
+    /// they can log in.
+    ///
+    /// Invited users are approved by the invite.
+    #[serde(rename = "require-approval", default)]
+    pub require_approval: bool,
+    /// How long invite codes are valid for.
+    #[serde(
+        rename = "invite-lifetime",
+        default = "default_invite_lifetime",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub invite_lifetime: Duration,
+}
+
+impl UsersRegisterConfig {
+    pub fn allows_email_domain(&self, email: &str) -> bool {
+        if self.allowed_email_domains.is_empty() {
+            return true;
+        }
+
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+
+        self.allowed_email_domains
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(domain))
+    }
+}
+
+const fn default_invite_lifetime() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
@@ -56,14 +100,441 @@ const fn default_bcrypt_cost() -> u32 {
 #[derive(Deserialize, Debug, Clone)]
 pub struct UsersAuthConfig {
     pub password: PasswordHashAlgorithmDescriptor,
+    #[serde(default)]
+    pub tokens: TokensConfig,
+    #[serde(rename = "two-factor", default)]
+    pub two_factor: TwoFactorConfig,
+    /// If set, passwords are checked against an LDAP directory instead of
+    /// the ones stored locally.
+    #[serde(default)]
+    pub ldap: Option<LdapConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LdapConfig {
+    pub directory: LdapDirectoryConfig,
+    #[serde(default)]
+    pub attributes: LdapAttributesConfig,
+    /// Directory groups whose members are added to an organization
+    /// (or one of its teams) when they log in.
+    ///
+    /// Membership of the organizations and teams listed here follows the
+    /// directory: users who are no longer in any of the groups mapped to
+    /// an organization are removed from it, and from the mapped teams.
+    #[serde(rename = "group-sync", default)]
+    pub group_sync: Vec<LdapGroupMapping>,
+    /// Users that always log in with their local password, so that admins
+    /// can still get in when the directory is down.
+    #[serde(rename = "local-accounts", default)]
+    pub local_accounts: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum LdapDirectoryConfig {
+    /// A real LDAP server.
+    ///
+    /// The user entry is looked up with `user-filter`, binding as `bind-dn`
+    /// (or anonymously), and the password is checked by binding as the entry.
+    #[serde(rename = "server")]
+    Server {
+        /// Like `ldap://localhost:389` or `ldaps://ldap.example.org`.
+        url: String,
+        #[serde(rename = "bind-dn", default)]
+        bind_dn: Option<String>,
+        #[serde(rename = "bind-password", default)]
+        bind_password: Option<String>,
+        #[serde(rename = "base-dn")]
+        base_dn: String,
+        /// `{username}` is replaced with the (escaped) name the user logs in with.
+        #[serde(rename = "user-filter", default = "default_ldap_user_filter")]
+        user_filter: String,
+        #[serde(default)]
+        starttls: bool,
+    },
+    /// Entries listed right here, in place of a server; meant for testing.
+    ///
+    /// Users are found by the `username` attribute.
+    #[serde(rename = "static")]
+    Static { entries: Vec<LdapStaticEntry> },
+}
+
+fn default_ldap_user_filter() -> String {
+    "(uid={username})".to_owned()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LdapStaticEntry {
+    pub dn: String,
+    pub password: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+/// The attributes of the user entries the user details are taken from.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LdapAttributesConfig {
+    #[serde(default = "default_ldap_username_attribute")]
+    pub username: String,
+    #[serde(
+        rename = "display-name",
+        default = "default_ldap_display_name_attribute"
+    )]
+    pub display_name: String,
+    #[serde(default = "default_ldap_email_attribute")]
+    pub email: String,
+    /// Lists the DNs of the groups the user is in.
+    #[serde(default = "default_ldap_groups_attribute")]
+    pub groups: String,
+}
+
+impl Default for LdapAttributesConfig {
+    fn default() -> Self {
+        Self {
+            username: default_ldap_username_attribute(),
+            display_name: default_ldap_display_name_attribute(),
+            email: default_ldap_email_attribute(),
+            groups: default_ldap_groups_attribute(),
+        }
+    }
+}
+
+fn default_ldap_username_attribute() -> String {
+    "uid".to_owned()
+}
+
+fn default_ldap_display_name_attribute() -> String {
+    "cn".to_owned()
+}
+
+fn default_ldap_email_attribute() -> String {
+    "mail".to_owned()
+}
+
+fn default_ldap_groups_attribute() -> String {
+    "memberOf".to_owned()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LdapGroupMapping {
+    /// The DN of the group.
+    pub group: String,
+    pub organization: String,
+    #[serde(default)]
+    pub team: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TwoFactorConfig {
+    /// The issuer shown in authenticator apps.
+    #[serde(default = "default_two_factor_issuer")]
+    pub issuer: String,
+    /// Where the key the TOTP secrets are encrypted with is kept.
+    ///
+    /// It is generated the first time, and has to stay the same afterwards,
+    /// or the secrets stored before can no longer be read.
+    #[serde(
+        rename = "encryption-key",
+        default = "default_two_factor_encryption_key"
+    )]
+    pub encryption_key: PathBuf,
+    /// Whether organization owners have to have 2FA enabled to manage
+    /// their organizations.
+    #[serde(rename = "require-for-org-owners", default)]
+    pub require_for_org_owners: bool,
+}
+
+impl Default for TwoFactorConfig {
+    fn default() -> Self {
+        Self {
+            issuer: default_two_factor_issuer(),
+            encryption_key: default_two_factor_encryption_key(),
+            require_for_org_owners: false,
+        }
+    }
+}
+
+fn default_two_factor_encryption_key() -> PathBuf {
+    PathBuf::from("two-factor.key")
+}
+
+fn default_two_factor_issuer() -> String {
+    "Upsilon".to_owned()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokensConfig {
+    /// How long access tokens are valid for.
+    #[serde(
+        default = "default_token_lifetime",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub lifetime: Duration,
+    /// How long a session lasts without being refreshed.
+    #[serde(
+        rename = "refresh-lifetime",
+        default = "default_refresh_token_lifetime",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub refresh_lifetime: Duration,
+    #[serde(default)]
+    pub keys: TokenKeysConfig,
+}
+
+impl Default for TokensConfig {
+    fn default() -> Self {
+        Self {
+            lifetime: default_token_lifetime(),
+            refresh_lifetime: default_refresh_token_lifetime(),
+            keys: TokenKeysConfig::default(),
+        }
+    }
+}
+
+const fn default_token_lifetime() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+const fn default_refresh_token_lifetime() -> Duration {
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+fn deserialize_duration<'de, D>(d: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(d)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
+/// Where the keys used to sign and verify auth tokens come from.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum TokenKeysConfig {
+    /// A key generated on startup.
+    ///
+    /// If `path` is set, the key is written there the first time
+    /// and loaded from there afterwards, so tokens survive restarts.
+    #[serde(rename = "generated")]
+    Generated {
+        #[serde(default)]
+        path: Option<PathBuf>,
+        #[serde(default = "default_rsa_bits")]
+        bits: u32,
+    },
+    /// RSA keys loaded from PEM files.
+    ///
+    /// New tokens are signed with `signing`, while tokens signed with
+    /// any of the `verification` keys are still accepted, which allows
+    /// rotating keys without logging everyone out.
+    #[serde(rename = "files")]
+    Files {
+        signing: TokenKeyFile,
+        #[serde(default)]
+        verification: Vec<TokenKeyFile>,
+    },
+}
+
+impl Default for TokenKeysConfig {
+    fn default() -> Self {
+        Self::Generated {
+            path: None,
+            bits: default_rsa_bits(),
+        }
+    }
+}
+
+const fn default_rsa_bits() -> u32 {
+    2048
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenKeyFile {
+    /// The `kid` of the tokens signed with this key.
+    ///
+    /// Defaults to a fingerprint of the public key.
+    #[serde(default)]
+    pub kid: Option<String>,
+    pub path: PathBuf,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct UsersConfig {
     pub register: UsersRegisterConfig,
     pub auth: UsersAuthConfig,
+    #[serde(rename = "password-reset", default)]
+    pub password_reset: PasswordResetConfig,
+    /// Usernames of the users that are instance admins.
+    ///
+    /// Meant for bootstrapping: the accounts that already exist are made
+    /// admins at startup, after which the flag is stored with the user and
+    /// can be managed from there. Listing a username that is not taken yet
+    /// does nothing, so it cannot be claimed by registering it.
+    #[serde(default)]
+    pub admins: Vec<String>,
+}
+
+impl UsersConfig {
+    pub fn is_bootstrap_admin(&self, username: &str) -> bool {
+        self.admins.iter().any(|admin| admin == username)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PasswordResetConfig {
+    /// How long the emailed reset tokens are valid for.
+    #[serde(
+        default = "default_password_reset_lifetime",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub lifetime: Duration,
+    /// The page where users can reset their password, put in the email.
+    ///
+    /// `{token}` is replaced with the reset token.
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        Self {
+            lifetime: default_password_reset_lifetime(),
+            link: None,
+        }
+    }
+}
+
+const fn default_password_reset_lifetime() -> Duration {
+    Duration::from_secs(60 * 60)
 }
 
+/// How emails are sent.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(tag = "type")]
+pub enum MailerConfig {
+    /// Emails are not sent, only logged.
+    #[serde(rename = "none")]
+    #[default]
+    None,
+    /// Every email is written to a file in `path`; meant for testing.
+    #[serde(rename = "file")]
+    File { path: PathBuf },
+}
+
+/// Limits on failed logins, for both the API and git authentication.
+///
+/// Failures are counted per IP address and per account. Once either goes
+/// over its limit, further attempts are rejected for `lockout`, doubling
+/// with every failure after that, up to `max-lockout`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_rate_limit_enabled")]
+    pub enabled: bool,
+    #[serde(
+        rename = "max-attempts-per-account",
+        default = "default_max_attempts_per_account"
+    )]
+    pub max_attempts_per_account: u32,
+    #[serde(
+        rename = "max-attempts-per-ip",
+        default = "default_max_attempts_per_ip"
+    )]
+    pub max_attempts_per_ip: u32,
+    #[serde(default = "default_lockout", deserialize_with = "deserialize_duration")]
+    pub lockout: Duration,
+    #[serde(
+        rename = "max-lockout",
+        default = "default_max_lockout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub max_lockout: Duration,
+    /// How long after the last failure the count starts over.
+    #[serde(
+        rename = "reset-after",
+        default = "default_rate_limit_reset_after",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub reset_after: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_rate_limit_enabled(),
+            max_attempts_per_account: default_max_attempts_per_account(),
+            max_attempts_per_ip: default_max_attempts_per_ip(),
+            lockout: default_lockout(),
+            max_lockout: default_max_lockout(),
+            reset_after: default_rate_limit_reset_after(),
+        }
+    }
+}
+
+const fn default_rate_limit_enabled() -> bool {
+    true
+}
+
+const fn default_max_attempts_per_account() -> u32 {
+    5
+}
+
+const fn default_max_attempts_per_ip() -> u32 {
+    20
+}
+
+const fn default_lockout() -> Duration {
+    Duration::from_secs(30)
+}
+
+const fn default_max_lockout() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+const fn default_rate_limit_reset_after() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+/// An OpenID Connect provider users can log in with.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OidcProviderConfig {
+    /// The name shown on the login button; defaults to the key of the provider.
+    #[serde(rename = "display-name", default)]
+    pub display_name: Option<String>,
+    /// The issuer URL, under which `.well-known/openid-configuration` is found.
+    pub issuer: String,
+    #[serde(rename = "client-id")]
+    pub client_id: String,
+    #[serde(rename = "client-secret", default)]
+    pub client_secret: Option<String>,
+    /// Where the provider sends the user back to after they logged in.
+    ///
+    /// This page should pass the `code` and `state` it receives to the
+    /// `completeOidcLogin` mutation.
+    #[serde(rename = "redirect-uri")]
+    pub redirect_uri: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Whether to link logins to the existing account with the same
+    /// email, if the provider says the email is verified.
+    #[serde(rename = "link-by-email", default = "default_oidc_link_by_email")]
+    pub link_by_email: bool,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_owned(),
+        "email".to_owned(),
+        "profile".to_owned(),
+    ]
+}
+
+const fn default_oidc_link_by_email() -> bool {
+    true
+}
+
+/// The OpenID Connect providers, by name.
+pub type OidcConfig = BTreeMap<String, OidcProviderConfig>;
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GqlDebugConfig {
     #[serde(rename = "enabled", default = "default_gql_debug_enabled")]
@@ -17,6 +17,7 @@
 use std::path::{Path, PathBuf};
 
 pub mod config;
+pub mod rate_limit;
 
 fn bin_folder() -> PathBuf {
     if let Ok(var) = std::env::var("UPSILON_BIN_DIR") {
@@ -0,0 +1,157 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+
+/// What failed attempts are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    Ip(IpAddr),
+    Account(String),
+}
+
+impl fmt::Display for RateLimitKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitKey::Ip(ip) => write!(f, "IP {ip}"),
+            RateLimitKey::Account(account) => write!(f, "account {account}"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Too many failed attempts, retry in {}s", .retry_after.as_secs().max(1))]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+#[derive(Debug)]
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Counts failed authentication attempts, and locks out the IPs and
+/// accounts with too many of them.
+///
+/// Clones share the same counts.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    attempts: Arc<Mutex<HashMap<RateLimitKey, Attempts>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            attempts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn max_attempts(&self, key: &RateLimitKey) -> u32 {
+        match key {
+            RateLimitKey::Ip(_) => self.config.max_attempts_per_ip,
+            RateLimitKey::Account(_) => self.config.max_attempts_per_account,
+        }
+    }
+
+    fn lockout_after(&self, key: &RateLimitKey, failures: u32) -> Option<Duration> {
+        let over = failures.checked_sub(self.max_attempts(key))?;
+
+        let lockout = self
+            .config
+            .lockout
+            .checked_mul(2u32.saturating_pow(over))
+            .unwrap_or(self.config.max_lockout);
+
+        Some(lockout.min(self.config.max_lockout))
+    }
+
+    /// Fails if any of `keys` is currently locked out.
+    pub fn check(&self, keys: &[RateLimitKey]) -> Result<(), RateLimited> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let attempts = self.attempts.lock().unwrap();
+
+        let retry_after = keys
+            .iter()
+            .filter_map(|key| attempts.get(key)?.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .max();
+
+        match retry_after {
+            Some(locked_until) => Err(RateLimited {
+                retry_after: locked_until - now,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed attempt against all of `keys`, locking them out
+    /// if they went over their limit.
+    pub fn record_failure(&self, keys: &[RateLimitKey]) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+
+        let forget_after = self.config.reset_after + self.config.max_lockout;
+        attempts.retain(|_, it| now.duration_since(it.last_failure) < forget_after);
+
+        for key in keys {
+            let entry = attempts.entry(key.clone()).or_insert(Attempts {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+
+            if now.duration_since(entry.last_failure) >= self.config.reset_after {
+                entry.failures = 0;
+            }
+
+            entry.failures += 1;
+            entry.last_failure = now;
+
+            if let Some(lockout) = self.lockout_after(key, entry.failures) {
+                entry.locked_until = Some(now + lockout);
+
+                log::warn!(
+                    "Locking out {key} for {} after {} failed attempts",
+                    humantime::format_duration(lockout),
+                    entry.failures
+                );
+            }
+        }
+    }
+
+    /// Forgets the failed attempts against `key`.
+    pub fn record_success(&self, key: &RateLimitKey) {
+        self.attempts.lock().unwrap().remove(key);
+    }
+}
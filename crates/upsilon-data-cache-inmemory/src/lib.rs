@@ -21,13 +21,23 @@ use moka::future::Cache;
 use upsilon_data::upsilon_models::organization::{
     Organization, OrganizationDisplayName, OrganizationId, OrganizationName, OrganizationNameRef, Team, TeamDisplayName, TeamId, TeamName, TeamNameRef
 };
-use upsilon_data::upsilon_models::repo::{Repo, RepoId, RepoName, RepoNameRef, RepoNamespace};
+use upsilon_data::upsilon_models::repo::{
+    BranchProtectionRule, DeployKey, DeployKeyId, Repo, RepoBranchName, RepoDescription, RepoDisplayName, RepoId, RepoName, RepoNameRef, RepoNamespace, RepoTopic, RepoTopicRef, RepoWebsite, TagProtectionRule
+};
 use upsilon_data::upsilon_models::users::{User, UserId, Username, UsernameRef};
 use upsilon_data::{
     async_trait, CommonDataClientError, CommonDataClientErrorExtractor, DataClient, DataClientMaster, DataClientQueryImpl, DataClientQueryMaster
 };
+use upsilon_models::email::Email;
 use upsilon_models::organization::OrganizationMember;
-use upsilon_models::repo::RepoPermissions;
+use upsilon_models::repo::{RepoPermissions, RepoRole};
+use upsilon_models::users::identities::ExternalIdentity;
+use upsilon_models::users::invites::{Invite, InviteId};
+use upsilon_models::users::password::HashedPassword;
+use upsilon_models::users::password_resets::PasswordReset;
+use upsilon_models::users::sessions::{Session, SessionId};
+use upsilon_models::users::tokens::{PersonalAccessToken, PersonalAccessTokenId};
+use upsilon_models::users::two_factor::TwoFactor;
 use upsilon_models::users::UserSshKey;
 
 #[derive(thiserror::Error, Debug)]
@@ -168,6 +178,11 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
         }
     }
 
+    async fn query_users(&self) -> Result<Vec<User>, Self::Error> {
+        // no way to cache this
+        self.inner.query_users().await.convert_error()
+    }
+
     async fn query_user_by_username_email(
         &self,
         username_email: &str,
@@ -202,6 +217,65 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_user_password(
+        &self,
+        user_id: UserId,
+        password: HashedPassword,
+    ) -> Result<(), Self::Error> {
+        self.store().users.invalidate(&user_id).await;
+
+        self.inner
+            .set_user_password(user_id, password)
+            .await
+            .convert_error()
+    }
+
+    async fn set_user_admin(&self, user_id: UserId, admin: bool) -> Result<(), Self::Error> {
+        self.store().users.invalidate(&user_id).await;
+
+        self.inner
+            .set_user_admin(user_id, admin)
+            .await
+            .convert_error()
+    }
+
+    async fn set_user_suspended(
+        &self,
+        user_id: UserId,
+        suspended: bool,
+    ) -> Result<(), Self::Error> {
+        self.store().users.invalidate(&user_id).await;
+
+        self.inner
+            .set_user_suspended(user_id, suspended)
+            .await
+            .convert_error()
+    }
+
+    async fn set_user_pending_approval(
+        &self,
+        user_id: UserId,
+        pending_approval: bool,
+    ) -> Result<(), Self::Error> {
+        self.store().users.invalidate(&user_id).await;
+
+        self.inner
+            .set_user_pending_approval(user_id, pending_approval)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_user(&self, user_id: UserId) -> Result<bool, Self::Error> {
+        self.store().users.invalidate(&user_id).await;
+        // keys, memberships and permissions of the user are spread
+        // over entries we cannot find by user id alone
+        self.store().user_ssh_keys.invalidate_all();
+        self.store().org_members.invalidate_all();
+        self.store().repo_permissions.invalidate_all();
+
+        self.inner.delete_user(user_id).await.convert_error()
+    }
+
     async fn add_user_ssh_key(
         &self,
         user_id: UserId,
@@ -255,6 +329,238 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
         }
     }
 
+    async fn create_personal_access_token(
+        &self,
+        token: PersonalAccessToken,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .create_personal_access_token(token)
+            .await
+            .convert_error()
+    }
+
+    async fn query_personal_access_token(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PersonalAccessToken>, Self::Error> {
+        // not cached, so that revoking a token takes effect immediately
+        self.inner
+            .query_personal_access_token(token_hash)
+            .await
+            .convert_error()
+    }
+
+    async fn query_user_personal_access_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<PersonalAccessToken>, Self::Error> {
+        self.inner
+            .query_user_personal_access_tokens(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_personal_access_token(
+        &self,
+        user_id: UserId,
+        token_id: PersonalAccessTokenId,
+    ) -> Result<bool, Self::Error> {
+        self.inner
+            .delete_personal_access_token(user_id, token_id)
+            .await
+            .convert_error()
+    }
+
+    async fn set_user_two_factor(&self, two_factor: TwoFactor) -> Result<(), Self::Error> {
+        self.inner
+            .set_user_two_factor(two_factor)
+            .await
+            .convert_error()
+    }
+
+    async fn query_user_two_factor(
+        &self,
+        user_id: UserId,
+    ) -> Result<Option<TwoFactor>, Self::Error> {
+        // not cached, so that used codes are seen right away
+        self.inner
+            .query_user_two_factor(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_user_two_factor(&self, user_id: UserId) -> Result<bool, Self::Error> {
+        self.inner
+            .delete_user_two_factor(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn create_external_identity(
+        &self,
+        identity: ExternalIdentity,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .create_external_identity(identity)
+            .await
+            .convert_error()
+    }
+
+    async fn query_external_identity(
+        &self,
+        provider: String,
+        subject: String,
+    ) -> Result<Option<ExternalIdentity>, Self::Error> {
+        // no way to cache this
+        self.inner
+            .query_external_identity(provider, subject)
+            .await
+            .convert_error()
+    }
+
+    async fn query_user_external_identities(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<ExternalIdentity>, Self::Error> {
+        self.inner
+            .query_user_external_identities(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn create_session(&self, session: Session) -> Result<(), Self::Error> {
+        self.inner.create_session(session).await.convert_error()
+    }
+
+    async fn update_session(&self, session: Session) -> Result<(), Self::Error> {
+        self.inner.update_session(session).await.convert_error()
+    }
+
+    async fn query_session(&self, session_id: SessionId) -> Result<Option<Session>, Self::Error> {
+        // not cached, so that revoking a session takes effect immediately
+        self.inner.query_session(session_id).await.convert_error()
+    }
+
+    async fn query_session_by_refresh_token(
+        &self,
+        refresh_token_hash: String,
+    ) -> Result<Option<Session>, Self::Error> {
+        self.inner
+            .query_session_by_refresh_token(refresh_token_hash)
+            .await
+            .convert_error()
+    }
+
+    async fn query_user_sessions(&self, user_id: UserId) -> Result<Vec<Session>, Self::Error> {
+        self.inner
+            .query_user_sessions(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_session(
+        &self,
+        user_id: UserId,
+        session_id: SessionId,
+    ) -> Result<bool, Self::Error> {
+        self.inner
+            .delete_session(user_id, session_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_user_sessions(&self, user_id: UserId) -> Result<(), Self::Error> {
+        self.inner
+            .delete_user_sessions(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn create_password_reset(&self, reset: PasswordReset) -> Result<(), Self::Error> {
+        self.inner
+            .create_password_reset(reset)
+            .await
+            .convert_error()
+    }
+
+    async fn query_password_reset(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PasswordReset>, Self::Error> {
+        // not cached, resets are only used once
+        self.inner
+            .query_password_reset(token_hash)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_user_password_resets(&self, user_id: UserId) -> Result<(), Self::Error> {
+        self.inner
+            .delete_user_password_resets(user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn create_invite(&self, invite: Invite) -> Result<(), Self::Error> {
+        self.inner.create_invite(invite).await.convert_error()
+    }
+
+    async fn query_invite_by_code(&self, code_hash: String) -> Result<Option<Invite>, Self::Error> {
+        // not cached, invites are only used once
+        self.inner
+            .query_invite_by_code(code_hash)
+            .await
+            .convert_error()
+    }
+
+    async fn query_invites(&self) -> Result<Vec<Invite>, Self::Error> {
+        // no way to cache this
+        self.inner.query_invites().await.convert_error()
+    }
+
+    async fn use_invite(&self, invite_id: InviteId, user_id: UserId) -> Result<bool, Self::Error> {
+        self.inner
+            .use_invite(invite_id, user_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_invite(&self, invite_id: InviteId) -> Result<bool, Self::Error> {
+        self.inner.delete_invite(invite_id).await.convert_error()
+    }
+
+    async fn block_user(&self, blocker: UserId, blocked: UserId) -> Result<bool, Self::Error> {
+        self.inner
+            .block_user(blocker, blocked)
+            .await
+            .convert_error()
+    }
+
+    async fn unblock_user(&self, blocker: UserId, blocked: UserId) -> Result<bool, Self::Error> {
+        self.inner
+            .unblock_user(blocker, blocked)
+            .await
+            .convert_error()
+    }
+
+    async fn query_user_blocked(
+        &self,
+        blocker: UserId,
+        blocked: UserId,
+    ) -> Result<bool, Self::Error> {
+        self.inner
+            .query_user_blocked(blocker, blocked)
+            .await
+            .convert_error()
+    }
+
+    async fn query_blocked_users(&self, blocker: UserId) -> Result<Vec<UserId>, Self::Error> {
+        self.inner
+            .query_blocked_users(blocker)
+            .await
+            .convert_error()
+    }
+
     async fn create_repo(&self, repo: Repo) -> Result<(), Self::Error> {
         self.store().repos.insert(repo.id, repo.clone()).await;
 
@@ -288,6 +594,27 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
         Ok(repo)
     }
 
+    async fn query_repo_by_previous_name<'self_ref>(
+        &'self_ref self,
+        repo_name: RepoNameRef<'self_ref>,
+        repo_namespace: &RepoNamespace,
+    ) -> Result<Option<Repo>, Self::Error> {
+        let repo = self
+            .inner
+            .query_repo_by_previous_name(repo_name, repo_namespace)
+            .await
+            .convert_error()?;
+        if let Some(ref repo) = repo {
+            self.store().repos.insert(repo.id, repo.clone()).await;
+        }
+        Ok(repo)
+    }
+
+    async fn query_repos(&self) -> Result<Vec<Repo>, Self::Error> {
+        // no way to cache this
+        self.inner.query_repos().await.convert_error()
+    }
+
     async fn set_repo_name(&self, repo_id: RepoId, repo_name: RepoName) -> Result<(), Self::Error> {
         self.store().repos.invalidate(&repo_id).await;
 
@@ -297,6 +624,165 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_repo_namespace(
+        &self,
+        repo_id: RepoId,
+        repo_namespace: RepoNamespace,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_namespace(repo_id, repo_namespace)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_display_name(
+        &self,
+        repo_id: RepoId,
+        repo_display_name: Option<RepoDisplayName>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_display_name(repo_id, repo_display_name)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_description(
+        &self,
+        repo_id: RepoId,
+        repo_description: Option<RepoDescription>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_description(repo_id, repo_description)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_website(
+        &self,
+        repo_id: RepoId,
+        repo_website: Option<RepoWebsite>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_website(repo_id, repo_website)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_topics(
+        &self,
+        repo_id: RepoId,
+        repo_topics: Vec<RepoTopic>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_topics(repo_id, repo_topics)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_default_branch(
+        &self,
+        repo_id: RepoId,
+        default_branch: Option<RepoBranchName>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_default_branch(repo_id, default_branch)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_protected_branches(
+        &self,
+        repo_id: RepoId,
+        protected_branches: Vec<BranchProtectionRule>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_protected_branches(repo_id, protected_branches)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_protected_tags(
+        &self,
+        repo_id: RepoId,
+        protected_tags: Vec<TagProtectionRule>,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_protected_tags(repo_id, protected_tags)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_global_permissions(
+        &self,
+        repo_id: RepoId,
+        global_permissions: RepoPermissions,
+    ) -> Result<(), Self::Error> {
+        self.store().repos.invalidate(&repo_id).await;
+
+        self.inner
+            .set_repo_global_permissions(repo_id, global_permissions)
+            .await
+            .convert_error()
+    }
+
+    async fn query_repos_with_topic<'self_ref>(
+        &'self_ref self,
+        topic: RepoTopicRef<'self_ref>,
+    ) -> Result<Vec<Repo>, Self::Error> {
+        // no way to cache this
+
+        self.inner
+            .query_repos_with_topic(topic)
+            .await
+            .convert_error()
+    }
+
+    async fn add_deploy_key(&self, deploy_key: DeployKey) -> Result<bool, Self::Error> {
+        self.inner.add_deploy_key(deploy_key).await.convert_error()
+    }
+
+    async fn query_deploy_key(&self, key: UserSshKey) -> Result<Option<DeployKey>, Self::Error> {
+        // not cached, so that removing a key takes effect immediately
+
+        self.inner.query_deploy_key(key).await.convert_error()
+    }
+
+    async fn query_repo_deploy_keys(&self, repo_id: RepoId) -> Result<Vec<DeployKey>, Self::Error> {
+        // no way to cache this
+
+        self.inner
+            .query_repo_deploy_keys(repo_id)
+            .await
+            .convert_error()
+    }
+
+    async fn delete_deploy_key(
+        &self,
+        repo_id: RepoId,
+        deploy_key_id: DeployKeyId,
+    ) -> Result<bool, Self::Error> {
+        self.inner
+            .delete_deploy_key(repo_id, deploy_key_id)
+            .await
+            .convert_error()
+    }
+
     async fn init_repo_user_perms(
         &self,
         repo_id: RepoId,
@@ -366,6 +852,59 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_repo_user_perms(
+        &self,
+        repo_id: RepoId,
+        user_id: UserId,
+        perms: Option<RepoPermissions>,
+    ) -> Result<(), Self::Error> {
+        self.store()
+            .repo_permissions
+            .invalidate(&(repo_id, user_id))
+            .await;
+
+        self.inner
+            .set_repo_user_perms(repo_id, user_id, perms)
+            .await
+            .convert_error()
+    }
+
+    async fn query_repo_team_perms(
+        &self,
+        repo_id: RepoId,
+    ) -> Result<Vec<(TeamId, RepoPermissions)>, Self::Error> {
+        // no way to cache this
+
+        self.inner
+            .query_repo_team_perms(repo_id)
+            .await
+            .convert_error()
+    }
+
+    async fn set_repo_team_perms(
+        &self,
+        repo_id: RepoId,
+        team_id: TeamId,
+        perms: Option<RepoPermissions>,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .set_repo_team_perms(repo_id, team_id, perms)
+            .await
+            .convert_error()
+    }
+
+    async fn query_team_repo_perms(
+        &self,
+        team_id: TeamId,
+    ) -> Result<Vec<(RepoId, RepoPermissions)>, Self::Error> {
+        // no way to cache this
+
+        self.inner
+            .query_team_repo_perms(team_id)
+            .await
+            .convert_error()
+    }
+
     async fn create_organization(&self, org: Organization) -> Result<(), Self::Error> {
         self.store().orgs.insert(org.id, org.clone()).await;
 
@@ -405,6 +944,11 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
         Ok(org)
     }
 
+    async fn query_organizations(&self) -> Result<Vec<Organization>, Self::Error> {
+        // no way to cache this
+        self.inner.query_organizations().await.convert_error()
+    }
+
     async fn set_organization_name(
         &self,
         org_id: OrganizationId,
@@ -431,6 +975,32 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_organization_email(
+        &self,
+        org_id: OrganizationId,
+        email: Option<Email>,
+    ) -> Result<(), Self::Error> {
+        self.store().orgs.invalidate(&org_id).await;
+
+        self.inner
+            .set_organization_email(org_id, email)
+            .await
+            .convert_error()
+    }
+
+    async fn set_organization_base_role(
+        &self,
+        org_id: OrganizationId,
+        base_role: Option<RepoRole>,
+    ) -> Result<(), Self::Error> {
+        self.store().orgs.invalidate(&org_id).await;
+
+        self.inner
+            .set_organization_base_role(org_id, base_role)
+            .await
+            .convert_error()
+    }
+
     async fn query_organization_member(
         &self,
         org_id: OrganizationId,
@@ -479,6 +1049,34 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_organization_member(&self, member: OrganizationMember) -> Result<(), Self::Error> {
+        self.store()
+            .org_members
+            .invalidate(&(member.organization_id, member.user_id))
+            .await;
+
+        self.inner
+            .set_organization_member(member)
+            .await
+            .convert_error()
+    }
+
+    async fn remove_organization_member(
+        &self,
+        org_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<bool, Self::Error> {
+        self.store()
+            .org_members
+            .invalidate(&(org_id, user_id))
+            .await;
+
+        self.inner
+            .remove_organization_member(org_id, user_id)
+            .await
+            .convert_error()
+    }
+
     async fn create_team(&self, team: Team) -> Result<(), Self::Error> {
         self.store().teams.insert(team.id, team.clone()).await;
 
@@ -546,6 +1144,19 @@ impl<'a> DataClientQueryImpl<'a> for CacheInMemoryQueryImpl<'a> {
             .convert_error()
     }
 
+    async fn set_team_parent(
+        &self,
+        team_id: TeamId,
+        parent: Option<TeamId>,
+    ) -> Result<(), Self::Error> {
+        self.store().teams.invalidate(&team_id).await;
+
+        self.inner
+            .set_team_parent(team_id, parent)
+            .await
+            .convert_error()
+    }
+
     async fn query_organization_and_team(
         &self,
         team_id: TeamId,
@@ -16,7 +16,7 @@
 
 #![deny(clippy::map_clone)]
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -25,13 +25,22 @@ use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use upsilon_data::{
     async_trait, query_master_impl_trait, CommonDataClientError, CommonDataClientErrorExtractor, DataClient, DataClientMaster, DataClientQueryImpl, DataClientQueryMaster
 };
+use upsilon_models::email::Email;
 use upsilon_models::namespace::{NamespaceId, NamespaceKind};
 use upsilon_models::organization::{
     Organization, OrganizationDisplayName, OrganizationId, OrganizationMember, OrganizationName, OrganizationNameRef, Team, TeamDisplayName, TeamId, TeamName, TeamNameRef
 };
-use upsilon_models::repo::{Repo, RepoId, RepoName, RepoNameRef, RepoNamespace, RepoPermissions};
+use upsilon_models::repo::{
+    BranchProtectionRule, DeployKey, DeployKeyId, Repo, RepoBranchName, RepoDescription, RepoDisplayName, RepoId, RepoName, RepoNameRef, RepoNamespace, RepoPermissions, RepoRole, RepoTopic, RepoTopicRef, RepoWebsite, TagProtectionRule
+};
+use upsilon_models::users::identities::ExternalIdentity;
+use upsilon_models::users::invites::{Invite, InviteId};
+use upsilon_models::users::password::HashedPassword;
+use upsilon_models::users::password_resets::PasswordReset;
+use upsilon_models::users::sessions::{Session, SessionId};
+use upsilon_models::users::tokens::{PersonalAccessToken, PersonalAccessTokenId};
+use upsilon_models::users::two_factor::TwoFactor;
 use upsilon_models::users::{User, UserId, UserSshKey, Username, UsernameRef};
-use upsilon_stdx::TakeIfUnless;
 
 #[derive(Debug, thiserror::Error)]
 pub enum InMemoryError {
@@ -53,6 +62,8 @@ pub enum InMemoryError {
     OrganizationMembersNotFound,
     #[error("Team not found")]
     TeamNotFound,
+    #[error("Session not found")]
+    SessionNotFound,
 
     #[error("Name conflict")]
     NameConflict,
@@ -84,12 +95,24 @@ pub struct InMemoryStorageConfiguration {
 struct InMemoryDataStore {
     users: Arc<RwLock<BTreeMap<UserId, User>>>,
     repos: Arc<RwLock<BTreeMap<RepoId, Repo>>>,
+    /// Every (namespace, name) a repo had before a rename or move, oldest first.
+    repo_previous_names: Arc<RwLock<Vec<(RepoId, RepoNamespace, RepoName)>>>,
     organizations: Arc<RwLock<BTreeMap<OrganizationId, Organization>>>,
     organization_members:
         Arc<RwLock<BTreeMap<OrganizationId, BTreeMap<UserId, OrganizationMember>>>>,
     teams: Arc<RwLock<BTreeMap<TeamId, Team>>>,
     repo_permissions: Arc<RwLock<BTreeMap<RepoId, BTreeMap<UserId, RepoPermissions>>>>,
+    repo_team_permissions: Arc<RwLock<BTreeMap<RepoId, BTreeMap<TeamId, RepoPermissions>>>>,
     ssh_key_map: Arc<RwLock<Vec<(UserSshKey, UserId)>>>,
+    deploy_keys: Arc<RwLock<Vec<DeployKey>>>,
+    personal_access_tokens: Arc<RwLock<BTreeMap<PersonalAccessTokenId, PersonalAccessToken>>>,
+    sessions: Arc<RwLock<BTreeMap<SessionId, Session>>>,
+    external_identities: Arc<RwLock<Vec<ExternalIdentity>>>,
+    two_factor: Arc<RwLock<BTreeMap<UserId, TwoFactor>>>,
+    password_resets: Arc<RwLock<Vec<PasswordReset>>>,
+    invites: Arc<RwLock<BTreeMap<InviteId, Invite>>>,
+    /// By blocker.
+    user_blocks: Arc<RwLock<BTreeMap<UserId, BTreeSet<UserId>>>>,
 }
 
 impl InMemoryDataStore {
@@ -101,11 +124,21 @@ impl InMemoryDataStore {
         Self {
             users: new_map(),
             repos: new_map(),
+            repo_previous_names: Arc::new(RwLock::new(vec![])),
             organizations: new_map(),
             organization_members: new_map(),
             teams: new_map(),
             repo_permissions: new_map(),
+            repo_team_permissions: new_map(),
             ssh_key_map: Arc::new(RwLock::new(vec![])),
+            deploy_keys: Arc::new(RwLock::new(vec![])),
+            personal_access_tokens: new_map(),
+            sessions: new_map(),
+            external_identities: Arc::new(RwLock::new(vec![])),
+            two_factor: new_map(),
+            password_resets: Arc::new(RwLock::new(vec![])),
+            invites: new_map(),
+            user_blocks: new_map(),
         }
     }
 }
@@ -241,7 +274,7 @@ impl RwGuardKinds {
             NamespaceKind::Team => Self {
                 users: RwGuardKind::None,
                 orgs: RwGuardKind::None,
-                teams: RwGuardKind::None,
+                teams: RwGuardKind::Read,
                 repos: RwGuardKind::Read,
             },
         }
@@ -410,6 +443,15 @@ impl<'a> InMemoryNamespaceMutQueryLock<'a> {
                 }
             }
             NamespaceId::Team(org, team) => {
+                // the sub-teams live in the same directory as the repos
+                if self
+                    .teams()
+                    .values()
+                    .any(|it| it.parent == Some(team) && it.name == name)
+                {
+                    return false;
+                }
+
                 if self
                     .repos()
                     .values()
@@ -480,6 +522,12 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
         Ok(user.cloned())
     }
 
+    async fn query_users(&self) -> Result<Vec<User>, Self::Error> {
+        let lock = self.store().users.read().await;
+
+        Ok(lock.values().cloned().collect())
+    }
+
     async fn set_user_name(&self, user_id: UserId, user_name: Username) -> Result<(), Self::Error> {
         let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
             self.store(),
@@ -498,6 +546,102 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             .ok_or(InMemoryError::UserNotFound)
     }
 
+    async fn set_user_password(
+        &self,
+        user_id: UserId,
+        password: HashedPassword,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().users.write().await;
+
+        lock.get_mut(&user_id)
+            .map(|user| user.password = password)
+            .ok_or(InMemoryError::UserNotFound)
+    }
+
+    async fn set_user_admin(&self, user_id: UserId, admin: bool) -> Result<(), Self::Error> {
+        let mut lock = self.store().users.write().await;
+
+        lock.get_mut(&user_id)
+            .map(|user| user.admin = admin)
+            .ok_or(InMemoryError::UserNotFound)
+    }
+
+    async fn set_user_suspended(
+        &self,
+        user_id: UserId,
+        suspended: bool,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().users.write().await;
+
+        lock.get_mut(&user_id)
+            .map(|user| user.suspended = suspended)
+            .ok_or(InMemoryError::UserNotFound)
+    }
+
+    async fn set_user_pending_approval(
+        &self,
+        user_id: UserId,
+        pending_approval: bool,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().users.write().await;
+
+        lock.get_mut(&user_id)
+            .map(|user| user.pending_approval = pending_approval)
+            .ok_or(InMemoryError::UserNotFound)
+    }
+
+    async fn delete_user(&self, user_id: UserId) -> Result<bool, Self::Error> {
+        let store = self.store();
+
+        if store.users.write().await.remove(&user_id).is_none() {
+            return Ok(false);
+        }
+
+        store
+            .ssh_key_map
+            .write()
+            .await
+            .retain(|(_, user)| *user != user_id);
+        store
+            .personal_access_tokens
+            .write()
+            .await
+            .retain(|_, token| token.user != user_id);
+        store
+            .sessions
+            .write()
+            .await
+            .retain(|_, session| session.user != user_id);
+        store
+            .external_identities
+            .write()
+            .await
+            .retain(|identity| identity.user != user_id);
+        store.two_factor.write().await.remove(&user_id);
+        store
+            .password_resets
+            .write()
+            .await
+            .retain(|reset| reset.user != user_id);
+
+        let mut user_blocks = store.user_blocks.write().await;
+        user_blocks.remove(&user_id);
+        for blocked in user_blocks.values_mut() {
+            blocked.remove(&user_id);
+        }
+        drop(user_blocks);
+
+        for members in store.organization_members.write().await.values_mut() {
+            members.remove(&user_id);
+        }
+
+        for perms in store.repo_permissions.write().await.values_mut() {
+            perms.remove(&user_id);
+        }
+
+        Ok(true)
+    }
+
     async fn add_user_ssh_key(
         &self,
         user_id: UserId,
@@ -520,6 +664,295 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
         Ok(lock.iter().find_map(|(k, u)| (k == &key).then_some(*u)))
     }
 
+    async fn create_personal_access_token(
+        &self,
+        token: PersonalAccessToken,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().personal_access_tokens.write().await;
+
+        lock.insert(token.id, token);
+
+        Ok(())
+    }
+
+    async fn query_personal_access_token(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PersonalAccessToken>, Self::Error> {
+        let lock = self.store().personal_access_tokens.read().await;
+
+        Ok(lock
+            .values()
+            .find(|token| token.token_hash == token_hash)
+            .cloned())
+    }
+
+    async fn query_user_personal_access_tokens(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<PersonalAccessToken>, Self::Error> {
+        let lock = self.store().personal_access_tokens.read().await;
+
+        Ok(lock
+            .values()
+            .filter(|token| token.user == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_personal_access_token(
+        &self,
+        user_id: UserId,
+        token_id: PersonalAccessTokenId,
+    ) -> Result<bool, Self::Error> {
+        let mut lock = self.store().personal_access_tokens.write().await;
+
+        match lock.get(&token_id) {
+            Some(token) if token.user == user_id => {
+                lock.remove(&token_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_user_two_factor(&self, two_factor: TwoFactor) -> Result<(), Self::Error> {
+        let mut lock = self.store().two_factor.write().await;
+
+        lock.insert(two_factor.user, two_factor);
+
+        Ok(())
+    }
+
+    async fn query_user_two_factor(
+        &self,
+        user_id: UserId,
+    ) -> Result<Option<TwoFactor>, Self::Error> {
+        let lock = self.store().two_factor.read().await;
+
+        Ok(lock.get(&user_id).cloned())
+    }
+
+    async fn delete_user_two_factor(&self, user_id: UserId) -> Result<bool, Self::Error> {
+        let mut lock = self.store().two_factor.write().await;
+
+        Ok(lock.remove(&user_id).is_some())
+    }
+
+    async fn create_external_identity(
+        &self,
+        identity: ExternalIdentity,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().external_identities.write().await;
+
+        lock.push(identity);
+
+        Ok(())
+    }
+
+    async fn query_external_identity(
+        &self,
+        provider: String,
+        subject: String,
+    ) -> Result<Option<ExternalIdentity>, Self::Error> {
+        let lock = self.store().external_identities.read().await;
+
+        Ok(lock
+            .iter()
+            .find(|identity| identity.provider == provider && identity.subject == subject)
+            .cloned())
+    }
+
+    async fn query_user_external_identities(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<ExternalIdentity>, Self::Error> {
+        let lock = self.store().external_identities.read().await;
+
+        Ok(lock
+            .iter()
+            .filter(|identity| identity.user == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_session(&self, session: Session) -> Result<(), Self::Error> {
+        let mut lock = self.store().sessions.write().await;
+
+        lock.insert(session.id, session);
+
+        Ok(())
+    }
+
+    async fn update_session(&self, session: Session) -> Result<(), Self::Error> {
+        let mut lock = self.store().sessions.write().await;
+
+        lock.get_mut(&session.id)
+            .map(|it| *it = session)
+            .ok_or(InMemoryError::SessionNotFound)
+    }
+
+    async fn query_session(&self, session_id: SessionId) -> Result<Option<Session>, Self::Error> {
+        let lock = self.store().sessions.read().await;
+
+        Ok(lock.get(&session_id).cloned())
+    }
+
+    async fn query_session_by_refresh_token(
+        &self,
+        refresh_token_hash: String,
+    ) -> Result<Option<Session>, Self::Error> {
+        let lock = self.store().sessions.read().await;
+
+        Ok(lock
+            .values()
+            .find(|session| {
+                session.refresh_token_hash == refresh_token_hash
+                    || session
+                        .used_refresh_token_hashes
+                        .contains(&refresh_token_hash)
+            })
+            .cloned())
+    }
+
+    async fn query_user_sessions(&self, user_id: UserId) -> Result<Vec<Session>, Self::Error> {
+        let lock = self.store().sessions.read().await;
+
+        Ok(lock
+            .values()
+            .filter(|session| session.user == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_session(
+        &self,
+        user_id: UserId,
+        session_id: SessionId,
+    ) -> Result<bool, Self::Error> {
+        let mut lock = self.store().sessions.write().await;
+
+        match lock.get(&session_id) {
+            Some(session) if session.user == user_id => {
+                lock.remove(&session_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_user_sessions(&self, user_id: UserId) -> Result<(), Self::Error> {
+        let mut lock = self.store().sessions.write().await;
+
+        lock.retain(|_, session| session.user != user_id);
+
+        Ok(())
+    }
+
+    async fn create_password_reset(&self, reset: PasswordReset) -> Result<(), Self::Error> {
+        let mut lock = self.store().password_resets.write().await;
+
+        lock.push(reset);
+
+        Ok(())
+    }
+
+    async fn query_password_reset(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<PasswordReset>, Self::Error> {
+        let lock = self.store().password_resets.read().await;
+
+        Ok(lock
+            .iter()
+            .find(|reset| reset.token_hash == token_hash)
+            .cloned())
+    }
+
+    async fn delete_user_password_resets(&self, user_id: UserId) -> Result<(), Self::Error> {
+        let mut lock = self.store().password_resets.write().await;
+
+        lock.retain(|reset| reset.user != user_id);
+
+        Ok(())
+    }
+
+    async fn create_invite(&self, invite: Invite) -> Result<(), Self::Error> {
+        let mut lock = self.store().invites.write().await;
+
+        lock.insert(invite.id, invite);
+
+        Ok(())
+    }
+
+    async fn query_invite_by_code(&self, code_hash: String) -> Result<Option<Invite>, Self::Error> {
+        let lock = self.store().invites.read().await;
+
+        Ok(lock
+            .values()
+            .find(|invite| invite.code_hash == code_hash)
+            .cloned())
+    }
+
+    async fn query_invites(&self) -> Result<Vec<Invite>, Self::Error> {
+        let lock = self.store().invites.read().await;
+
+        Ok(lock.values().cloned().collect())
+    }
+
+    async fn use_invite(&self, invite_id: InviteId, user_id: UserId) -> Result<bool, Self::Error> {
+        let mut lock = self.store().invites.write().await;
+
+        match lock.get_mut(&invite_id) {
+            Some(invite) if invite.used_by.is_none() => {
+                invite.used_by = Some(user_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_invite(&self, invite_id: InviteId) -> Result<bool, Self::Error> {
+        let mut lock = self.store().invites.write().await;
+
+        Ok(lock.remove(&invite_id).is_some())
+    }
+
+    async fn block_user(&self, blocker: UserId, blocked: UserId) -> Result<bool, Self::Error> {
+        let mut lock = self.store().user_blocks.write().await;
+
+        Ok(lock.entry(blocker).or_default().insert(blocked))
+    }
+
+    async fn unblock_user(&self, blocker: UserId, blocked: UserId) -> Result<bool, Self::Error> {
+        let mut lock = self.store().user_blocks.write().await;
+
+        Ok(lock
+            .get_mut(&blocker)
+            .map_or(false, |blocked_users| blocked_users.remove(&blocked)))
+    }
+
+    async fn query_user_blocked(
+        &self,
+        blocker: UserId,
+        blocked: UserId,
+    ) -> Result<bool, Self::Error> {
+        let lock = self.store().user_blocks.read().await;
+
+        Ok(lock
+            .get(&blocker)
+            .map_or(false, |blocked_users| blocked_users.contains(&blocked)))
+    }
+
+    async fn query_blocked_users(&self, blocker: UserId) -> Result<Vec<UserId>, Self::Error> {
+        let lock = self.store().user_blocks.read().await;
+
+        Ok(lock
+            .get(&blocker)
+            .map(|blocked_users| blocked_users.iter().copied().collect())
+            .unwrap_or_default())
+    }
+
     async fn create_repo(&self, repo: Repo) -> Result<(), Self::Error> {
         let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
             self.store(),
@@ -561,6 +994,32 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
         Ok(repo.cloned())
     }
 
+    async fn query_repo_by_previous_name<'self_ref>(
+        &'self_ref self,
+        repo_name: RepoNameRef<'self_ref>,
+        repo_namespace: &RepoNamespace,
+    ) -> Result<Option<Repo>, Self::Error> {
+        let previous_names_lock = self.store().repo_previous_names.read().await;
+
+        let Some(&(repo_id, _, _)) = previous_names_lock
+            .iter()
+            .rev()
+            .find(|(_, namespace, name)| *name == repo_name && namespace == repo_namespace)
+        else {
+            return Ok(None);
+        };
+
+        let lock = self.store().repos.read().await;
+
+        Ok(lock.get(&repo_id).cloned())
+    }
+
+    async fn query_repos(&self) -> Result<Vec<Repo>, Self::Error> {
+        let lock = self.store().repos.read().await;
+
+        Ok(lock.values().cloned().collect())
+    }
+
     async fn set_repo_name(&self, repo_id: RepoId, repo_name: RepoName) -> Result<(), Self::Error> {
         let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
             self.store(),
@@ -574,14 +1033,24 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
         )
         .await;
 
-        let repo_ns = ns_query_lock
+        let (repo_ns, old_name) = ns_query_lock
             .repos()
             .get(&repo_id)
-            .map(|repo| repo.namespace)
+            .map(|repo| (repo.namespace, repo.name.clone()))
             .ok_or(InMemoryError::RepoNotFound)?;
 
+        if old_name == repo_name {
+            return Ok(());
+        }
+
         ns_query_lock.check_allows_name_in_namespace(repo_name.as_str(), repo_ns.0)?;
 
+        self.store()
+            .repo_previous_names
+            .write()
+            .await
+            .push((repo_id, repo_ns, old_name));
+
         ns_query_lock
             .repos_mut()
             .get_mut(&repo_id)
@@ -589,6 +1058,198 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             .ok_or(InMemoryError::RepoNotFound)
     }
 
+    async fn set_repo_namespace(
+        &self,
+        repo_id: RepoId,
+        repo_namespace: RepoNamespace,
+    ) -> Result<(), Self::Error> {
+        let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
+            self.store(),
+            NamespaceKind::Global,
+            |it| {
+                it.need_users(RwGuardKind::Read)
+                    .need_orgs(RwGuardKind::Read)
+                    .need_teams(RwGuardKind::Read)
+                    .need_repos(RwGuardKind::Write)
+            },
+        )
+        .await;
+
+        let (old_namespace, repo_name) = ns_query_lock
+            .repos()
+            .get(&repo_id)
+            .map(|repo| (repo.namespace, repo.name.clone()))
+            .ok_or(InMemoryError::RepoNotFound)?;
+
+        if old_namespace == repo_namespace {
+            return Ok(());
+        }
+
+        ns_query_lock.check_allows_name_in_namespace(repo_name.as_str(), repo_namespace.0)?;
+
+        self.store().repo_previous_names.write().await.push((
+            repo_id,
+            old_namespace,
+            repo_name.clone(),
+        ));
+
+        ns_query_lock
+            .repos_mut()
+            .get_mut(&repo_id)
+            .map(|repo| repo.namespace = repo_namespace)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_display_name(
+        &self,
+        repo_id: RepoId,
+        repo_display_name: Option<RepoDisplayName>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.display_name = repo_display_name)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_description(
+        &self,
+        repo_id: RepoId,
+        repo_description: Option<RepoDescription>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.description = repo_description)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_website(
+        &self,
+        repo_id: RepoId,
+        repo_website: Option<RepoWebsite>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.website = repo_website)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_topics(
+        &self,
+        repo_id: RepoId,
+        repo_topics: Vec<RepoTopic>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.topics = repo_topics)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_default_branch(
+        &self,
+        repo_id: RepoId,
+        default_branch: Option<RepoBranchName>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.default_branch = default_branch)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_protected_branches(
+        &self,
+        repo_id: RepoId,
+        protected_branches: Vec<BranchProtectionRule>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.repo_config.protected_branches = protected_branches)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_protected_tags(
+        &self,
+        repo_id: RepoId,
+        protected_tags: Vec<TagProtectionRule>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.repo_config.protected_tags = protected_tags)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn set_repo_global_permissions(
+        &self,
+        repo_id: RepoId,
+        global_permissions: RepoPermissions,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repos.write().await;
+
+        lock.get_mut(&repo_id)
+            .map(|repo| repo.repo_config.global_permissions = global_permissions)
+            .ok_or(InMemoryError::RepoNotFound)
+    }
+
+    async fn query_repos_with_topic<'self_ref>(
+        &'self_ref self,
+        topic: RepoTopicRef<'self_ref>,
+    ) -> Result<Vec<Repo>, Self::Error> {
+        let lock = self.store().repos.read().await;
+
+        Ok(lock
+            .values()
+            .filter(|repo| repo.topics.iter().any(|it| *it == topic))
+            .cloned()
+            .collect())
+    }
+
+    async fn add_deploy_key(&self, deploy_key: DeployKey) -> Result<bool, Self::Error> {
+        let mut lock = self.store().deploy_keys.write().await;
+
+        if lock.iter().any(|it| it.key == deploy_key.key) {
+            return Ok(false);
+        }
+
+        lock.push(deploy_key);
+
+        Ok(true)
+    }
+
+    async fn query_deploy_key(&self, key: UserSshKey) -> Result<Option<DeployKey>, Self::Error> {
+        let lock = self.store().deploy_keys.read().await;
+
+        Ok(lock.iter().find(|it| it.key == key).cloned())
+    }
+
+    async fn query_repo_deploy_keys(&self, repo_id: RepoId) -> Result<Vec<DeployKey>, Self::Error> {
+        let lock = self.store().deploy_keys.read().await;
+
+        Ok(lock
+            .iter()
+            .filter(|it| it.repo == repo_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_deploy_key(
+        &self,
+        repo_id: RepoId,
+        deploy_key_id: DeployKeyId,
+    ) -> Result<bool, Self::Error> {
+        let mut lock = self.store().deploy_keys.write().await;
+
+        let len = lock.len();
+        lock.retain(|it| !(it.repo == repo_id && it.id == deploy_key_id));
+
+        Ok(lock.len() != len)
+    }
+
     async fn init_repo_user_perms(
         &self,
         repo_id: RepoId,
@@ -666,6 +1327,74 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             .ok_or(InMemoryError::PermsNotFound)
     }
 
+    async fn set_repo_user_perms(
+        &self,
+        repo_id: RepoId,
+        user_id: UserId,
+        perms: Option<RepoPermissions>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repo_permissions.write().await;
+
+        let repo_perms_map = lock.entry(repo_id).or_default();
+
+        match perms {
+            Some(perms) => {
+                repo_perms_map.insert(user_id, perms);
+            }
+            None => {
+                repo_perms_map.remove(&user_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn query_repo_team_perms(
+        &self,
+        repo_id: RepoId,
+    ) -> Result<Vec<(TeamId, RepoPermissions)>, Self::Error> {
+        let lock = self.store().repo_team_permissions.read().await;
+
+        Ok(lock
+            .get(&repo_id)
+            .map(|map| map.iter().map(|(k, v)| (*k, *v)).collect())
+            .unwrap_or_default())
+    }
+
+    async fn set_repo_team_perms(
+        &self,
+        repo_id: RepoId,
+        team_id: TeamId,
+        perms: Option<RepoPermissions>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().repo_team_permissions.write().await;
+
+        let repo_perms_map = lock.entry(repo_id).or_default();
+
+        match perms {
+            Some(perms) => {
+                repo_perms_map.insert(team_id, perms);
+            }
+            None => {
+                repo_perms_map.remove(&team_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn query_team_repo_perms(
+        &self,
+        team_id: TeamId,
+    ) -> Result<Vec<(RepoId, RepoPermissions)>, Self::Error> {
+        let lock = self.store().repo_team_permissions.read().await;
+
+        Ok(lock
+            .iter()
+            .filter_map(|(repo_id, map)| map.get(&team_id).map(|perms| (*repo_id, *perms)))
+            .collect())
+    }
+
     async fn create_organization(&self, org: Organization) -> Result<(), Self::Error> {
         let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
             self.store(),
@@ -704,6 +1433,12 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
         Ok(org.cloned())
     }
 
+    async fn query_organizations(&self) -> Result<Vec<Organization>, Self::Error> {
+        let lock = self.store().organizations.read().await;
+
+        Ok(lock.values().cloned().collect())
+    }
+
     async fn set_organization_name(
         &self,
         org_id: OrganizationId,
@@ -738,6 +1473,30 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             .ok_or(InMemoryError::OrganizationNotFound)
     }
 
+    async fn set_organization_email(
+        &self,
+        org_id: OrganizationId,
+        email: Option<Email>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().organizations.write().await;
+
+        lock.get_mut(&org_id)
+            .map(|org| org.email = email)
+            .ok_or(InMemoryError::OrganizationNotFound)
+    }
+
+    async fn set_organization_base_role(
+        &self,
+        org_id: OrganizationId,
+        base_role: Option<RepoRole>,
+    ) -> Result<(), Self::Error> {
+        let mut lock = self.store().organizations.write().await;
+
+        lock.get_mut(&org_id)
+            .map(|org| org.base_role = base_role)
+            .ok_or(InMemoryError::OrganizationNotFound)
+    }
+
     async fn query_organization_member(
         &self,
         org_id: OrganizationId,
@@ -770,11 +1529,33 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
 
         Ok(lock
             .values()
-            .filter_map(|members| members.take_if(|members| members.contains_key(&user_id)))
-            .flat_map(|members| members.values().cloned())
+            .filter_map(|members| members.get(&user_id).cloned())
             .collect())
     }
 
+    async fn set_organization_member(&self, member: OrganizationMember) -> Result<(), Self::Error> {
+        let mut lock = self.store().organization_members.write().await;
+
+        lock.entry(member.organization_id)
+            .or_default()
+            .insert(member.user_id, member);
+
+        Ok(())
+    }
+
+    async fn remove_organization_member(
+        &self,
+        org_id: OrganizationId,
+        user_id: UserId,
+    ) -> Result<bool, Self::Error> {
+        let mut lock = self.store().organization_members.write().await;
+
+        Ok(lock
+            .get_mut(&org_id)
+            .and_then(|members| members.remove(&user_id))
+            .is_some())
+    }
+
     async fn create_team(&self, team: Team) -> Result<(), Self::Error> {
         let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
             self.store(),
@@ -788,6 +1569,13 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             NamespaceId::Organization(team.organization_id),
         )?;
 
+        if let Some(parent) = team.parent {
+            ns_query_lock.check_allows_name_in_namespace(
+                team.name.as_str(),
+                NamespaceId::Team(team.organization_id, parent),
+            )?;
+        }
+
         ns_query_lock.teams_mut().insert(team.id, team);
 
         Ok(())
@@ -866,6 +1654,44 @@ impl<'a> DataClientQueryImpl<'a> for InMemoryQueryImpl<'a> {
             .ok_or(InMemoryError::TeamNotFound)
     }
 
+    async fn set_team_parent(
+        &self,
+        team_id: TeamId,
+        parent: Option<TeamId>,
+    ) -> Result<(), Self::Error> {
+        let mut ns_query_lock = InMemoryNamespaceMutQueryLock::for_namespace_kind(
+            self.store(),
+            NamespaceKind::Team,
+            |it| it.need_teams(RwGuardKind::Write),
+        )
+        .await;
+
+        let team = ns_query_lock
+            .teams()
+            .get(&team_id)
+            .cloned()
+            .ok_or(InMemoryError::TeamNotFound)?;
+
+        if team.parent == parent {
+            return Ok(());
+        }
+
+        // Top-level teams cannot conflict with the repos of the organization,
+        // as team names are checked against them wherever the team is.
+        if let Some(parent) = parent {
+            ns_query_lock.check_allows_name_in_namespace(
+                team.name.as_str(),
+                NamespaceId::Team(team.organization_id, parent),
+            )?;
+        }
+
+        ns_query_lock
+            .teams_mut()
+            .get_mut(&team_id)
+            .map(|team| team.parent = parent)
+            .ok_or(InMemoryError::TeamNotFound)
+    }
+
     async fn query_organization_and_team(
         &self,
         team_id: TeamId,
@@ -209,6 +209,7 @@ query_impl_trait! {
     async fn query_user<'self_ref>(
         {into} user_id: upsilon_models::users::UserId
     ) -> upsilon_models::users::User;
+    async fn query_users<'self_ref>() -> Vec<upsilon_models::users::User>;
     async fn query_user_by_username_email<'self_ref>(
         username_email: &str,
     ) -> Option<upsilon_models::users::User>;
@@ -219,6 +220,28 @@ query_impl_trait! {
         {into} user_id: upsilon_models::users::UserId,
         {into} user_name: upsilon_models::users::Username,
     );
+    async fn set_user_password<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        {into} password: upsilon_models::users::password::HashedPassword,
+    );
+    async fn set_user_admin<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        admin: bool,
+    );
+    async fn set_user_suspended<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        suspended: bool,
+    );
+    async fn set_user_pending_approval<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        pending_approval: bool,
+    );
+    // removes the user along with everything that only makes sense while the user exists:
+    // ssh keys, tokens, sessions, 2FA, external identities, password resets,
+    // blocks, organization memberships and repo permissions
+    async fn delete_user<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> bool;
     async fn add_user_ssh_key<'self_ref>(
         {into} user_id: upsilon_models::users::UserId,
         {into} key: upsilon_models::users::UserSshKey,
@@ -226,6 +249,101 @@ query_impl_trait! {
     async fn query_user_ssh_key<'self_ref>(
         {into} key: upsilon_models::users::UserSshKey,
     ) -> Option<upsilon_models::users::UserId>;
+    async fn create_personal_access_token<'self_ref>(
+        token: upsilon_models::users::tokens::PersonalAccessToken,
+    );
+    async fn query_personal_access_token<'self_ref>(
+        token_hash: String,
+    ) -> Option<upsilon_models::users::tokens::PersonalAccessToken>;
+    async fn query_user_personal_access_tokens<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> Vec<upsilon_models::users::tokens::PersonalAccessToken>;
+    async fn delete_personal_access_token<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        {into} token_id: upsilon_models::users::tokens::PersonalAccessTokenId,
+    ) -> bool;
+    async fn set_user_two_factor<'self_ref>(
+        two_factor: upsilon_models::users::two_factor::TwoFactor,
+    );
+    async fn query_user_two_factor<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> Option<upsilon_models::users::two_factor::TwoFactor>;
+    async fn delete_user_two_factor<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> bool;
+    async fn create_external_identity<'self_ref>(
+        identity: upsilon_models::users::identities::ExternalIdentity,
+    );
+    async fn query_external_identity<'self_ref>(
+        provider: String,
+        subject: String,
+    ) -> Option<upsilon_models::users::identities::ExternalIdentity>;
+    async fn query_user_external_identities<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> Vec<upsilon_models::users::identities::ExternalIdentity>;
+    async fn create_session<'self_ref>(
+        session: upsilon_models::users::sessions::Session,
+    );
+    async fn update_session<'self_ref>(
+        session: upsilon_models::users::sessions::Session,
+    );
+    async fn query_session<'self_ref>(
+        {into} session_id: upsilon_models::users::sessions::SessionId,
+    ) -> Option<upsilon_models::users::sessions::Session>;
+    // finds the session by either its current or one of its used refresh tokens
+    async fn query_session_by_refresh_token<'self_ref>(
+        refresh_token_hash: String,
+    ) -> Option<upsilon_models::users::sessions::Session>;
+    async fn query_user_sessions<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> Vec<upsilon_models::users::sessions::Session>;
+    async fn delete_session<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+        {into} session_id: upsilon_models::users::sessions::SessionId,
+    ) -> bool;
+    async fn delete_user_sessions<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    );
+    async fn create_password_reset<'self_ref>(
+        reset: upsilon_models::users::password_resets::PasswordReset,
+    );
+    async fn query_password_reset<'self_ref>(
+        token_hash: String,
+    ) -> Option<upsilon_models::users::password_resets::PasswordReset>;
+    async fn delete_user_password_resets<'self_ref>(
+        {into} user_id: upsilon_models::users::UserId,
+    );
+    async fn create_invite<'self_ref>(
+        invite: upsilon_models::users::invites::Invite,
+    );
+    async fn query_invite_by_code<'self_ref>(
+        code_hash: String,
+    ) -> Option<upsilon_models::users::invites::Invite>;
+    async fn query_invites<'self_ref>() -> Vec<upsilon_models::users::invites::Invite>;
+    // marks the invite as used by `user_id`, unless it was already used
+    async fn use_invite<'self_ref>(
+        {into} invite_id: upsilon_models::users::invites::InviteId,
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> bool;
+    async fn delete_invite<'self_ref>(
+        {into} invite_id: upsilon_models::users::invites::InviteId,
+    ) -> bool;
+    // returns false if `blocked` was already blocked by `blocker`
+    async fn block_user<'self_ref>(
+        {into} blocker: upsilon_models::users::UserId,
+        {into} blocked: upsilon_models::users::UserId,
+    ) -> bool;
+    async fn unblock_user<'self_ref>(
+        {into} blocker: upsilon_models::users::UserId,
+        {into} blocked: upsilon_models::users::UserId,
+    ) -> bool;
+    async fn query_user_blocked<'self_ref>(
+        {into} blocker: upsilon_models::users::UserId,
+        {into} blocked: upsilon_models::users::UserId,
+    ) -> bool;
+    async fn query_blocked_users<'self_ref>(
+        {into} blocker: upsilon_models::users::UserId,
+    ) -> Vec<upsilon_models::users::UserId>;
 
     // ===========================
     // ======== Repos ============
@@ -238,10 +356,68 @@ query_impl_trait! {
         {into} repo_name: upsilon_models::repo::RepoNameRef<'self_ref>,
         {into} repo_namespace: &upsilon_models::repo::RepoNamespace,
     ) -> Option<upsilon_models::repo::Repo>;
+    // the repo that most recently had this name, if it was renamed or moved since
+    async fn query_repo_by_previous_name<'self_ref>(
+        {into} repo_name: upsilon_models::repo::RepoNameRef<'self_ref>,
+        {into} repo_namespace: &upsilon_models::repo::RepoNamespace,
+    ) -> Option<upsilon_models::repo::Repo>;
+    async fn query_repos<'self_ref>() -> Vec<upsilon_models::repo::Repo>;
     async fn set_repo_name<'self_ref>(
         {into} repo_id: upsilon_models::repo::RepoId,
         {into} repo_name: upsilon_models::repo::RepoName,
     );
+    async fn set_repo_namespace<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} repo_namespace: upsilon_models::repo::RepoNamespace,
+    );
+    async fn set_repo_display_name<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} repo_display_name: Option<upsilon_models::repo::RepoDisplayName>,
+    );
+    async fn set_repo_description<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} repo_description: Option<upsilon_models::repo::RepoDescription>,
+    );
+    async fn set_repo_website<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} repo_website: Option<upsilon_models::repo::RepoWebsite>,
+    );
+    async fn set_repo_topics<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        repo_topics: Vec<upsilon_models::repo::RepoTopic>,
+    );
+    async fn set_repo_default_branch<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} default_branch: Option<upsilon_models::repo::RepoBranchName>,
+    );
+    async fn set_repo_protected_branches<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        protected_branches: Vec<upsilon_models::repo::BranchProtectionRule>,
+    );
+    async fn set_repo_protected_tags<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        protected_tags: Vec<upsilon_models::repo::TagProtectionRule>,
+    );
+    async fn set_repo_global_permissions<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} global_permissions: upsilon_models::repo::RepoPermissions,
+    );
+    async fn query_repos_with_topic<'self_ref>(
+        {into} topic: upsilon_models::repo::RepoTopicRef<'self_ref>,
+    ) -> Vec<upsilon_models::repo::Repo>;
+    async fn add_deploy_key<'self_ref>(
+        deploy_key: upsilon_models::repo::DeployKey,
+    ) -> bool;
+    async fn query_deploy_key<'self_ref>(
+        {into} key: upsilon_models::users::UserSshKey,
+    ) -> Option<upsilon_models::repo::DeployKey>;
+    async fn query_repo_deploy_keys<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+    ) -> Vec<upsilon_models::repo::DeployKey>;
+    async fn delete_deploy_key<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} deploy_key_id: upsilon_models::repo::DeployKeyId,
+    ) -> bool;
     async fn init_repo_user_perms<'self_ref>(
         {into} repo_id: upsilon_models::repo::RepoId,
         {into} user_id: upsilon_models::users::UserId,
@@ -260,6 +436,28 @@ query_impl_trait! {
         {into} user_id: upsilon_models::users::UserId,
         {into} perms: upsilon_models::repo::RepoPermissions,
     ) -> upsilon_models::repo::RepoPermissions;
+    async fn set_repo_user_perms<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} user_id: upsilon_models::users::UserId,
+        {into} perms: Option<upsilon_models::repo::RepoPermissions>,
+    );
+    async fn query_repo_team_perms<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+    ) -> Vec<(
+        upsilon_models::organization::TeamId,
+        upsilon_models::repo::RepoPermissions,
+    )>;
+    async fn set_repo_team_perms<'self_ref>(
+        {into} repo_id: upsilon_models::repo::RepoId,
+        {into} team_id: upsilon_models::organization::TeamId,
+        {into} perms: Option<upsilon_models::repo::RepoPermissions>,
+    );
+    async fn query_team_repo_perms<'self_ref>(
+        {into} team_id: upsilon_models::organization::TeamId,
+    ) -> Vec<(
+        upsilon_models::repo::RepoId,
+        upsilon_models::repo::RepoPermissions,
+    )>;
 
     // ================================
     // ======== Organizations =========
@@ -273,6 +471,7 @@ query_impl_trait! {
     async fn query_organization_by_name<'self_ref>(
         {into} org_name: upsilon_models::organization::OrganizationNameRef<'self_ref>,
     ) -> Option<upsilon_models::organization::Organization>;
+    async fn query_organizations<'self_ref>() -> Vec<upsilon_models::organization::Organization>;
     async fn set_organization_name<'self_ref>(
         {into} org_id: upsilon_models::organization::OrganizationId,
         {into} org_name: upsilon_models::organization::OrganizationName,
@@ -281,6 +480,14 @@ query_impl_trait! {
         {into} org_id: upsilon_models::organization::OrganizationId,
         {into} org_display_name: Option<upsilon_models::organization::OrganizationDisplayName>,
     );
+    async fn set_organization_email<'self_ref>(
+        {into} org_id: upsilon_models::organization::OrganizationId,
+        {into} email: Option<upsilon_models::email::Email>,
+    );
+    async fn set_organization_base_role<'self_ref>(
+        {into} org_id: upsilon_models::organization::OrganizationId,
+        {into} base_role: Option<upsilon_models::repo::RepoRole>,
+    );
 
     async fn query_organization_member<'self_ref>(
         {into} org_id: upsilon_models::organization::OrganizationId,
@@ -295,6 +502,15 @@ query_impl_trait! {
         {into} user_id: upsilon_models::users::UserId,
     ) -> Vec<upsilon_models::organization::OrganizationMember>;
 
+    async fn set_organization_member<'self_ref>(
+        member: upsilon_models::organization::OrganizationMember,
+    );
+
+    async fn remove_organization_member<'self_ref>(
+        {into} org_id: upsilon_models::organization::OrganizationId,
+        {into} user_id: upsilon_models::users::UserId,
+    ) -> bool;
+
     // ===========================
     // ======== Teams ============
     // ===========================
@@ -319,6 +535,10 @@ query_impl_trait! {
         {into} team_id: upsilon_models::organization::TeamId,
         {into} team_display_name: Option<upsilon_models::organization::TeamDisplayName>,
     );
+    async fn set_team_parent<'self_ref>(
+        {into} team_id: upsilon_models::organization::TeamId,
+        {into} parent: Option<upsilon_models::organization::TeamId>,
+    );
 
     async fn query_organization_and_team<'self_ref>(
         {into} team_id: upsilon_models::organization::TeamId,
@@ -15,7 +15,7 @@
  */
 
 use clap::Parser;
-use upsilon_git_hooks::repo_config::RepoConfig;
+use upsilon_git_hooks::repo_config::{RepoConfig, RepoConfigError};
 
 use crate::app::GitHook;
 use crate::sha_sha_ref::ShaShaRefLines;
@@ -27,11 +27,13 @@ pub struct PostReceive {
     pub lines: ShaShaRefLines,
 
     #[clap(skip = RepoConfig::from_env())]
-    pub repo_config: RepoConfig,
+    pub repo_config: Result<RepoConfig, RepoConfigError>,
 }
 
 fn run_hook(hook: PostReceive) -> GitHookResult<()> {
     let PostReceive { lines, repo_config } = hook;
+    // the push went through already, so there is nothing left to reject
+    let repo_config = repo_config?;
 
     println!("post-receive");
     dbg!(&repo_config);
@@ -14,8 +14,10 @@
  *    limitations under the License.
  */
 
+use std::process::exit;
+
 use clap::Parser;
-use upsilon_git_hooks::repo_config::RepoConfig;
+use upsilon_git_hooks::repo_config::{RepoConfig, RepoConfigError};
 
 use crate::app::GitHook;
 use crate::sha_sha_ref::ShaShaRefLines;
@@ -27,12 +29,22 @@ pub struct PreReceive {
     pub lines: ShaShaRefLines,
 
     #[clap(skip = RepoConfig::from_env())]
-    pub repo_config: RepoConfig,
+    pub repo_config: Result<RepoConfig, RepoConfigError>,
 }
 
 fn run_hook(hook: PreReceive) -> GitHookResult<()> {
     let PreReceive { lines, repo_config } = hook;
 
+    let repo_config = match repo_config {
+        Ok(repo_config) => repo_config,
+        Err(e) => {
+            println!(
+                "pre-receive: rejecting the push, the repository settings are unreadable: {e}"
+            );
+            exit(1);
+        }
+    };
+
     println!("pre-receive");
     dbg!(&repo_config);
 
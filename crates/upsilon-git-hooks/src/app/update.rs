@@ -17,7 +17,8 @@
 use std::process::exit;
 
 use clap::Parser;
-use upsilon_git_hooks::repo_config::RepoConfig;
+use git2::{Oid, Repository};
+use upsilon_git_hooks::repo_config::{RefUpdate, RefUpdateKind, RepoConfig, RepoConfigError};
 use upsilon_git_hooks::user_config::UserConfig;
 
 use crate::app::GitHook;
@@ -30,11 +31,72 @@ pub struct Update {
     pub new_oid: String,
 
     #[clap(skip = RepoConfig::from_env())]
-    pub repo_config: RepoConfig,
+    pub repo_config: Result<RepoConfig, RepoConfigError>,
     #[clap(skip = UserConfig::from_env())]
     pub user_config: UserConfig,
 }
 
+fn introduces_merges(repo: &Repository, old: Oid, new: Oid) -> GitHookResult<bool> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(new)?;
+
+    if old.is_zero() {
+        // a new ref; only look at the commits no other branch has yet
+        for reference in repo.references_glob("refs/heads/*")? {
+            if let Some(oid) = reference?.target() {
+                revwalk.hide(oid)?;
+            }
+        }
+    } else {
+        revwalk.hide(old)?;
+    }
+
+    for oid in revwalk {
+        if repo.find_commit(oid?)?.parent_count() > 1 {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn ref_update<'a>(
+    repo: &Repository,
+    ref_name: &'a str,
+    old_oid: &str,
+    new_oid: &str,
+) -> GitHookResult<RefUpdate<'a>> {
+    let old = Oid::from_str(old_oid)?;
+    let new = Oid::from_str(new_oid)?;
+
+    let kind = if old.is_zero() {
+        RefUpdateKind::Create
+    } else if new.is_zero() {
+        RefUpdateKind::Delete
+    } else if !ref_name.starts_with("refs/heads/") {
+        // tags (and other refs) can point to non-commit objects, so
+        // treat every other change as a rewrite
+        return Ok(RefUpdate {
+            ref_name,
+            kind: RefUpdateKind::NonFastForward,
+            introduces_merges: false,
+        });
+    } else if repo.graph_descendant_of(new, old)? {
+        RefUpdateKind::FastForward
+    } else {
+        RefUpdateKind::NonFastForward
+    };
+
+    let introduces_merges =
+        !new.is_zero() && ref_name.starts_with("refs/heads/") && introduces_merges(repo, old, new)?;
+
+    Ok(RefUpdate {
+        ref_name,
+        kind,
+        introduces_merges,
+    })
+}
+
 fn run_hook(hook: Update) -> GitHookResult<()> {
     let Update {
         ref_name,
@@ -44,18 +106,37 @@ fn run_hook(hook: Update) -> GitHookResult<()> {
         user_config,
     } = hook;
 
-    println!("update {ref_name} {old_oid} {new_oid}");
-    dbg!(&repo_config);
-
-    for rule in repo_config
-        .protected_branches
-        .iter()
-        .filter(|it| format!("refs/heads/{}", it.name) == ref_name)
-    {
-        if rule.needs_admin && !user_config.permissions.has_admin {
-            println!("update: protected branch, needs admin");
+    let repo_config = match repo_config {
+        Ok(repo_config) => repo_config,
+        Err(e) => {
+            println!("update: rejecting {ref_name}, the repository settings are unreadable: {e}");
             exit(1);
         }
+    };
+
+    let repo = Repository::open_from_env()?;
+    let update = ref_update(&repo, &ref_name, &old_oid, &new_oid)?;
+
+    let violations = repo_config.check_update(&update, &user_config);
+
+    for (rule, violation) in &violations {
+        println!(
+            "update: {ref_name} is protected by rule `{}`: {violation}",
+            rule.name
+        );
+    }
+
+    let tag_violations = repo_config.check_tag_update(&update, &user_config);
+
+    for rule in &tag_violations {
+        println!(
+            "update: {ref_name} is protected by tag rule `{}`: it cannot be moved or deleted",
+            rule.name
+        );
+    }
+
+    if !violations.is_empty() || !tag_violations.is_empty() {
+        exit(1);
     }
 
     Ok(())
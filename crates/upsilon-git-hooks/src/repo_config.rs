@@ -14,26 +14,298 @@
  *    limitations under the License.
  */
 
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub use glob::PatternError;
+use glob::{MatchOptions, Pattern};
+
+use crate::user_config::UserConfig;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct RepoConfig {
     pub protected_branches: Vec<ProtectedBranchRule>,
+    #[serde(default)]
+    pub protected_tags: Vec<ProtectedTagRule>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ProtectedBranchRule {
+    /// A glob pattern matched against the branch name, without `refs/heads/`.
     pub name: String,
     pub needs_admin: bool,
+    #[serde(default)]
+    pub block_force_push: bool,
+    #[serde(default)]
+    pub block_deletion: bool,
+    #[serde(default)]
+    pub require_linear_history: bool,
+    /// If this or `push_teams` is not empty, only the users listed here,
+    /// the members of `push_teams` and admins can push.
+    #[serde(default)]
+    pub push_users: Vec<String>,
+    #[serde(default)]
+    pub push_teams: Vec<String>,
+}
+
+/// Anyone who can push can create tags matching the rule, but only admins
+/// and `allowed_users` can move or delete them.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProtectedTagRule {
+    /// A glob pattern matched against the tag name, without `refs/tags/`.
+    pub name: String,
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+}
+
+const BRANCH_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    // `release/*` should not match `release/1.0/hotfix`
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Checks that `pattern` can be used as the name of a [`ProtectedBranchRule`]
+/// or a [`ProtectedTagRule`].
+pub fn validate_branch_pattern(pattern: &str) -> Result<(), PatternError> {
+    Pattern::new(pattern).map(|_| ())
+}
+
+fn ref_matches(pattern: &str, ref_name: &str, prefix: &str) -> bool {
+    let name = match ref_name.strip_prefix(prefix) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    match Pattern::new(pattern) {
+        Ok(p) => p.matches_with(name, BRANCH_MATCH_OPTIONS),
+        Err(_) => pattern == name,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefUpdateKind {
+    Create,
+    Delete,
+    /// The new commit is a descendant of the old one.
+    FastForward,
+    /// Anything else, which needs a force-push.
+    NonFastForward,
+}
+
+#[derive(Debug, Clone)]
+pub struct RefUpdate<'a> {
+    pub ref_name: &'a str,
+    pub kind: RefUpdateKind,
+    /// Whether any of the commits the update adds to the ref is a merge commit.
+    pub introduces_merges: bool,
+}
+
+impl<'a> RefUpdate<'a> {
+    /// A plain push of some new, linear history on top of `ref_name`.
+    pub fn plain_push(ref_name: &'a str) -> Self {
+        Self {
+            ref_name,
+            kind: RefUpdateKind::FastForward,
+            introduces_merges: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RuleViolation {
+    NeedsAdmin,
+    PushRestricted,
+    ForcePush,
+    Deletion,
+    NonLinearHistory,
+}
+
+impl fmt::Display for RuleViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RuleViolation::NeedsAdmin => "needs admin",
+            RuleViolation::PushRestricted => "only specific users and teams can push",
+            RuleViolation::ForcePush => "force-pushes are not allowed",
+            RuleViolation::Deletion => "cannot be deleted",
+            RuleViolation::NonLinearHistory => "merge commits are not allowed",
+        };
+
+        f.write_str(s)
+    }
+}
+
+/// Why the hooks could not get the config of the repository.
+#[derive(Debug)]
+pub enum RepoConfigError {
+    /// Neither [`REPO_CONFIG_FILE`] nor [`ENV_VAR_REPO_CONFIG`] is there.
+    Missing,
+    /// The config from the named source is not valid.
+    Malformed(&'static str, serde_json::Error),
+}
+
+impl fmt::Display for RepoConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoConfigError::Missing => write!(
+                f,
+                "neither {REPO_CONFIG_FILE} nor {ENV_VAR_REPO_CONFIG} is available"
+            ),
+            RepoConfigError::Malformed(source, e) => write!(f, "cannot parse {source}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RepoConfigError {}
+
+impl ProtectedBranchRule {
+    /// Whether the rule covers `ref_name`, which is a full ref name (`refs/heads/...`).
+    pub fn applies_to(&self, ref_name: &str) -> bool {
+        ref_matches(&self.name, ref_name, "refs/heads/")
+    }
+
+    fn restricts_pushes(&self) -> bool {
+        !self.push_users.is_empty() || !self.push_teams.is_empty()
+    }
+
+    fn allows_pusher(&self, user: &UserConfig) -> bool {
+        if user.permissions.has_admin || !self.restricts_pushes() {
+            return true;
+        }
+
+        let listed_user = user
+            .user
+            .as_ref()
+            .map_or(false, |user| self.push_users.contains(user));
+        let listed_team = user.teams.iter().any(|team| self.push_teams.contains(team));
+
+        listed_user || listed_team
+    }
+
+    /// Everything about `update` the rule does not allow, assuming it
+    /// [applies to](Self::applies_to) the updated ref.
+    pub fn violations(&self, update: &RefUpdate, user: &UserConfig) -> Vec<RuleViolation> {
+        let mut violations = Vec::new();
+
+        if self.needs_admin && !user.permissions.has_admin {
+            violations.push(RuleViolation::NeedsAdmin);
+        }
+
+        if !self.allows_pusher(user) {
+            violations.push(RuleViolation::PushRestricted);
+        }
+
+        match update.kind {
+            RefUpdateKind::Delete if self.block_deletion => {
+                violations.push(RuleViolation::Deletion);
+            }
+            RefUpdateKind::NonFastForward if self.block_force_push => {
+                violations.push(RuleViolation::ForcePush);
+            }
+            _ => {}
+        }
+
+        if self.require_linear_history && update.introduces_merges {
+            violations.push(RuleViolation::NonLinearHistory);
+        }
+
+        violations
+    }
+
+    /// Whether the rule rejects even plain pushes from `user`.
+    pub fn blocks_push(&self, user: &UserConfig) -> bool {
+        !self.violations(&RefUpdate::plain_push(""), user).is_empty()
+    }
+}
+
+impl ProtectedTagRule {
+    /// Whether the rule covers `ref_name`, which is a full ref name (`refs/tags/...`).
+    pub fn applies_to(&self, ref_name: &str) -> bool {
+        ref_matches(&self.name, ref_name, "refs/tags/")
+    }
+
+    /// Whether the rule allows `update`, assuming it [applies to](Self::applies_to)
+    /// the updated ref.
+    pub fn allows(&self, update: &RefUpdate, user: &UserConfig) -> bool {
+        if update.kind == RefUpdateKind::Create || user.permissions.has_admin {
+            return true;
+        }
+
+        user.user
+            .as_ref()
+            .map_or(false, |user| self.allowed_users.contains(user))
+    }
 }
 
 impl RepoConfig {
-    pub fn from_env() -> Self {
-        let config = std::env::var(ENV_VAR_REPO_CONFIG).expect("UPSILON_REPO_CONFIG not set");
-        serde_json::from_str(&config).expect("Cannot parse UPSILON_REPO_CONFIG")
+    /// The config of the repository the hook runs in.
+    ///
+    /// [`REPO_CONFIG_FILE`] is rewritten every time the settings of the repo change,
+    /// so it is preferred over the environment, which only has the config from when
+    /// the push started.
+    pub fn from_env() -> Result<Self, RepoConfigError> {
+        // git sets GIT_DIR for hooks, which do not always run in the repository
+        let config_file = std::env::var_os("GIT_DIR").map_or_else(
+            || PathBuf::from(REPO_CONFIG_FILE),
+            |git_dir| Path::new(&git_dir).join(REPO_CONFIG_FILE),
+        );
+
+        if let Ok(config) = std::fs::read_to_string(config_file) {
+            return serde_json::from_str(&config)
+                .map_err(|e| RepoConfigError::Malformed(REPO_CONFIG_FILE, e));
+        }
+
+        let config = std::env::var(ENV_VAR_REPO_CONFIG).map_err(|_| RepoConfigError::Missing)?;
+        serde_json::from_str(&config)
+            .map_err(|e| RepoConfigError::Malformed(ENV_VAR_REPO_CONFIG, e))
     }
 
     pub fn serialized(&self) -> String {
         serde_json::to_string(self).expect("Failed to serialize RepoConfig")
     }
+
+    /// Checks `update` against all the rules that apply to the updated ref.
+    pub fn check_update<'a>(
+        &'a self,
+        update: &RefUpdate,
+        user: &UserConfig,
+    ) -> Vec<(&'a ProtectedBranchRule, RuleViolation)> {
+        self.protected_branches
+            .iter()
+            .filter(|rule| rule.applies_to(update.ref_name))
+            .flat_map(|rule| {
+                rule.violations(update, user)
+                    .into_iter()
+                    .map(move |violation| (rule, violation))
+            })
+            .collect()
+    }
+
+    /// The tag rules that reject `update`.
+    pub fn check_tag_update<'a>(
+        &'a self,
+        update: &RefUpdate,
+        user: &UserConfig,
+    ) -> Vec<&'a ProtectedTagRule> {
+        self.protected_tags
+            .iter()
+            .filter(|rule| rule.applies_to(update.ref_name) && !rule.allows(update, user))
+            .collect()
+    }
+
+    /// The rules that would reject a plain push to `ref_name` from `user`.
+    pub fn rules_blocking_push<'a>(
+        &'a self,
+        ref_name: &'a str,
+        user: &'a UserConfig,
+    ) -> impl Iterator<Item = &'a ProtectedBranchRule> + 'a {
+        self.protected_branches
+            .iter()
+            .filter(move |rule| rule.applies_to(ref_name) && rule.blocks_push(user))
+    }
 }
 
 pub const ENV_VAR_REPO_CONFIG: &str = "UPSILON_REPO_CONFIG";
+
+/// Where the current config of a repository is kept, relative to the repository.
+pub const REPO_CONFIG_FILE: &str = "upsilon-repo-config.json";
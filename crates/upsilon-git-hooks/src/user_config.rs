@@ -17,6 +17,13 @@
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct UserConfig {
     pub permissions: UserPermissions,
+    /// The id of the user pushing, if any.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The ids of the teams the user is in, as far as the branch protection
+    /// rules of the repo are concerned.
+    #[serde(default)]
+    pub teams: Vec<String>,
 }
 
 impl UserConfig {
@@ -17,11 +17,19 @@
 use crate::organization::{Organization, OrganizationId, Team, TeamId};
 use crate::users::{User, UserId};
 
+/// Where a repo lives.
+///
+/// Only organizations nest: groups and subgroups are the teams of the
+/// organization, and [`Team::parent`] is what makes a hierarchy of any
+/// depth out of them. A repo of a team is identified by the team alone,
+/// so moving a team around moves its repos along with it.
 #[derive(Copy, Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum NamespaceId {
     GlobalNamespace,
     User(UserId),
     Organization(OrganizationId),
+    /// A team at any depth in the hierarchy of the organization. Its repos
+    /// are addressed through all of its parents, as in `org/team/sub-team/repo`.
     Team(OrganizationId, TeamId),
 }
 
@@ -16,6 +16,7 @@
 
 use crate::email::Email;
 use crate::namespace::{PlainNamespaceFragment, PlainNamespaceFragmentRef};
+use crate::repo::RepoRole;
 use crate::users::{UserId, Username, UsernameRef};
 upsilon_id::id_ty! {
     #[uuid]
@@ -50,23 +51,26 @@ crate::utils::str_newtype! {
 }
 crate::utils::str_newtype!(TeamDisplayName, TeamDisplayNameRef);
 
+/// The owners of an organization are its members with the
+/// [`Owner`](OrganizationRole::Owner) role; there can be several of them.
 #[derive(Debug, Clone)]
 pub struct Organization {
     pub id: OrganizationId,
-    pub owner: UserId,
     pub name: OrganizationName,
     pub display_name: Option<OrganizationDisplayName>,
     pub email: Option<Email>,
+    /// The role every member of the organization has on all of its repos.
+    pub base_role: Option<RepoRole>,
 }
 
 impl Organization {
-    pub fn new(owner: UserId, name: OrganizationName) -> Organization {
+    pub fn new(name: OrganizationName) -> Organization {
         Organization {
             id: OrganizationId::new(),
-            owner,
             name,
             display_name: None,
             email: None,
+            base_role: None,
         }
     }
 
@@ -79,17 +83,76 @@ impl Organization {
     }
 }
 
+/// Teams can be nested inside other teams of the same organization.
+/// The members of a sub-team are also members of its parent, and get
+/// the repo access of the parent.
 #[derive(Debug, Clone)]
 pub struct Team {
     pub id: TeamId,
     pub organization_id: OrganizationId,
     pub name: TeamName,
     pub display_name: Option<TeamDisplayName>,
+    pub parent: Option<TeamId>,
+}
+
+impl Team {
+    pub fn new(organization_id: OrganizationId, name: TeamName, parent: Option<TeamId>) -> Team {
+        Team {
+            id: TeamId::new(),
+            organization_id,
+            name,
+            display_name: None,
+            parent,
+        }
+    }
+}
+
+// What a member can do in the organization itself; access to its repos
+// comes from the base role and from teams.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, juniper::GraphQLEnum)]
+pub enum OrganizationRole {
+    // everything, including managing the other owners
+    Owner,
+    // change the settings, members, teams and repos of the organization
+    Admin,
+    // get the base role on the repos of the organization
+    Member,
+    // see the settings of the organization, without access to its repos
+    BillingViewer,
+}
+
+impl OrganizationRole {
+    pub fn is_owner(self) -> bool {
+        self == OrganizationRole::Owner
+    }
+
+    /// Whether the role can change the settings and members of the organization.
+    pub fn can_manage(self) -> bool {
+        matches!(self, OrganizationRole::Owner | OrganizationRole::Admin)
+    }
+
+    /// Whether the role gets the [base role](Organization::base_role)
+    /// on the repos of the organization.
+    pub fn gets_base_role(self) -> bool {
+        self != OrganizationRole::BillingViewer
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct OrganizationMember {
     pub organization_id: OrganizationId,
     pub user_id: UserId,
+    pub role: OrganizationRole,
     pub teams: Vec<TeamId>,
 }
+
+impl OrganizationMember {
+    pub fn new(organization_id: OrganizationId, user_id: UserId, role: OrganizationRole) -> Self {
+        Self {
+            organization_id,
+            user_id,
+            role,
+            teams: Vec::new(),
+        }
+    }
+}
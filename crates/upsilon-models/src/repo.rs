@@ -22,6 +22,8 @@ use bitflags::bitflags;
 use crate::namespace::{
     NamespaceId, NamespaceKind, PlainNamespaceFragment, PlainNamespaceFragmentRef
 };
+use crate::organization::TeamId;
+use crate::users::{UserId, UserSshKey};
 
 upsilon_id::id_ty! {
     #[uuid]
@@ -29,6 +31,12 @@ upsilon_id::id_ty! {
     pub struct RepoId;
 }
 
+upsilon_id::id_ty! {
+    #[uuid]
+    #[timestamped]
+    pub struct DeployKeyId;
+}
+
 crate::utils::str_newtype!(RepoName, RepoNameRef @derives [PartialOrd, Ord]);
 crate::utils::str_newtype! {
     @conversions #[all]
@@ -37,6 +45,70 @@ crate::utils::str_newtype! {
 }
 
 crate::utils::str_newtype!(RepoDisplayName, RepoDisplayNameRef);
+crate::utils::str_newtype!(RepoDescription, RepoDescriptionRef);
+crate::utils::str_newtype!(RepoWebsite, RepoWebsiteRef);
+crate::utils::str_newtype!(RepoTopic, RepoTopicRef @derives [PartialOrd, Ord]);
+crate::utils::str_newtype!(RepoBranchName, RepoBranchNameRef);
+
+const REPO_TOPIC_MAX_LEN: usize = 50;
+
+crate::utils::qerror! {
+    pub RepoTopicError,
+    Empty: "topic is empty",
+    TooLong: "topic is too long",
+    InvalidCharacter: "topics may only contain lowercase letters, digits and '-'",
+}
+
+impl RepoTopic {
+    /// Normalizes a user-supplied topic (trims it and lowercases it),
+    /// and checks that it is a valid topic.
+    pub fn parse(topic: &str) -> Result<Self, RepoTopicError> {
+        let topic = topic.trim().to_lowercase();
+
+        if topic.is_empty() {
+            return Err(RepoTopicError::Empty);
+        }
+
+        if topic.len() > REPO_TOPIC_MAX_LEN {
+            return Err(RepoTopicError::TooLong);
+        }
+
+        if !topic
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            return Err(RepoTopicError::InvalidCharacter);
+        }
+
+        Ok(Self(topic))
+    }
+}
+
+crate::utils::qerror! {
+    pub RepoWebsiteError,
+    NotHttp: "the website has to be an http:// or https:// URL",
+    NoHost: "the website URL has no host",
+}
+
+impl RepoWebsite {
+    /// Checks that a user-supplied website is an `http(s)://` URL,
+    /// so that it can be safely linked to.
+    pub fn parse(website: &str) -> Result<Self, RepoWebsiteError> {
+        let website = website.trim();
+
+        let rest = website
+            .strip_prefix("https://")
+            .or_else(|| website.strip_prefix("http://"))
+            .ok_or(RepoWebsiteError::NotHttp)?;
+
+        let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        if host.is_empty() || host.chars().any(char::is_whitespace) {
+            return Err(RepoWebsiteError::NoHost);
+        }
+
+        Ok(Self(website.to_owned()))
+    }
+}
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct RepoNamespace(pub NamespaceId);
@@ -59,30 +131,115 @@ pub struct Repo {
     pub name: RepoName,
     pub namespace: RepoNamespace,
     pub display_name: Option<RepoDisplayName>,
+    pub description: Option<RepoDescription>,
+    pub website: Option<RepoWebsite>,
+    pub topics: Vec<RepoTopic>,
+    /// The branch `HEAD` points to in the bare repository.
+    ///
+    /// `None` means whatever `git init` (or the mirrored remote) chose.
+    pub default_branch: Option<RepoBranchName>,
     pub repo_config: RepoConfig,
 }
 
+impl Repo {
+    pub fn new(namespace: RepoNamespace, name: RepoName, repo_config: RepoConfig) -> Repo {
+        Repo {
+            id: RepoId::new(),
+            name,
+            namespace,
+            display_name: None,
+            description: None,
+            website: None,
+            topics: Vec::new(),
+            default_branch: None,
+            repo_config,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RepoConfig {
     /// Permissions all users have by default.
     pub global_permissions: RepoPermissions,
 
     pub protected_branches: Vec<BranchProtectionRule>,
+
+    pub protected_tags: Vec<TagProtectionRule>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BranchProtectionRule {
-    pub branch_name: String,
+    /// A glob pattern matched against branch names, like `trunk` or `release/*`.
+    pub pattern: String,
     pub needs_admin: bool,
+    pub block_force_push: bool,
+    pub block_deletion: bool,
+    pub require_linear_history: bool,
+    /// If this or `push_teams` is not empty, only the users listed here,
+    /// the members of `push_teams` and admins can push.
+    pub push_users: Vec<UserId>,
+    pub push_teams: Vec<TeamId>,
+}
+
+/// Tags matching a rule can be created by anyone with write permissions,
+/// but only admins and `allowed_users` can move or delete them.
+#[derive(Debug, Clone)]
+pub struct TagProtectionRule {
+    /// A glob pattern matched against tag names, like `v*`.
+    pub pattern: String,
+    pub allowed_users: Vec<UserId>,
+}
+
+/// An SSH key that gives access to exactly one repository,
+/// without a user account behind it.
+#[derive(Debug, Clone)]
+pub struct DeployKey {
+    pub id: DeployKeyId,
+    pub repo: RepoId,
+    pub title: String,
+    pub key: UserSshKey,
+    /// Whether the key can push, and not only clone and fetch.
+    pub write: bool,
+}
+
+impl DeployKey {
+    pub fn permissions(&self) -> RepoPermissions {
+        if self.write {
+            RepoPermissions::READ | RepoPermissions::WRITE
+        } else {
+            RepoPermissions::READ
+        }
+    }
+}
+
+impl BranchProtectionRule {
+    pub fn new(pattern: String) -> Self {
+        Self {
+            pattern,
+            needs_admin: false,
+            block_force_push: false,
+            block_deletion: false,
+            require_linear_history: false,
+            push_users: Vec::new(),
+            push_teams: Vec::new(),
+        }
+    }
 }
 
 bitflags! {
     #[derive(Copy, Clone, Eq, PartialEq)]
     pub struct RepoPermissions: i32 {
         const NONE = 0;
+        /// Clone, fetch and browse the repository.
         const READ = 0b0000_0001;
+        /// Push to branches that are not protected.
         const WRITE = 0b0000_0010;
+        /// Manage access, protected branches and everything else.
         const ADMIN = 0b0000_0100;
+        /// Manage issues and pull requests.
+        const TRIAGE = 0b0000_1000;
+        /// Change the repository settings that do not affect access.
+        const MAINTAIN = 0b0001_0000;
     }
 }
 
@@ -98,32 +255,37 @@ impl RepoPermissions {
     pub fn has_admin(&self) -> bool {
         self.contains(RepoPermissions::ADMIN)
     }
+
+    pub fn can_triage(&self) -> bool {
+        self.contains(RepoPermissions::TRIAGE)
+    }
+
+    pub fn can_maintain(&self) -> bool {
+        self.contains(RepoPermissions::MAINTAIN)
+    }
 }
 
 impl fmt::Debug for RepoPermissions {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const NAMES: [(RepoPermissions, &str); 5] = [
+            (RepoPermissions::READ, "READ"),
+            (RepoPermissions::WRITE, "WRITE"),
+            (RepoPermissions::ADMIN, "ADMIN"),
+            (RepoPermissions::TRIAGE, "TRIAGE"),
+            (RepoPermissions::MAINTAIN, "MAINTAIN"),
+        ];
+
         let mut wrote_any = false;
 
         write!(f, "RepoPermissions(")?;
-        if self.contains(RepoPermissions::READ) {
-            write!(f, "READ")?;
-            wrote_any = true;
-        }
-
-        if self.contains(RepoPermissions::WRITE) {
-            if wrote_any {
-                write!(f, ", ")?;
-            }
-            write!(f, "WRITE")?;
-            wrote_any = true;
-        }
-
-        if self.contains(RepoPermissions::ADMIN) {
-            if wrote_any {
-                write!(f, ", ")?;
+        for (flag, name) in NAMES {
+            if self.contains(flag) {
+                if wrote_any {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{name}")?;
+                wrote_any = true;
             }
-            write!(f, "ADMIN")?;
-            wrote_any = true;
         }
 
         if !wrote_any {
@@ -136,6 +298,55 @@ impl fmt::Debug for RepoPermissions {
     }
 }
 
+/// A named set of [`RepoPermissions`], from least to most privileged.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, juniper::GraphQLEnum)]
+pub enum RepoRole {
+    /// Clone and browse.
+    Read,
+    /// Read, and manage issues and pull requests.
+    Triage,
+    /// Triage, and push to unprotected branches.
+    Write,
+    /// Write, and change settings that don't affect access.
+    Maintain,
+    /// Everything.
+    Admin,
+}
+
+impl RepoRole {
+    pub const ALL: [RepoRole; 5] = [
+        RepoRole::Read,
+        RepoRole::Triage,
+        RepoRole::Write,
+        RepoRole::Maintain,
+        RepoRole::Admin,
+    ];
+
+    pub fn permissions(self) -> RepoPermissions {
+        match self {
+            RepoRole::Read => RepoPermissions::READ,
+            RepoRole::Triage => RepoRole::Read.permissions() | RepoPermissions::TRIAGE,
+            RepoRole::Write => RepoRole::Triage.permissions() | RepoPermissions::WRITE,
+            RepoRole::Maintain => RepoRole::Write.permissions() | RepoPermissions::MAINTAIN,
+            RepoRole::Admin => RepoPermissions::all(),
+        }
+    }
+
+    /// The most privileged role whose permissions are all included in `perms`.
+    ///
+    /// [`RepoPermissions::ADMIN`] on its own is enough for [`RepoRole::Admin`].
+    pub fn from_permissions(perms: RepoPermissions) -> Option<RepoRole> {
+        if perms.has_admin() {
+            return Some(RepoRole::Admin);
+        }
+
+        RepoRole::ALL
+            .into_iter()
+            .rev()
+            .find(|role| perms.contains(role.permissions()))
+    }
+}
+
 #[juniper::graphql_scalar]
 impl<S> juniper::GraphQLScalar for RepoPermissions
 where
@@ -15,11 +15,18 @@
  */
 
 pub mod emails;
+pub mod identities;
+pub mod invites;
 pub mod password;
+pub mod password_resets;
+pub mod sessions;
+pub mod tokens;
+pub mod two_factor;
 
 use std::str::FromStr;
 
-use russh_keys::key::PublicKey;
+use russh::keys::ssh_key::HashAlg;
+use russh::keys::PublicKey;
 
 use crate::assets::ImageAssetId;
 use crate::namespace::{PlainNamespaceFragment, PlainNamespaceFragmentRef};
@@ -49,21 +56,20 @@ pub struct User {
 
     pub emails: UserEmails,
     pub avatar: Option<ImageAssetId>,
+
+    pub admin: bool,
+    pub suspended: bool,
+    /// Accounts waiting for an admin to approve them cannot log in.
+    pub pending_approval: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct UserSshKey(PublicKey);
 
+// the comment is not part of the key
 impl PartialEq for UserSshKey {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (UserSshKey(PublicKey::Ed25519(a)), UserSshKey(PublicKey::Ed25519(b))) => a == b,
-            (
-                UserSshKey(PublicKey::RSA { key: a, .. }),
-                UserSshKey(PublicKey::RSA { key: b, .. }),
-            ) => a == b,
-            _ => false,
-        }
+        self.0.key_data() == other.0.key_data()
     }
 }
 
@@ -75,14 +81,14 @@ impl UserSshKey {
     }
 
     pub fn fingerprint(&self) -> String {
-        self.0.fingerprint()
+        self.0.fingerprint(HashAlg::Sha256).to_string()
     }
 }
 
 impl FromStr for UserSshKey {
-    type Err = russh_keys::Error;
+    type Err = russh::keys::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(UserSshKey(russh_keys::parse_public_key_base64(s)?))
+        Ok(UserSshKey(russh::keys::parse_public_key_base64(s)?))
     }
 }
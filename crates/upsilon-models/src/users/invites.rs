@@ -0,0 +1,54 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_id::chrono::NaiveDateTime;
+
+use crate::organization::OrganizationId;
+use crate::users::UserId;
+
+upsilon_id::id_ty!(
+    #[uuid]
+    #[timestamped]
+    pub struct InviteId;
+);
+
+/// An invite to register, issued by an instance admin or by the owner
+/// of an organization.
+///
+/// Each invite can only be used once.
+#[derive(Debug, Clone)]
+pub struct Invite {
+    pub id: InviteId,
+    /// The hex-encoded SHA-256 of the invite code. The code itself is only
+    /// shown once, when the invite is created.
+    pub code_hash: String,
+    pub created_by: UserId,
+    /// The organization the invited user joins when they register.
+    pub organization: Option<OrganizationId>,
+    /// In UTC.
+    pub expires_at: NaiveDateTime,
+    pub used_by: Option<UserId>,
+}
+
+impl Invite {
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at <= now
+    }
+
+    pub fn is_usable(&self, now: NaiveDateTime) -> bool {
+        self.used_by.is_none() && !self.is_expired(now)
+    }
+}
@@ -70,15 +70,60 @@ impl PasswordHashAlgorithmDescriptor {
         HashedPassword::from(hashed)
     }
 
-    pub fn verify_password(self, password: &PlainPassword, hash: &HashedPassword) -> bool {
-        match self {
-            PasswordHashAlgorithmDescriptor::Argon2 { .. } => {
-                argon2::verify_encoded(&hash.0, password.0.as_bytes())
-                    .expect("argon2 verify failed")
-            }
-            PasswordHashAlgorithmDescriptor::Bcrypt { .. } => {
-                bcrypt::verify(&password.0, &hash.0).expect("bcrypt verify failed")
+    /// Whether `hash` was made with another algorithm or other parameters,
+    /// and should be replaced with a hash made with these.
+    pub fn is_outdated(self, hash: &HashedPassword) -> bool {
+        hash.algorithm() != Some(self)
+    }
+}
+
+impl HashedPassword {
+    /// The algorithm and parameters this hash was made with, read from the
+    /// hash itself.
+    ///
+    /// `None` if it isn't a hash [`PasswordHashAlgorithmDescriptor::hash_password`]
+    /// could have made.
+    pub fn algorithm(&self) -> Option<PasswordHashAlgorithmDescriptor> {
+        let mut parts = self.0.split('$').skip(1);
+
+        match parts.next()? {
+            "argon2id" => {
+                if parts.next()? != "v=19" {
+                    return None;
+                }
+
+                let mut passes = None;
+                let mut mem_cost = None;
+
+                for param in parts.next()?.split(',') {
+                    match param.split_once('=')? {
+                        ("t", t) => passes = Some(t.parse().ok()?),
+                        ("m", m) => mem_cost = Some(m.parse().ok()?),
+                        _ => {}
+                    }
+                }
+
+                Some(PasswordHashAlgorithmDescriptor::Argon2 {
+                    passes: passes?,
+                    mem_cost: mem_cost?,
+                })
             }
+            "2b" => Some(PasswordHashAlgorithmDescriptor::Bcrypt {
+                cost: parts.next()?.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Checks `password` against this hash, with whatever algorithm
+    /// the hash was made with.
+    pub fn verify(&self, password: &PlainPassword) -> bool {
+        if self.0.starts_with("$argon2") {
+            argon2::verify_encoded(&self.0, password.0.as_bytes()).unwrap_or(false)
+        } else if self.0.starts_with("$2") {
+            bcrypt::verify(&password.0, &self.0).unwrap_or(false)
+        } else {
+            false
         }
     }
 }
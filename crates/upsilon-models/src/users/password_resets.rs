@@ -0,0 +1,38 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_id::chrono::NaiveDateTime;
+
+use crate::users::UserId;
+
+/// A pending request to reset the password of a user, sent to them by email.
+///
+/// Only usable once; all of the user's pending resets are removed once
+/// their password changes.
+#[derive(Debug, Clone)]
+pub struct PasswordReset {
+    pub user: UserId,
+    /// The hex-encoded SHA-256 of the token that was sent.
+    pub token_hash: String,
+    /// In UTC.
+    pub expires_at: NaiveDateTime,
+}
+
+impl PasswordReset {
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at <= now
+    }
+}
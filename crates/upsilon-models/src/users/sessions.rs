@@ -0,0 +1,56 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_id::chrono::NaiveDateTime;
+
+use crate::users::UserId;
+
+upsilon_id::id_ty!(
+    #[uuid]
+    #[timestamped]
+    pub struct SessionId;
+);
+
+/// A login of a user on some device, kept alive by refreshing it.
+///
+/// All the times are in UTC.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: SessionId,
+    pub user: UserId,
+    /// The hex-encoded SHA-256 of the current refresh token.
+    pub refresh_token_hash: String,
+    /// The hashes of the refresh tokens that were already exchanged for
+    /// new ones. Seeing one of them again means the token was stolen.
+    pub used_refresh_token_hashes: Vec<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl Session {
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Replaces the refresh token with the one hashing to `new_hash`.
+    pub fn rotate_refresh_token(&mut self, new_hash: String) {
+        let old_hash = std::mem::replace(&mut self.refresh_token_hash, new_hash);
+        self.used_refresh_token_hashes.push(old_hash);
+    }
+}
@@ -0,0 +1,89 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_id::chrono::NaiveDateTime;
+
+use crate::repo::{RepoId, RepoPermissions};
+use crate::users::UserId;
+
+upsilon_id::id_ty!(
+    #[uuid]
+    #[timestamped]
+    pub struct PersonalAccessTokenId;
+);
+
+// What a personal access token can be used for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, juniper::GraphQLEnum)]
+pub enum TokenScope {
+    // clone and read repos
+    RepoRead,
+    // push to and manage repos
+    RepoWrite,
+    // manage organizations and their teams
+    AdminOrg,
+    // manage the ssh keys of the user
+    UserKeys,
+}
+
+#[derive(Debug, Clone)]
+pub struct PersonalAccessToken {
+    pub id: PersonalAccessTokenId,
+    pub user: UserId,
+    pub name: String,
+    /// The hex-encoded SHA-256 of the token. The token itself is only
+    /// shown once, when it is created.
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    /// If not empty, the token can only access these repos.
+    pub repos: Vec<RepoId>,
+    /// In UTC.
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl PersonalAccessToken {
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+            || (scope == TokenScope::RepoRead && self.scopes.contains(&TokenScope::RepoWrite))
+    }
+
+    pub fn allows_repo(&self, repo: RepoId) -> bool {
+        self.repos.is_empty() || self.repos.contains(&repo)
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= now)
+    }
+
+    /// The most `perms` on `repo` can be narrowed down to by this token.
+    pub fn restrict_repo_permissions(
+        &self,
+        repo: RepoId,
+        perms: RepoPermissions,
+    ) -> RepoPermissions {
+        if !self.allows_repo(repo) {
+            return RepoPermissions::empty();
+        }
+
+        if self.has_scope(TokenScope::RepoWrite) {
+            perms
+        } else if self.has_scope(TokenScope::RepoRead) {
+            perms & RepoPermissions::READ
+        } else {
+            RepoPermissions::empty()
+        }
+    }
+}
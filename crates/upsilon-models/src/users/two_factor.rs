@@ -0,0 +1,50 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::users::UserId;
+
+/// The TOTP second factor of a user.
+#[derive(Debug, Clone)]
+pub struct TwoFactor {
+    pub user: UserId,
+    /// The TOTP secret, encrypted with the two-factor key of the instance.
+    pub encrypted_secret: Vec<u8>,
+    /// Set once the user entered a code, proving their authenticator works.
+    /// Until then, it is not asked for when logging in.
+    pub confirmed: bool,
+    /// The hex-encoded SHA-256 of the recovery codes that weren't used yet.
+    pub recovery_code_hashes: Vec<String>,
+    /// The last time step a code was accepted for, so that a code
+    /// cannot be used twice.
+    pub last_used_step: Option<u64>,
+}
+
+impl TwoFactor {
+    /// Removes the recovery code with the given hash, if it wasn't used yet.
+    pub fn use_recovery_code(&mut self, code_hash: &str) -> bool {
+        let Some(position) = self
+            .recovery_code_hashes
+            .iter()
+            .position(|hash| hash == code_hash)
+        else {
+            return false;
+        };
+
+        self.recovery_code_hashes.remove(position);
+
+        true
+    }
+}
@@ -0,0 +1,127 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Checks on OpenSSH user certificates, beyond what russh itself does.
+//!
+//! russh parses the certificate and checks that the client holds the
+//! certified key, the rest is up to us: the CA, the validity window and
+//! the critical options, as described in `PROTOCOL.certkeys` in the
+//! OpenSSH sources.
+
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use russh::keys::ssh_key::HashAlg;
+use russh::keys::{Certificate, PublicKey};
+use upsilon_models::users::UserSshKey;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CertificateError {
+    #[error("malformed certificate")]
+    Malformed,
+    #[error("not a user certificate")]
+    NotAUserCertificate,
+    #[error("certificate signed by an untrusted CA")]
+    UntrustedCa,
+    #[error("bad CA signature, or certificate expired or not yet valid")]
+    Invalid,
+    #[error("unsupported critical option: {0}")]
+    UnsupportedCriticalOption(String),
+}
+
+/// A user certificate that was signed by a trusted CA and is
+/// currently valid.
+#[derive(Debug)]
+pub struct UserCertificate {
+    pub key_id: String,
+    pub principals: Vec<String>,
+    /// The `force-command` critical option: the only command the
+    /// certificate may run, whatever the client asks for.
+    pub force_command: Option<String>,
+    /// The `source-address` critical option: the addresses the
+    /// certificate may be used from.
+    pub source_address: Option<Vec<IpNet>>,
+}
+
+impl UserCertificate {
+    /// Checks `certificate` against everything that doesn't depend on
+    /// the connection: the CA has to be one of `trusted_cas`, its
+    /// signature has to be valid, the current time has to be within
+    /// the validity window, and all the critical options have to be known.
+    pub fn verify(
+        certificate: &Certificate,
+        trusted_cas: &[UserSshKey],
+    ) -> Result<Self, CertificateError> {
+        let ca_key = certificate.signature_key();
+
+        if !trusted_cas.contains(&UserSshKey::new(PublicKey::from(ca_key.clone()))) {
+            return Err(CertificateError::UntrustedCa);
+        }
+
+        certificate
+            .validate([&ca_key.fingerprint(HashAlg::Sha256)])
+            .map_err(|_| CertificateError::Invalid)?;
+
+        if !certificate.cert_type().is_user() {
+            return Err(CertificateError::NotAUserCertificate);
+        }
+
+        let mut force_command = None;
+        let mut source_address = None;
+
+        for (name, value) in certificate.critical_options().iter() {
+            match name.as_str() {
+                "force-command" => {
+                    force_command = Some(value.clone());
+                }
+                "source-address" => {
+                    let addresses = value
+                        .split(',')
+                        .map(|addr| {
+                            addr.parse::<IpNet>()
+                                .or_else(|_| addr.parse::<IpAddr>().map(IpNet::from))
+                                .map_err(|_| CertificateError::Malformed)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    source_address = Some(addresses);
+                }
+                name => {
+                    return Err(CertificateError::UnsupportedCriticalOption(name.to_owned()));
+                }
+            }
+        }
+
+        Ok(Self {
+            key_id: certificate.key_id().to_owned(),
+            principals: certificate.valid_principals().to_vec(),
+            force_command,
+            source_address,
+        })
+    }
+
+    /// Whether the `source-address` critical option, if any, allows
+    /// connections from `addr`.
+    pub fn allows_source_address(&self, addr: Option<IpAddr>) -> bool {
+        match (&self.source_address, addr) {
+            (None, _) => true,
+            (Some(source_address), Some(addr)) => {
+                source_address.iter().any(|it| it.contains(&addr))
+            }
+            (Some(_), None) => false,
+        }
+    }
+}
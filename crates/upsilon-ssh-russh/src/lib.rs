@@ -16,32 +16,41 @@
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
 use std::time::Duration;
 
 use log::error;
+use russh::keys::{Certificate, PublicKey};
 use russh::server::{Auth, Handle, Handler, Msg, Server, Session};
-use russh::{Channel, ChannelId, CryptoVec, MethodSet};
-use russh_keys::key::PublicKey;
+use russh::{Channel, ChannelId, CryptoVec, MethodKind, MethodSet};
 use serde::{Deserialize, Deserializer};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::ChildStdin;
 use tokio::task::JoinHandle;
+use upsilon_core::rate_limit::{RateLimitKey, RateLimiter};
+use upsilon_models::repo::DeployKey;
 use upsilon_models::users::{UserId, UserSshKey};
 use upsilon_ssh::async_trait::async_trait;
 use upsilon_ssh::{
     impl_wrapper, CommonSSHError, SSHServer, SSHServerConfig, SSHServerInitializer, SSHServerWrapper
 };
 use upsilon_vcs::UpsilonVcsConfig;
-use upsilon_vcs_permissions::{check_user_has_permissions, GitService};
+use upsilon_vcs_permissions::{
+    check_deploy_key_has_permissions, check_user_has_permissions, GitService
+};
+
+use crate::certificate::UserCertificate;
+
+mod certificate;
 
 #[derive(thiserror::Error, Debug)]
 pub enum RusshServerError {
     #[error("russh error: {0}")]
     Russh(#[from] russh::Error),
-    #[error("russh-keys error: {0}")]
-    RusshKeys(#[from] russh_keys::Error),
+    #[error("russh keys error: {0}")]
+    RusshKeys(#[from] russh::keys::Error),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("other error: {0}")]
@@ -61,6 +70,12 @@ pub struct RusshServerConfig {
     pub port: u16,
     auth_rejection_time_initial: Option<Duration>,
     auth_rejection_time: Duration,
+    /// CAs whose user certificates are accepted, in addition to the
+    /// users' own keys. The certificate principals are usernames.
+    ///
+    /// Certificates need a validity interval (`ssh-keygen -V`), as russh
+    /// cannot read the ones that are valid forever.
+    trusted_user_ca_keys: Vec<UserSshKey>,
 }
 
 #[derive(Clone, Debug)]
@@ -68,16 +83,24 @@ pub struct CompleteRusshServerConfig {
     pub port: u16,
     auth_rejection_time_initial: Option<Duration>,
     auth_rejection_time: Duration,
+    trusted_user_ca_keys: Vec<UserSshKey>,
     vcs_config: UpsilonVcsConfig,
+    rate_limiter: RateLimiter,
 }
 
 impl RusshServerConfig {
-    pub fn complete(self, vcs_config: UpsilonVcsConfig) -> CompleteRusshServerConfig {
+    pub fn complete(
+        self,
+        vcs_config: UpsilonVcsConfig,
+        rate_limiter: RateLimiter,
+    ) -> CompleteRusshServerConfig {
         CompleteRusshServerConfig {
             port: self.port,
             auth_rejection_time_initial: self.auth_rejection_time_initial,
             auth_rejection_time: self.auth_rejection_time,
+            trusted_user_ca_keys: self.trusted_user_ca_keys,
             vcs_config,
+            rate_limiter,
         }
     }
 }
@@ -88,6 +111,7 @@ impl Default for RusshServerConfig {
             port: 22,
             auth_rejection_time_initial: Some(Duration::from_secs(1)),
             auth_rejection_time: Duration::from_secs(10),
+            trusted_user_ca_keys: Vec::new(),
         }
     }
 }
@@ -105,6 +129,8 @@ impl<'de> Deserialize<'de> for RusshServerConfig {
             auth_rejection_time_initial: Option<Duration>,
             #[serde(default, deserialize_with = "deserialize_duration_opt")]
             auth_rejection_time: Option<Duration>,
+            #[serde(default)]
+            trusted_user_ca_keys: Vec<String>,
         }
 
         let patch = RusshServerConfigPatch::deserialize(deserializer)?;
@@ -123,10 +149,33 @@ impl<'de> Deserialize<'de> for RusshServerConfig {
             config.auth_rejection_time = auth_rejection_time;
         }
 
+        config.trusted_user_ca_keys = patch
+            .trusted_user_ca_keys
+            .iter()
+            .map(|key| parse_ca_key::<D>(key))
+            .collect::<Result<_, _>>()?;
+
         Ok(config)
     }
 }
 
+/// Parses a CA key either in the `authorized_keys` format, as in the
+/// `.pub` file `ssh-keygen` writes, or as only the base64-encoded key.
+fn parse_ca_key<'de, D>(s: &str) -> Result<UserSshKey, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut parts = s.split_whitespace();
+    let key = match (parts.next(), parts.next()) {
+        (Some(_key_type), Some(key)) => key,
+        (Some(key), None) => key,
+        (None, _) => return Err(serde::de::Error::custom("empty CA key")),
+    };
+
+    key.parse::<UserSshKey>()
+        .map_err(|e| serde::de::Error::custom(format!("invalid CA key: {e}")))
+}
+
 fn parse_duration<'de, D>(s: &str) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
@@ -185,18 +234,22 @@ impl SSHServerInitializer for RusshServerInitializer {
             let russh_config = russh::server::Config {
                 auth_rejection_time_initial: config.auth_rejection_time_initial,
                 auth_rejection_time: config.auth_rejection_time,
-                keys: vec![russh_keys::key::KeyPair::generate_ed25519().unwrap()],
+                keys: vec![russh::keys::PrivateKey::random(
+                    &mut rand::rngs::OsRng,
+                    russh::keys::Algorithm::Ed25519,
+                )
+                .unwrap()],
                 ..Default::default()
             };
 
             tokio::spawn(async move {
-                let server = receiver.await.unwrap();
-                let result = russh::server::run(
-                    Arc::new(russh_config),
-                    SocketAddr::from(([0, 0, 0, 0], config.port)),
-                    server,
-                )
-                .await;
+                let mut server: RusshServer = receiver.await.unwrap();
+                let result = server
+                    .run_on_address(
+                        Arc::new(russh_config),
+                        SocketAddr::from(([0, 0, 0, 0], config.port)),
+                    )
+                    .await;
 
                 if let Err(e) = result {
                     log::error!("russh server error: {e}");
@@ -260,6 +313,11 @@ pub struct RusshServerHandler {
     internals: Arc<RusshServerInternals>,
     peer_addr: Option<SocketAddr>,
     user: Option<UserId>,
+    /// Set instead of `user` if the client authenticated with a deploy key.
+    deploy_key: Option<DeployKey>,
+    /// The `force-command` of the certificate the client authenticated
+    /// with, which replaces whatever command the client asks for.
+    force_command: Option<String>,
     stdin: HashMap<ChannelId, ChildStdin>,
 }
 
@@ -269,17 +327,17 @@ impl RusshServerHandler {
             internals: Arc::clone(&server.internals),
             peer_addr,
             user: None,
+            deploy_key: None,
+            force_command: None,
             stdin: HashMap::new(),
         }
     }
 
-    fn auth_reject_pubkey(self) -> Result<(Self, Auth), RusshServerError> {
-        Ok((
-            self,
-            Auth::Reject {
-                proceed_with_methods: Some(MethodSet::PUBLICKEY),
-            },
-        ))
+    fn auth_reject_pubkey(&self) -> Result<Auth, RusshServerError> {
+        Ok(Auth::Reject {
+            proceed_with_methods: Some(MethodSet::from(&[MethodKind::PublicKey][..])),
+            partial_success: false,
+        })
     }
 
     async fn send_stdin(
@@ -296,115 +354,327 @@ impl RusshServerHandler {
 }
 
 macro_rules! reject_not_git_user {
-    ($self:expr, $user:expr) => {
+    ($user:expr) => {
         if $user != "git" {
-            return Ok((
-                $self,
-                Auth::Reject {
-                    proceed_with_methods: None,
-                },
-            ));
+            return Ok(Auth::Reject {
+                proceed_with_methods: None,
+                partial_success: false,
+            });
         }
     };
 }
 
-#[async_trait]
+/// Tells the client why its request failed on stderr, then fails it.
+fn fail_channel(session: &mut Session, channel: ChannelId, message: &[u8]) {
+    // if the client is already gone, there's no one left to tell
+    let _ = session.extended_data(channel, 1, CryptoVec::from_slice(message));
+    let _ = session.channel_failure(channel);
+}
+
 impl Handler for RusshServerHandler {
     type Error = RusshServerError;
 
-    async fn auth_none(self, user: &str) -> Result<(Self, Auth), Self::Error> {
-        reject_not_git_user!(self, user);
+    async fn auth_none(&mut self, user: &str) -> Result<Auth, Self::Error> {
+        reject_not_git_user!(user);
 
         self.auth_reject_pubkey()
     }
 
-    async fn auth_password(self, user: &str, _password: &str) -> Result<(Self, Auth), Self::Error> {
-        reject_not_git_user!(self, user);
+    async fn auth_password(&mut self, user: &str, _password: &str) -> Result<Auth, Self::Error> {
+        reject_not_git_user!(user);
 
         self.auth_reject_pubkey()
     }
 
     async fn auth_publickey(
-        self,
+        &mut self,
         user: &str,
         public_key: &PublicKey,
-    ) -> Result<(Self, Auth), Self::Error> {
-        reject_not_git_user!(self, user);
+    ) -> Result<Auth, Self::Error> {
+        reject_not_git_user!(user);
 
-        let result = self
-            .internals
-            .dcmh
-            .query_master()
-            .query_user_ssh_key(UserSshKey::new(public_key.clone()))
-            .await
-            .map_err(|e| {
+        // the user is always `git`, so only the IP can be limited here
+        let rate_limit_keys = self
+            .peer_addr
+            .map(|addr| vec![RateLimitKey::Ip(addr.ip())])
+            .unwrap_or_default();
+
+        let rate_limiter = &self.internals.config.rate_limiter;
+
+        if let Err(e) = rate_limiter.check(&rate_limit_keys) {
+            log::info!("Rejecting public key auth from {:?}: {e}", self.peer_addr);
+
+            return self.auth_reject_pubkey();
+        }
+
+        let (user, deploy_key, locked_out) = {
+            let qm = self.internals.dcmh.query_master();
+            let key = UserSshKey::new(public_key.clone());
+
+            let user = qm.query_user_ssh_key(key.clone()).await.map_err(|e| {
                 error!("Failed to query user ssh key: {}", e);
 
                 RusshServerError::Other(Box::new(e))
             })?;
 
-        match result {
-            Some(user) => Ok((
-                Self {
-                    user: Some(user),
-                    ..self
-                },
-                Auth::Accept,
-            )),
-            None => self.auth_reject_pubkey(),
+            // accounts still waiting for approval cannot log in either
+            let locked_out = match user {
+                Some(user_id) => {
+                    let user = qm.query_user(user_id).await.map_err(|e| {
+                        error!("Failed to query user: {}", e);
+
+                        RusshServerError::Other(Box::new(e))
+                    })?;
+
+                    user.suspended || user.pending_approval
+                }
+                None => false,
+            };
+
+            // user keys and deploy keys never overlap
+            let deploy_key = match user {
+                Some(_) => None,
+                None => qm.query_deploy_key(key).await.map_err(|e| {
+                    error!("Failed to query deploy key: {}", e);
+
+                    RusshServerError::Other(Box::new(e))
+                })?,
+            };
+
+            (user, deploy_key, locked_out)
+        };
+
+        if user.is_none() && deploy_key.is_none() {
+            rate_limiter.record_failure(&rate_limit_keys);
+
+            return self.auth_reject_pubkey();
+        }
+
+        // clients try all their keys in turn, so rejected keys
+        // before the right one shouldn't add up
+        for key in &rate_limit_keys {
+            rate_limiter.record_success(key);
+        }
+
+        if locked_out {
+            log::info!(
+                "Rejecting public key of suspended or unapproved user {:?}",
+                user
+            );
+
+            return self.auth_reject_pubkey();
+        }
+
+        self.user = user;
+        self.deploy_key = deploy_key;
+
+        Ok(Auth::Accept)
+    }
+
+    async fn auth_openssh_certificate(
+        &mut self,
+        user: &str,
+        certificate: &Certificate,
+    ) -> Result<Auth, Self::Error> {
+        reject_not_git_user!(user);
+
+        let rate_limit_keys = self
+            .peer_addr
+            .map(|addr| vec![RateLimitKey::Ip(addr.ip())])
+            .unwrap_or_default();
+
+        let rate_limiter = &self.internals.config.rate_limiter;
+
+        if let Err(e) = rate_limiter.check(&rate_limit_keys) {
+            log::info!("Rejecting certificate auth from {:?}: {e}", self.peer_addr);
+
+            return self.auth_reject_pubkey();
+        }
+
+        let certificate =
+            match UserCertificate::verify(certificate, &self.internals.config.trusted_user_ca_keys)
+            {
+                Ok(certificate) => certificate,
+                Err(e) => {
+                    log::info!("Rejecting certificate from {:?}: {e}", self.peer_addr);
+
+                    rate_limiter.record_failure(&rate_limit_keys);
+
+                    return self.auth_reject_pubkey();
+                }
+            };
+
+        if !certificate.allows_source_address(self.peer_addr.map(|addr| addr.ip())) {
+            log::info!(
+                "Rejecting certificate {:?}: source-address does not allow {:?}",
+                certificate.key_id,
+                self.peer_addr
+            );
+
+            rate_limiter.record_failure(&rate_limit_keys);
+
+            return self.auth_reject_pubkey();
+        }
+
+        // the first principal naming a user is the one the certificate is used as
+        let user = {
+            let qm = self.internals.dcmh.query_master();
+
+            let mut user = None;
+            for principal in &certificate.principals {
+                user = qm
+                    .query_user_by_username(principal.as_str())
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to query user by username: {}", e);
+
+                        RusshServerError::Other(Box::new(e))
+                    })?;
+
+                if user.is_some() {
+                    break;
+                }
+            }
+
+            user
+        };
+
+        let Some(user) = user else {
+            log::info!(
+                "Rejecting certificate {:?}: no principal names a user",
+                certificate.key_id
+            );
+
+            rate_limiter.record_failure(&rate_limit_keys);
+
+            return self.auth_reject_pubkey();
+        };
+
+        for key in &rate_limit_keys {
+            rate_limiter.record_success(key);
+        }
+
+        if user.suspended {
+            log::info!(
+                "Rejecting certificate {:?}: {} is suspended",
+                certificate.key_id,
+                user.username
+            );
+
+            return self.auth_reject_pubkey();
+        }
+
+        if user.pending_approval {
+            log::info!(
+                "Rejecting certificate {:?}: {} is not approved yet",
+                certificate.key_id,
+                user.username
+            );
+
+            return self.auth_reject_pubkey();
         }
+
+        self.user = Some(user.id);
+        self.force_command = certificate.force_command;
+
+        Ok(Auth::Accept)
     }
 
     async fn channel_eof(
-        mut self,
+        &mut self,
         channel: ChannelId,
-        session: Session,
-    ) -> Result<(Self, Session), Self::Error> {
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
         let stdin = self.stdin.remove(&channel);
         if let Some(mut stdin) = stdin {
             stdin.shutdown().await?;
         }
 
-        Ok((self, session))
+        Ok(())
     }
 
     async fn channel_open_session(
-        self,
+        &mut self,
         _channel: Channel<Msg>,
-        session: Session,
-    ) -> Result<(Self, bool, Session), Self::Error> {
-        Ok((self, true, session))
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        Ok(true)
     }
 
     async fn data(
-        mut self,
+        &mut self,
         channel: ChannelId,
         data: &[u8],
-        session: Session,
-    ) -> Result<(Self, Session), Self::Error> {
-        self.send_stdin(channel, data).await?;
-
-        Ok((self, session))
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.send_stdin(channel, data).await
     }
 
     async fn exec_request(
-        mut self,
+        &mut self,
         channel: ChannelId,
         data: &[u8],
-        mut session: Session,
-    ) -> Result<(Self, Session), Self::Error> {
-        let git_shell_cmd = std::str::from_utf8(data).expect("invalid utf8");
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let git_shell_cmd = match &self.force_command {
+            Some(force_command) => force_command.as_str(),
+            None => std::str::from_utf8(data).expect("invalid utf8"),
+        };
 
-        let (service, path) = parse_git_shell_cmd(git_shell_cmd);
+        let Some((service, path)) = parse_git_shell_cmd(git_shell_cmd) else {
+            error!("Invalid git shell command: {git_shell_cmd:?}");
+            fail_channel(session, channel, b"Invalid command");
+            return Ok(());
+        };
 
         let path = path.strip_prefix('/').unwrap_or(path);
 
+        if !upsilon_vcs::is_plain_repo_path(path) {
+            error!("Invalid repo path: {path:?}");
+            fail_channel(session, channel, b"Invalid repo path");
+            return Ok(());
+        }
+
+        // the repo might have been renamed or moved since, in which case
+        // it is served from where it lives now
+        let current_path;
+        let path = if self.internals.config.vcs_config.repo_dir(path).exists() {
+            path
+        } else {
+            let qm = self.internals.dcmh.query_master();
+
+            let previous_path_repo =
+                upsilon_vcs_permissions::query_repo_by_previous_path(&qm, Path::new(path))
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to query repo by previous path: {}", e);
+                        fail_channel(session, channel, b"Internal server error");
+                        RusshServerError::Other(Box::new(e))
+                    })?;
+
+            match previous_path_repo {
+                Some(repo) => {
+                    current_path = upsilon_vcs_permissions::repo_path(&qm, &repo)
+                        .await
+                        .map_err(|e| {
+                            error!("Failed to get repo path: {}", e);
+                            fail_channel(session, channel, b"Internal server error");
+                            RusshServerError::Other(Box::new(e))
+                        })?
+                        .to_string_lossy()
+                        .into_owned();
+
+                    current_path.as_str()
+                }
+                None => path,
+            }
+        };
+
         let repo_id = upsilon_vcs::read_repo_id(&self.internals.config.vcs_config, path)
             .await
             .map_err(|e| {
                 error!("Failed to read repo id: {}", e);
-                session.extended_data(channel, 1, CryptoVec::from_slice(b"Internal server error"));
-                session.channel_failure(channel);
+                fail_channel(session, channel, b"Internal server error");
                 RusshServerError::Other(Box::new(e))
             })?;
 
@@ -412,8 +682,7 @@ impl Handler for RusshServerHandler {
             .parse::<upsilon_models::repo::RepoId>()
             .map_err(|e| {
                 error!("Failed to parse repo id: {}", e);
-                session.extended_data(channel, 1, CryptoVec::from_slice(b"Internal server error"));
-                session.channel_failure(channel);
+                fail_channel(session, channel, b"Internal server error");
                 RusshServerError::Other(Box::new(e))
             })?;
 
@@ -425,24 +694,28 @@ impl Handler for RusshServerHandler {
             .await
             .map_err(|e| {
                 error!("Failed to query repo: {}", e);
-                session.extended_data(channel, 1, CryptoVec::from_slice(b"Internal server error"));
-                session.channel_failure(channel);
+                fail_channel(session, channel, b"Internal server error");
                 RusshServerError::Other(Box::new(e))
             })?;
 
-        let user_id = self.user.expect("user not set");
+        let permissions_check = {
+            let qm = self.internals.dcmh.query_master();
 
-        let (lowered_repo_config, user_config) = check_user_has_permissions(
-            &repo,
-            service,
-            &self.internals.dcmh.query_master(),
-            Some(user_id),
-        )
-        .await
-        .map_err(|e| {
+            match &self.deploy_key {
+                Some(deploy_key) => {
+                    check_deploy_key_has_permissions(&repo, service, &qm, deploy_key).await
+                }
+                None => {
+                    let user_id = self.user.expect("user not set");
+
+                    check_user_has_permissions(&repo, service, &qm, Some(user_id), None).await
+                }
+            }
+        };
+
+        let (lowered_repo_config, user_config) = permissions_check.map_err(|e| {
             error!("Failed to check repo perms: {}", e);
-            session.extended_data(channel, 1, CryptoVec::from_slice(b"Internal server error"));
-            session.channel_failure(channel);
+            fail_channel(session, channel, b"Internal server error");
             RusshServerError::Other(Box::new(e))
         })?;
 
@@ -473,11 +746,11 @@ impl Handler for RusshServerHandler {
             .spawn()
         {
             Ok(shell) => {
-                session.channel_success(channel);
+                session.channel_success(channel)?;
                 shell
             }
             Err(e) => {
-                session.channel_failure(channel);
+                let _ = session.channel_failure(channel);
                 return Err(RusshServerError::from(e));
             }
         };
@@ -522,7 +795,7 @@ impl Handler for RusshServerHandler {
                 Ok(())
             }
 
-            use futures::future::FutureExt;
+            use futures::future::{FusedFuture, FutureExt};
 
             let stdout_fut = forward(
                 &session_handle,
@@ -570,8 +843,14 @@ impl Handler for RusshServerHandler {
                     Pipe::Exit(result) => {
                         let status = result?;
 
-                        stdout_fut.await?;
-                        stderr_fut.await?;
+                        // a pipe that was already read to the end would never
+                        // complete again
+                        if !stdout_fut.is_terminated() {
+                            stdout_fut.await?;
+                        }
+                        if !stderr_fut.is_terminated() {
+                            stderr_fut.await?;
+                        }
 
                         let status_code = status.code().unwrap_or(128) as u32; // TODO: handle signals properly
 
@@ -592,7 +871,7 @@ impl Handler for RusshServerHandler {
 
         tokio::spawn(fut);
 
-        Ok((self, session))
+        Ok(())
     }
 }
 
@@ -605,15 +884,15 @@ fn strip_apostrophes(s: &str) -> &str {
     }
 }
 
-fn parse_git_shell_cmd(git_shell_cmd: &str) -> (GitService, &str) {
+fn parse_git_shell_cmd(git_shell_cmd: &str) -> Option<(GitService, &str)> {
     if let Some(rec_pack_path) = git_shell_cmd.strip_prefix("git-receive-pack ") {
-        (GitService::ReceivePack, strip_apostrophes(rec_pack_path))
+        Some((GitService::ReceivePack, strip_apostrophes(rec_pack_path)))
     } else if let Some(upl_ref_path) = git_shell_cmd.strip_prefix("git-upload-pack ") {
-        (GitService::UploadPack, strip_apostrophes(upl_ref_path))
+        Some((GitService::UploadPack, strip_apostrophes(upl_ref_path)))
     } else if let Some(upl_arc_path) = git_shell_cmd.strip_prefix("git-upload-archive ") {
-        (GitService::UploadArchive, strip_apostrophes(upl_arc_path))
+        Some((GitService::UploadArchive, strip_apostrophes(upl_arc_path)))
     } else {
-        panic!("invalid git shell command: {git_shell_cmd:?}");
+        None
     }
 }
 
@@ -14,8 +14,18 @@
  *    limitations under the License.
  */
 
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use upsilon_data::{CommonDataClientError, DataQueryMaster};
+use upsilon_models::namespace::NamespaceId;
+use upsilon_models::organization::{OrganizationId, OrganizationRole, TeamId};
+use upsilon_models::repo::{
+    BranchProtectionRule, DeployKey, Repo, RepoNamespace, RepoPermissions, RepoRole
+};
+use upsilon_models::users::tokens::PersonalAccessToken;
+use upsilon_models::users::UserId;
 use upsilon_vcs::upsilon_git_hooks;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -73,11 +83,414 @@ pub enum LackingPermissionsError {
     Write,
 }
 
+/// Where some of the permissions a user has on a repo come from.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PermissionSource {
+    /// The global permissions of the repo, which everyone has.
+    Global,
+    /// Permissions granted directly to the user.
+    Direct,
+    /// Permissions granted to a team the user is in, directly or
+    /// through one of its sub-teams.
+    Team(TeamId),
+    /// The base role of the organization the repo belongs to.
+    OrganizationBaseRole(OrganizationId),
+    /// The user is an owner or an admin of the organization the repo belongs to.
+    OrganizationRole(OrganizationId, OrganizationRole),
+    /// The repo lives in the namespace of the user.
+    NamespaceOwnership,
+}
+
+#[derive(Clone, Debug)]
+pub struct PermissionGrant {
+    pub source: PermissionSource,
+    pub permissions: RepoPermissions,
+}
+
+#[derive(Clone, Debug)]
+pub struct PermissionsExplanation {
+    /// The union of the permissions of all the `grants`.
+    pub permissions: RepoPermissions,
+    pub grants: Vec<PermissionGrant>,
+}
+
+impl PermissionsExplanation {
+    fn new() -> Self {
+        Self {
+            permissions: RepoPermissions::NONE,
+            grants: Vec::new(),
+        }
+    }
+
+    fn grant(&mut self, source: PermissionSource, permissions: RepoPermissions) {
+        self.permissions |= permissions;
+        self.grants.push(PermissionGrant {
+            source,
+            permissions,
+        });
+    }
+
+    pub fn role(&self) -> Option<RepoRole> {
+        RepoRole::from_permissions(self.permissions)
+    }
+}
+
+/// `team_id` followed by its parent, the parent of its parent, and so on.
+///
+/// Stops at the first team that was already seen, so a cycle in the
+/// hierarchy cannot make this loop forever.
+pub async fn team_ancestors(
+    qm: &DataQueryMaster<'_>,
+    team_id: TeamId,
+) -> Result<Vec<TeamId>, CommonDataClientError> {
+    let mut ancestors = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut next = Some(team_id);
+
+    while let Some(team_id) = next {
+        if !seen.insert(team_id) {
+            break;
+        }
+
+        ancestors.push(team_id);
+        next = qm.query_team(team_id).await?.parent;
+    }
+
+    Ok(ancestors)
+}
+
+/// Whether `user` is in `team_id`, or in any of its sub-teams.
+pub async fn is_team_member(
+    qm: &DataQueryMaster<'_>,
+    team_id: TeamId,
+    user: UserId,
+) -> Result<bool, CommonDataClientError> {
+    let team = qm.query_team(team_id).await?;
+    let Some(member) = qm
+        .query_organization_member(team.organization_id, user)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    for member_team in member.teams {
+        if team_ancestors(qm, member_team).await?.contains(&team_id) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// The directory the repos of `namespace` live in, relative to the repos root.
+///
+/// The directory of a team is nested in the ones of its parents, as in `org/team/sub-team`.
+pub async fn namespace_dir(
+    qm: &DataQueryMaster<'_>,
+    namespace: NamespaceId,
+) -> Result<PathBuf, CommonDataClientError> {
+    let mut pb = PathBuf::new();
+
+    match namespace {
+        NamespaceId::GlobalNamespace => {}
+        NamespaceId::User(user) => {
+            let user = qm.query_user(user).await?;
+            pb.push(user.username.as_str());
+        }
+        NamespaceId::Organization(org) => {
+            let org = qm.query_organization(org).await?;
+            pb.push(org.name.as_str());
+        }
+        NamespaceId::Team(org, team) => {
+            let org = qm.query_organization(org).await?;
+            pb.push(org.name.as_str());
+
+            let ancestors = team_ancestors(qm, team).await?;
+            for team in ancestors.into_iter().rev() {
+                let team = qm.query_team(team).await?;
+                pb.push(team.name.as_str());
+            }
+        }
+    }
+
+    Ok(pb)
+}
+
+/// The directory of `repo`, relative to the repos root.
+pub async fn repo_path(
+    qm: &DataQueryMaster<'_>,
+    repo: &Repo,
+) -> Result<PathBuf, CommonDataClientError> {
+    let mut pb = namespace_dir(qm, repo.namespace.0).await?;
+    pb.push(repo.name.as_str());
+
+    Ok(pb)
+}
+
+/// Finds the repo that lived at `path` (relative to the repos root) before it
+/// was renamed or moved, if any.
+///
+/// This is only meant as a fallback for when there is no repo at `path`, as a
+/// repo that currently lives there always takes precedence. The namespace
+/// the repo was in still has to exist under the same name.
+pub async fn query_repo_by_previous_path(
+    qm: &DataQueryMaster<'_>,
+    path: &Path,
+) -> Result<Option<Repo>, CommonDataClientError> {
+    let Some(segments) = path
+        .iter()
+        .map(|segment| segment.to_str())
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(None);
+    };
+
+    let Some((&name, namespace_segments)) = segments.split_last() else {
+        return Ok(None);
+    };
+
+    let namespace = match namespace_segments {
+        [] => NamespaceId::GlobalNamespace,
+        [first, teams @ ..] => match qm.query_organization_by_name(*first).await? {
+            Some(org) => {
+                let mut namespace = NamespaceId::Organization(org.id);
+
+                for &team_name in teams {
+                    let parent = match namespace {
+                        NamespaceId::Team(_, team) => Some(team),
+                        _ => None,
+                    };
+
+                    match qm
+                        .query_team_by_name(org.id, team_name)
+                        .await?
+                        .filter(|team| team.parent == parent)
+                    {
+                        Some(team) => namespace = NamespaceId::Team(org.id, team.id),
+                        None => return Ok(None),
+                    }
+                }
+
+                namespace
+            }
+            None if teams.is_empty() => match qm.query_user_by_username(*first).await? {
+                Some(user) => NamespaceId::User(user.id),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        },
+    };
+
+    qm.query_repo_by_previous_name(name, &RepoNamespace(namespace))
+        .await
+}
+
+/// Collects all the permissions `user` has on `repo`, together with where they come from.
+///
+/// The effective permissions are the union of:
+///
+/// * the global permissions of the repo,
+/// * the permissions granted directly to the user,
+/// * the permissions granted to any of the teams the user is in, or to their parent teams,
+/// * the base role of the organization the repo belongs to, if the user is a member,
+/// * admin, if the user owns the namespace (or the organization) of the repo.
+pub async fn explain_permissions(
+    repo: &Repo,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+) -> Result<PermissionsExplanation, CommonDataClientError> {
+    let mut explanation = PermissionsExplanation::new();
+
+    explanation.grant(
+        PermissionSource::Global,
+        repo.repo_config.global_permissions,
+    );
+
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(explanation),
+    };
+
+    if let Some(user_perms) = qm.query_repo_user_perms(repo.id, user).await? {
+        explanation.grant(PermissionSource::Direct, user_perms);
+    }
+
+    match repo.namespace.0 {
+        NamespaceId::GlobalNamespace => {}
+        NamespaceId::User(owner) => {
+            if owner == user {
+                explanation.grant(
+                    PermissionSource::NamespaceOwnership,
+                    RepoRole::Admin.permissions(),
+                );
+            }
+        }
+        NamespaceId::Organization(org_id) | NamespaceId::Team(org_id, _) => {
+            let org = qm.query_organization(org_id).await?;
+
+            if let Some(member) = qm.query_organization_member(org_id, user).await? {
+                if member.role.can_manage() {
+                    explanation.grant(
+                        PermissionSource::OrganizationRole(org_id, member.role),
+                        RepoRole::Admin.permissions(),
+                    );
+                } else if let Some(base_role) = org.base_role {
+                    if member.role.gets_base_role() {
+                        explanation.grant(
+                            PermissionSource::OrganizationBaseRole(org_id),
+                            base_role.permissions(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    for (team_id, team_perms) in qm.query_repo_team_perms(repo.id).await? {
+        if is_team_member(qm, team_id, user).await? {
+            explanation.grant(PermissionSource::Team(team_id), team_perms);
+        }
+    }
+
+    Ok(explanation)
+}
+
+/// Computes the permissions `user` has on `repo`.
+///
+/// See [`explain_permissions`] for where they can come from.
+pub async fn effective_permissions(
+    repo: &Repo,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+) -> Result<RepoPermissions, CommonDataClientError> {
+    Ok(explain_permissions(repo, qm, user).await?.permissions)
+}
+
+/// The most privileged [`RepoRole`] `user` has on `repo`.
+pub async fn effective_role(
+    repo: &Repo,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+) -> Result<Option<RepoRole>, CommonDataClientError> {
+    Ok(RepoRole::from_permissions(
+        effective_permissions(repo, qm, user).await?,
+    ))
+}
+
+/// The [`RepoConfig`](upsilon_git_hooks::repo_config::RepoConfig) passed to the git hooks.
+pub fn lower_repo_config(repo: &Repo) -> upsilon_git_hooks::repo_config::RepoConfig {
+    upsilon_git_hooks::repo_config::RepoConfig {
+        protected_branches: repo
+            .repo_config
+            .protected_branches
+            .iter()
+            .map(|it| upsilon_git_hooks::repo_config::ProtectedBranchRule {
+                name: it.pattern.clone(),
+                needs_admin: it.needs_admin,
+                block_force_push: it.block_force_push,
+                block_deletion: it.block_deletion,
+                require_linear_history: it.require_linear_history,
+                push_users: it.push_users.iter().map(ToString::to_string).collect(),
+                push_teams: it.push_teams.iter().map(ToString::to_string).collect(),
+            })
+            .collect(),
+        protected_tags: repo
+            .repo_config
+            .protected_tags
+            .iter()
+            .map(|it| upsilon_git_hooks::repo_config::ProtectedTagRule {
+                name: it.pattern.clone(),
+                allowed_users: it.allowed_users.iter().map(ToString::to_string).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// The [`UserConfig`](upsilon_git_hooks::user_config::UserConfig) passed to the git hooks.
+///
+/// Only the teams that some branch protection rule of `repo` restricts pushes to
+/// are looked up, as those are the only ones the hooks care about.
+pub async fn lower_user_config(
+    repo: &Repo,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+    perms: RepoPermissions,
+) -> Result<upsilon_git_hooks::user_config::UserConfig, CommonDataClientError> {
+    let mut teams = Vec::new();
+
+    if let Some(user) = user {
+        let push_teams = repo
+            .repo_config
+            .protected_branches
+            .iter()
+            .flat_map(|rule| rule.push_teams.iter().copied())
+            .collect::<BTreeSet<_>>();
+
+        for team_id in push_teams {
+            if is_team_member(qm, team_id, user).await? {
+                teams.push(team_id.to_string());
+            }
+        }
+    }
+
+    Ok(upsilon_git_hooks::user_config::UserConfig {
+        permissions: upsilon_git_hooks::user_config::UserPermissions {
+            has_read: perms.can_read(),
+            has_write: perms.can_write(),
+            has_admin: perms.has_admin(),
+        },
+        user: user.map(|user| user.to_string()),
+        teams,
+    })
+}
+
+/// The branch protection rules of `repo` that would make the `update` hook
+/// reject a plain push to `ref_name` from `user`, who has `perms`.
+pub async fn blocking_branch_protection_rules<'a>(
+    repo: &'a Repo,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+    ref_name: &str,
+    perms: RepoPermissions,
+) -> Result<Vec<&'a BranchProtectionRule>, CommonDataClientError> {
+    let lowered_repo_config = lower_repo_config(repo);
+    let user_config = lower_user_config(repo, qm, user, perms).await?;
+
+    // lowering maps the rules one to one, so we can zip them back together.
+    Ok(repo
+        .repo_config
+        .protected_branches
+        .iter()
+        .zip(lowered_repo_config.protected_branches.iter())
+        .filter(|(_, lowered)| lowered.applies_to(ref_name) && lowered.blocks_push(&user_config))
+        .map(|(rule, _)| rule)
+        .collect())
+}
+
+/// Narrows `perms` down to what `token` allows on `repo`, if the user
+/// authenticated with a personal access token.
+///
+/// The token never takes away what [`global_permissions`](upsilon_models::repo::RepoConfig::global_permissions)
+/// give to everyone anyway.
+pub fn restrict_to_token(
+    repo: &Repo,
+    perms: RepoPermissions,
+    token: Option<&PersonalAccessToken>,
+) -> RepoPermissions {
+    match token {
+        Some(token) => {
+            token.restrict_repo_permissions(repo.id, perms) | repo.repo_config.global_permissions
+        }
+        None => perms,
+    }
+}
+
 pub async fn check_user_has_permissions(
-    repo: &upsilon_models::repo::Repo,
+    repo: &Repo,
     service: GitService,
-    qm: &upsilon_data::DataQueryMaster<'_>,
-    user: Option<upsilon_models::users::UserId>,
+    qm: &DataQueryMaster<'_>,
+    user: Option<UserId>,
+    token: Option<&PersonalAccessToken>,
 ) -> Result<
     (
         upsilon_git_hooks::repo_config::RepoConfig,
@@ -85,48 +498,57 @@ pub async fn check_user_has_permissions(
     ),
     LackingPermissionsError,
 > {
-    let required = RequiredRepoPermissions::for_service(service);
+    let user_perms = restrict_to_token(repo, effective_permissions(repo, qm, user).await?, token);
 
-    let user_perms = if let Some(user) = user {
-        let user_perms = qm.query_repo_user_perms(repo.id, user).await?;
+    check_required_permissions(service, user_perms)?;
 
-        user_perms.unwrap_or(repo.repo_config.global_permissions)
-    } else {
-        repo.repo_config.global_permissions
-    };
+    Ok((
+        lower_repo_config(repo),
+        lower_user_config(repo, qm, user, user_perms).await?,
+    ))
+}
 
-    if required.read {
-        let has_read = user_perms.can_read();
-        if !has_read {
-            return Err(LackingPermissionsError::Read);
-        }
+/// Like [`check_user_has_permissions`], but for a session authenticated with
+/// a deploy key, which can only ever access the repo it was added to.
+pub async fn check_deploy_key_has_permissions(
+    repo: &Repo,
+    service: GitService,
+    qm: &DataQueryMaster<'_>,
+    deploy_key: &DeployKey,
+) -> Result<
+    (
+        upsilon_git_hooks::repo_config::RepoConfig,
+        upsilon_git_hooks::user_config::UserConfig,
+    ),
+    LackingPermissionsError,
+> {
+    if deploy_key.repo != repo.id {
+        return Err(LackingPermissionsError::Read);
     }
 
-    if required.write {
-        let has_write = user_perms.can_write();
-        if !has_write {
-            return Err(LackingPermissionsError::Write);
-        }
-    }
+    let perms = deploy_key.permissions();
+
+    check_required_permissions(service, perms)?;
 
     Ok((
-        upsilon_git_hooks::repo_config::RepoConfig {
-            protected_branches: repo
-                .repo_config
-                .protected_branches
-                .iter()
-                .map(|it| upsilon_git_hooks::repo_config::ProtectedBranchRule {
-                    name: it.branch_name.clone(),
-                    needs_admin: it.needs_admin,
-                })
-                .collect(),
-        },
-        upsilon_git_hooks::user_config::UserConfig {
-            permissions: upsilon_git_hooks::user_config::UserPermissions {
-                has_read: user_perms.can_read(),
-                has_write: user_perms.can_write(),
-                has_admin: user_perms.has_admin(),
-            },
-        },
+        lower_repo_config(repo),
+        lower_user_config(repo, qm, None, perms).await?,
     ))
 }
+
+fn check_required_permissions(
+    service: GitService,
+    perms: RepoPermissions,
+) -> Result<(), LackingPermissionsError> {
+    let required = RequiredRepoPermissions::for_service(service);
+
+    if required.read && !perms.can_read() {
+        return Err(LackingPermissionsError::Read);
+    }
+
+    if required.write && !perms.can_write() {
+        return Err(LackingPermissionsError::Write);
+    }
+
+    Ok(())
+}
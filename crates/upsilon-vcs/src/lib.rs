@@ -24,7 +24,7 @@ mod daemon;
 mod http_backend;
 
 use std::cell::RefCell;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
 use std::result::Result as StdResult;
 
@@ -44,6 +44,14 @@ impl UpsilonVcsConfig {
     }
 }
 
+/// Whether `repo` is a relative path made only of plain segments, and
+/// so cannot point outside of the repos directory, no matter how deep it is.
+pub fn is_plain_repo_path(repo: impl AsRef<Path>) -> bool {
+    let mut components = repo.as_ref().components().peekable();
+
+    components.peek().is_some() && components.all(|it| matches!(it, Component::Normal(_)))
+}
+
 pub struct Repository {
     repo: git2::Repository,
     path: PathBuf,
@@ -104,6 +112,22 @@ impl Repository {
             revision: self.repo.revparse(revspec)?,
         })
     }
+
+    /// Points `HEAD` to `refs/heads/{branch}`.
+    ///
+    /// The branch doesn't have to exist yet, in which case `HEAD` will be unborn
+    /// until the first push to it.
+    pub fn set_default_branch(&self, branch: &str) -> Result<()> {
+        let refname = format!("refs/heads/{branch}");
+
+        if !git2::Reference::is_valid_name(&refname) {
+            return Err(Error::InvalidBranchName);
+        }
+
+        self.repo.set_head(&refname)?;
+
+        Ok(())
+    }
 }
 
 pub struct Revspec<'r> {
@@ -559,6 +583,9 @@ pub enum Error {
 
     #[error("no such repo")]
     NoSuchRepo,
+
+    #[error("invalid branch name")]
+    InvalidBranchName,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -682,6 +709,13 @@ fn setup_hook(hooks_exe: &Path, hook_path: &Path, hook_name: &str) -> Result<()>
         ),
     )?;
 
+    // git skips hooks that are not executable
+    #[cfg(unix)]
+    std::fs::set_permissions(
+        hook_path,
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )?;
+
     Ok(())
 }
 
@@ -709,6 +743,32 @@ pub async fn read_repo_id(
     read_repo_id_absolute(config, config.repo_dir(repo_path)).await
 }
 
+/// Makes `repo_config` the config the hooks of the repo see from now on,
+/// including the hooks of the pushes that are already in progress.
+pub async fn write_hook_repo_config(
+    config: &UpsilonVcsConfig,
+    repo_path: impl AsRef<Path>,
+    repo_config: &upsilon_git_hooks::repo_config::RepoConfig,
+) -> Result<()> {
+    write_hook_repo_config_absolute(config.repo_dir(repo_path), repo_config).await
+}
+
+pub async fn write_hook_repo_config_absolute(
+    repo_path: impl AsRef<Path>,
+    repo_config: &upsilon_git_hooks::repo_config::RepoConfig,
+) -> Result<()> {
+    let config_file = repo_path
+        .as_ref()
+        .join(upsilon_git_hooks::repo_config::REPO_CONFIG_FILE);
+    let tmp_file = config_file.with_extension("json.tmp");
+
+    // hooks should never see a partially written config
+    tokio::fs::write(&tmp_file, repo_config.serialized()).await?;
+    tokio::fs::rename(&tmp_file, &config_file).await?;
+
+    Ok(())
+}
+
 pub fn setup_mirror(
     config: &UpsilonVcsConfig,
     url: impl AsRef<str>,
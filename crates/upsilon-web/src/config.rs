@@ -17,7 +17,10 @@
 use std::path::PathBuf;
 
 use serde::{Deserialize, Deserializer};
-use upsilon_core::config::{GqlDebugConfig, UsersConfig};
+use upsilon_core::config::{
+    GqlDebugConfig, MailerConfig, OidcConfig, RateLimitConfig, UsersConfig
+};
+use upsilon_core::rate_limit::RateLimiter;
 use upsilon_ssh_russh::{CompleteRusshServerConfig, RusshServerConfig};
 use upsilon_vcs::UpsilonVcsConfig;
 
@@ -34,6 +37,12 @@ pub struct Config {
     pub data_backend: DataBackendConfig,
 
     pub users: UsersConfig,
+    #[serde(default)]
+    pub oidc: OidcConfig,
+    #[serde(rename = "rate-limit", default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub mailer: MailerConfig,
     pub plugins: Option<PluginsConfigMap>,
 
     pub frontend: FrontendConfig,
@@ -101,10 +110,10 @@ impl From<RusshServerConfig> for RusshServerConfigTemp {
 }
 
 impl RusshServerConfigTemp {
-    pub fn complete(&mut self, vcs_config: UpsilonVcsConfig) {
+    pub fn complete(&mut self, vcs_config: UpsilonVcsConfig, rate_limiter: RateLimiter) {
         let old = std::mem::replace(self, RusshServerConfigTemp::Temp);
         if let RusshServerConfigTemp::Incomplete(config) = old {
-            *self = RusshServerConfigTemp::Complete(config.complete(vcs_config));
+            *self = RusshServerConfigTemp::Complete(config.complete(vcs_config, rate_limiter));
         }
     }
 
@@ -19,7 +19,7 @@ use std::collections::HashMap;
 use std::convert::Infallible;
 use std::io::Cursor;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
 use path_slash::PathExt;
@@ -29,13 +29,15 @@ use rocket::fs::NamedFile;
 use rocket::http::uri::fmt::ValidRoutePrefix;
 use rocket::http::uri::Origin;
 use rocket::http::{Header, HeaderMap, Status};
+use rocket::outcome::try_outcome;
 use rocket::request::{FromRequest, Outcome};
-use rocket::response::Responder;
+use rocket::response::{Redirect, Responder};
 use rocket::{routes, Build, Data, Request, Response, Rocket, State};
 use rocket_basicauth::{BasicAuth, BasicAuthError};
-use upsilon_api::auth::{AuthContext, AuthToken, AuthTokenError};
+use upsilon_api::auth::{hash_secret, AuthContext, AuthToken, AuthTokenError};
 use upsilon_core::config::Cfg;
-use upsilon_data::upsilon_models::repo::{Repo, RepoId};
+use upsilon_core::rate_limit::{RateLimitKey, RateLimited, RateLimiter};
+use upsilon_data::upsilon_models::repo::{Repo, RepoId, RepoPermissions};
 use upsilon_data::{DataClientMasterHolder, DataQueryMaster};
 use upsilon_vcs::{
     GitBackendCgiRequest, GitBackendCgiRequestMethod, GitBackendCgiResponse, UpsilonVcsConfig
@@ -111,9 +113,14 @@ impl<'r> FromRequest<'r> for RepoPath {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        RepoPathRaw::from_request(request)
-            .await
-            .map(|it| Self(PathBuf::from(it.0)))
+        let path = PathBuf::from(try_outcome!(RepoPathRaw::from_request(request).await).0);
+
+        // repos can be nested arbitrarily deep, but never outside of the repos directory
+        if !upsilon_vcs::is_plain_repo_path(&path) {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+
+        Outcome::Success(Self(path))
     }
 }
 
@@ -127,6 +134,8 @@ enum GetRepoError {
     InvalidUUID(#[from] upsilon_id::FromStrError),
     #[error("Data backend error: {0}")]
     DataBackendError(#[from] upsilon_data::CommonDataClientError),
+    #[error("Repo moved from {from:?} to {to:?}")]
+    RepoMoved { from: PathBuf, to: PathBuf },
 }
 
 impl RepoPath {
@@ -136,17 +145,64 @@ impl RepoPath {
             .parse()?)
     }
 
+    /// Finds the repo at this path.
+    ///
+    /// If the repo was renamed or moved away from here, the client is only
+    /// redirected if `auth` can read it; to anyone else, it looks as if
+    /// there never was a repo here.
     async fn get_repo<'a>(
         &self,
         vcs_config: &UpsilonVcsConfig,
         data: &DataQueryMaster<'a>,
+        auth: Option<&AuthTokenBasic>,
     ) -> Result<Repo, GetRepoError> {
-        let id = self.get_repo_id(vcs_config).await?;
+        let id = match self.get_repo_id(vcs_config).await {
+            Ok(id) => id,
+            Err(GetRepoError::VcsError(upsilon_vcs::Error::NoSuchRepo)) => {
+                // the repo might have been renamed or moved since
+                let Some(repo) =
+                    upsilon_vcs_permissions::query_repo_by_previous_path(data, &self.0).await?
+                else {
+                    return Err(GetRepoError::VcsError(upsilon_vcs::Error::NoSuchRepo));
+                };
+
+                if !user_permissions(&repo, data, auth).await?.can_read() {
+                    return Err(GetRepoError::VcsError(upsilon_vcs::Error::NoSuchRepo));
+                }
+
+                return Err(GetRepoError::RepoMoved {
+                    from: self.0.clone(),
+                    to: upsilon_vcs_permissions::repo_path(data, &repo).await?,
+                });
+            }
+            Err(e) => return Err(e),
+        };
 
         Ok(data.query_repo(id).await?)
     }
 }
 
+/// What `auth` can do with `repo`, by the same rules as for every other way
+/// of accessing it.
+async fn user_permissions(
+    repo: &Repo,
+    data: &DataQueryMaster<'_>,
+    auth: Option<&AuthTokenBasic>,
+) -> Result<RepoPermissions, upsilon_data::CommonDataClientError> {
+    let perms = upsilon_vcs_permissions::effective_permissions(
+        repo,
+        data,
+        auth.map(|it| it.token.claims.sub),
+    )
+    .await?;
+
+    Ok(upsilon_vcs_permissions::restrict_to_token(
+        repo,
+        perms,
+        auth.and_then(|it| it.token.personal_access_token.as_ref()),
+    ))
+}
+
 pub(crate) struct GitHttpProtocolFairing;
 
 #[rocket::async_trait]
@@ -254,6 +310,8 @@ enum GitHttpBackendError {
     DataBackendError(#[from] upsilon_data::CommonDataClientError),
     #[error("Unknown git service")]
     UnknownGitService,
+    #[error("Not a static git file")]
+    NotServed,
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for GitHttpBackendError {
@@ -261,6 +319,12 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for GitHttpBackendError {
         let vcs_errors = request.rocket().state::<Cfg<VcsErrorsConfig>>().unwrap();
 
         match self {
+            GitHttpBackendError::GetRepoError(GetRepoError::RepoMoved { from, to }) => {
+                Redirect::permanent(moved_repo_location(request, &from, &to)).respond_to(request)
+            }
+            GitHttpBackendError::GetRepoError(GetRepoError::VcsError(
+                upsilon_vcs::Error::NoSuchRepo,
+            )) => (Status::NotFound, "").respond_to(request),
             GitHttpBackendError::HandleGitHttpBackend(_) => (
                 Status::InternalServerError,
                 vcs_errors.if_verbose(|| self.to_string()),
@@ -299,10 +363,33 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for GitHttpBackendError {
             GitHttpBackendError::UnknownGitService => {
                 (Status::BadRequest, "Requested unknown git service").respond_to(request)
             }
+            GitHttpBackendError::NotServed => (Status::NotFound, "").respond_to(request),
         }
     }
 }
 
+/// Where the request would have gone if it used the current path of the repo,
+/// which is `to` instead of `from`.
+fn moved_repo_location(request: &Request<'_>, from: &Path, to: &Path) -> String {
+    let uri = request.uri();
+    let path = uri.path();
+    let path = path.as_str();
+    let path = path
+        .strip_prefix(PRIVATE_GIT_HTTP_BACKEND_ROOT)
+        .or_else(|| path.strip_prefix(PRIVATE_GIT_STATIC_ROOT))
+        .unwrap_or(path);
+
+    let rest = path
+        .strip_prefix('/')
+        .and_then(|it| it.strip_prefix(&*from.to_slash_lossy()))
+        .unwrap_or_default();
+
+    match uri.query() {
+        Some(query) => format!("/{}{rest}?{query}", to.to_slash_lossy()),
+        None => format!("/{}{rest}", to.to_slash_lossy()),
+    }
+}
+
 fn status_code_from_status_line(status_line: &str) -> Status {
     let status_num = status_line
         .bytes()
@@ -324,6 +411,8 @@ enum AuthTokenBasicError {
     BasicAuthError(BasicAuthError),
     #[error("auth token error: {0}")]
     AuthTokenError(#[from] AuthTokenError),
+    #[error("{0}")]
+    RateLimited(#[from] RateLimited),
 }
 
 impl From<BasicAuthError> for AuthTokenBasicError {
@@ -347,12 +436,65 @@ impl<'r> FromRequest<'r> for AuthTokenBasic {
 
         let BasicAuth { username, password } = basic_auth;
 
+        // Any username goes with a token, so the account the attempts are
+        // counted against is the one the token belongs to, or the token
+        // itself while it is not known to be valid.
+        let rate_limiter = <&State<RateLimiter>>::from_request(request).await.unwrap();
+        let ip_key = request.client_ip().map(RateLimitKey::Ip);
+        let token_key = RateLimitKey::Account(hash_secret(&password));
+        let rate_limit_keys = ip_key
+            .clone()
+            .into_iter()
+            .chain([token_key.clone()])
+            .collect::<Vec<_>>();
+
+        if let Err(e) = rate_limiter.check(&rate_limit_keys) {
+            return Outcome::Failure((Status::TooManyRequests, e.into()));
+        }
+
         let cx = <&State<AuthContext>>::from_request(request).await.unwrap();
+        let data = <&State<DataClientMasterHolder>>::from_request(request)
+            .await
+            .unwrap();
+        let qm = data.query_master();
+
+        let token = match AuthToken::authenticate(password, cx, &qm).await {
+            Ok(token) => token,
+            // the password was right, so it shouldn't count as a failed attempt
+            Err(e @ AuthTokenError::UserSuspended) => {
+                return Outcome::Failure((Status::Forbidden, e.into()));
+            }
+            Err(e) => {
+                rate_limiter.record_failure(&rate_limit_keys);
+
+                return Outcome::Failure((Status::Unauthorized, e.into()));
+            }
+        };
+
+        let owner = match qm.query_user(token.claims.sub).await {
+            Ok(owner) => owner,
+            Err(e) => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    AuthTokenError::from(e).into(),
+                ));
+            }
+        };
 
-        match AuthToken::from_string(password, cx) {
-            Ok(token) => Outcome::Success(AuthTokenBasic { username, token }),
-            Err(e) => Outcome::Failure((Status::Unauthorized, e.into())),
+        let account_key = RateLimitKey::Account(owner.username.to_string());
+        let owner_keys = ip_key
+            .into_iter()
+            .chain([account_key.clone()])
+            .collect::<Vec<_>>();
+
+        if let Err(e) = rate_limiter.check(&owner_keys) {
+            return Outcome::Failure((Status::TooManyRequests, e.into()));
         }
+
+        rate_limiter.record_success(&token_key);
+        rate_limiter.record_success(&account_key);
+
+        Outcome::Success(AuthTokenBasic { username, token })
     }
 }
 
@@ -441,7 +583,9 @@ async fn git_http_backend_cgi_get(
     data: &State<DataClientMasterHolder>,
 ) -> Result<GitHttpBackendResponder, GitHttpBackendError> {
     let qm = data.query_master();
-    let repo = repo_path.get_repo(vcs_config, &qm).await?;
+    let repo = repo_path
+        .get_repo(vcs_config, &qm, auth_token.as_ref())
+        .await?;
     let path = PathBuf::from("/").join(path); // add the root /
 
     let service = get_service_for_path_and_query(&path, query.as_ref()).await?;
@@ -451,6 +595,9 @@ async fn git_http_backend_cgi_get(
         service,
         &qm,
         auth_token.as_ref().map(|it| it.token.claims.sub),
+        auth_token
+            .as_ref()
+            .and_then(|it| it.token.personal_access_token.as_ref()),
     )
     .await;
 
@@ -487,7 +634,9 @@ async fn git_http_backend_cgi_post(
     data_client_master: &State<DataClientMasterHolder>,
 ) -> Result<GitHttpBackendResponder, GitHttpBackendError> {
     let qm = data_client_master.query_master();
-    let repo = repo_path.get_repo(vcs_config, &qm).await?;
+    let repo = repo_path
+        .get_repo(vcs_config, &qm, auth_token.as_ref())
+        .await?;
     let path = PathBuf::from("/").join(path); // add the root /
 
     let service = get_service_for_path_and_query(&path, query.as_ref()).await?;
@@ -497,6 +646,9 @@ async fn git_http_backend_cgi_post(
         service,
         &qm,
         auth_token.as_ref().map(|it| it.token.claims.sub),
+        auth_token
+            .as_ref()
+            .and_then(|it| it.token.personal_access_token.as_ref()),
     )
     .await;
 
@@ -529,14 +681,8 @@ async fn git_static_get(
     auth: Option<AuthTokenBasic>,
 ) -> Result<NamedFile, GitHttpBackendError> {
     let qm = data.query_master();
-    let repo = repo_path.get_repo(vcs_config, &qm).await?;
-    let repo_perms_for_user = match &auth {
-        Some(auth) => qm
-            .query_repo_user_perms(repo.id, auth.token.claims.sub)
-            .await?
-            .unwrap_or(repo.repo_config.global_permissions),
-        None => repo.repo_config.global_permissions,
-    };
+    let repo = repo_path.get_repo(vcs_config, &qm, auth.as_ref()).await?;
+    let repo_perms_for_user = user_permissions(&repo, &qm, auth.as_ref()).await?;
 
     // we only need read perms to send static files
     if !repo_perms_for_user.can_read() {
@@ -547,6 +693,16 @@ async fn git_static_get(
         };
     }
 
+    // only objects and packs are served from here; everything else in the
+    // repo directory, such as the config file of the hooks, stays private
+    let is_static_path = path.to_str().map_or(false, |p| {
+        GIT_HTTP_PROTOCOL_STATIC_PATHS.is_match(&format!("/{p}"))
+    });
+
+    if !is_static_path {
+        return Err(GitHttpBackendError::NotServed);
+    }
+
     let file_path = vcs_config.get_path().join(path);
 
     Ok(NamedFile::open(file_path).await?)
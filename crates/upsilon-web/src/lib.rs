@@ -26,6 +26,7 @@ use rocket::{async_trait, error, Build, Orbit, Rocket, Shutdown};
 use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, Cors, CorsOptions, Method};
 use upsilon_api::{GraphQLApiConfigurator, UshArgs};
 use upsilon_core::config::Cfg;
+use upsilon_core::rate_limit::RateLimiter;
 use upsilon_vcs::{SpawnDaemonError, UpsilonVcsConfig};
 use upsilon_web_interface::WebFairing;
 
@@ -57,6 +58,9 @@ impl Fairing for ConfigManager {
             git_ssh,
             data_backend,
             users,
+            oidc,
+            rate_limit,
+            mailer,
             vcs_errors,
             debug,
             frontend,
@@ -97,10 +101,12 @@ impl Fairing for ConfigManager {
             rocket = rocket.attach(git::GitHttpProtocolFairing);
         }
 
+        let rate_limiter = RateLimiter::new(rate_limit);
+
         let ssh_port = if let Some(mut git_ssh) = git_ssh {
             let ssh_port = match &mut git_ssh {
                 GitSshProtocol::Russh(russh) => {
-                    russh.complete(vcs.clone());
+                    russh.complete(vcs.clone(), rate_limiter.clone());
                     russh.port()
                 }
             };
@@ -143,7 +149,13 @@ impl Fairing for ConfigManager {
             ]);
         }
 
-        rocket = rocket.attach(GraphQLApiConfigurator::new(UshArgs::new(ush_args)));
+        rocket = rocket.attach(GraphQLApiConfigurator::new(
+            UshArgs::new(ush_args),
+            users.auth.clone(),
+            users.admins.clone(),
+            oidc,
+            mailer,
+        ));
 
         let cors = Cors::from_options(
             &CorsOptions::default()
@@ -193,7 +205,8 @@ impl Fairing for ConfigManager {
             .manage(Cfg::new(vcs))
             .manage(Cfg::new(vcs_errors))
             .manage(Cfg::new(graphql))
-            .manage(Cfg::new(users)))
+            .manage(Cfg::new(users))
+            .manage(rate_limiter))
     }
 
     async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
@@ -23,11 +23,19 @@ use clap::Parser;
 #[derive(Parser, Debug)]
 enum App {
     #[clap(name = "web")]
-    Web,
+    Web {
+        /// Make the user with this username an instance admin.
+        ///
+        /// Takes the place of `users.admins` from the config; the user
+        /// has to exist already, and is made an admin at startup.
+        #[clap(long = "admin")]
+        admins: Vec<String>,
+    },
 }
 
-fn proc(exe: &Path, subproc: &Mutex<Option<Child>>) {
+fn proc(exe: &Path, env: &[(&str, String)], subproc: &Mutex<Option<Child>>) {
     let child = std::process::Command::new(exe)
+        .envs(env.iter().cloned())
         .spawn()
         .expect("Failed to spawn subprocess");
 
@@ -68,8 +76,14 @@ fn main() {
     }
 
     match app {
-        App::Web => {
-            proc(&upsilon_core::alt_exe("upsilon-web"), &subprocess);
+        App::Web { admins } => {
+            let mut env = vec![];
+
+            if !admins.is_empty() {
+                env.push(("UPSILON_USERS_ADMINS", format!("[{}]", admins.join(","))));
+            }
+
+            proc(&upsilon_core::alt_exe("upsilon-web"), &env, &subprocess);
         }
     }
 
@@ -14,6 +14,7 @@
  *    limitations under the License.
  */
 
+use std::ffi::OsString;
 use std::path::Path;
 use std::time::Duration;
 
@@ -34,12 +35,13 @@ fn graceful_shutdown_host_setup(
     cmd.arg("--kfile").arg(kfile);
     cmd.arg("--grace").arg(format!("{}s", grace.as_secs()));
 
-    cmd.args(
-        old_cmd
-            .as_std()
-            .get_args()
-            .flat_map(|arg| vec!["--arg".as_ref(), arg]),
-    );
+    // `--arg=...`, so that arguments starting with `-` are not taken
+    // as options of the host
+    cmd.args(old_cmd.as_std().get_args().map(|arg| {
+        let mut host_arg = OsString::from("--arg=");
+        host_arg.push(arg);
+        host_arg
+    }));
 }
 
 #[cfg(target_os = "linux")]
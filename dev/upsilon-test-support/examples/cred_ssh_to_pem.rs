@@ -14,11 +14,11 @@
  *    limitations under the License.
  */
 
-use russh_keys::key::{KeyPair, SignatureHash};
+use upsilon_test_support::helpers::create_ssh_key;
 use upsilon_test_support::TestCx;
 
 fn main() {
-    let kp = KeyPair::generate_rsa(2048, SignatureHash::SHA2_512).unwrap();
+    let kp = create_ssh_key().unwrap();
     let result = TestCx::cred_ssh_to_pem(&kp).unwrap();
     println!("{}", result);
 }
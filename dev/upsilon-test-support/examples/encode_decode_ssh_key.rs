@@ -18,16 +18,17 @@ use upsilon_test_support::prelude::*;
 
 fn main_impl() -> TestResult {
     let k = create_ssh_key()?;
-    let pubk = k.clone_public_key()?;
+    let pubk = k.public_key();
 
-    let encoded = TestCx::encode_ssh_key(&pubk)?;
+    let encoded = TestCx::encode_ssh_key(pubk)?;
 
     println!("Encoded:");
     println!("{encoded}");
 
-    let decode_pubk = russh_keys::parse_public_key_base64(&encoded)?;
+    let decode_pubk = russh::keys::parse_public_key_base64(&encoded)?;
 
-    assert_eq!(pubk, decode_pubk);
+    // the comment is not part of the encoding
+    assert_eq!(pubk.key_data(), decode_pubk.key_data());
 
     Ok(())
 }
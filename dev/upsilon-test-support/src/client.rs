@@ -65,7 +65,9 @@ impl Client {
 
         #[derive(Deserialize)]
         struct GqlResponse<T> {
-            data: T,
+            data: Option<T>,
+            #[serde(default)]
+            errors: Vec<serde_json::Value>,
         }
 
         let res = req
@@ -77,18 +79,29 @@ impl Client {
             .await?;
 
         #[cfg(debug_assertions)]
-        let data = {
+        let response = {
             let t = res.text().await?;
 
             println!("GQL response: {t}");
 
-            serde_json::from_str::<GqlResponse<T>>(&t)?.data
+            serde_json::from_str::<GqlResponse<T>>(&t)?
         };
 
         #[cfg(not(debug_assertions))]
-        let data = res.json::<GqlResponse<T>>().await?.data;
+        let response = res.json::<GqlResponse<T>>().await?;
+
+        // a query that was (even partially) rejected should fail the
+        // test, unless the test expects that
+        if !response.errors.is_empty() {
+            anyhow::bail!(
+                "GraphQL errors: {}",
+                serde_json::Value::from(response.errors)
+            );
+        }
 
-        Ok(data)
+        response
+            .data
+            .ok_or_else(|| anyhow::anyhow!("GraphQL response without data"))
     }
 
     pub async fn gql_query<T: for<'de> Deserialize<'de>>(&self, query: &str) -> TestResult<T> {
@@ -113,6 +126,34 @@ impl Client {
 
         Ok(())
     }
+
+    /// Sends an anonymous GET request to `path`, following redirects,
+    /// and returns the status of the last response.
+    pub async fn get_status(&self, path: &str) -> TestResult<reqwest::StatusCode> {
+        let res = self
+            .core
+            .inner
+            .get(format!("{}{path}", self.core.root))
+            .send()
+            .await?;
+
+        Ok(res.status())
+    }
+
+    /// Sends a GET request to `path` the way git does over dumb HTTP,
+    /// authenticating as `username` with the token of the client, if any.
+    pub async fn git_get_status(
+        &self,
+        path: &str,
+        username: &str,
+    ) -> TestResult<reqwest::StatusCode> {
+        let mut req = self.core.inner.get(format!("{}{path}", self.core.root));
+        if let Some(token) = &self.token {
+            req = req.basic_auth(username, Some(token));
+        }
+
+        Ok(req.send().await?.status())
+    }
 }
 
 #[derive(Deserialize)]
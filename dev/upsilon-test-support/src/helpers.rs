@@ -16,19 +16,26 @@
 
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Stdio};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 use anyhow::{bail, format_err};
-use git2::{BranchType, Cred, CredentialType, FetchOptions, RemoteCallbacks, Repository};
+use git2::{
+    BranchType, Cred, CredentialType, FetchOptions, PushOptions, RemoteCallbacks, Repository, Signature
+};
 use log::info;
-use russh_keys::key::{KeyPair, PublicKey};
-use russh_keys::PublicKeyBase64;
-
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::rsa::Rsa;
+use russh::keys::ssh_key::private::{KeypairData, RsaKeypair, RsaPrivateKey};
+use russh::keys::ssh_key::public::RsaPublicKey;
+use russh::keys::ssh_key::Mpint;
+use russh::keys::{PrivateKey, PublicKey, PublicKeyBase64};
+
+use crate::ssh_ca::SshCertificate;
 use crate::{
-    env_var, gql_vars, Anything, IdHolder, TestCx, TestCxConfig, TestResult, Token, Username
+    env_var, gql_vars, Anything, IdHolder, TestCx, TestCxConfig, TestResult, Token, Username, MAIL_DIR
 };
 
 pub async fn register_dummy_user(cx: &mut TestCx) {
@@ -84,6 +91,115 @@ users:
     );
 }
 
+pub fn upsilon_mock_oidc(cfg: &mut TestCxConfig) {
+    cfg.with_mock_oidc();
+}
+
+pub fn upsilon_ssh_user_ca(cfg: &mut TestCxConfig) {
+    cfg.with_ssh_user_ca();
+}
+
+pub fn upsilon_require_2fa_for_org_owners(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n  auth:\n",
+        "\n  auth:\n    two-factor:\n      require-for-org-owners: true\n",
+    );
+}
+
+/// Makes `admin` an instance admin.
+pub fn upsilon_admin_user(cfg: &mut TestCxConfig) {
+    cfg.config = cfg
+        .config
+        .replace("\nusers:\n", "\nusers:\n  admins:\n    - admin\n");
+}
+
+pub fn upsilon_invite_only(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n  register:\n    enabled: true\n",
+        "\n  register:\n    enabled: true\n    invite-only: true\n",
+    );
+}
+
+/// Only lets `example.org` addresses register without an invite.
+pub fn upsilon_allowed_email_domains(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n  register:\n    enabled: true\n",
+        "\n  register:\n    enabled: true\n    allowed-email-domains:\n      - example.org\n",
+    );
+}
+
+pub fn upsilon_require_approval(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n  register:\n    enabled: true\n",
+        "\n  register:\n    enabled: true\n    require-approval: true\n",
+    );
+}
+
+pub fn upsilon_bcrypt_passwords(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n    password:\n      type: argon2\n",
+        "\n    password:\n      type: bcrypt\n      cost: 4\n",
+    );
+}
+
+/// Checks passwords against a static LDAP directory with two users:
+///
+/// * `alice`, with password `alice-password`, in the `devs` group, which is
+///   synced to the `example` organization;
+/// * `bob`, with password `bob-password`, in no groups.
+///
+/// `admin` is a local account.
+pub fn upsilon_static_ldap(cfg: &mut TestCxConfig) {
+    cfg.config = cfg.config.replace(
+        "\n  auth:\n",
+        r#"
+  auth:
+    ldap:
+      directory:
+        type: static
+        entries:
+          - dn: uid=alice,ou=people,dc=example,dc=org
+            password: alice-password
+            attributes:
+              uid: [alice]
+              cn: [Alice Example]
+              mail: [alice@example.org]
+              memberOf: ["cn=devs,ou=groups,dc=example,dc=org"]
+          - dn: uid=bob,ou=people,dc=example,dc=org
+            password: bob-password
+            attributes:
+              uid: [bob]
+              mail: [bob@example.org]
+      group-sync:
+        - group: cn=devs,ou=groups,dc=example,dc=org
+          organization: example
+      local-accounts: [admin]
+"#,
+    );
+}
+
+/// Writes emails to files, which can be read with [`TestCx::sent_mails`].
+pub fn upsilon_file_mailer(cfg: &mut TestCxConfig) {
+    cfg.config.push_str(&format!(
+        r#"
+mailer:
+  type: file
+  path: ./{MAIL_DIR}
+"#
+    ));
+}
+
+/// Locks accounts out for an hour after 2 failed logins.
+pub fn upsilon_strict_rate_limit(cfg: &mut TestCxConfig) {
+    cfg.config.push_str(
+        r#"
+rate-limit:
+  max-attempts-per-account: 2
+  lockout: 1h
+"#,
+    );
+}
+
 pub fn upsilon_basic_config_with_git_daemon(cfg: &mut TestCxConfig) {
     cfg.with_config(
         r#"
@@ -320,10 +436,50 @@ fn upsilon_host_repo_git() -> PathBuf {
 }
 
 impl TestCx {
-    pub fn cred_ssh_to_pem(kp: &KeyPair) -> TestResult<String> {
-        let KeyPair::RSA {ref key, ..} = kp else {bail!("Not RSA")};
-        let result_vec = key.private_key_to_pem()?;
-        let k = String::from_utf8(result_vec)?;
+    /// The PEM encoding of the RSA key `kp`, which is what libgit2 takes.
+    pub fn cred_ssh_to_pem(kp: &PrivateKey) -> TestResult<String> {
+        let Some(keypair) = kp.key_data().rsa() else {
+            bail!("Not RSA")
+        };
+
+        let bn = |mpint: &Mpint| -> TestResult<BigNum> {
+            let bytes = mpint
+                .as_positive_bytes()
+                .ok_or_else(|| format_err!("Negative RSA key parameter"))?;
+
+            Ok(BigNum::from_slice(bytes)?)
+        };
+
+        let d = bn(&keypair.private.d)?;
+        let p = bn(&keypair.private.p)?;
+        let q = bn(&keypair.private.q)?;
+
+        // the exponents of the CRT are not part of the SSH encoding
+        let mut ctx = BigNumContext::new()?;
+        let one = BigNum::from_u32(1)?;
+        let crt_exponent = |prime: &BigNumRef, ctx: &mut BigNumContext| -> TestResult<BigNum> {
+            let mut prime_minus_one = BigNum::new()?;
+            prime_minus_one.checked_sub(prime, &one)?;
+
+            let mut exponent = BigNum::new()?;
+            exponent.nnmod(&d, &prime_minus_one, ctx)?;
+
+            Ok(exponent)
+        };
+        let dmp1 = crt_exponent(&p, &mut ctx)?;
+        let dmq1 = crt_exponent(&q, &mut ctx)?;
+
+        let key = Rsa::from_private_components(
+            bn(&keypair.public.n)?,
+            bn(&keypair.public.e)?,
+            d,
+            p,
+            q,
+            dmp1,
+            dmq1,
+            bn(&keypair.private.iqmp)?,
+        )?;
+        let k = String::from_utf8(key.private_key_to_pem()?)?;
 
         Ok(k)
     }
@@ -524,6 +680,102 @@ impl TestCx {
         Ok((path, repo))
     }
 
+    /// Clones over SSH with the OpenSSH client, logging in with `certificate`.
+    pub async fn clone_with_ssh_certificate<F>(
+        &self,
+        name: &str,
+        remote_path: F,
+        certificate: &SshCertificate,
+    ) -> TestResult<(PathBuf, Repository)>
+    where
+        F: FnOnce(GitRemoteRefBuilder) -> GitRemoteRefBuilder,
+    {
+        let path = self.tempdir(name).await?;
+
+        let (target_url, is_ssh) = self.build_target_url(remote_path)?;
+
+        if !is_ssh {
+            bail!("Certificates can only be used with SSH");
+        }
+
+        let exit_status = self
+            .run_command(
+                "git",
+                |c| {
+                    c.arg("-c")
+                        .arg(format!("core.sshCommand={}", certificate.ssh_command()))
+                        .arg("clone")
+                        .arg(target_url)
+                        .arg(&path)
+                },
+                Duration::from_secs(30),
+            )
+            .await?;
+
+        if !exit_status.success() {
+            bail!("git clone failed with exit status: {:?}", exit_status);
+        }
+
+        let repo = Repository::open(&path)?;
+
+        Ok((path, repo))
+    }
+
+    /// Pushes `refspec` from the repo at `repo_path` to the remote at `remote_path`,
+    /// failing if the server rejects any of the updates.
+    pub async fn push<F>(
+        &self,
+        repo_path: &Path,
+        remote_path: F,
+        refspec: &str,
+        credentials: impl Into<Option<Credentials>>,
+    ) -> TestResult
+    where
+        F: FnOnce(GitRemoteRefBuilder) -> GitRemoteRefBuilder,
+    {
+        let (target_url, is_ssh) = self.build_target_url(remote_path)?;
+        let credentials = self.process_credentials(credentials.into())?;
+
+        if is_ssh && !matches!(credentials, Some(Credentials::SshKeyPem(_))) {
+            bail!("SSH access requires SSH credentials");
+        }
+
+        let repo_path = repo_path.to_path_buf();
+        let refspec = refspec.to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            info!("Pushing {} to {}", refspec, target_url);
+
+            let repo = Repository::open(&repo_path)?;
+
+            let mut rcb = RemoteCallbacks::new();
+            Self::add_credentials_to_callbacks(credentials, &mut rcb);
+            rcb.certificate_check(|cert, _valid| {
+                Ok(cert.as_hostkey().map_or(
+                    git2::CertificateCheckStatus::CertificatePassthrough,
+                    |_it| git2::CertificateCheckStatus::CertificateOk,
+                ))
+            });
+            rcb.push_update_reference(|ref_name, status| match status {
+                None => Ok(()),
+                Some(status) => Err(git2::Error::from_str(&format!(
+                    "{ref_name} rejected: {status}"
+                ))),
+            });
+
+            let mut po = PushOptions::new();
+            po.remote_callbacks(rcb);
+
+            repo.remote_anonymous(&target_url)?
+                .push(&[refspec.as_str()], Some(&mut po))?;
+
+            Ok::<_, git2::Error>(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
     pub async fn lookup(&self, path: &str) -> TestResult<String> {
         #[derive(serde::Deserialize)]
         struct LookupResult {
@@ -549,6 +801,28 @@ impl TestCx {
         username: &str,
         password: &str,
         email: &str,
+    ) -> TestResult<CreateUserResult> {
+        self.create_user_impl(username, password, None, email).await
+    }
+
+    /// Creates a user whose password is stored as `password_hash`, as if
+    /// it was hashed with other settings.
+    pub async fn create_user_with_password_hash(
+        &mut self,
+        username: &str,
+        password_hash: &str,
+        email: &str,
+    ) -> TestResult<CreateUserResult> {
+        self.create_user_impl(username, "", Some(password_hash), email)
+            .await
+    }
+
+    async fn create_user_impl(
+        &mut self,
+        username: &str,
+        password: &str,
+        password_hash: Option<&str>,
+        email: &str,
     ) -> TestResult<CreateUserResult> {
         #[derive(serde::Deserialize)]
         struct CreateUserToken {
@@ -560,13 +834,14 @@ impl TestCx {
             .with_client(|cl| async move {
                 cl.gql_query_with_variables::<CreateUserToken>(
                     r#"
-mutation ($username: Username!, $password: PlainPassword!, $email: Email!) {
-  _debug__createTestUser(username: $username, password: $password, email: $email)
+mutation ($username: Username!, $password: PlainPassword!, $passwordHash: HashedPassword, $email: Email!) {
+  _debug__createTestUser(username: $username, password: $password, passwordHash: $passwordHash, email: $email)
 }
 "#,
                     gql_vars! {
                         "username": username,
                         "password": password,
+                        "passwordHash": password_hash,
                         "email": email,
                     },
                 )
@@ -581,6 +856,42 @@ mutation ($username: Username!, $password: PlainPassword!, $email: Email!) {
         Ok(result)
     }
 
+    pub async fn user_id(&self, user: impl Into<Username>) -> TestResult<String> {
+        #[derive(serde::Deserialize)]
+        struct ViewerResult {
+            viewer: IdHolder,
+        }
+
+        Ok(self
+            .with_client_as_user(user, |cl| async move {
+                cl.gql_query::<ViewerResult>(r#"query { viewer { id } }"#)
+                    .await
+            })
+            .await?
+            .viewer
+            .id)
+    }
+
+    pub async fn create_repo(&self, user: impl Into<Username>, name: &str) -> TestResult<String> {
+        #[derive(serde::Deserialize)]
+        struct CreateRepoResult {
+            #[serde(rename = "createRepo")]
+            create_repo: IdHolder,
+        }
+
+        Ok(self
+            .with_client_as_user(user, |cl| async move {
+                cl.gql_query_with_variables::<CreateRepoResult>(
+                    r#"mutation($name: RepoName!) { createRepo(name: $name) { id } }"#,
+                    gql_vars! {"name": name},
+                )
+                .await
+            })
+            .await?
+            .create_repo
+            .id)
+    }
+
     pub async fn run_command<F>(
         &self,
         program: impl AsRef<OsStr>,
@@ -673,11 +984,11 @@ mutation ($username: Username!, $password: PlainPassword!, $email: Email!) {
     }
 
     pub fn encode_ssh_key(key: &PublicKey) -> TestResult<String> {
-        if !matches!(key, PublicKey::RSA { .. }) {
+        if key.key_data().rsa().is_none() {
             bail!("Only RSA keys are supported");
         }
 
-        let key_string = PublicKey::public_key_base64(key);
+        let key_string = key.public_key_base64();
 
         Ok(key_string)
     }
@@ -823,7 +1134,7 @@ impl GitRemoteRefBuilder {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum GitAccessProtocol {
     Http,
     Git,
@@ -842,16 +1153,53 @@ pub fn upsilon_global_ssh(rb: GitRemoteRefBuilder) -> GitRemoteRefBuilder {
     rb.protocol(GitAccessProtocol::Ssh).path("upsilon")
 }
 
-pub fn create_ssh_key() -> TestResult<KeyPair> {
-    let key_pair = KeyPair::generate_rsa(2048, russh_keys::key::SignatureHash::SHA2_256)
-        .ok_or_else(|| format_err!("Failed to generate ssh key pair"))?;
+/// Commits an empty tree on top of `HEAD`, so that there is something to push.
+pub fn commit_empty(repo: &Repository, message: &str) -> TestResult<git2::Oid> {
+    let signature = Signature::now("test", "test@example.org")?;
+    let tree = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents = parent.iter().collect::<Vec<_>>();
+
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?)
+}
 
-    Ok(key_pair)
+pub fn create_ssh_key() -> TestResult<PrivateKey> {
+    // openssl generates RSA keys much faster than ssh-key does in debug builds
+    let rsa = Rsa::generate(2048)?;
+
+    let mpint = |bn: Option<&BigNumRef>| -> TestResult<Mpint> {
+        let bn = bn.ok_or_else(|| format_err!("Missing RSA key parameter"))?;
+
+        Ok(Mpint::from_positive_bytes(&bn.to_vec())?)
+    };
+
+    let keypair = RsaKeypair {
+        public: RsaPublicKey {
+            e: mpint(Some(rsa.e()))?,
+            n: mpint(Some(rsa.n()))?,
+        },
+        private: RsaPrivateKey {
+            d: mpint(Some(rsa.d()))?,
+            iqmp: mpint(rsa.iqmp())?,
+            p: mpint(rsa.p())?,
+            q: mpint(rsa.q())?,
+        },
+    };
+
+    Ok(PrivateKey::new(KeypairData::Rsa(keypair), "upsilon-test")?)
 }
 
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Credentials {
-    SshKey(KeyPair),
+    SshKey(PrivateKey),
     SshKeyPem(String),
     UsernameToken(Username, Token),
     UsernameAndTokenFromTokenList(Username),
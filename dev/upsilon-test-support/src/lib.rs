@@ -43,6 +43,9 @@ pub use upsilon_test_support_macros::upsilon_test;
 pub use crate::client::Client;
 
 mod client;
+pub mod oidc;
+pub mod ssh_ca;
+pub mod totp;
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone)]
 pub struct Username(String);
@@ -124,7 +127,7 @@ impl TestCx {
         stream: Option<&mut (impl AsyncRead + Unpin)>,
         mut output: impl AsyncWrite + Unpin,
     ) -> std::io::Result<()> {
-        let Some(stream) = stream else {return Ok(())};
+        let Some(stream) = stream else { return Ok(()) };
 
         let mut buffer = Vec::with_capacity(32 * 1024);
         stream.read_to_end(&mut buffer).await?;
@@ -401,6 +404,20 @@ impl TestCx {
         Ok(cx)
     }
 
+    pub fn mock_oidc(&self) -> &oidc::MockOidcServer {
+        self.config
+            .mock_oidc
+            .as_ref()
+            .expect("No mock OIDC server; use #[cfg_setup(upsilon_mock_oidc)]")
+    }
+
+    pub fn ssh_user_ca(&self) -> &ssh_ca::SshUserCa {
+        self.config
+            .ssh_user_ca
+            .as_ref()
+            .expect("No SSH user CA; use #[cfg_setup(upsilon_ssh_user_ca)]")
+    }
+
     pub fn set_panic_info(&mut self, pi: Box<dyn Any + Send>) {
         self.rt.panic = Some(TestRtPanicInfo::from(pi));
     }
@@ -420,6 +437,48 @@ Help: Annotate it with `#[offline(ignore)]` instead."#
         Ok(())
     }
 
+    /// The emails sent so far, oldest first.
+    ///
+    /// Needs `#[cfg_setup(upsilon_file_mailer)]`.
+    pub async fn sent_mails(&self) -> TestResult<Vec<String>> {
+        let dir = self.config.workdir().join(MAIL_DIR);
+
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = vec![];
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            paths.push(entry.path());
+        }
+        paths.sort();
+
+        let mut mails = vec![];
+        for path in paths {
+            mails.push(tokio::fs::read_to_string(path).await?);
+        }
+
+        Ok(mails)
+    }
+
+    /// Where the bare repository at `path` (like `user/repo`) is stored.
+    pub fn repo_dir(&self, path: &str) -> PathBuf {
+        let repos_dir = self.config.workdir().join(REPOS_DIR);
+
+        // jailed instances keep their repos in `vcs-jail-<pid>`
+        let jail = std::fs::read_dir(&repos_dir).ok().and_then(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .find(|entry| entry.file_name().to_string_lossy().starts_with("vcs-jail-"))
+        });
+
+        match jail {
+            Some(jail) => jail.path().join(path),
+            None => repos_dir.join(path),
+        }
+    }
+
     pub async fn tempdir(&self, name: &str) -> std::io::Result<PathBuf> {
         let mut p = self.config.workdir();
         p.push("tmp");
@@ -546,6 +605,8 @@ pub struct TestCxConfig {
     works_offline: bool,
     has_git_protocol: bool,
     has_ssh_protocol: bool,
+    mock_oidc: Option<oidc::MockOidcServer>,
+    ssh_user_ca: Option<ssh_ca::SshUserCa>,
 }
 
 impl TestCxConfig {
@@ -567,6 +628,8 @@ impl TestCxConfig {
             works_offline: vars.works_offline,
             has_git_protocol: false,
             has_ssh_protocol: false,
+            mock_oidc: None,
+            ssh_user_ca: None,
         };
 
         (vars.config_init)(&mut test_cx_config);
@@ -604,6 +667,30 @@ impl TestCxConfig {
         self
     }
 
+    /// Starts a mock OpenID Connect provider, and adds it to the config.
+    pub fn with_mock_oidc(&mut self) -> &mut Self {
+        let server = oidc::MockOidcServer::start().expect("Failed to start mock OIDC server");
+        self.config.push_str(&server.config());
+        self.mock_oidc = Some(server);
+        self
+    }
+
+    /// Generates an SSH CA, and makes the russh server trust the user
+    /// certificates it signs.
+    pub fn with_ssh_user_ca(&mut self) -> &mut Self {
+        let ca = ssh_ca::SshUserCa::generate(&self.workdir().join("ssh-ca"))
+            .expect("Failed to generate SSH user CA");
+        self.config = self.config.replace(
+            "\ngit-ssh:\n  type: russh\n",
+            &format!(
+                "\ngit-ssh:\n  type: russh\n  trusted_user_ca_keys:\n    - \"{}\"\n",
+                ca.public_key()
+            ),
+        );
+        self.ssh_user_ca = Some(ca);
+        self
+    }
+
     fn workdir(&self) -> PathBuf {
         let mut p = self.tempdir.clone();
         p.push(self.source_file_path_hash.to_string());
@@ -612,6 +699,12 @@ impl TestCxConfig {
     }
 }
 
+/// Where the file mailer writes emails, relative to the workdir.
+const MAIL_DIR: &str = "mail";
+
+/// Where the configs in [`helpers`] keep the repositories, relative to the workdir.
+const REPOS_DIR: &str = "vcs/repos";
+
 pub type TestError = anyhow::Error;
 pub type TestResult<T = ()> = Result<T, TestError>;
 pub use client::Anything;
@@ -630,6 +723,9 @@ pub mod prelude {
     pub use serde_json::json;
 
     pub use crate::helpers::*;
+    pub use crate::oidc::{MockOidcUser, MOCK_OIDC_PROVIDER};
+    pub use crate::ssh_ca::{SshCertificateBuilder, SshUserCa};
+    pub use crate::totp::totp_code;
     pub use crate::{
         assert_json_eq, gql_vars, upsilon_test, Anything, Client, IdHolder, TestCx, TestCxConfig, TestResult
     };
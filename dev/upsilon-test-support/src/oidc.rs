@@ -0,0 +1,356 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A minimal OpenID Connect provider, to test logging in with one.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, format_err};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use jwt::{AlgorithmType, Header, PKeyWithDigest, SignWithKey, Token};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::TestResult;
+
+pub const MOCK_OIDC_PROVIDER: &str = "mock";
+const CLIENT_ID: &str = "upsilon";
+const CLIENT_SECRET: &str = "mock-secret";
+const REDIRECT_URI: &str = "http://localhost/oidc/callback";
+const KID: &str = "mock-key";
+
+/// Who logs in at the mock provider.
+#[derive(Clone, Debug, Default)]
+pub struct MockOidcUser {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+struct IssuedCode {
+    user: MockOidcUser,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+}
+
+struct MockOidcState {
+    issuer: String,
+    key: PKey<Private>,
+    user: MockOidcUser,
+    codes: HashMap<String, IssuedCode>,
+}
+
+pub struct MockOidcServer {
+    issuer: String,
+    state: Arc<Mutex<MockOidcState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockOidcServer {
+    /// Starts the server on a free port; must be called from within a tokio runtime.
+    pub fn start() -> TestResult<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let issuer = format!("http://127.0.0.1:{}", listener.local_addr()?.port());
+
+        let state = Arc::new(Mutex::new(MockOidcState {
+            issuer: issuer.clone(),
+            key: PKey::from_rsa(Rsa::generate(2048)?)?,
+            user: MockOidcUser::default(),
+            codes: HashMap::new(),
+        }));
+
+        let service_state = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let state = Arc::clone(&service_state);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = Arc::clone(&state);
+
+                    async move { Ok::<_, Infallible>(handle(&state, req).await) }
+                }))
+            }
+        });
+
+        let server = Server::from_tcp(listener)?.serve(make_service);
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("Mock OIDC server error: {e}");
+            }
+        });
+
+        Ok(Self {
+            issuer,
+            state,
+            task,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The part of the upsilon config that registers this server as the `mock` provider.
+    pub fn config(&self) -> String {
+        format!(
+            r#"
+oidc:
+  {MOCK_OIDC_PROVIDER}:
+    display-name: Mock
+    issuer: "{issuer}"
+    client-id: {CLIENT_ID}
+    client-secret: {CLIENT_SECRET}
+    redirect-uri: "{REDIRECT_URI}"
+"#,
+            issuer = self.issuer,
+        )
+    }
+
+    /// Sets who logs in next.
+    pub fn set_user(&self, user: MockOidcUser) {
+        self.state.lock().unwrap().user = user;
+    }
+
+    /// Logs in at the authorization URL returned by `beginOidcLogin`,
+    /// returning the `code` and `state` the provider redirects back with.
+    pub async fn authorize(&self, authorization_url: &str) -> TestResult<(String, String)> {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        let response = client.get(authorization_url).send().await?;
+
+        if response.status() != reqwest::StatusCode::FOUND {
+            bail!("Authorization failed: {}", response.status());
+        }
+
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .ok_or_else(|| format_err!("No redirect location"))?
+            .to_str()?;
+
+        let params = query_params(location);
+
+        let code = params
+            .get("code")
+            .ok_or_else(|| format_err!("No code in redirect"))?;
+        let state = params
+            .get("state")
+            .ok_or_else(|| format_err!("No state in redirect"))?;
+
+        Ok((code.clone(), state.clone()))
+    }
+}
+
+impl Drop for MockOidcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn query_params(url: &str) -> HashMap<String, String> {
+    reqwest::Url::parse(url)
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+fn b64(bytes: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn random_code() -> String {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+    b64(bytes)
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn handle(state: &Mutex<MockOidcState>, req: Request<Body>) -> Response<Body> {
+    let uri = format!("http://mock{}", req.uri());
+
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/.well-known/openid-configuration") => {
+            let issuer = state.lock().unwrap().issuer.clone();
+
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "issuer": issuer,
+                    "authorization_endpoint": format!("{issuer}/authorize"),
+                    "token_endpoint": format!("{issuer}/token"),
+                    "jwks_uri": format!("{issuer}/jwks"),
+                }),
+            )
+        }
+        (&Method::GET, "/jwks") => {
+            let state = state.lock().unwrap();
+            let rsa = state.key.rsa().unwrap();
+
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "keys": [{
+                        "kty": "RSA",
+                        "kid": KID,
+                        "use": "sig",
+                        "alg": "RS256",
+                        "n": b64(rsa.n().to_vec()),
+                        "e": b64(rsa.e().to_vec()),
+                    }]
+                }),
+            )
+        }
+        (&Method::GET, "/authorize") => {
+            let params = query_params(&uri);
+
+            if params.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+                || params.get("redirect_uri").map(String::as_str) != Some(REDIRECT_URI)
+            {
+                return json_response(StatusCode::BAD_REQUEST, json!({"error": "invalid_request"}));
+            }
+
+            let code = random_code();
+
+            let mut state = state.lock().unwrap();
+            let issued = IssuedCode {
+                user: state.user.clone(),
+                nonce: params.get("nonce").cloned(),
+                code_challenge: params.get("code_challenge").cloned(),
+            };
+            state.codes.insert(code.clone(), issued);
+
+            let location = reqwest::Url::parse_with_params(
+                REDIRECT_URI,
+                &[
+                    ("code", code.as_str()),
+                    ("state", params.get("state").map_or("", String::as_str)),
+                ],
+            )
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::FOUND)
+                .header("Location", location.as_str())
+                .body(Body::empty())
+                .unwrap()
+        }
+        (&Method::POST, "/token") => {
+            let body = hyper::body::to_bytes(req.into_body())
+                .await
+                .unwrap_or_default();
+            let params = query_params(&format!("http://mock/?{}", String::from_utf8_lossy(&body)));
+
+            match issue_id_token(state, &params) {
+                Ok(id_token) => json_response(
+                    StatusCode::OK,
+                    json!({
+                        "access_token": random_code(),
+                        "token_type": "Bearer",
+                        "id_token": id_token,
+                    }),
+                ),
+                Err(error) => json_response(StatusCode::BAD_REQUEST, json!({ "error": error })),
+            }
+        }
+        _ => json_response(StatusCode::NOT_FOUND, json!({"error": "not_found"})),
+    }
+}
+
+fn issue_id_token(
+    state: &Mutex<MockOidcState>,
+    params: &HashMap<String, String>,
+) -> Result<String, &'static str> {
+    let param = |name: &str| params.get(name).map(String::as_str);
+
+    if param("client_id") != Some(CLIENT_ID) || param("client_secret") != Some(CLIENT_SECRET) {
+        return Err("invalid_client");
+    }
+
+    let mut state = state.lock().unwrap();
+
+    let issued = param("code")
+        .and_then(|code| state.codes.remove(code))
+        .ok_or("invalid_grant")?;
+
+    let verifier_matches = match (&issued.code_challenge, param("code_verifier")) {
+        (Some(challenge), Some(verifier)) => {
+            *challenge == b64(openssl::sha::sha256(verifier.as_bytes()))
+        }
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !verifier_matches {
+        return Err("invalid_grant");
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let claims = json!({
+        "iss": state.issuer,
+        "sub": issued.user.subject,
+        "aud": CLIENT_ID,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": issued.nonce,
+        "email": issued.user.email,
+        "email_verified": issued.user.email_verified,
+        "preferred_username": issued.user.preferred_username,
+        "name": issued.user.name,
+    });
+
+    let header = Header {
+        algorithm: AlgorithmType::Rs256,
+        key_id: Some(KID.to_owned()),
+        ..Default::default()
+    };
+
+    let key = PKeyWithDigest {
+        digest: MessageDigest::sha256(),
+        key: state.key.clone(),
+    };
+
+    let token = Token::new(header, claims)
+        .sign_with_key(&key)
+        .map_err(|_| "server_error")?;
+
+    Ok(token.as_str().to_owned())
+}
@@ -0,0 +1,183 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! An SSH certificate authority, to test logging in with OpenSSH user
+//! certificates. The keys and certificates are made by `ssh-keygen`.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::bail;
+
+use crate::TestResult;
+
+pub struct SshUserCa {
+    private_key: String,
+    public_key: String,
+}
+
+impl SshUserCa {
+    /// Generates a new CA key, using `dir` as scratch space.
+    pub fn generate(dir: &Path) -> TestResult<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let key = dir.join("ca");
+        ssh_keygen(|c| {
+            c.args([
+                "-q",
+                "-t",
+                "ed25519",
+                "-N",
+                "",
+                "-C",
+                "upsilon-test-ca",
+                "-f",
+            ])
+            .arg(&key)
+        })?;
+
+        Ok(Self {
+            private_key: std::fs::read_to_string(&key)?,
+            public_key: std::fs::read_to_string(key.with_extension("pub"))?
+                .trim()
+                .to_owned(),
+        })
+    }
+
+    /// The CA key, as in the `.pub` file `ssh-keygen` writes.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Generates a key pair in `dir`, and signs a certificate for it.
+    pub fn issue<F>(&self, dir: &Path, cert: F) -> TestResult<SshCertificate>
+    where
+        F: FnOnce(SshCertificateBuilder) -> SshCertificateBuilder,
+    {
+        let builder = cert(SshCertificateBuilder::default());
+
+        let ca_key = dir.join("ca");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            // ssh-keygen refuses to use private keys others can read.
+            options.mode(0o600);
+        }
+        options
+            .open(&ca_key)?
+            .write_all(self.private_key.as_bytes())?;
+
+        let key = dir.join("id_ed25519");
+        ssh_keygen(|c| {
+            c.args(["-q", "-t", "ed25519", "-N", "", "-C", "", "-f"])
+                .arg(&key)
+        })?;
+
+        ssh_keygen(|c| {
+            c.arg("-q")
+                .arg("-s")
+                .arg(&ca_key)
+                .args(["-I", "upsilon-test"]);
+            if !builder.principals.is_empty() {
+                c.arg("-n").arg(builder.principals.join(","));
+            }
+            c.arg("-V")
+                .arg(builder.validity.as_deref().unwrap_or("-5m:+5m"));
+            for option in &builder.options {
+                c.arg("-O").arg(option);
+            }
+            c.arg(key.with_extension("pub"))
+        })?;
+
+        std::fs::remove_file(&ca_key)?;
+
+        Ok(SshCertificate {
+            certificate: dir.join("id_ed25519-cert.pub"),
+            key,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SshCertificateBuilder {
+    principals: Vec<String>,
+    validity: Option<String>,
+    options: Vec<String>,
+}
+
+impl SshCertificateBuilder {
+    pub fn principal(mut self, principal: &str) -> Self {
+        self.principals.push(principal.to_owned());
+        self
+    }
+
+    /// The validity interval, in the format `ssh-keygen -V` takes.
+    ///
+    /// Defaults to a few minutes either side of now, as certificates
+    /// valid forever are rejected by the server.
+    pub fn validity(mut self, validity: &str) -> Self {
+        self.validity = Some(validity.to_owned());
+        self
+    }
+
+    pub fn force_command(mut self, command: &str) -> Self {
+        self.options.push(format!("force-command={command}"));
+        self
+    }
+
+    pub fn source_address(mut self, addresses: &str) -> Self {
+        self.options.push(format!("source-address={addresses}"));
+        self
+    }
+}
+
+pub struct SshCertificate {
+    pub key: PathBuf,
+    pub certificate: PathBuf,
+}
+
+impl SshCertificate {
+    /// An `ssh` command that logs in with only this certificate.
+    pub fn ssh_command(&self) -> String {
+        format!(
+            "ssh -i {key} -o CertificateFile={certificate} -o IdentitiesOnly=yes \
+             -o IdentityAgent=none -o BatchMode=yes -o StrictHostKeyChecking=no \
+             -o UserKnownHostsFile=/dev/null",
+            key = self.key.display(),
+            certificate = self.certificate.display(),
+        )
+    }
+}
+
+fn ssh_keygen(args: impl FnOnce(&mut Command) -> &mut Command) -> TestResult {
+    let mut cmd = Command::new("ssh-keygen");
+    let output = args(&mut cmd).output()?;
+
+    if !output.status.success() {
+        bail!(
+            "ssh-keygen failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Computing TOTP codes, like an authenticator app would.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+fn base32_decode(s: &str) -> Vec<u8> {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut result = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in s.bytes() {
+        let value = ALPHABET
+            .iter()
+            .position(|&it| it == c.to_ascii_uppercase())
+            .expect("Invalid base32") as u32;
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+
+    result
+}
+
+/// The code for the base32-encoded `secret`, `step_offset` steps of
+/// 30 seconds away from now.
+///
+/// Every code is only accepted once, so use increasing offsets to get
+/// more codes in the same test.
+pub fn totp_code(secret: &str, step_offset: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let step = (now / 30).checked_add_signed(step_offset).unwrap();
+
+    let key = PKey::hmac(&base32_decode(secret)).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha1(), &key).unwrap();
+    let mac = signer.sign_oneshot_to_vec(&step.to_be_bytes()).unwrap();
+
+    let offset = (mac[mac.len() - 1] & 0xf) as usize;
+    let code = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+
+    format!("{:06}", code % 1_000_000)
+}
@@ -0,0 +1,211 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use upsilon_test_support::prelude::*;
+
+async fn admin_mutation(
+    cx: &TestCx,
+    query: &'static str,
+    vars: HashMap<String, serde_json::Value>,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(query, vars)
+                .await
+        })
+        .await?;
+
+    Ok(result["admin"].clone())
+}
+
+#[upsilon_test]
+async fn configured_admin_is_admin(#[cfg_setup(upsilon_admin_user)] cx: &mut TestCx) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    let result = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"query { admin { stats { users admins suspendedUsers } } }"#,
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"admin": {"stats": {"users": 2, "admins": 1, "suspendedUsers": 0}}}
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_admins_can_use_admin_api(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query::<Anything>(r#"query { admin { users { id } } }"#)
+                .await
+        })
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn suspended_users_cannot_log_in(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    let user_id = cx.user_id("test").await?;
+
+    let result = admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { suspendUser(userId: $user) { suspended } } }"#,
+        gql_vars! {"user": user_id},
+    )
+    .await?;
+    assert_json_eq!(result, {"suspendUser": {"suspended": true}});
+
+    let login = || {
+        cx.with_client(|cl| async move {
+            cl.gql_query::<Anything>(
+                r#"mutation { login(usernameOrEmail: "test", password: "password") }"#,
+            )
+            .await
+        })
+    };
+
+    assert!(login().await.is_err());
+
+    admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { unsuspendUser(userId: $user) { suspended } } }"#,
+        gql_vars! {"user": user_id},
+    )
+    .await?;
+
+    login().await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn suspended_users_tokens_stop_working(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let user_id = cx.user_id("test").await?;
+
+    admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { suspendUser(userId: $user) { suspended } } }"#,
+        gql_vars! {"user": user_id},
+    )
+    .await?;
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query::<serde_json::Value>(r#"query { viewer { id } }"#)
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"viewer": null});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn admins_cannot_suspend_themselves(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+
+    let result = admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { suspendUser(userId: $user) { suspended } } }"#,
+        gql_vars! {"user": admin_id},
+    )
+    .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn transfer_repo_then_delete_user(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let user_id = cx.user_id("test").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    // the user still owns a repo
+    let result = admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { deleteUser(userId: $user) } }"#,
+        gql_vars! {"user": user_id},
+    )
+    .await;
+    assert!(result.is_err());
+
+    admin_mutation(
+        cx,
+        r#"
+mutation($repo: RepoId!, $user: UserId!) {
+  admin { transferRepoToUser(repoId: $repo, userId: $user) { id } }
+}
+"#,
+        gql_vars! {"repo": repo_id, "user": admin_id},
+    )
+    .await?;
+
+    assert_eq!(cx.lookup("admin/repo").await?, repo_id);
+
+    let result = admin_mutation(
+        cx,
+        r#"mutation($user: UserId!) { admin { deleteUser(userId: $user) } }"#,
+        gql_vars! {"user": user_id},
+    )
+    .await?;
+    assert_json_eq!(result, {"deleteUser": true});
+
+    let result = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(r#"query { admin { users { username } } }"#)
+                .await
+        })
+        .await?;
+    assert_json_eq!(result, {"admin": {"users": [{"username": "admin"}]}});
+
+    Ok(())
+}
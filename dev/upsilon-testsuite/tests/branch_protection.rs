@@ -0,0 +1,171 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn set_rule(cx: &TestCx, repo_id: &str, rule: serde_json::Value) -> TestResult<Anything> {
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!, $rule: BranchProtectionRuleInput!) {
+  setBranchProtectionRule(repo: $repo, rule: $rule) { id }
+}
+"#,
+            gql_vars! {"repo": repo_id, "rule": rule},
+        )
+        .await
+    })
+    .await
+}
+
+async fn blocking_rules(
+    cx: &TestCx,
+    repo_id: &str,
+    user_id: &str,
+    ref_name: &str,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+query($repo: RepoId!, $user: UserId!, $ref: String!) {
+  repo(repoId: $repo) {
+    permissionExplain(user: $user, refName: $ref) {
+      blockingRules {
+        pattern
+      }
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id, "user": user_id, "ref": ref_name},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["repo"]["permissionExplain"]["blockingRules"].clone())
+}
+
+#[upsilon_test]
+async fn restrict_pushes_with_glob(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let owner_id = cx.user_id("owner").await?;
+    let other_id = cx.user_id("other").await?;
+
+    set_rule(
+        cx,
+        &repo_id,
+        json!({
+            "pattern": "release/*",
+            "blockForcePush": true,
+            "pushUsers": [owner_id],
+        }),
+    )
+    .await?;
+
+    let blocking = blocking_rules(cx, &repo_id, &other_id, "refs/heads/release/1.0").await?;
+    assert_json_eq!(blocking, [{"pattern": "release/*"}]);
+
+    let blocking = blocking_rules(cx, &repo_id, &other_id, "refs/heads/release/1.0/x").await?;
+    assert_json_eq!(blocking, []);
+
+    let blocking = blocking_rules(cx, &repo_id, &other_id, "refs/heads/trunk").await?;
+    assert_json_eq!(blocking, []);
+
+    let blocking = blocking_rules(cx, &repo_id, &owner_id, "refs/heads/release/1.0").await?;
+    assert_json_eq!(blocking, []);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn replace_and_remove_rules(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    set_rule(cx, &repo_id, json!({"pattern": "trunk"})).await?;
+    set_rule(
+        cx,
+        &repo_id,
+        json!({"pattern": "trunk", "blockDeletion": true}),
+    )
+    .await?;
+
+    let repo_id_clone = repo_id.clone();
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+query($repo: RepoId!) {
+  repo(repoId: $repo) {
+    branchProtectionRules {
+      pattern
+      blockDeletion
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id_clone},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"repo": {"branchProtectionRules": [{"pattern": "trunk", "blockDeletion": true}]}}
+    );
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!) {
+  removeBranchProtectionRule(repo: $repo, pattern: "trunk") {
+    branchProtectionRules {
+      pattern
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"removeBranchProtectionRule": {"branchProtectionRules": []}}
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn invalid_pattern(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let result = set_rule(cx, &repo_id, json!({"pattern": "release/[*"})).await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn add_deploy_key(
+    cx: &TestCx,
+    user: &str,
+    repo_id: &str,
+    key: &str,
+    write: bool,
+) -> TestResult<serde_json::Value> {
+    cx.with_client_as_user(user, |cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"
+mutation($repo: RepoId!, $key: String!, $write: Boolean!) {
+  addDeployKey(repo: $repo, title: "ci", key: $key, write: $write) {
+    id
+    title
+    write
+  }
+}
+"#,
+            gql_vars! {"repo": repo_id, "key": key, "write": write},
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn deploy_key_can_only_clone_its_repo(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    cx.create_repo("owner", "other").await?;
+
+    let kp = create_ssh_key()?;
+    let key = TestCx::encode_ssh_key(kp.public_key())?;
+    add_deploy_key(cx, "owner", &repo_id, &key, false).await?;
+
+    cx.clone(
+        "repo-clone",
+        |rb| rb.protocol(GitAccessProtocol::Ssh).path("owner/repo"),
+        Credentials::SshKey(kp.clone()),
+    )
+    .await?;
+
+    let result = cx
+        .clone(
+            "other-clone",
+            |rb| rb.protocol(GitAccessProtocol::Ssh).path("owner/other"),
+            Credentials::SshKey(kp),
+        )
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn user_keys_and_deploy_keys_do_not_overlap(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let user_kp = create_ssh_key()?;
+    let user_key = user_kp.public_key();
+    cx.add_ssh_key_to_user(user_key, "owner").await?;
+
+    let user_key = TestCx::encode_ssh_key(user_key)?;
+    let result = add_deploy_key(cx, "owner", &repo_id, &user_key, false).await;
+    assert!(result.is_err());
+
+    let deploy_kp = create_ssh_key()?;
+    let deploy_key = TestCx::encode_ssh_key(deploy_kp.public_key())?;
+    add_deploy_key(cx, "owner", &repo_id, &deploy_key, true).await?;
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($key: String!) {
+  addUserSshKey(key: $key)
+}
+"#,
+                gql_vars! {"key": deploy_key},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"addUserSshKey": false});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_repo_admins_manage_deploy_keys(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let key = TestCx::encode_ssh_key(create_ssh_key()?.public_key())?;
+
+    let result = add_deploy_key(cx, "other", &repo_id, &key, false).await;
+    assert!(result.is_err());
+
+    let added = add_deploy_key(cx, "owner", &repo_id, &key, true).await?;
+    let deploy_key_id = added["addDeployKey"]["id"].clone();
+    assert_json_eq!(added, {"addDeployKey": {"id": deploy_key_id, "title": "ci", "write": true}});
+
+    let repo_id_clone = repo_id.clone();
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query_with_variables::<Anything>(
+                r#"
+query($repo: RepoId!) {
+  repo(repoId: $repo) {
+    deployKeys { id }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id_clone},
+            )
+            .await
+        })
+        .await;
+    assert!(result.is_err());
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!, $deployKey: DeployKeyId!) {
+  removeDeployKey(repo: $repo, deployKey: $deployKey)
+}
+"#,
+                gql_vars! {"repo": repo_id, "deployKey": deploy_key_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"removeDeployKey": true});
+
+    Ok(())
+}
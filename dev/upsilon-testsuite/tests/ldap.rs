@@ -0,0 +1,207 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn login(cx: &TestCx, username: &str, password: &str) -> TestResult<String> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($username: String!, $password: PlainPassword!) {
+  login(usernameOrEmail: $username, password: $password)
+}
+"#,
+                gql_vars! {"username": username, "password": password},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["login"]
+        .as_str()
+        .expect("token is a string")
+        .to_owned())
+}
+
+async fn viewer(cx: &TestCx, token: String) -> TestResult<serde_json::Value> {
+    cx.with_client(|cl| async move {
+        cl.with_token(token)
+            .gql_query::<serde_json::Value>(
+                r#"
+query {
+  viewer {
+    username
+    displayName
+  }
+}
+"#,
+            )
+            .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn directory_users_get_an_account_on_first_login(
+    #[cfg_setup(upsilon_static_ldap)] cx: &mut TestCx,
+) -> TestResult {
+    let token = login(cx, "alice", "alice-password").await?;
+    let result = viewer(cx, token).await?;
+    assert_json_eq!(result, {"viewer": {"username": "alice", "displayName": "Alice Example"}});
+
+    // the second login finds the same account
+    let token = login(cx, "alice", "alice-password").await?;
+    let result = viewer(cx, token).await?;
+    assert_json_eq!(result, {"viewer": {"username": "alice", "displayName": "Alice Example"}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn wrong_directory_password_is_rejected(
+    #[cfg_setup(upsilon_static_ldap)] cx: &mut TestCx,
+) -> TestResult {
+    assert!(login(cx, "alice", "bob-password").await.is_err());
+    assert!(login(cx, "alice", "").await.is_err());
+    assert!(login(cx, "carol", "carol-password").await.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn local_accounts_skip_the_directory(
+    #[cfg_setup(upsilon_static_ldap)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+    cx.create_user("local", "password", "local@example.org")
+        .await?;
+
+    let token = login(cx, "admin", "password").await?;
+    let result = viewer(cx, token).await?;
+    assert_json_eq!(result, {"viewer": {"username": "admin", "displayName": null}});
+
+    // everyone else has to be in the directory
+    assert!(login(cx, "local", "password").await.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn directory_users_wait_for_approval(
+    #[cfg_setup(upsilon_static_ldap)]
+    #[cfg_setup(upsilon_admin_user)]
+    #[cfg_setup(upsilon_require_approval)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+
+    assert!(login(cx, "alice", "alice-password").await.is_err());
+
+    let pending = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(r#"query { admin { pendingUsers { id username } } }"#)
+                .await
+        })
+        .await?;
+
+    let user_id = pending["admin"]["pendingUsers"][0]["id"].clone();
+    assert_json_eq!(
+        pending,
+        {"admin": {"pendingUsers": [{"id": user_id, "username": "alice"}]}}
+    );
+
+    cx.with_client_as_user("admin", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"mutation($user: UserId!) { admin { approveUser(userId: $user) { id } } }"#,
+            gql_vars! {"user": user_id},
+        )
+        .await
+    })
+    .await?;
+
+    login(cx, "alice", "alice-password").await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn local_users_are_not_taken_over_by_the_directory(
+    #[cfg_setup(upsilon_static_ldap)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("alice", "password", "alice@example.org")
+        .await?;
+
+    assert!(login(cx, "alice", "alice-password").await.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn groups_are_synced_to_organizations(
+    #[cfg_setup(upsilon_static_ldap)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+
+    cx.with_client_as_user("admin", |cl| async move {
+        cl.gql_query::<Anything>(
+            r#"
+mutation {
+  createOrganization(name: "example") { id }
+}
+"#,
+        )
+        .await
+    })
+    .await?;
+
+    login(cx, "alice", "alice-password").await?;
+    login(cx, "bob", "bob-password").await?;
+
+    let result = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+query {
+  organizationByName(name: "example") {
+    members {
+      user {
+        username
+      }
+      role
+    }
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    // the creator of the organization is its owner
+    assert_json_eq!(
+        result,
+        {"organizationByName": {"members": [
+            {"user": {"username": "admin"}, "role": "OWNER"},
+            {"user": {"username": "alice"}, "role": "MEMBER"},
+        ]}}
+    );
+
+    Ok(())
+}
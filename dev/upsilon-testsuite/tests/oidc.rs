@@ -0,0 +1,228 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn oidc_login(cx: &TestCx) -> TestResult<String> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+mutation {
+  beginOidcLogin(provider: "mock")
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    let authorization_url = result["beginOidcLogin"]
+        .as_str()
+        .expect("authorization URL is a string");
+
+    let (code, state) = cx.mock_oidc().authorize(authorization_url).await?;
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($code: String!, $state: String!) {
+  completeOidcLogin(provider: "mock", code: $code, state: $state) {
+    accessToken
+  }
+}
+"#,
+                gql_vars! {"code": code, "state": state},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["completeOidcLogin"]["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned())
+}
+
+async fn viewer(cx: &TestCx, access_token: String) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    id
+    username
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    Ok(result["viewer"].clone())
+}
+
+#[upsilon_test]
+async fn providers_are_listed(#[cfg_setup(upsilon_mock_oidc)] cx: &mut TestCx) -> TestResult {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+query {
+  oidcProviders {
+    name
+    displayName
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"oidcProviders": [{"name": MOCK_OIDC_PROVIDER, "displayName": "Mock"}]}
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn new_users_are_provisioned(#[cfg_setup(upsilon_mock_oidc)] cx: &mut TestCx) -> TestResult {
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "alice-sub".to_owned(),
+        email: Some("alice@example.org".to_owned()),
+        email_verified: true,
+        preferred_username: Some("alice".to_owned()),
+        name: None,
+    });
+
+    let access_token = oidc_login(cx).await?;
+    let first = viewer(cx, access_token).await?;
+
+    assert_eq!(first["username"], "alice");
+
+    // logging in again finds the same account
+    let access_token = oidc_login(cx).await?;
+    let second = viewer(cx, access_token).await?;
+
+    assert_eq!(first["id"], second["id"]);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn new_users_follow_the_email_domain_allowlist(
+    #[cfg_setup(upsilon_mock_oidc)]
+    #[cfg_setup(upsilon_allowed_email_domains)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "alice-sub".to_owned(),
+        email: Some("alice@example.com".to_owned()),
+        email_verified: true,
+        preferred_username: Some("alice".to_owned()),
+        name: None,
+    });
+
+    assert!(oidc_login(cx).await.is_err());
+
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "bob-sub".to_owned(),
+        email: Some("bob@example.org".to_owned()),
+        email_verified: true,
+        preferred_username: Some("bob".to_owned()),
+        name: None,
+    });
+
+    oidc_login(cx).await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn verified_email_links_existing_account(
+    #[cfg_setup(upsilon_mock_oidc)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+    let user_id = cx.user_id("test").await?;
+
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "test-sub".to_owned(),
+        email: Some("test@example.org".to_owned()),
+        email_verified: true,
+        preferred_username: Some("someone-else".to_owned()),
+        name: None,
+    });
+
+    let access_token = oidc_login(cx).await?;
+    let result = viewer(cx, access_token).await?;
+
+    assert_json_eq!(result, {"id": user_id, "username": "test"});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn username_is_not_linked_as_email(
+    #[cfg_setup(upsilon_mock_oidc)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test@example.org", "test", "other@example.org")
+        .await?;
+    let user_id = cx.user_id("test@example.org").await?;
+
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "test-sub".to_owned(),
+        email: Some("test@example.org".to_owned()),
+        email_verified: true,
+        preferred_username: Some("test".to_owned()),
+        name: None,
+    });
+
+    let access_token = oidc_login(cx).await?;
+    let result = viewer(cx, access_token).await?;
+
+    assert_eq!(result["username"], "test");
+    assert_ne!(result["id"], user_id);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn unverified_email_is_not_linked(
+    #[cfg_setup(upsilon_mock_oidc)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    cx.mock_oidc().set_user(MockOidcUser {
+        subject: "test-sub".to_owned(),
+        email: Some("test@example.org".to_owned()),
+        email_verified: false,
+        preferred_username: Some("someone-else".to_owned()),
+        name: None,
+    });
+
+    let result = oidc_login(cx).await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
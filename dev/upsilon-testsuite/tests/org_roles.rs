@@ -0,0 +1,171 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn create_org(cx: &TestCx, user: &str) -> TestResult<String> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"mutation { createOrganization(name: "org") { id } }"#,
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createOrganization"]["id"]
+        .as_str()
+        .expect("id is a string")
+        .to_owned())
+}
+
+async fn set_member_role(
+    cx: &TestCx,
+    as_user: &str,
+    org_id: &str,
+    user_id: &str,
+    role: &'static str,
+) -> TestResult<Anything> {
+    let query = format!(
+        r#"
+mutation($org: OrganizationId!, $user: UserId!) {{
+  setOrganizationMemberRole(organization: $org, user: $user, role: {role}) {{ role }}
+}}
+"#
+    );
+
+    cx.with_client_as_user(as_user, |cl| async move {
+        cl.gql_query_with_variables::<Anything>(&query, gql_vars! {"org": org_id, "user": user_id})
+            .await
+    })
+    .await
+}
+
+async fn org_roles(cx: &TestCx, as_user: &str, org_id: &str) -> TestResult<serde_json::Value> {
+    cx.with_client_as_user(as_user, |cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"
+query($org: OrganizationId!) {
+  organization(orgId: $org) {
+    owners { username }
+    viewerRole
+  }
+}
+"#,
+            gql_vars! {"org": org_id},
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn creator_is_owner(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+
+    let result = org_roles(cx, "owner", &org_id).await?;
+    assert_json_eq!(
+        result,
+        {"organization": {"owners": [{"username": "owner"}], "viewerRole": "OWNER"}}
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn admins_can_change_settings(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("member", "test", "member@example.org")
+        .await?;
+    let admin_id = cx.user_id("admin").await?;
+    let member_id = cx.user_id("member").await?;
+    let org_id = create_org(cx, "owner").await?;
+
+    set_member_role(cx, "owner", &org_id, &admin_id, "ADMIN").await?;
+    set_member_role(cx, "owner", &org_id, &member_id, "MEMBER").await?;
+
+    let set_display_name = |user: &'static str| {
+        let org_id = org_id.clone();
+        cx.with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($org: OrganizationId!) {
+  setOrganizationDisplayName(organization: $org, displayName: "Org") { displayName }
+}
+"#,
+                gql_vars! {"org": org_id},
+            )
+            .await
+        })
+    };
+
+    assert!(set_display_name("member").await.is_err());
+
+    let result = set_display_name("admin").await?;
+    assert_json_eq!(result, {"setOrganizationDisplayName": {"displayName": "Org"}});
+
+    // only owners can make someone an owner
+    assert!(set_member_role(cx, "admin", &org_id, &member_id, "OWNER")
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn organizations_keep_an_owner(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let owner_id = cx.user_id("owner").await?;
+    let org_id = create_org(cx, "owner").await?;
+
+    assert!(set_member_role(cx, "owner", &org_id, &owner_id, "ADMIN")
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn transfer_ownership(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let other_id = cx.user_id("other").await?;
+    let org_id = create_org(cx, "owner").await?;
+
+    let org_id_clone = org_id.clone();
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($org: OrganizationId!, $user: UserId!) {
+  transferOrganizationOwnership(organization: $org, user: $user) { id }
+}
+"#,
+            gql_vars! {"org": org_id_clone, "user": other_id},
+        )
+        .await
+    })
+    .await?;
+
+    let result = org_roles(cx, "owner", &org_id).await?;
+    assert_json_eq!(
+        result,
+        {"organization": {"owners": [{"username": "other"}], "viewerRole": "ADMIN"}}
+    );
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+// argon2id of "password", with one pass over 1 MiB; the instance uses
+// the default parameters
+const OLD_ARGON2_HASH: &str =
+    "$argon2id$v=19$m=1024,t=1,p=1$dXBzaWxvbi10ZXN0c2FsdA$PQxLp9+9Opzj06xmmDI6OBL6MW/6e+75m457P5gdH1U";
+
+async fn password_hash_report(cx: &TestCx) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+query {
+  admin {
+    passwordHashReport {
+      users
+      outdated
+    }
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["admin"]["passwordHashReport"].clone())
+}
+
+async fn start_session(cx: &TestCx) -> TestResult {
+    cx.with_client(|cl| async move {
+        cl.gql_query::<serde_json::Value>(
+            r#"
+mutation {
+  startSession(usernameOrEmail: "test", password: "password") {
+    accessToken
+  }
+}
+"#,
+        )
+        .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn outdated_hashes_are_reported(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    // has a placeholder hash, which isn't from any algorithm
+    cx.create_user("other", "test", "other@example.org").await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 3, "outdated": 1});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn bcrypt_hashes_are_verified(
+    #[cfg_setup(upsilon_admin_user)]
+    #[cfg_setup(upsilon_bcrypt_passwords)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    start_session(cx).await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 2, "outdated": 0});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn outdated_hashes_are_upgraded_on_login(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+    // bcrypt, cost 4, of "password"; the instance is set up for argon2
+    cx.create_user_with_password_hash(
+        "test",
+        "$2b$04$bV/xYUvtZgzyXVLya0Dqb.oHTuQpoZMPP3y/EhDzOohe7ox1aUGv2",
+        "test@example.org",
+    )
+    .await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 2, "outdated": 1});
+
+    start_session(cx).await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 2, "outdated": 0});
+
+    // the new hash still checks out
+    start_session(cx).await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn hashes_with_old_argon2_parameters_are_upgraded_on_login(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "password", "admin@example.org")
+        .await?;
+    cx.create_user_with_password_hash("test", OLD_ARGON2_HASH, "test@example.org")
+        .await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 2, "outdated": 1});
+
+    start_session(cx).await?;
+
+    let result = password_hash_report(cx).await?;
+    assert_json_eq!(result, {"users": 2, "outdated": 0});
+
+    Ok(())
+}
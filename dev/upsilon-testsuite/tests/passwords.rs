@@ -0,0 +1,217 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn start_session(cx: &TestCx, password: &str) -> TestResult<String> {
+    let password = password.to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($password: PlainPassword!) {
+  startSession(usernameOrEmail: "test", password: $password) {
+    accessToken
+  }
+}
+"#,
+                gql_vars! {"password": password},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["startSession"]["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned())
+}
+
+async fn viewer(cx: &TestCx, access_token: String) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    username
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    Ok(result)
+}
+
+async fn change_password(
+    cx: &TestCx,
+    access_token: String,
+    current_password: &str,
+) -> TestResult<String> {
+    let current_password = current_password.to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query_with_variables::<serde_json::Value>(
+                    r#"
+mutation($currentPassword: PlainPassword!) {
+  changePassword(currentPassword: $currentPassword, newPassword: "new-password") {
+    accessToken
+  }
+}
+"#,
+                    gql_vars! {"currentPassword": current_password},
+                )
+                .await
+        })
+        .await?;
+
+    Ok(result["changePassword"]["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned())
+}
+
+async fn request_password_reset(cx: &TestCx, email: &str) -> TestResult<serde_json::Value> {
+    let email = email.to_owned();
+
+    cx.with_client(|cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"
+mutation($email: Email!) {
+  requestPasswordReset(email: $email)
+}
+"#,
+            gql_vars! {"email": email},
+        )
+        .await
+    })
+    .await
+}
+
+async fn reset_password(cx: &TestCx, token: String) -> TestResult<serde_json::Value> {
+    cx.with_client(|cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"
+mutation($token: String!) {
+  resetPassword(token: $token, newPassword: "new-password")
+}
+"#,
+            gql_vars! {"token": token},
+        )
+        .await
+    })
+    .await
+}
+
+fn reset_token(mail: &str) -> String {
+    mail.split_whitespace()
+        .find(|word| word.starts_with("upprt_"))
+        .expect("no reset token in mail")
+        .to_owned()
+}
+
+#[upsilon_test]
+async fn change_password_ends_sessions(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let other_session = start_session(cx, "password").await?;
+    let this_session = start_session(cx, "password").await?;
+
+    let new_session = change_password(cx, this_session.clone(), "password").await?;
+
+    let result = viewer(cx, other_session).await?;
+    assert_json_eq!(result, {"viewer": null});
+
+    let result = viewer(cx, this_session).await?;
+    assert_json_eq!(result, {"viewer": null});
+
+    let result = viewer(cx, new_session).await?;
+    assert_json_eq!(result, {"viewer": {"username": "test"}});
+
+    assert!(start_session(cx, "password").await.is_err());
+    start_session(cx, "new-password").await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn change_password_checks_current_password(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let access_token = start_session(cx, "password").await?;
+
+    let result = change_password(cx, access_token, "wrong").await;
+    assert!(result.is_err());
+
+    start_session(cx, "password").await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn password_reset_by_email(#[cfg_setup(upsilon_file_mailer)] cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let old_session = start_session(cx, "password").await?;
+
+    let result = request_password_reset(cx, "test@example.org").await?;
+    assert_json_eq!(result, {"requestPasswordReset": true});
+
+    let mails = cx.sent_mails().await?;
+    assert_eq!(mails.len(), 1);
+    assert!(mails[0].starts_with("To: test@example.org\n"));
+
+    let token = reset_token(&mails[0]);
+
+    let result = reset_password(cx, token.clone()).await?;
+    assert_json_eq!(result, {"resetPassword": true});
+
+    let result = viewer(cx, old_session).await?;
+    assert_json_eq!(result, {"viewer": null});
+    start_session(cx, "new-password").await?;
+
+    // tokens only work once
+    let result = reset_password(cx, token).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn password_reset_for_unknown_email(
+    #[cfg_setup(upsilon_file_mailer)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    // doesn't tell that there is no such account
+    let result = request_password_reset(cx, "nobody@example.org").await?;
+    assert_json_eq!(result, {"requestPasswordReset": true});
+
+    assert!(cx.sent_mails().await?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,155 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn create_token(cx: &TestCx, user: &str, scopes: serde_json::Value) -> TestResult<String> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($scopes: [TokenScope!]!) {
+  createPersonalAccessToken(name: "test", scopes: $scopes) {
+    secret
+  }
+}
+"#,
+                gql_vars! {"scopes": scopes},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createPersonalAccessToken"]["secret"]
+        .as_str()
+        .expect("secret is a string")
+        .to_owned())
+}
+
+#[upsilon_test]
+async fn token_authenticates_as_user(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+    let user_id = cx.user_id("test").await?;
+
+    let secret = create_token(cx, "test", json!(["REPO_READ"])).await?;
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(secret)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    id
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"viewer": {"id": user_id}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn token_scopes_are_enforced(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    let secret = create_token(cx, "test", json!(["REPO_READ"])).await?;
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(secret)
+                .gql_query::<Anything>(
+                    r#"
+mutation {
+  createOrganization(name: "org") { id }
+}
+"#,
+                )
+                .await
+        })
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn revoked_tokens_are_rejected(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    let secret = create_token(cx, "test", json!(["USER_KEYS"])).await?;
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+query {
+  viewer {
+    personalAccessTokens {
+      id
+    }
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    let token_id = result["viewer"]["personalAccessTokens"][0]["id"].clone();
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($token: PersonalAccessTokenId!) {
+  revokePersonalAccessToken(token: $token)
+}
+"#,
+                gql_vars! {"token": token_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"revokePersonalAccessToken": true});
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(secret)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    id
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"viewer": null});
+
+    Ok(())
+}
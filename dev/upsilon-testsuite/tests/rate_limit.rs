@@ -0,0 +1,123 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn start_session(cx: &TestCx, username: &str, password: &str) -> TestResult {
+    let username = username.to_owned();
+    let password = password.to_owned();
+
+    cx.with_client(|cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"
+mutation($username: String!, $password: PlainPassword!) {
+  startSession(usernameOrEmail: $username, password: $password) {
+    accessToken
+  }
+}
+"#,
+            gql_vars! {"username": username, "password": password},
+        )
+        .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn account_is_locked_out_after_failed_logins(
+    #[cfg_setup(upsilon_strict_rate_limit)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    cx.create_user("other", "password", "other@example.org")
+        .await?;
+
+    assert!(start_session(cx, "test", "wrong").await.is_err());
+    assert!(start_session(cx, "test", "wrong").await.is_err());
+
+    // the right password doesn't help while locked out
+    let result = start_session(cx, "test", "password").await;
+    assert!(result.is_err());
+
+    // other accounts can still log in
+    start_session(cx, "other", "password").await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn successful_login_resets_failures(
+    #[cfg_setup(upsilon_strict_rate_limit)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    assert!(start_session(cx, "test", "wrong").await.is_err());
+    start_session(cx, "test", "password").await?;
+
+    assert!(start_session(cx, "test", "wrong").await.is_err());
+    start_session(cx, "test", "password").await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn git_auth_failures_do_not_lock_out_the_named_user(
+    #[cfg_setup(upsilon_strict_rate_limit)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    cx.with_client_as_user("test", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { setGlobalPermissions(perms: 0) { id } }
+}
+"#,
+            gql_vars! {"repo": repo_id},
+        )
+        .await
+    })
+    .await?;
+
+    const PATH: &str = "/test/repo/info/refs?service=git-upload-pack";
+
+    for _ in 0..3 {
+        let status = cx
+            .with_client(
+                |cl| async move { cl.with_token("wrong").git_get_status(PATH, "test").await },
+            )
+            .await?;
+        assert_ne!(status, 200);
+    }
+
+    // the failures were counted against the token, not against "test"
+    start_session(cx, "test", "password").await?;
+
+    let status = cx
+        .with_client_as_user(
+            "test",
+            |cl| async move { cl.git_get_status(PATH, "test").await },
+        )
+        .await?;
+    assert_eq!(status, 200);
+
+    Ok(())
+}
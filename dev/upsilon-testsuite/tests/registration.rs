@@ -0,0 +1,230 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn register(
+    cx: &TestCx,
+    username: &str,
+    email: &str,
+    invite_code: Option<&str>,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($username: Username!, $email: Email!, $inviteCode: String) {
+  createUser(username: $username, email: $email, password: "password", inviteCode: $inviteCode)
+}
+"#,
+                gql_vars! {"username": username, "email": email, "inviteCode": invite_code},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createUser"].clone())
+}
+
+async fn create_invite(
+    cx: &TestCx,
+    user: &str,
+    organization_id: Option<String>,
+) -> TestResult<String> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($org: OrganizationId) {
+  createInvite(organizationId: $org) { code }
+}
+"#,
+                gql_vars! {"org": organization_id},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createInvite"]["code"]
+        .as_str()
+        .expect("code is a string")
+        .to_owned())
+}
+
+#[upsilon_test]
+async fn invite_only_requires_an_invite(
+    #[cfg_setup(upsilon_admin_user)]
+    #[cfg_setup(upsilon_invite_only)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+
+    let result = register(cx, "test", "test@example.org", None).await;
+    assert!(result.is_err());
+
+    let code = create_invite(cx, "admin", None).await?;
+
+    let token = register(cx, "test", "test@example.org", Some(&code)).await?;
+    assert!(token.is_string());
+
+    // invites can only be used once
+    let result = register(cx, "test2", "test2@example.org", Some(&code)).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_admins_can_create_instance_invites(
+    #[cfg_setup(upsilon_invite_only)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+
+    let result = create_invite(cx, "test", None).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn email_domain_allowlist(
+    #[cfg_setup(upsilon_admin_user)]
+    #[cfg_setup(upsilon_allowed_email_domains)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+
+    let result = register(cx, "test", "test@example.com", None).await;
+    assert!(result.is_err());
+
+    register(cx, "test", "test@EXAMPLE.org", None).await?;
+
+    // invites bypass the allowlist
+    let code = create_invite(cx, "admin", None).await?;
+    register(cx, "test2", "test2@example.com", Some(&code)).await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn registrations_wait_for_approval(
+    #[cfg_setup(upsilon_admin_user)]
+    #[cfg_setup(upsilon_require_approval)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+
+    let token = register(cx, "test", "test@example.org", None).await?;
+    assert!(token.is_null());
+
+    let login = || {
+        cx.with_client(|cl| async move {
+            cl.gql_query::<Anything>(
+                r#"mutation { login(usernameOrEmail: "test", password: "password") }"#,
+            )
+            .await
+        })
+    };
+
+    assert!(login().await.is_err());
+
+    let pending = cx
+        .with_client_as_user("admin", |cl| async move {
+            cl.gql_query::<serde_json::Value>(r#"query { admin { pendingUsers { id username } } }"#)
+                .await
+        })
+        .await?;
+
+    let user_id = pending["admin"]["pendingUsers"][0]["id"].clone();
+    assert_json_eq!(
+        pending,
+        {"admin": {"pendingUsers": [{"id": user_id, "username": "test"}]}}
+    );
+
+    cx.with_client_as_user("admin", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"mutation($user: UserId!) { admin { approveUser(userId: $user) { id } } }"#,
+            gql_vars! {"user": user_id},
+        )
+        .await
+    })
+    .await?;
+
+    login().await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn registering_a_configured_admin_name_does_not_grant_admin(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    let token = register(cx, "admin", "admin@example.org", None).await?;
+    let token = token.as_str().expect("token is a string").to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(token)
+                .gql_query::<Anything>(r#"query { admin { stats { admins } } }"#)
+                .await
+        })
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn organization_invites_add_membership(
+    #[cfg_setup(upsilon_invite_only)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+
+    let org = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"mutation { createOrganization(name: "org") { id } }"#,
+            )
+            .await
+        })
+        .await?;
+    let org_id = org["createOrganization"]["id"]
+        .as_str()
+        .expect("id is a string")
+        .to_owned();
+
+    let code = create_invite(cx, "owner", Some(org_id)).await?;
+
+    let token = register(cx, "test", "test@example.org", Some(&code)).await?;
+    let token = token.as_str().expect("token is a string").to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(token)
+                .gql_query::<serde_json::Value>(
+                    r#"query { viewer { organizations { organization { name } } } }"#,
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"viewer": {"organizations": [{"organization": {"name": "org"}}]}}
+    );
+
+    Ok(())
+}
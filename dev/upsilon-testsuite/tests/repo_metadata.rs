@@ -0,0 +1,195 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+#[upsilon_test]
+async fn set_metadata(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+    let repo_id = cx.create_repo("test", "meta").await?;
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!) {
+  setRepoDescription(repo: $repo, description: "A repository") { id }
+  setRepoWebsite(repo: $repo, website: "https://example.org") { id }
+  setRepoTopics(repo: $repo, topics: ["Rust", "git", "rust"]) { id }
+  setRepoDefaultBranch(repo: $repo, branch: "main") {
+    description
+    website
+    topics
+    defaultBranch
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await?;
+
+    let repo = &result["setRepoDefaultBranch"];
+
+    assert_json_eq!(
+        repo,
+        {
+            "description": "A repository",
+            "website": "https://example.org",
+            "topics": ["git", "rust"],
+            "defaultBranch": "main",
+        }
+    );
+
+    let head = tokio::fs::read_to_string(cx.repo_dir("test/meta").join("HEAD")).await?;
+    assert_eq!(head.trim(), "ref: refs/heads/main");
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn website_has_to_be_http(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+    let repo_id = cx.create_repo("test", "meta").await?;
+
+    for website in [
+        "javascript:alert(1)",
+        "ftp://example.org",
+        "example.org",
+        "https://",
+    ] {
+        let repo_id = repo_id.clone();
+        let result = cx
+            .with_client_as_user("test", |cl| async move {
+                cl.gql_query_with_variables::<serde_json::Value>(
+                    r#"
+mutation($repo: RepoId!, $website: RepoWebsite!) {
+  setRepoWebsite(repo: $repo, website: $website) { id }
+}
+"#,
+                    gql_vars! {"repo": repo_id, "website": website},
+                )
+                .await
+            })
+            .await;
+
+        assert!(result.is_err(), "{website} was accepted");
+    }
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn search_by_topic(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "test", "test@example.org").await?;
+    let tagged = cx.create_repo("test", "tagged").await?;
+    cx.create_repo("test", "untagged").await?;
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!) {
+  setRepoTopics(repo: $repo, topics: ["search-me"]) { id }
+}
+"#,
+                gql_vars! {"repo": tagged},
+            )
+            .await?;
+
+            cl.gql_query::<serde_json::Value>(
+                r#"query { reposWithTopic(topic: "Search-Me") { name } }"#,
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"reposWithTopic": [{"name": "tagged"}]});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn search_by_topic_hides_private_repos(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let public = cx.create_repo("owner", "public").await?;
+    let private = cx.create_repo("owner", "private").await?;
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($public: RepoId!, $private: RepoId!) {
+  public: setRepoTopics(repo: $public, topics: ["search-me"]) { id }
+  private: setRepoTopics(repo: $private, topics: ["search-me"]) { id }
+  updateRepoSettings(repo: $private) { setGlobalPermissions(perms: 0) { id } }
+}
+"#,
+            gql_vars! {"public": public, "private": private},
+        )
+        .await
+    })
+    .await?;
+
+    const QUERY: &str = r#"query { reposWithTopic(topic: "search-me") { name } }"#;
+
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query::<serde_json::Value>(QUERY).await
+        })
+        .await?;
+    assert_json_eq!(result, {"reposWithTopic": [{"name": "public"}]});
+
+    let result = cx
+        .with_client(|cl| async move { cl.gql_query::<serde_json::Value>(QUERY).await })
+        .await?;
+    assert_json_eq!(result, {"reposWithTopic": [{"name": "public"}]});
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query::<serde_json::Value>(QUERY).await
+        })
+        .await?;
+    assert_eq!(result["reposWithTopic"].as_array().map(Vec::len), Some(2));
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_admins_can_set_metadata(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "meta").await?;
+
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!) {
+  setRepoDescription(repo: $repo, description: "hijacked") { id }
+}
+"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::time::Duration;
+
+use upsilon_test_support::prelude::*;
+
+async fn transfer_repo_to_user(cx: &TestCx, repo_id: &str, user_id: &str) -> TestResult {
+    cx.with_client_as_user("admin", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!, $user: UserId!) {
+  admin { transferRepoToUser(repoId: $repo, userId: $user) { id } }
+}
+"#,
+            gql_vars! {"repo": repo_id, "user": user_id},
+        )
+        .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn lookup_follows_transferred_repo(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    transfer_repo_to_user(cx, &repo_id, &admin_id).await?;
+
+    assert_eq!(cx.lookup("admin/repo").await?, repo_id);
+    assert_eq!(cx.lookup("test/repo").await?, repo_id);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn new_repo_takes_precedence_over_redirect(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    transfer_repo_to_user(cx, &repo_id, &admin_id).await?;
+
+    let new_repo_id = cx.create_repo("test", "repo").await?;
+
+    assert_eq!(cx.lookup("test/repo").await?, new_repo_id);
+    assert_eq!(cx.lookup("admin/repo").await?, repo_id);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn transfer_to_a_taken_name_keeps_the_repo(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let admin_repo_id = cx.create_repo("admin", "repo").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    assert!(transfer_repo_to_user(cx, &repo_id, &admin_id)
+        .await
+        .is_err());
+
+    assert_eq!(cx.lookup("test/repo").await?, repo_id);
+    assert_eq!(cx.lookup("admin/repo").await?, admin_repo_id);
+
+    cx.clone_git_binary(
+        "repo-clone",
+        |rb| rb.protocol(GitAccessProtocol::Http).path("test/repo"),
+        Duration::from_secs(30),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn http_clone_is_redirected(#[cfg_setup(upsilon_admin_user)] cx: &mut TestCx) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    transfer_repo_to_user(cx, &repo_id, &admin_id).await?;
+
+    cx.clone_git_binary(
+        "repo-clone",
+        |rb| rb.protocol(GitAccessProtocol::Http).path("test/repo"),
+        Duration::from_secs(30),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn http_redirect_needs_read_permission(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    transfer_repo_to_user(cx, &repo_id, &admin_id).await?;
+
+    cx.with_client_as_user("admin", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { setGlobalPermissions(perms: 0) { id } }
+}
+"#,
+            gql_vars! {"repo": repo_id},
+        )
+        .await
+    })
+    .await?;
+
+    let moved = cx
+        .with_client(|cl| async move {
+            cl.get_status("/test/repo/info/refs?service=git-upload-pack")
+                .await
+        })
+        .await?;
+    let missing = cx
+        .with_client(|cl| async move {
+            cl.get_status("/test/missing/info/refs?service=git-upload-pack")
+                .await
+        })
+        .await?;
+
+    // to anyone who cannot read it, the repo was never there
+    assert_eq!(moved, missing);
+    assert_eq!(moved, 404);
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn ssh_clone_uses_the_current_path(
+    #[cfg_setup(upsilon_admin_user)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("admin", "test", "admin@example.org").await?;
+    cx.create_user("test", "test", "test@example.org").await?;
+    let admin_id = cx.user_id("admin").await?;
+    let repo_id = cx.create_repo("test", "repo").await?;
+
+    transfer_repo_to_user(cx, &repo_id, &admin_id).await?;
+
+    let kp = create_ssh_key()?;
+    cx.add_ssh_key_to_user(kp.public_key(), "admin").await?;
+
+    cx.clone(
+        "repo-clone",
+        |rb| rb.protocol(GitAccessProtocol::Ssh).path("test/repo"),
+        Credentials::SshKey(kp),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn viewer_role(cx: &TestCx, user: &str, repo_id: &str) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"query($repo: RepoId!) { repo(repoId: $repo) { viewerRole } }"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["repo"]["viewerRole"].clone())
+}
+
+#[upsilon_test]
+async fn owner_is_admin(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    assert_eq!(viewer_role(cx, "owner", &repo_id).await?, "ADMIN");
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn grant_user_role(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let other_id = cx.user_id("other").await?;
+
+    assert_eq!(viewer_role(cx, "other", &repo_id).await?, "READ");
+
+    let repo_id_clone = repo_id.clone();
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!, $user: UserId!) {
+  setRepoUserRole(repo: $repo, user: $user, role: MAINTAIN)
+}
+"#,
+            gql_vars! {"repo": repo_id_clone, "user": other_id},
+        )
+        .await
+    })
+    .await?;
+
+    assert_eq!(viewer_role(cx, "other", &repo_id).await?, "MAINTAIN");
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_admins_can_grant_roles(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let other_id = cx.user_id("other").await?;
+
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query_with_variables::<Anything>(
+                r#"
+mutation($repo: RepoId!, $user: UserId!) {
+  setRepoUserRole(repo: $repo, user: $user, role: ADMIN)
+}
+"#,
+                gql_vars! {"repo": repo_id, "user": other_id},
+            )
+            .await
+        })
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn explain_permissions(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let owner_id = cx.user_id("owner").await?;
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+query($repo: RepoId!, $user: UserId!) {
+  repo(repoId: $repo) {
+    permissionExplain(user: $user, refName: "refs/heads/trunk") {
+      role
+      sources {
+        kind
+        role
+      }
+      blockingRules {
+        pattern
+      }
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id, "user": owner_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {
+            "repo": {
+                "permissionExplain": {
+                    "role": "ADMIN",
+                    "sources": [
+                        {"kind": "GLOBAL", "role": "READ"},
+                        {"kind": "NAMESPACE_OWNERSHIP", "role": "ADMIN"},
+                    ],
+                    "blockingRules": [],
+                }
+            }
+        }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,288 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use upsilon_test_support::prelude::*;
+
+async fn update_settings(
+    cx: &TestCx,
+    user: &str,
+    query: &'static str,
+    vars: HashMap<String, serde_json::Value>,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(query, vars)
+                .await
+        })
+        .await?;
+
+    Ok(result["updateRepoSettings"].clone())
+}
+
+#[upsilon_test]
+async fn only_repo_admins_can_update_settings(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let result = update_settings(
+        cx,
+        "other",
+        r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { setDisplayName(displayName: "Repo") { id } }
+}
+"#,
+        gql_vars! {"repo": repo_id},
+    )
+    .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn rename_and_set_display_name(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let result = update_settings(
+        cx,
+        "owner",
+        r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { rename(name: "renamed") { name } }
+}
+"#,
+        gql_vars! {"repo": repo_id},
+    )
+    .await?;
+
+    assert_json_eq!(result, {"rename": {"name": "renamed"}});
+
+    let result = update_settings(
+        cx,
+        "owner",
+        r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) {
+    setDisplayName(displayName: "Renamed repo") { name displayName }
+  }
+}
+"#,
+        gql_vars! {"repo": repo_id},
+    )
+    .await?;
+
+    assert_json_eq!(
+        result,
+        {"setDisplayName": {"name": "renamed", "displayName": "Renamed repo"}}
+    );
+
+    assert_eq!(cx.lookup("owner/renamed").await?, repo_id);
+    assert_eq!(cx.lookup("owner/repo").await?, repo_id);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn rename_rejects_paths(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    for name in ["../repo", "..", ".", "a/b", "/repo", "repo/"] {
+        let result = update_settings(
+            cx,
+            "owner",
+            r#"
+mutation($repo: RepoId!, $name: RepoName!) {
+  updateRepoSettings(repo: $repo) { rename(name: $name) { name } }
+}
+"#,
+            gql_vars! {"repo": repo_id, "name": name},
+        )
+        .await;
+
+        assert!(result.is_err(), "{name} was accepted");
+    }
+
+    assert_eq!(cx.lookup("owner/repo").await?, repo_id);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn rename_to_a_taken_name_keeps_the_repo(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let other_id = cx.create_repo("owner", "other").await?;
+
+    let result = update_settings(
+        cx,
+        "owner",
+        r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { rename(name: "other") { name } }
+}
+"#,
+        gql_vars! {"repo": repo_id},
+    )
+    .await;
+
+    assert!(result.is_err());
+
+    assert_eq!(cx.lookup("owner/repo").await?, repo_id);
+    assert_eq!(cx.lookup("owner/other").await?, other_id);
+
+    cx.clone_git_binary(
+        "repo-clone",
+        |rb| rb.protocol(GitAccessProtocol::Http).path("owner/repo"),
+        Duration::from_secs(30),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn set_global_permissions(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let result = update_settings(
+        cx,
+        "owner",
+        r#"
+mutation($repo: RepoId!) {
+  updateRepoSettings(repo: $repo) { setGlobalPermissions(perms: 0) { globalPermissions } }
+}
+"#,
+        gql_vars! {"repo": repo_id},
+    )
+    .await?;
+
+    assert_json_eq!(result, {"setGlobalPermissions": {"globalPermissions": 0}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn set_protected_branches_replaces_rules(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let query = r#"
+mutation($repo: RepoId!, $rules: [BranchProtectionRuleInput!]!) {
+  updateRepoSettings(repo: $repo) {
+    setProtectedBranches(rules: $rules) { branchProtectionRules { pattern blockDeletion } }
+  }
+}
+"#;
+
+    update_settings(
+        cx,
+        "owner",
+        query,
+        gql_vars! {"repo": repo_id, "rules": [{"pattern": "trunk"}, {"pattern": "release/*"}]},
+    )
+    .await?;
+
+    let result = update_settings(
+        cx,
+        "owner",
+        query,
+        gql_vars! {
+            "repo": repo_id,
+            "rules": [{"pattern": "dev"}, {"pattern": "dev", "blockDeletion": true}]
+        },
+    )
+    .await?;
+
+    assert_json_eq!(
+        result,
+        {
+            "setProtectedBranches": {
+                "branchProtectionRules": [{"pattern": "dev", "blockDeletion": true}]
+            }
+        }
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn hook_config_is_not_served(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    assert!(cx
+        .repo_dir("owner/repo")
+        .join("upsilon-repo-config.json")
+        .exists());
+
+    for path in [
+        "/owner/repo/upsilon-repo-config.json",
+        "/__priv-git-static/owner/repo/upsilon-repo-config.json",
+    ] {
+        let status = cx
+            .with_client_as_user("owner", |cl| async move {
+                cl.git_get_status(path, "owner").await
+            })
+            .await?;
+        assert_ne!(status, 200);
+    }
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn pushes_are_rejected_with_a_malformed_hook_config(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    let credentials = || Credentials::UsernameAndTokenFromTokenList("owner".into());
+    let (path, repo) = cx
+        .clone(
+            "repo-clone",
+            |rb| rb.protocol(GitAccessProtocol::Http).path("owner/repo"),
+            credentials(),
+        )
+        .await?;
+    commit_empty(&repo, "initial")?;
+    let head = repo.head()?.name().expect("utf-8 ref name").to_owned();
+
+    tokio::fs::write(
+        cx.repo_dir("owner/repo").join("upsilon-repo-config.json"),
+        "not json",
+    )
+    .await?;
+
+    let result = cx
+        .push(
+            &path,
+            |rb| rb.protocol(GitAccessProtocol::Http).path("owner/repo"),
+            &format!("{head}:{head}"),
+            credentials(),
+        )
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,233 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn start_session(cx: &TestCx) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+mutation {
+  startSession(usernameOrEmail: "test", password: "password") {
+    accessToken
+    refreshToken
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["startSession"].clone())
+}
+
+async fn refresh_session(
+    cx: &TestCx,
+    refresh_token: serde_json::Value,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($refreshToken: String!) {
+  refreshSession(refreshToken: $refreshToken) {
+    accessToken
+    refreshToken
+  }
+}
+"#,
+                gql_vars! {"refreshToken": refresh_token},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["refreshSession"].clone())
+}
+
+async fn viewer_id(cx: &TestCx, access_token: &serde_json::Value) -> TestResult<serde_json::Value> {
+    let access_token = access_token
+        .as_str()
+        .expect("access token is a string")
+        .to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    id
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    Ok(result)
+}
+
+#[upsilon_test]
+async fn refresh_rotates_tokens(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    let user_id = cx.user_id("test").await?;
+
+    let tokens = start_session(cx).await?;
+
+    let refreshed = refresh_session(cx, tokens["refreshToken"].clone()).await?;
+
+    assert_ne!(refreshed["refreshToken"], tokens["refreshToken"]);
+
+    let result = viewer_id(cx, &refreshed["accessToken"]).await?;
+    assert_json_eq!(result, {"viewer": {"id": user_id}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn reused_refresh_token_ends_session(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let tokens = start_session(cx).await?;
+
+    let refreshed = refresh_session(cx, tokens["refreshToken"].clone()).await?;
+
+    let reused = refresh_session(cx, tokens["refreshToken"].clone()).await;
+    assert!(reused.is_err());
+
+    // the whole session is gone, so the rotated token doesn't work either
+    let result = refresh_session(cx, refreshed["refreshToken"].clone()).await;
+    assert!(result.is_err());
+
+    let result = viewer_id(cx, &refreshed["accessToken"]).await?;
+    assert_json_eq!(result, {"viewer": null});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn logout_ends_session(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let tokens = start_session(cx).await?;
+    let access_token = tokens["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+mutation {
+  logout
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"logout": true});
+
+    let result = viewer_id(cx, &tokens["accessToken"]).await?;
+    assert_json_eq!(result, {"viewer": null});
+
+    let result = refresh_session(cx, tokens["refreshToken"].clone()).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn sessions_can_be_listed_and_revoked(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let first = start_session(cx).await?;
+    let second = start_session(cx).await?;
+
+    let access_token = first["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    sessions {
+      id
+      current
+    }
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    let sessions = result["viewer"]["sessions"]
+        .as_array()
+        .expect("sessions is an array");
+    assert_eq!(sessions.len(), 2);
+
+    let other = sessions
+        .iter()
+        .find(|session| session["current"] == false)
+        .expect("the other session is listed")["id"]
+        .clone();
+
+    let access_token = first["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query_with_variables::<serde_json::Value>(
+                    r#"
+mutation($session: SessionId!) {
+  revokeSession(session: $session)
+}
+"#,
+                    gql_vars! {"session": other},
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"revokeSession": true});
+
+    let result = viewer_id(cx, &second["accessToken"]).await?;
+    assert_json_eq!(result, {"viewer": null});
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn clone_with(
+    cx: &TestCx,
+    name: &str,
+    cert: impl FnOnce(SshCertificateBuilder) -> SshCertificateBuilder,
+) -> TestResult {
+    let certificate = cx
+        .ssh_user_ca()
+        .issue(&cx.tempdir(&format!("{name}-key")).await?, cert)?;
+
+    cx.clone_with_ssh_certificate(
+        name,
+        |rb| rb.protocol(GitAccessProtocol::Ssh).path("owner/repo"),
+        &certificate,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn principal_logs_in_as_user(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    clone_with(cx, "clone", |cb| cb.principal("owner").validity("-5m:+5m")).await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn first_known_principal_is_used(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    clone_with(cx, "clone", |cb| cb.principal("nobody").principal("owner")).await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn unknown_principal_is_rejected(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    let result = clone_with(cx, "clone", |cb| cb.principal("nobody")).await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn expired_certificate_is_rejected(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    let result = clone_with(cx, "clone", |cb| {
+        cb.principal("owner").validity("20200101:20200102")
+    })
+    .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn untrusted_ca_is_rejected(#[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    let untrusted_ca = SshUserCa::generate(&cx.tempdir("untrusted-ca").await?)?;
+    let certificate =
+        untrusted_ca.issue(&cx.tempdir("clone-key").await?, |cb| cb.principal("owner"))?;
+
+    let result = cx
+        .clone_with_ssh_certificate(
+            "clone",
+            |rb| rb.protocol(GitAccessProtocol::Ssh).path("owner/repo"),
+            &certificate,
+        )
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn source_address_is_enforced(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    clone_with(cx, "local", |cb| {
+        cb.principal("owner").source_address("127.0.0.0/8,::1")
+    })
+    .await?;
+
+    let result = clone_with(cx, "remote", |cb| {
+        cb.principal("owner").source_address("192.0.2.0/24")
+    })
+    .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn force_command_replaces_the_requested_one(
+    #[cfg_setup(upsilon_ssh_user_ca)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    clone_with(cx, "allowed", |cb| {
+        cb.principal("owner")
+            .force_command("git-upload-pack 'owner/repo'")
+    })
+    .await?;
+
+    let result = clone_with(cx, "replaced", |cb| {
+        cb.principal("owner")
+            .force_command("git-upload-pack 'owner/missing'")
+    })
+    .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn unapproved_user_is_rejected(
+    #[cfg_setup(upsilon_ssh_user_ca)]
+    #[cfg_setup(upsilon_require_approval)]
+    cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_repo("owner", "repo").await?;
+
+    cx.with_client(|cl| async move {
+        cl.gql_query::<Anything>(
+            r#"
+mutation {
+  createUser(username: "pending", email: "pending@example.org", password: "password")
+}
+"#,
+        )
+        .await
+    })
+    .await?;
+
+    let result = clone_with(cx, "clone", |cb| cb.principal("pending")).await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
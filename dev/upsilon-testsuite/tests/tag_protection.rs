@@ -0,0 +1,100 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+#[upsilon_test]
+async fn set_and_remove_tag_rules(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let owner_id = cx.user_id("owner").await?;
+
+    let repo_id_clone = repo_id.clone();
+    let owner_id_clone = owner_id.clone();
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!, $user: UserId!) {
+  setTagProtectionRule(repo: $repo, rule: {pattern: "v*", allowedUsers: [$user]}) {
+    tagProtectionRules {
+      pattern
+      allowedUserIds
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id_clone, "user": owner_id_clone},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"setTagProtectionRule": {"tagProtectionRules": [{"pattern": "v*", "allowedUserIds": [owner_id]}]}}
+    );
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($repo: RepoId!) {
+  removeTagProtectionRule(repo: $repo, pattern: "v*") {
+    tagProtectionRules {
+      pattern
+    }
+  }
+}
+"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {"removeTagProtectionRule": {"tagProtectionRules": []}}
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn only_admins_manage_tag_rules(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query_with_variables::<Anything>(
+                r#"
+mutation($repo: RepoId!) {
+  setTagProtectionRule(repo: $repo, rule: {pattern: "v*"}) { id }
+}
+"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await;
+
+    assert!(result.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,489 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn create_org(cx: &TestCx, user: &str) -> TestResult<String> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"mutation { createOrganization(name: "org") { id } }"#,
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createOrganization"]["id"]
+        .as_str()
+        .expect("id is a string")
+        .to_owned())
+}
+
+async fn create_team(
+    cx: &TestCx,
+    org_id: &str,
+    name: &str,
+    parent: Option<&str>,
+) -> TestResult<String> {
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($org: OrganizationId!, $name: TeamName!, $parent: TeamId) {
+  createTeam(organization: $org, name: $name, parent: $parent) { id }
+}
+"#,
+                gql_vars! {"org": org_id, "name": name, "parent": parent},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["createTeam"]["id"]
+        .as_str()
+        .expect("id is a string")
+        .to_owned())
+}
+
+async fn set_team_parent(cx: &TestCx, team_id: &str, parent: Option<&str>) -> TestResult<Anything> {
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($team: TeamId!, $parent: TeamId) {
+  setTeamParent(team: $team, parent: $parent) { id }
+}
+"#,
+            gql_vars! {"team": team_id, "parent": parent},
+        )
+        .await
+    })
+    .await
+}
+
+async fn add_team_member(cx: &TestCx, org_id: &str, team_id: &str, user: &str) -> TestResult {
+    let user_id = cx.user_id(user).await?;
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($org: OrganizationId!, $team: TeamId!, $user: UserId!) {
+  setOrganizationMemberRole(organization: $org, user: $user, role: MEMBER) { role }
+  addTeamMember(team: $team, user: $user) { teamIds }
+}
+"#,
+            gql_vars! {"org": org_id, "team": team_id, "user": user_id},
+        )
+        .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn sub_team_members_inherit_repo_access(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("member", "test", "member@example.org")
+        .await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+    add_team_member(cx, &org_id, &child_id, "member").await?;
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($org: OrganizationId!) {
+  createRepoInOrganization(name: "repo", organizationId: $org) { id }
+}
+"#,
+                gql_vars! {"org": org_id},
+            )
+            .await
+        })
+        .await?;
+    let repo_id = result["createRepoInOrganization"]["id"].clone();
+
+    let repo_id_clone = repo_id.clone();
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!, $team: TeamId!) {
+  setRepoTeamRole(repo: $repo, team: $team, role: MAINTAIN)
+}
+"#,
+            gql_vars! {"repo": repo_id_clone, "team": parent_id},
+        )
+        .await
+    })
+    .await?;
+
+    let result = cx
+        .with_client_as_user("member", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"query($repo: RepoId!) { repo(repoId: $repo) { viewerRole } }"#,
+                gql_vars! {"repo": repo_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"repo": {"viewerRole": "MAINTAIN"}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn parent_and_children(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("member", "test", "member@example.org")
+        .await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+    add_team_member(cx, &org_id, &child_id, "member").await?;
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+query($org: OrganizationId!) {
+  organization(orgId: $org) {
+    teams {
+      name
+      parent { name }
+      children { name }
+      members { user { username } }
+      allMembers: members(includeSubTeams: true) { user { username } }
+    }
+  }
+}
+"#,
+                gql_vars! {"org": org_id},
+            )
+            .await
+        })
+        .await?;
+
+    assert_json_eq!(
+        result,
+        {
+            "organization": {
+                "teams": [
+                    {
+                        "name": "parent",
+                        "parent": null,
+                        "children": [{"name": "child"}],
+                        "members": [],
+                        "allMembers": [{"user": {"username": "member"}}]
+                    },
+                    {
+                        "name": "child",
+                        "parent": {"name": "parent"},
+                        "children": [],
+                        "members": [{"user": {"username": "member"}}],
+                        "allMembers": [{"user": {"username": "member"}}]
+                    }
+                ]
+            }
+        }
+    );
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn team_hierarchy_has_no_cycles(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+    let grandchild_id = create_team(cx, &org_id, "grandchild", Some(&child_id)).await?;
+
+    assert!(set_team_parent(cx, &parent_id, Some(&parent_id))
+        .await
+        .is_err());
+    assert!(set_team_parent(cx, &parent_id, Some(&grandchild_id))
+        .await
+        .is_err());
+
+    // moving a team somewhere else in the hierarchy is fine
+    set_team_parent(cx, &grandchild_id, Some(&parent_id)).await?;
+
+    Ok(())
+}
+
+async fn repo_path(cx: &TestCx, repo_id: &str) -> TestResult<serde_json::Value> {
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"query($repo: RepoId!) { repo(repoId: $repo) { path } }"#,
+            gql_vars! {"repo": repo_id},
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn nested_team_repos_move_with_the_team(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+
+    let child_id_clone = child_id.clone();
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($team: TeamId!) {
+  createRepoInTeam(name: "repo", teamId: $team) { id }
+}
+"#,
+                gql_vars! {"team": child_id_clone},
+            )
+            .await
+        })
+        .await?;
+    let repo_id = result["createRepoInTeam"]["id"]
+        .as_str()
+        .expect("id is a string")
+        .to_owned();
+
+    let result = repo_path(cx, &repo_id).await?;
+    assert_json_eq!(result, {"repo": {"path": "org/parent/child/repo"}});
+
+    set_team_parent(cx, &child_id, None).await?;
+
+    let result = repo_path(cx, &repo_id).await?;
+    assert_json_eq!(result, {"repo": {"path": "org/child/repo"}});
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"query { lookupRepo(path: "org/child/repo") { id } }"#,
+            )
+            .await
+        })
+        .await?;
+    assert_json_eq!(result, {"lookupRepo": {"id": repo_id}});
+
+    Ok(())
+}
+
+async fn lookup_repo(cx: &TestCx, path: &str) -> TestResult<serde_json::Value> {
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<serde_json::Value>(
+            r#"query($path: String!) { lookupRepo(path: $path) { path } }"#,
+            gql_vars! {"path": path},
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn nested_team_repos_use_the_full_path(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+    let grandchild_id = create_team(cx, &org_id, "grandchild", Some(&child_id)).await?;
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($team: TeamId!) {
+  createRepoInTeam(name: "repo", teamId: $team) { id }
+}
+"#,
+            gql_vars! {"team": grandchild_id},
+        )
+        .await
+    })
+    .await?;
+
+    let result = lookup_repo(cx, "org/parent/child/grandchild/repo").await?;
+    assert_json_eq!(
+        result,
+        {"lookupRepo": {"path": "org/parent/child/grandchild/repo"}}
+    );
+
+    // nested teams are only reachable through their parents
+    let result = lookup_repo(cx, "org/grandchild/repo").await?;
+    assert_json_eq!(result, {"lookupRepo": null});
+
+    set_team_parent(cx, &child_id, None).await?;
+
+    let result = lookup_repo(cx, "org/child/grandchild/repo").await?;
+    assert_json_eq!(result, {"lookupRepo": {"path": "org/child/grandchild/repo"}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn team_members_can_fetch_over_dumb_http(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("member", "test", "member@example.org")
+        .await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+    let team_id = create_team(cx, &org_id, "team", None).await?;
+    add_team_member(cx, &org_id, &team_id, "member").await?;
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($org: OrganizationId!) {
+  createRepoInOrganization(name: "repo", organizationId: $org) { id }
+}
+"#,
+                gql_vars! {"org": org_id},
+            )
+            .await
+        })
+        .await?;
+    let repo_id = result["createRepoInOrganization"]["id"].clone();
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($repo: RepoId!, $team: TeamId!) {
+  updateRepoSettings(repo: $repo) { setGlobalPermissions(perms: 0) { id } }
+  setRepoTeamRole(repo: $repo, team: $team, role: READ)
+}
+"#,
+            gql_vars! {"repo": repo_id, "team": team_id},
+        )
+        .await
+    })
+    .await?;
+
+    let blob = git2::Repository::open_bare(cx.repo_dir("org/repo"))?.blob(b"hello")?;
+    let blob = blob.to_string();
+    let path = format!("/org/repo/objects/{}/{}", &blob[..2], &blob[2..]);
+
+    let path_clone = path.clone();
+    let status = cx
+        .with_client_as_user("member", |cl| async move {
+            cl.git_get_status(&path_clone, "git").await
+        })
+        .await?;
+    assert_eq!(status, 200);
+
+    let path_clone = path.clone();
+    let status = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.git_get_status(&path_clone, "git").await
+        })
+        .await?;
+    assert_ne!(status, 200);
+
+    let status = cx
+        .with_client(|cl| async move { cl.git_get_status(&path, "git").await })
+        .await?;
+    assert_ne!(status, 200);
+
+    Ok(())
+}
+
+const NESTED_REPO_PATH: &str = "org/parent/child/grandchild/repo";
+
+/// Sets up [`NESTED_REPO_PATH`], owned by `owner`.
+async fn create_nested_team_repo(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let org_id = create_org(cx, "owner").await?;
+    let parent_id = create_team(cx, &org_id, "parent", None).await?;
+    let child_id = create_team(cx, &org_id, "child", Some(&parent_id)).await?;
+    let grandchild_id = create_team(cx, &org_id, "grandchild", Some(&child_id)).await?;
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            r#"
+mutation($team: TeamId!) {
+  createRepoInTeam(name: "repo", teamId: $team) { id }
+}
+"#,
+            gql_vars! {"team": grandchild_id},
+        )
+        .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Pushes a commit to [`NESTED_REPO_PATH`], then clones it again to see it is there.
+async fn push_and_clone_nested_repo(
+    cx: &TestCx,
+    protocol: GitAccessProtocol,
+    credentials: impl Fn() -> Credentials,
+) -> TestResult {
+    let (path, repo) = cx
+        .clone(
+            "push-clone",
+            |rb| rb.protocol(protocol).path(NESTED_REPO_PATH),
+            credentials(),
+        )
+        .await?;
+    let commit = commit_empty(&repo, "initial")?;
+    let head = repo.head()?.name().expect("utf-8 ref name").to_owned();
+
+    cx.push(
+        &path,
+        |rb| rb.protocol(protocol).path(NESTED_REPO_PATH),
+        &format!("{head}:{head}"),
+        credentials(),
+    )
+    .await?;
+
+    let (_, clone) = cx
+        .clone(
+            "check-clone",
+            |rb| rb.protocol(protocol).path(NESTED_REPO_PATH),
+            credentials(),
+        )
+        .await?;
+    assert_eq!(clone.refname_to_id(&head)?, commit);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn nested_team_repos_can_be_pushed_and_cloned_over_http(cx: &mut TestCx) -> TestResult {
+    create_nested_team_repo(cx).await?;
+
+    push_and_clone_nested_repo(cx, GitAccessProtocol::Http, || {
+        Credentials::UsernameAndTokenFromTokenList("owner".into())
+    })
+    .await
+}
+
+#[upsilon_test]
+#[git_ssh]
+async fn nested_team_repos_can_be_pushed_and_cloned_over_ssh(cx: &mut TestCx) -> TestResult {
+    create_nested_team_repo(cx).await?;
+
+    let kp = create_ssh_key()?;
+    cx.add_ssh_key_to_user(kp.public_key(), "owner").await?;
+
+    push_and_clone_nested_repo(cx, GitAccessProtocol::Ssh, || {
+        Credentials::SshKey(kp.clone())
+    })
+    .await
+}
@@ -0,0 +1,250 @@
+/*
+ *        Copyright (c) 2022-2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+/// Enrolls `test` in 2FA, returning the secret, the code it was
+/// confirmed with and the recovery codes.
+async fn enable_two_factor(cx: &TestCx) -> TestResult<(String, String, serde_json::Value)> {
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+mutation {
+  enrollTwoFactor {
+    secret
+    otpauthUri
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    let secret = result["enrollTwoFactor"]["secret"]
+        .as_str()
+        .expect("secret is a string")
+        .to_owned();
+
+    let code = totp_code(&secret, 0);
+    let confirm_code = code.clone();
+
+    let result = cx
+        .with_client_as_user("test", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($code: String!) {
+  confirmTwoFactor(code: $code)
+}
+"#,
+                gql_vars! {"code": code},
+            )
+            .await
+        })
+        .await?;
+
+    Ok((secret, confirm_code, result["confirmTwoFactor"].clone()))
+}
+
+async fn begin_login(cx: &TestCx) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+mutation {
+  beginLogin(usernameOrEmail: "test", password: "password") {
+    tokens {
+      accessToken
+    }
+    twoFactorToken
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["beginLogin"].clone())
+}
+
+async fn complete_login(
+    cx: &TestCx,
+    two_factor_token: serde_json::Value,
+    code: String,
+) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"
+mutation($twoFactorToken: String!, $code: String!) {
+  completeTwoFactorLogin(twoFactorToken: $twoFactorToken, code: $code) {
+    accessToken
+  }
+}
+"#,
+                gql_vars! {"twoFactorToken": two_factor_token, "code": code},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["completeTwoFactorLogin"].clone())
+}
+
+async fn create_org(cx: &TestCx) -> TestResult<serde_json::Value> {
+    cx.with_client_as_user("test", |cl| async move {
+        cl.gql_query::<serde_json::Value>(
+            r#"
+mutation {
+  createOrganization(name: "org") {
+    name
+  }
+}
+"#,
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn login_without_two_factor_is_done_in_one_step(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let result = begin_login(cx).await?;
+
+    assert!(result["tokens"]["accessToken"].is_string());
+    assert_eq!(result["twoFactorToken"], serde_json::Value::Null);
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn login_requires_totp_code(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+    let user_id = cx.user_id("test").await?;
+
+    let (secret, confirm_code, _) = enable_two_factor(cx).await?;
+
+    // the password alone is no longer enough
+    let result = cx
+        .with_client(|cl| async move {
+            cl.gql_query::<serde_json::Value>(
+                r#"
+mutation {
+  startSession(usernameOrEmail: "test", password: "password") {
+    accessToken
+  }
+}
+"#,
+            )
+            .await
+        })
+        .await;
+    assert!(result.is_err());
+
+    let step = begin_login(cx).await?;
+    assert_eq!(step["tokens"], serde_json::Value::Null);
+
+    // the code used to confirm cannot be used again
+    let result = complete_login(cx, step["twoFactorToken"].clone(), confirm_code).await;
+    assert!(result.is_err());
+
+    let tokens = complete_login(cx, step["twoFactorToken"].clone(), totp_code(&secret, 1)).await?;
+    let access_token = tokens["accessToken"]
+        .as_str()
+        .expect("access token is a string")
+        .to_owned();
+
+    let result = cx
+        .with_client(|cl| async move {
+            cl.with_token(access_token)
+                .gql_query::<serde_json::Value>(
+                    r#"
+query {
+  viewer {
+    id
+    twoFactorEnabled
+  }
+}
+"#,
+                )
+                .await
+        })
+        .await?;
+
+    assert_json_eq!(result, {"viewer": {"id": user_id, "twoFactorEnabled": true}});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn recovery_codes_work_once(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let (_, _, recovery_codes) = enable_two_factor(cx).await?;
+    let recovery_code = recovery_codes[0]
+        .as_str()
+        .expect("recovery code is a string")
+        .to_owned();
+
+    let step = begin_login(cx).await?;
+    let tokens = complete_login(cx, step["twoFactorToken"].clone(), recovery_code.clone()).await?;
+    assert!(tokens["accessToken"].is_string());
+
+    let step = begin_login(cx).await?;
+    let result = complete_login(cx, step["twoFactorToken"].clone(), recovery_code).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn wrong_code_is_rejected(cx: &mut TestCx) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let (secret, _, _) = enable_two_factor(cx).await?;
+
+    let step = begin_login(cx).await?;
+    let result = complete_login(cx, step["twoFactorToken"].clone(), totp_code(&secret, 10)).await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn org_owners_need_two_factor(
+    #[cfg_setup(upsilon_require_2fa_for_org_owners)] cx: &mut TestCx,
+) -> TestResult {
+    cx.create_user("test", "password", "test@example.org")
+        .await?;
+
+    let result = create_org(cx).await;
+    assert!(result.is_err());
+
+    enable_two_factor(cx).await?;
+
+    let result = create_org(cx).await?;
+    assert_json_eq!(result, {"createOrganization": {"name": "org"}});
+
+    Ok(())
+}
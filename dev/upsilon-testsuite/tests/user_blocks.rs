@@ -0,0 +1,126 @@
+/*
+ *        Copyright (c) 2023 Dinu Blanovschi
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        https://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use upsilon_test_support::prelude::*;
+
+async fn block(cx: &TestCx, user: &str, blocked_id: &str) -> TestResult<serde_json::Value> {
+    let result = cx
+        .with_client_as_user(user, |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"mutation($user: UserId!) { blockUser(user: $user) }"#,
+                gql_vars! {"user": blocked_id},
+            )
+            .await
+        })
+        .await?;
+
+    Ok(result["blockUser"].clone())
+}
+
+async fn set_role(
+    cx: &TestCx,
+    repo_id: &str,
+    user_id: &str,
+    role: &'static str,
+) -> TestResult<Anything> {
+    let query = format!(
+        r#"
+mutation($repo: RepoId!, $user: UserId!) {{
+  setRepoUserRole(repo: $repo, user: $user, role: {role})
+}}
+"#
+    );
+
+    cx.with_client_as_user("owner", |cl| async move {
+        cl.gql_query_with_variables::<Anything>(
+            &query,
+            gql_vars! {"repo": repo_id, "user": user_id},
+        )
+        .await
+    })
+    .await
+}
+
+#[upsilon_test]
+async fn blocked_users_are_listed(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let other_id = cx.user_id("other").await?;
+
+    assert_eq!(block(cx, "owner", &other_id).await?, true);
+    // already blocked
+    assert_eq!(block(cx, "owner", &other_id).await?, false);
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query::<serde_json::Value>(r#"query { viewer { blockedUsers { username } } }"#)
+                .await
+        })
+        .await?;
+    assert_json_eq!(result, {"viewer": {"blockedUsers": [{"username": "other"}]}});
+
+    let result = cx
+        .with_client_as_user("owner", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"mutation($user: UserId!) { unblockUser(user: $user) }"#,
+                gql_vars! {"user": other_id},
+            )
+            .await
+        })
+        .await?;
+    assert_json_eq!(result, {"unblockUser": true});
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn users_cannot_block_themselves(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    let owner_id = cx.user_id("owner").await?;
+
+    assert!(block(cx, "owner", &owner_id).await.is_err());
+
+    Ok(())
+}
+
+#[upsilon_test]
+async fn blocking_revokes_repo_roles(cx: &mut TestCx) -> TestResult {
+    cx.create_user("owner", "test", "owner@example.org").await?;
+    cx.create_user("other", "test", "other@example.org").await?;
+    let repo_id = cx.create_repo("owner", "repo").await?;
+    let other_id = cx.user_id("other").await?;
+
+    set_role(cx, &repo_id, &other_id, "WRITE").await?;
+
+    block(cx, "owner", &other_id).await?;
+
+    let repo_id_clone = repo_id.clone();
+    let result = cx
+        .with_client_as_user("other", |cl| async move {
+            cl.gql_query_with_variables::<serde_json::Value>(
+                r#"query($repo: RepoId!) { repo(repoId: $repo) { viewerRole } }"#,
+                gql_vars! {"repo": repo_id_clone},
+            )
+            .await
+        })
+        .await?;
+    assert_json_eq!(result, {"repo": {"viewerRole": "READ"}});
+
+    // and they cannot be given new ones
+    assert!(set_role(cx, &repo_id, &other_id, "WRITE").await.is_err());
+
+    Ok(())
+}